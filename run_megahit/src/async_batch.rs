@@ -0,0 +1,179 @@
+use crate::report::{Hooks, JobResult, ProgressHandler, SampleResult};
+use crate::{run_with, Config, RunMegahitError};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::watch;
+use tokio::task::{self, JoinHandle};
+
+/// A batch running on tokio's blocking threads, for async programs that
+/// dispatch assemblies, e.g., a web service:
+///
+/// ```no_run
+/// # async fn assemble() -> Result<(), run_megahit::RunMegahitError> {
+/// use run_megahit::{report::Hooks, AsyncBatch, ConfigBuilder};
+///
+/// let config = ConfigBuilder::new().query("reads").build()?;
+/// let batch = AsyncBatch::spawn(config, Hooks::new());
+/// if let Some(result) = batch.sample("S1").await {
+///     println!("S1 {}", result.status());
+/// }
+/// let results = batch.finish().await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The batch doesn't catch Ctrl-C; `cancel` stops it instead.
+pub struct AsyncBatch {
+    /// The jobs that have ended so far
+    finished: watch::Receiver<Vec<JobResult>>,
+    cancel: Arc<AtomicBool>,
+    task: JoinHandle<Result<Vec<SampleResult>, RunMegahitError>>,
+}
+
+impl AsyncBatch {
+    /// Starts the batch, which must be from within a tokio runtime
+    pub fn spawn(config: Config, hooks: Hooks) -> AsyncBatch {
+        let mut config = config;
+        let mut hooks = hooks;
+        let cancel = Arc::new(AtomicBool::new(false));
+        config.cancel = Some(Arc::clone(&cancel));
+
+        let (sender, finished) = watch::channel(vec![]);
+        hooks.add_progress_handler(Box::new(Finished(sender)));
+
+        // The hooks, and so the sender, are dropped when the batch ends
+        let task = task::spawn_blocking(move || run_with(config, &hooks));
+
+        AsyncBatch {
+            finished,
+            cancel,
+            task,
+        }
+    }
+
+    /// Resolves to how the sample's job ended, or to None if the batch
+    /// ended without running it (e.g., no such sample)
+    pub fn sample(
+        &self,
+        name: &str,
+    ) -> impl Future<Output = Option<JobResult>> + Send + 'static {
+        let mut finished = self.finished.clone();
+        let name = name.to_string();
+        async move {
+            loop {
+                let found = finished
+                    .borrow_and_update()
+                    .iter()
+                    .find(|res| res.sample == name)
+                    .cloned();
+                if found.is_some() {
+                    return found;
+                }
+                if finished.changed().await.is_err() {
+                    return finished
+                        .borrow()
+                        .iter()
+                        .find(|res| res.sample == name)
+                        .cloned();
+                }
+            }
+        }
+    }
+
+    /// The jobs that have ended so far
+    pub fn finished(&self) -> Vec<JobResult> {
+        self.finished.borrow().clone()
+    }
+
+    /// Starts no more jobs and kills the running ones, which end as
+    /// interrupted
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::SeqCst);
+    }
+
+    /// Waits for the batch to end and returns what became of each sample
+    pub async fn finish(self) -> Result<Vec<SampleResult>, RunMegahitError> {
+        self.task
+            .await
+            .map_err(|e| RunMegahitError::Other(e.to_string()))?
+    }
+}
+
+/// Sends each ended job to the batch's futures
+struct Finished(watch::Sender<Vec<JobResult>>);
+
+impl ProgressHandler for Finished {
+    fn on_job_finish(&self, result: &JobResult) {
+        self.0.send_modify(|finished| finished.push(result.clone()));
+    }
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ConfigBuilder;
+    use std::{env, fs, os::unix::fs::PermissionsExt, time::Instant};
+
+    #[test]
+    fn test_async_batch() {
+        let dir = env::temp_dir().join("run_megahit_async");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("in")).unwrap();
+        for name in ["fast", "slow"] {
+            fs::write(dir.join(format!("in/{}.fq", name)), "@r\nA\n+\nI\n")
+                .unwrap();
+        }
+
+        // Assembles "fast" at once and "slow" not for a long time
+        let megahit = dir.join("megahit");
+        fs::write(
+            &megahit,
+            "#!/bin/sh\n\
+             [ \"$1\" = --version ] && echo MEGAHIT v1.2.9 && exit 0\n\
+             case \"$*\" in *slow*) sleep 30;; esac\n\
+             exit 0\n",
+        )
+        .unwrap();
+        fs::set_permissions(&megahit, fs::Permissions::from_mode(0o755))
+            .unwrap();
+
+        let config = ConfigBuilder::new()
+            .query(dir.join("in"))
+            .out_dir(dir.join("out"))
+            .megahit(&megahit)
+            .num_concurrent_jobs(2)
+            .no_progress()
+            .switch("quiet")
+            .build()
+            .unwrap();
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let start = Instant::now();
+        let results = runtime.block_on(async {
+            let batch = AsyncBatch::spawn(config, Hooks::new());
+            let fast = batch.sample("fast").await.unwrap();
+            assert_eq!(fast.status(), "ok");
+
+            batch.cancel();
+            assert_eq!(
+                batch.sample("slow").await.unwrap().status(),
+                "interrupted"
+            );
+            assert!(batch.sample("nope").await.is_none());
+            batch.finish().await.unwrap()
+        });
+
+        assert!(start.elapsed().as_secs() < 20);
+        let mut statuses: Vec<_> = results
+            .iter()
+            .map(|res| (res.sample.as_str(), res.status))
+            .collect();
+        statuses.sort();
+        assert_eq!(statuses, vec![("fast", "ok"), ("slow", "interrupted")]);
+    }
+}
@@ -0,0 +1,375 @@
+use crate::scheduler::{file_name, Resources, Scheduler, Script, Task};
+use crate::{shell_quote, Job, RunMegahitError};
+use serde_json::{json, Value};
+use std::cell::OnceCell;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+type MyResult<T> = Result<T, Box<dyn Error>>;
+
+/// Container the jobs run in unless --aws_image says otherwise
+pub const DEFAULT_IMAGE: &str = "vout/megahit:latest";
+
+/// Job definition registered for the batch
+const JOB_DEFINITION: &str = "run_megahit";
+
+/// Where a dry run leaves the job definition, beside the submit-job
+/// inputs, for `aws batch ... --cli-input-json file://...`
+const JOB_DEFINITION_FILE: &str = "job_definition.json";
+
+/// AWS Batch through the `aws` CLI. Inputs are copied to S3 under the
+/// prefix and back to the same paths inside the container, so the
+/// megahit command needs no rewriting; each out_dir comes back the same
+/// way once its job succeeds.
+pub struct AwsBatch {
+    prefix: String,
+    queue: String,
+    image: String,
+    /// ARN of the job definition, once registered
+    definition: OnceCell<String>,
+}
+
+impl AwsBatch {
+    pub fn new(prefix: &str, queue: &str, image: &str) -> AwsBatch {
+        AwsBatch {
+            prefix: prefix.trim_end_matches('/').to_string(),
+            queue: queue.to_string(),
+            image: image.to_string(),
+            definition: OnceCell::new(),
+        }
+    }
+
+    /// Where a local path is kept on S3
+    fn s3_path(&self, area: &str, path: &str) -> String {
+        format!("{}/{}/{}", self.prefix, area, path.trim_start_matches('/'))
+    }
+
+    /// The input of `aws batch register-job-definition` for jobs that
+    /// each get `res`
+    fn job_definition(&self, res: &Resources) -> Value {
+        let mut requirements =
+            vec![json!({ "type": "VCPU", "value": res.cpus.to_string() })];
+        if let Some(mem_mb) = res.mem_mb {
+            requirements
+                .push(json!({ "type": "MEMORY", "value": mem_mb.to_string() }));
+        }
+
+        let mut definition = json!({
+            "jobDefinitionName": JOB_DEFINITION,
+            "type": "container",
+            "containerProperties": {
+                "image": self.image,
+                "resourceRequirements": requirements,
+            },
+        });
+        if let Some(time) = res.time {
+            definition["timeout"] =
+                json!({ "attemptDurationSeconds": time.as_secs() });
+        }
+        definition
+    }
+
+    /// The input of `aws batch submit-job` for the script, which the job
+    /// fetches from S3 and runs
+    fn submit_job(&self, script: &Path, definition: &str) -> Value {
+        let name = script
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let script_s3 = self.s3_path("scripts", &script.display().to_string());
+
+        json!({
+            "jobName": batch_name(&format!("megahit-{}", name)),
+            "jobQueue": self.queue,
+            "jobDefinition": definition,
+            "containerOverrides": {
+                "command": [
+                    "bash",
+                    "-c",
+                    format!("aws s3 cp {} - | bash", shell_quote(&script_s3)),
+                ],
+            },
+        })
+    }
+}
+
+impl Scheduler for AwsBatch {
+    fn name(&self) -> &'static str {
+        "aws-batch"
+    }
+
+    fn directives(
+        &self,
+        job_name: &str,
+        _res: &Resources,
+        _log: &Path,
+    ) -> String {
+        format!(
+            "# AWS Batch job {} on queue {}",
+            batch_name(job_name),
+            self.queue
+        )
+    }
+
+    fn prepare(&self, jobs: &[Job], res: &Resources) -> MyResult<()> {
+        for job in jobs {
+            for file in job.read_files() {
+                aws(&["s3", "cp", file, &self.s3_path("inputs", file)])?;
+            }
+        }
+
+        let registered: Value = serde_json::from_str(&aws(&[
+            "batch",
+            "register-job-definition",
+            "--cli-input-json",
+            &self.job_definition(res).to_string(),
+        ])?)?;
+        let arn = registered["jobDefinitionArn"]
+            .as_str()
+            .ok_or("No jobDefinitionArn from register-job-definition")?;
+        let _ = self.definition.set(arn.to_string());
+
+        Ok(())
+    }
+
+    fn command(&self, job: &Job) -> String {
+        let mut lines = vec![];
+        for file in job.read_files() {
+            lines.push(format!(
+                "mkdir -p \"$(dirname {0})\" && aws s3 cp {1} {0}",
+                shell_quote(file),
+                shell_quote(&self.s3_path("inputs", file))
+            ));
+        }
+
+        let out_dir = job.out_dir.display().to_string();
+        lines
+            .push(format!("mkdir -p \"$(dirname {})\"", shell_quote(&out_dir)));
+        lines.push(job.command_line());
+        lines.push(format!(
+            "aws s3 sync {} {}",
+            shell_quote(&out_dir),
+            shell_quote(&self.s3_path("outputs", &out_dir))
+        ));
+
+        lines.join("\n")
+    }
+
+    fn submit(&self, script: &Path) -> MyResult<String> {
+        let definition = self
+            .definition
+            .get()
+            .ok_or("The AWS Batch job definition is not registered")?;
+        let script_s3 = self.s3_path("scripts", &script.display().to_string());
+        aws(&["s3", "cp", &script.display().to_string(), &script_s3])?;
+
+        aws(&[
+            "batch",
+            "submit-job",
+            "--cli-input-json",
+            &self.submit_job(script, definition).to_string(),
+            "--query",
+            "jobId",
+            "--output",
+            "text",
+        ])
+    }
+
+    fn poll(&self, ids: &[&str]) -> MyResult<Vec<Task>> {
+        let mut tasks = vec![];
+
+        // describe-jobs takes at most 100 IDs
+        for chunk in ids.chunks(100) {
+            let mut args = vec!["batch", "describe-jobs", "--jobs"];
+            args.extend(chunk);
+            tasks.extend(parse_jobs(&aws(&args)?)?);
+        }
+
+        Ok(tasks)
+    }
+
+    fn write_requests(
+        &self,
+        scripts: &[Script],
+        res: &Resources,
+        dir: &Path,
+    ) -> MyResult<()> {
+        fs::create_dir_all(dir)?;
+        let write = |name: &str, input: &Value| -> MyResult<()> {
+            let path = dir.join(name);
+            fs::write(&path, serde_json::to_string_pretty(input)? + "\n")
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            Ok(())
+        };
+
+        write(JOB_DEFINITION_FILE, &self.job_definition(res))?;
+        for script in scripts {
+            write(
+                &format!("{}.submit.json", file_name(&script.sample)),
+                &self.submit_job(&script.path, JOB_DEFINITION),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn fetch(&self, job: &Job) -> MyResult<()> {
+        let out_dir = job.out_dir.display().to_string();
+        aws(&["s3", "sync", &self.s3_path("outputs", &out_dir), &out_dir])?;
+        Ok(())
+    }
+}
+
+// --------------------------------------------------
+/// Runs the AWS CLI and returns its STDOUT
+fn aws(args: &[&str]) -> MyResult<String> {
+    let output = Command::new("aws").args(args).output().map_err(|e| {
+        RunMegahitError::SpawnFailed {
+            program: "aws".to_string(),
+            reason: e.to_string(),
+        }
+    })?;
+
+    if !output.status.success() {
+        return Err(From::from(format!(
+            "aws {} failed: {}",
+            args.iter().take(2).copied().collect::<Vec<_>>().join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// --------------------------------------------------
+/// Batch job names allow only letters, numbers, hyphens, and underscores
+fn batch_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(128)
+        .collect()
+}
+
+// --------------------------------------------------
+/// Parses the JSON of `aws batch describe-jobs`
+fn parse_jobs(json: &str) -> MyResult<Vec<Task>> {
+    let described: Value = serde_json::from_str(json)?;
+    let jobs = described["jobs"].as_array().cloned().unwrap_or_default();
+
+    Ok(jobs
+        .iter()
+        .map(|job| {
+            let status = job["status"].as_str().unwrap_or("").to_string();
+            let finished = status == "SUCCEEDED" || status == "FAILED";
+            let exit_code = match status.as_str() {
+                "SUCCEEDED" => Some(0),
+                "FAILED" => Some(
+                    job["container"]["exitCode"]
+                        .as_i64()
+                        .map(|code| code as i32)
+                        .filter(|&code| code != 0)
+                        .unwrap_or(1),
+                ),
+                _ => None,
+            };
+            let elapsed_secs =
+                match (job["startedAt"].as_f64(), job["stoppedAt"].as_f64()) {
+                    (Some(start), Some(stop)) => (stop - start) / 1000.,
+                    _ => 0.,
+                };
+
+            Task {
+                id: job["jobId"].as_str().unwrap_or("").to_string(),
+                state: status,
+                finished,
+                exit_code,
+                timed_out: job["statusReason"]
+                    .as_str()
+                    .is_some_and(|reason| reason.contains("timeout")),
+                elapsed_secs,
+            }
+        })
+        .collect())
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_aws_batch() {
+        let job = Job {
+            sample: "S1".to_string(),
+            out_dir: PathBuf::from("/out/S1"),
+            program: "megahit".to_string(),
+            args: [
+                "-o",
+                "/out/S1",
+                "-1",
+                "/in/a_1.fq,/in/b_1.fq",
+                "-r",
+                "s.fq",
+            ]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect(),
+        };
+        assert_eq!(job.read_files(), vec!["/in/a_1.fq", "/in/b_1.fq", "s.fq"]);
+
+        let batch = AwsBatch::new("s3://bucket/run/", "q", DEFAULT_IMAGE);
+        let command = batch.command(&job);
+        assert!(command.contains("aws s3 cp s3://bucket/run/inputs/s.fq s.fq"));
+        assert!(command
+            .ends_with("aws s3 sync /out/S1 s3://bucket/run/outputs/out/S1"));
+
+        assert_eq!(batch_name("megahit-S 1.x"), "megahit-S_1_x");
+
+        let res = Resources::new(
+            4,
+            Some(8e9),
+            Some(std::time::Duration::from_secs(3600)),
+        );
+        let definition = batch.job_definition(&res);
+        assert_eq!(definition["jobDefinitionName"], "run_megahit");
+        assert_eq!(
+            definition["containerProperties"]["resourceRequirements"][1]
+                ["value"],
+            "8000"
+        );
+        assert_eq!(definition["timeout"]["attemptDurationSeconds"], 3600);
+
+        let submit = batch.submit_job(Path::new("/out/aws-batch/S1.sh"), "d");
+        assert_eq!(submit["jobName"], "megahit-S1");
+        assert_eq!(submit["jobQueue"], "q");
+        assert_eq!(
+            submit["containerOverrides"]["command"][2],
+            "aws s3 cp s3://bucket/run/scripts/out/aws-batch/S1.sh - | bash"
+        );
+
+        let tasks = parse_jobs(
+            r#"{"jobs": [
+                {"jobId": "a", "status": "SUCCEEDED",
+                 "startedAt": 1000, "stoppedAt": 61000},
+                {"jobId": "b", "status": "FAILED",
+                 "statusReason": "Job attempt duration exceeded timeout",
+                 "container": {"exitCode": 137}},
+                {"jobId": "c", "status": "RUNNABLE"}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(tasks[0].exit_code, Some(0));
+        assert_eq!(tasks[0].elapsed_secs, 60.);
+        assert_eq!(tasks[1].exit_code, Some(137));
+        assert!(tasks[1].timed_out);
+        assert!(!tasks[2].finished);
+    }
+}
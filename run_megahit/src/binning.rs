@@ -0,0 +1,123 @@
+use crate::report::Metric;
+use crate::stats;
+use crate::trim::args;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+type MyResult<T> = Result<T, Box<dyn Error>>;
+
+/// The binners --bin takes
+pub const TOOLS: &[&str] = &["metabat2"];
+
+/// Where the bins go, relative to a sample's out_dir
+pub const BINS_DIR: &str = "bins";
+
+/// The bins of the batch's samples side by side, relative to out_dir
+pub const SUMMARY_FILE: &str = "bins_summary.tsv";
+
+// --------------------------------------------------
+/// MetaBAT2 on the sample's final contigs with the depth of each contig
+/// the coverage stage made. The bins are bins/bin.1.fa and so on, in place
+/// of any from an earlier run.
+pub fn command(
+    dir: &Path,
+    depth: &Path,
+    threads: Option<u32>,
+) -> Vec<Vec<String>> {
+    let bins = dir.join(BINS_DIR).display().to_string();
+    let mut cmd = args(&[
+        "metabat2",
+        "-i",
+        &dir.join("final.contigs.fa").display().to_string(),
+        "-o",
+        &dir.join(BINS_DIR).join("bin").display().to_string(),
+        "-a",
+        &depth.display().to_string(),
+    ]);
+    if let Some(threads) = threads {
+        cmd.extend(args(&["-t", &threads.to_string()]));
+    }
+
+    vec![
+        args(&["rm", "-rf", &bins]),
+        args(&["mkdir", "-p", &bins]),
+        cmd,
+    ]
+}
+
+// --------------------------------------------------
+/// How many bins the sample's contigs went into, and how many bases and
+/// contigs they hold between them
+pub fn read_summary(dir: &Path) -> MyResult<Vec<Metric>> {
+    let bins = dir.join(BINS_DIR);
+    let entries = fs::read_dir(&bins)
+        .map_err(|e| format!("{}: {}", bins.display(), e))?;
+
+    let (mut num_bins, mut contigs, mut length) = (0, 0, 0);
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != "fa") {
+            continue;
+        }
+        let found = stats::assembly_stats(&path)?;
+        num_bins += 1;
+        contigs += found.num_contigs;
+        length += found.total_length;
+    }
+
+    let metric = |name: &str, value: String| Metric {
+        stage: "bin".to_string(),
+        name: name.to_string(),
+        value,
+    };
+    Ok(vec![
+        metric("bins", num_bins.to_string()),
+        metric("binned_contigs", contigs.to_string()),
+        metric("binned_length", length.to_string()),
+    ])
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binning() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let depth = dir.join("coverage/depth.txt");
+        let cmds = command(dir, &depth, Some(8));
+        assert_eq!(cmds[0].join(" "), format!("rm -rf {}/bins", dir.display()));
+        assert_eq!(
+            cmds[2].join(" "),
+            format!(
+                "metabat2 -i {}/final.contigs.fa -o {}/bins/bin -a {} -t 8",
+                dir.display(),
+                dir.display(),
+                depth.display()
+            )
+        );
+        assert!(!command(dir, &depth, None)[2].contains(&"-t".to_string()));
+
+        assert!(read_summary(dir).is_err());
+        fs::create_dir_all(dir.join(BINS_DIR)).unwrap();
+        fs::write(dir.join("bins/bin.1.fa"), ">c1\nACGT\n>c2\nAC\n").unwrap();
+        fs::write(dir.join("bins/bin.2.fa"), ">c3\nGGCC\n").unwrap();
+        fs::write(dir.join("bins/bin.log"), "done\n").unwrap();
+        let values: Vec<String> = read_summary(dir)
+            .unwrap()
+            .into_iter()
+            .map(|metric| format!("{}={}", metric.name, metric.value))
+            .collect();
+        assert_eq!(
+            values,
+            vec!["bins=2", "binned_contigs=3", "binned_length=10"]
+        );
+
+        // A bin that can't be read
+        fs::create_dir(dir.join("bins/bin.3.fa")).unwrap();
+        assert!(read_summary(dir).is_err());
+    }
+}
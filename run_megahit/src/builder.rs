@@ -0,0 +1,212 @@
+use crate::cli::Cli;
+use crate::{clap_error, config_from, Action, Config, RunMegahitError};
+use clap::{ColorChoice, CommandFactory, FromArgMatches};
+use std::error::Error;
+use std::path::Path;
+use std::time::Duration;
+
+type MyResult<T> = Result<T, Box<dyn Error>>;
+
+/// Makes the Config of a batch without a command line, for programs that
+/// run run_megahit as a library:
+///
+/// ```no_run
+/// let config = run_megahit::ConfigBuilder::new()
+///     .query("reads")
+///     .out_dir("assemblies")
+///     .num_concurrent_jobs(4)
+///     .option("preset", "meta-large")
+///     .build()?;
+/// run_megahit::run(config)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+///
+/// The options are the same as on the command line and are checked the
+/// same way by `build`. They are kept as the command line they make so that
+/// `rerun-failed` and `resume` work on the batch. Unlike `get_args`, no
+/// RUN_MEGAHIT_* variables or --config file are read.
+#[derive(Debug, Clone)]
+pub struct ConfigBuilder {
+    args: Vec<String>,
+    /// Passed to every megahit, after the options of the batch
+    megahit_args: Vec<String>,
+}
+
+impl Default for ConfigBuilder {
+    fn default() -> ConfigBuilder {
+        ConfigBuilder::new()
+    }
+}
+
+impl ConfigBuilder {
+    pub fn new() -> ConfigBuilder {
+        ConfigBuilder {
+            args: vec!["run_megahit".to_string()],
+            megahit_args: vec![],
+        }
+    }
+
+    /// Sets an option by its long name, e.g., ("k_min", "27")
+    pub fn option(&mut self, name: &str, value: &str) -> &mut Self {
+        self.args.push(format!("--{}={}", name, value));
+        self
+    }
+
+    /// Turns on a switch by its long name, e.g., "dry_run"
+    pub fn switch(&mut self, name: &str) -> &mut Self {
+        self.args.push(format!("--{}", name));
+        self
+    }
+
+    /// A read file or a directory of them, one per call
+    pub fn query<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.path_option("query", path.as_ref())
+    }
+
+    pub fn manifest<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.path_option("manifest", path.as_ref())
+    }
+
+    pub fn out_dir<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.path_option("out_dir", path.as_ref())
+    }
+
+    /// The megahit program to run instead of megahit in PATH
+    pub fn megahit<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.path_option("megahit_path", path.as_ref())
+    }
+
+    pub fn num_concurrent_jobs(&mut self, jobs: u32) -> &mut Self {
+        self.option("num_concurrent_jobs", &jobs.to_string())
+    }
+
+    pub fn num_cpu_threads(&mut self, threads: u32) -> &mut Self {
+        self.option("num_cpu_threads", &threads.to_string())
+    }
+
+    /// A fraction of the RAM (0.5) or bytes, for each job
+    pub fn memory(&mut self, memory: f64) -> &mut Self {
+        self.option("memory", &memory.to_string())
+    }
+
+    pub fn k_list(&mut self, k_list: &[u32]) -> &mut Self {
+        let k_list: Vec<String> = k_list.iter().map(u32::to_string).collect();
+        self.option("k_list", &k_list.join(","))
+    }
+
+    /// A policy as --on_failure takes it, e.g., "fail-fast"
+    pub fn on_failure(&mut self, policy: &str) -> &mut Self {
+        self.option("on_failure", policy)
+    }
+
+    pub fn retries(&mut self, retries: u32) -> &mut Self {
+        self.option("retries", &retries.to_string())
+    }
+
+    pub fn job_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.option("job_timeout", &format!("{}s", timeout.as_secs()))
+    }
+
+    /// More options for every megahit command, e.g., ["--no-mercy"]
+    pub fn megahit_args<S: AsRef<str>>(&mut self, args: &[S]) -> &mut Self {
+        self.megahit_args
+            .extend(args.iter().map(|arg| arg.as_ref().to_string()));
+        self
+    }
+
+    pub fn dry_run(&mut self) -> &mut Self {
+        self.switch("dry_run")
+    }
+
+    pub fn resume(&mut self) -> &mut Self {
+        self.switch("resume")
+    }
+
+    /// Don't draw progress bars, e.g., when the output isn't a terminal
+    pub fn no_progress(&mut self) -> &mut Self {
+        self.switch("no_progress")
+    }
+
+    /// The config of the batch, or why the options don't make one
+    pub fn build(&self) -> Result<Config, RunMegahitError> {
+        Ok(self.parse()?)
+    }
+
+    fn parse(&self) -> MyResult<Config> {
+        let mut args = self.args.clone();
+        if !self.megahit_args.is_empty() {
+            args.push("--".to_string());
+            args.extend(self.megahit_args.iter().cloned());
+        }
+
+        let matches = Cli::command()
+            .color(ColorChoice::Never)
+            .try_get_matches_from(&args)
+            .map_err(clap_error)?;
+        let cli = Cli::from_arg_matches(&matches)?;
+        config_from(Action::Run, cli.run.out_dir.clone(), cli.run, args)
+    }
+
+    fn path_option(&mut self, name: &str, path: &Path) -> &mut Self {
+        self.option(name, &path.to_string_lossy())
+    }
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_builder() {
+        let config = ConfigBuilder::new()
+            .query("tests/inputs")
+            .out_dir("out")
+            .num_concurrent_jobs(2)
+            .k_list(&[21, 41, 61])
+            .on_failure("fail-fast")
+            .job_timeout(Duration::from_secs(3600))
+            .option("preset", "meta-large")
+            .megahit_args(&["--no-mercy"])
+            .dry_run()
+            .build()
+            .unwrap();
+
+        assert_eq!(config.action, Action::Run);
+        assert_eq!(config.query, vec!["tests/inputs"]);
+        assert_eq!(config.out_dir, PathBuf::from("out"));
+        assert_eq!(config.num_concurrent_jobs, 2);
+        assert_eq!(config.k_list, Some(vec![21, 41, 61]));
+        assert_eq!(config.job_timeout, Some(Duration::from_secs(3600)));
+        assert_eq!(config.preset.as_deref(), Some("meta-large"));
+        assert_eq!(config.megahit_args, vec!["--no-mercy"]);
+        assert!(config.dry_run);
+        assert_eq!(config.args[0], "run_megahit");
+        assert_eq!(config.args.last().unwrap(), "--no-mercy");
+
+        let err = ConfigBuilder::new().out_dir("out").build().unwrap_err();
+        assert_eq!(err.to_string(), "--query or --manifest is required");
+
+        let err = ConfigBuilder::new()
+            .query("x")
+            .option("k_min", "20")
+            .build()
+            .unwrap_err();
+        assert!(matches!(err, RunMegahitError::InvalidK(_)));
+
+        let err = ConfigBuilder::new()
+            .query("x")
+            .option("k_min", "oops")
+            .build()
+            .unwrap_err();
+        assert!(err.to_string().contains("--k_min"));
+
+        let err = ConfigBuilder::new()
+            .query("x")
+            .switch("bogus")
+            .build()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "unknown option \"--bogus\"");
+    }
+}
@@ -0,0 +1,195 @@
+use md5::Md5;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::error::Error;
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+
+type MyResult<T> = Result<T, Box<dyn Error>>;
+
+/// Expected digests keyed on the full path of each file in the manifest
+pub type ChecksumLookup = HashMap<String, String>;
+
+#[derive(Debug, PartialEq)]
+pub enum Mismatch {
+    Missing,
+    Differs { expected: String, actual: String },
+}
+
+// --------------------------------------------------
+/// Reads an `md5sum`/`sha256sum`-style manifest ("<digest>  <file>"),
+/// whose files are relative to the manifest's directory as `md5sum`
+/// would have listed them there
+pub fn read_manifest(path: &Path) -> MyResult<ChecksumLookup> {
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    Ok(read_entries(path)?
+        .into_iter()
+        .map(|(name, digest)| {
+            (full_path(&dir.join(name).to_string_lossy()), digest)
+        })
+        .collect())
+}
+
+// --------------------------------------------------
+/// The (file, digest) lines of a manifest with the file names as written
+pub fn read_entries(path: &Path) -> MyResult<Vec<(String, String)>> {
+    let file =
+        File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut entries = vec![];
+
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match line.split_once(char::is_whitespace) {
+            Some((digest, name)) if is_digest(digest) => {
+                let name = name.trim_start().trim_start_matches('*');
+                entries.push((name.to_string(), digest.to_lowercase()));
+            }
+            _ => {
+                return Err(From::from(format!(
+                    "{} line {}: expected \"<digest>  <file>\", got \"{}\"",
+                    path.display(),
+                    i + 1,
+                    line
+                )))
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+// --------------------------------------------------
+/// Hashes a file with the algorithm matching the length of `expected`
+pub fn digest_like(path: &str, expected: &str) -> MyResult<String> {
+    if expected.len() == 32 {
+        hash_file::<Md5>(path)
+    } else {
+        hash_file::<Sha256>(path)
+    }
+}
+
+// --------------------------------------------------
+pub fn sha256_file(path: &str) -> MyResult<String> {
+    hash_file::<Sha256>(path)
+}
+
+// --------------------------------------------------
+/// Checks one file against the manifest, returning the problem if any
+pub fn verify(
+    path: &str,
+    lookup: &ChecksumLookup,
+) -> MyResult<Option<Mismatch>> {
+    let expected = match lookup.get(&full_path(path)) {
+        Some(digest) => digest,
+        _ => return Ok(Some(Mismatch::Missing)),
+    };
+
+    let actual = digest_like(path, expected)?;
+
+    if &actual == expected {
+        Ok(None)
+    } else {
+        Ok(Some(Mismatch::Differs {
+            expected: expected.to_string(),
+            actual,
+        }))
+    }
+}
+
+// --------------------------------------------------
+fn hash_file<D: Digest + io::Write>(path: &str) -> MyResult<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = D::new();
+    io::copy(&mut file, &mut hasher)?;
+
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+// --------------------------------------------------
+/// MD5 digests are 32 hex digits, SHA256 are 64
+fn is_digest(val: &str) -> bool {
+    (val.len() == 32 || val.len() == 64)
+        && val.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+// --------------------------------------------------
+/// The path a file is known by in a lookup, so that files of the same
+/// name in different directories stay apart
+pub fn full_path(path: &str) -> String {
+    let path = Path::new(path);
+    fs::canonicalize(path)
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_path_buf())
+        .to_string_lossy()
+        .to_string()
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        fs::create_dir_all(dir.join("data")).unwrap();
+        let reads = dir.join("data/S1_R1.fastq");
+        fs::write(&reads, "@r1\nACGT\n+\nIIII\n").unwrap();
+        // Another run's reads of the same name
+        let other = dir.join("S1_R1.fastq");
+        fs::write(&other, "@r1\nTTTT\n+\nIIII\n").unwrap();
+
+        let manifest = dir.join("md5sum.txt");
+        fs::write(
+            &manifest,
+            "d5a1bc60d2c3bf3a9d9d3a0f8c0e8a31  S1_R2.fastq\n\
+             2e6a7ff7ee8bd8b2f3a0c3d2a6d7f0a1  ./data/S1_R1.fastq\n",
+        )
+        .unwrap();
+
+        let lookup = read_manifest(&manifest).unwrap();
+        assert_eq!(lookup.len(), 2);
+
+        let reads = reads.to_str().unwrap();
+        let res = verify(reads, &lookup).unwrap();
+        assert!(matches!(res, Some(Mismatch::Differs { .. })));
+        let other = other.to_str().unwrap();
+        assert_eq!(verify(other, &lookup).unwrap(), Some(Mismatch::Missing));
+
+        let actual = hash_file::<Md5>(reads).unwrap();
+        fs::write(
+            &manifest,
+            format!(
+                "{}  data/S1_R1.fastq\n{}  S1_R1.fastq\n",
+                actual,
+                hash_file::<Md5>(other).unwrap()
+            ),
+        )
+        .unwrap();
+        let lookup = read_manifest(&manifest).unwrap();
+        assert_eq!(verify(reads, &lookup).unwrap(), None);
+        assert_eq!(verify(other, &lookup).unwrap(), None);
+
+        let sha = hash_file::<Sha256>(reads).unwrap();
+        assert_eq!(sha.len(), 64);
+        let mut lookup = ChecksumLookup::new();
+        lookup.insert(full_path(reads), sha);
+        assert_eq!(verify(reads, &lookup).unwrap(), None);
+
+        lookup.clear();
+        assert_eq!(verify(reads, &lookup).unwrap(), Some(Mismatch::Missing));
+    }
+}
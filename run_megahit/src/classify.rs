@@ -0,0 +1,132 @@
+use crate::report::Metric;
+use crate::trim::args;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+type MyResult<T> = Result<T, Box<dyn Error>>;
+
+/// The classifiers --classify takes
+pub const TOOLS: &[&str] = &["kraken2"];
+
+/// Where the classify stage writes, relative to a sample's out_dir
+pub const CLASSIFY_DIR: &str = "classify";
+
+/// Kraken2's taxonomy report of the sample's contigs
+pub const REPORT_FILE: &str = "kraken2.report";
+
+/// The taxonomy of the batch's samples side by side, relative to out_dir
+pub const SUMMARY_FILE: &str = "classify_summary.tsv";
+
+/// How many of the most common species the summary names
+const NUM_TOP_SPECIES: usize = 3;
+
+// --------------------------------------------------
+/// Kraken2 on the sample's final contigs, with what it made of each contig
+/// and its report under classify/
+pub fn command(
+    dir: &Path,
+    db: &Path,
+    threads: Option<u32>,
+) -> Vec<Vec<String>> {
+    let out = dir.join(CLASSIFY_DIR);
+    let path = |name: &str| out.join(name).display().to_string();
+    let mut cmd = args(&["kraken2", "--db", &db.display().to_string()]);
+    if let Some(threads) = threads {
+        cmd.extend(args(&["--threads", &threads.to_string()]));
+    }
+    cmd.extend(args(&[
+        "--output",
+        &path("kraken2.out"),
+        "--report",
+        &path(REPORT_FILE),
+        &dir.join("final.contigs.fa").display().to_string(),
+    ]));
+
+    vec![args(&["mkdir", "-p", &out.display().to_string()]), cmd]
+}
+
+// --------------------------------------------------
+/// The share of the sample's contigs Kraken2 classified, and the species
+/// most of them went to
+pub fn read_summary(dir: &Path) -> MyResult<Vec<Metric>> {
+    let path = dir.join(CLASSIFY_DIR).join(REPORT_FILE);
+    let contents = fs::read_to_string(&path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    // Percent, clade count, direct count, rank, taxid, indented name
+    let mut unclassified = 0.;
+    let mut species: Vec<(f64, &str)> = vec![];
+    for line in contents.lines() {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() < 6 {
+            continue;
+        }
+        let pct: f64 = fields[0].trim().parse().unwrap_or(0.);
+        match fields[3] {
+            "U" => unclassified = pct,
+            "S" => species.push((pct, fields[5].trim())),
+            _ => (),
+        }
+    }
+    species.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let metric = |name: &str, value: String| Metric {
+        stage: "classify".to_string(),
+        name: name.to_string(),
+        value,
+    };
+    Ok(vec![
+        metric("pct_classified", format!("{:.2}", 100. - unclassified)),
+        metric(
+            "top_species",
+            species
+                .iter()
+                .take(NUM_TOP_SPECIES)
+                .map(|(pct, name)| format!("{} ({:.2}%)", name, pct))
+                .collect::<Vec<_>>()
+                .join("; "),
+        ),
+    ])
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let cmds = command(Path::new("out/S1"), Path::new("db/k2"), Some(8));
+        assert_eq!(
+            cmds[1].join(" "),
+            "kraken2 --db db/k2 --threads 8 --output out/S1/classify/kraken2.out \
+             --report out/S1/classify/kraken2.report out/S1/final.contigs.fa"
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        assert!(read_summary(dir).is_err());
+        fs::create_dir_all(dir.join(CLASSIFY_DIR)).unwrap();
+        fs::write(
+            dir.join(CLASSIFY_DIR).join(REPORT_FILE),
+            " 25.00\t5\t5\tU\t0\tunclassified\n\
+             \x2075.00\t15\t0\tR\t1\troot\n\
+             \x2010.00\t2\t2\tS\t562\t                Escherichia coli\n\
+             \x2050.00\t10\t10\tS\t1280\t                Staphylococcus aureus\n",
+        )
+        .unwrap();
+        let values: Vec<String> = read_summary(dir)
+            .unwrap()
+            .into_iter()
+            .map(|metric| metric.value)
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                "75.00",
+                "Staphylococcus aureus (50.00%); Escherichia coli (10.00%)"
+            ]
+        );
+    }
+}
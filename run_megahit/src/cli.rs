@@ -0,0 +1,833 @@
+use crate::executor::{self, FailurePolicy};
+use crate::remote::{self, Node};
+use crate::{
+    aws, binning, classify, coverage, export, host, host_removal, limits,
+    logging, pipeline, quast, trim, PRESETS,
+};
+use clap::builder::PossibleValuesParser;
+use clap::{ArgAction, Args, Parser, Subcommand};
+use std::error::Error;
+use std::path::PathBuf;
+use std::time::Duration;
+
+type MyResult<T> = Result<T, Box<dyn Error>>;
+
+/// The command line. The options of a run are also what a --config file
+/// and RUN_MEGAHIT_* variables may set, by the same names.
+#[derive(Debug, Parser)]
+#[command(
+    name = "run_megahit",
+    version = "0.1.0",
+    author = "Ken Youens-Clark <kyclark@email.arizona.edu>",
+    about = "Runs TrimGalore",
+    args_conflicts_with_subcommands = true,
+    after_help = "EXIT STATUS:\n    0    every sample assembled\n    \
+                  1    error before or while running the batch\n    \
+                  2    some samples failed, timed out, or never ran\n    \
+                  130  interrupted"
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub run: RunArgs,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Check a finished out_dir for missing or altered files
+    Verify {
+        /// Output directory to verify
+        #[arg(short, long = "out_dir", alias = "out-dir", value_name = "DIR")]
+        out_dir: PathBuf,
+    },
+    /// Write the batch as a workflow instead of running it
+    Export {
+        /// Workflow language
+        #[arg(short, long, value_parser = PossibleValuesParser::new(export::FORMATS))]
+        format: String,
+        /// The options of the batch, as for a run
+        #[arg(
+            value_name = "RUN_OPTIONS",
+            trailing_var_arg = true,
+            allow_hyphen_values = true
+        )]
+        run_args: Vec<String>,
+    },
+    /// Run the batch (the same as giving no subcommand)
+    Run {
+        /// The options of the batch
+        #[arg(
+            value_name = "RUN_OPTIONS",
+            trailing_var_arg = true,
+            allow_hyphen_values = true
+        )]
+        run_args: Vec<String>,
+    },
+    /// Check that the batch's programs, output directory, and machine are
+    /// ready before running it
+    Doctor {
+        /// The options of the batch, as for a run (--query not needed)
+        #[arg(
+            value_name = "RUN_OPTIONS",
+            trailing_var_arg = true,
+            allow_hyphen_values = true
+        )]
+        run_args: Vec<String>,
+    },
+    /// Print the contig statistics of a batch's assemblies
+    Stats {
+        /// Output directory of the batch
+        out_dir: PathBuf,
+    },
+    /// Write the depth of each contig in sorted BAM files as
+    /// jgi_summarize_bam_contig_depths does, for binning
+    Depth {
+        /// Depth table [default: STDOUT]
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+        /// BAM files sorted by position, mapped to the same contigs
+        #[arg(value_name = "BAM", required = true)]
+        bams: Vec<PathBuf>,
+    },
+    /// Remove the intermediate files of finished assemblies
+    Clean {
+        /// Output directory of the batch
+        out_dir: PathBuf,
+    },
+    /// Run an interrupted batch again, skipping finished samples
+    Resume {
+        /// Output directory of the batch
+        out_dir: PathBuf,
+    },
+    /// Write the reports of a batch again from its state
+    Report {
+        /// Output directory of the batch
+        out_dir: PathBuf,
+        /// Report format
+        #[arg(
+            short,
+            long,
+            value_name = "FORMAT",
+            value_parser = ["console", "json", "html"],
+            default_value = "console"
+        )]
+        format: Vec<String>,
+    },
+    /// Run a batch's failed samples again as before
+    RerunFailed {
+        /// Output directory of the batch
+        out_dir: PathBuf,
+    },
+}
+
+// A value that is a list in one argument (e.g., "--nodes a,b:8") is typed
+// with the full path of Vec so that clap parses it as one value.
+#[derive(Debug, Args)]
+#[command(rename_all = "snake_case")]
+pub struct RunArgs {
+    /// File input or directory ("-" for paths on STDIN)
+    #[arg(short = 'Q', long, value_name = "FILE_OR_DIR", num_args = 1..)]
+    pub query: Vec<String>,
+
+    /// Output directory
+    #[arg(short, long, value_name = "DIR")]
+    pub out_dir: Option<PathBuf>,
+
+    /// Number of concurrent jobs [default: fits the machine]
+    #[arg(
+        short = 'J',
+        long,
+        value_name = "INT",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    pub num_concurrent_jobs: Option<u32>,
+
+    /// Threads per megahit job [default: cores / jobs]
+    #[arg(
+        short = 't',
+        long,
+        alias = "num-cpu-threads",
+        value_name = "INT",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    pub num_cpu_threads: Option<u32>,
+
+    /// continue, fail-fast, threshold=N, or percent=X of jobs
+    #[arg(
+        long,
+        alias = "on-failure",
+        value_name = "POLICY",
+        default_value = "continue",
+        value_parser = failure_policy
+    )]
+    pub on_failure: FailurePolicy,
+
+    /// Same as --on_failure threshold=INT
+    #[arg(
+        short = 'H',
+        long,
+        value_name = "INT",
+        hide = true,
+        conflicts_with = "on_failure"
+    )]
+    pub num_halt: Option<u32>,
+
+    /// megahit preset parameters, override the k-mer options
+    #[arg(long, value_name = "NAME", value_parser = PossibleValuesParser::new(PRESETS))]
+    pub preset: Option<String>,
+
+    /// minimum multiplicity for filtering (k_min+1)-mers
+    #[arg(long, alias = "min-count", value_name = "INT")]
+    pub min_count: Option<u32>,
+
+    /// minimum kmer size (<= 255), must be odd number
+    #[arg(long, alias = "k-min", value_name = "INT")]
+    pub k_min: Option<u32>,
+
+    /// maximum kmer size (<= 255), must be odd number
+    #[arg(long, alias = "k-max", value_name = "INT")]
+    pub k_max: Option<u32>,
+
+    /// increment of kmer size of each iteration (<= 28), must be even number
+    #[arg(long, alias = "k-step", value_name = "INT")]
+    pub k_step: Option<u32>,
+
+    /// comma-separated kmer sizes (<= 255), odd and ascending
+    #[arg(
+        long,
+        alias = "k-list",
+        value_name = "INT,INT",
+        value_parser = k_list,
+        conflicts_with_all = ["k_min", "k_max", "k_step"]
+    )]
+    pub k_list: Option<::std::vec::Vec<u32>>,
+
+    /// minimum length of contigs to output
+    #[arg(long, alias = "min-contig-len", value_name = "INT")]
+    pub min_contig_len: Option<u32>,
+
+    /// strength of low depth pruning (0-3)
+    #[arg(
+        long,
+        alias = "prune-level",
+        value_name = "INT",
+        value_parser = clap::value_parser!(u32).range(0..=3)
+    )]
+    pub prune_level: Option<u32>,
+
+    /// remove unitigs with avg kmer depth less than this value
+    #[arg(long, alias = "prune-depth", value_name = "INT")]
+    pub prune_depth: Option<u32>,
+
+    /// intensity of bubble merging (0-2)
+    #[arg(
+        long,
+        alias = "bubble-level",
+        value_name = "INT",
+        value_parser = clap::value_parser!(u32).range(0..=2)
+    )]
+    pub bubble_level: Option<u32>,
+
+    /// merge complex bubbles of length <= l*kmer_size and similarity >= s
+    #[arg(
+        long,
+        alias = "merge-level",
+        value_name = "l,s",
+        value_parser = merge_level
+    )]
+    pub merge_level: Option<String>,
+
+    /// ratio threshold to define low local coverage contigs
+    #[arg(
+        long,
+        alias = "low-local-ratio",
+        value_name = "FLOAT",
+        value_parser = ratio
+    )]
+    pub low_local_ratio: Option<f32>,
+
+    /// number of rounds for graph cleaning
+    #[arg(
+        long,
+        alias = "cleaning-rounds",
+        value_name = "INT",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    pub cleaning_rounds: Option<u32>,
+
+    /// disable local assembly
+    #[arg(long, alias = "no-local")]
+    pub no_local: bool,
+
+    /// disconnect unitigs if its depth is less than this ratio times the
+    /// total depth of itself and its siblings
+    #[arg(
+        long,
+        alias = "disconnect-ratio",
+        value_name = "FLOAT",
+        value_parser = ratio
+    )]
+    pub disconnect_ratio: Option<f32>,
+
+    /// use 1pass mode to build SdBG of k_min, for ultra low depth data
+    #[arg(long, alias = "kmin-1pass")]
+    pub kmin_1pass: bool,
+
+    /// megahit temp directory, must exist where megahit runs
+    #[arg(long, alias = "tmp-dir", value_name = "DIR")]
+    pub tmp_dir: Option<PathBuf>,
+
+    /// keep megahit's intermediate files
+    #[arg(long, alias = "keep-tmp-files")]
+    pub keep_tmp_files: bool,
+
+    /// TOML file of options and [profiles], under the command line
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// megahit options from this profile of the --config
+    #[arg(long, value_name = "NAME", requires = "config")]
+    pub profile: Option<String>,
+
+    /// megahit program [default: megahit in PATH]
+    #[arg(
+        long,
+        alias = "megahit-path",
+        value_name = "PATH",
+        env = "MEGAHIT_PATH"
+    )]
+    pub megahit_path: Option<String>,
+
+    /// More megahit options, quoted as one string
+    #[arg(
+        long,
+        alias = "megahit-args",
+        value_name = "ARGS",
+        allow_hyphen_values = true,
+        value_parser = words
+    )]
+    pub megahit_args: Option<::std::vec::Vec<String>>,
+
+    /// More megahit options, after "--"
+    #[arg(value_name = "MEGAHIT_OPTIONS", last = true)]
+    pub megahit_extra: Vec<String>,
+
+    /// Memory as a fraction (0.5, 80%) or size (64G) [default: 90% of RAM]
+    #[arg(
+        short,
+        long,
+        value_name = "FLOAT",
+        value_parser = |val: &str| host::parse_memory("memory", val)
+    )]
+    pub memory: Option<f64>,
+
+    /// Memory for each job, overriding --memory
+    #[arg(
+        long,
+        alias = "memory-per-job",
+        value_name = "FLOAT",
+        value_parser = |val: &str| host::parse_memory("memory_per_job", val)
+    )]
+    pub memory_per_job: Option<f64>,
+
+    /// Divide --memory among the concurrent jobs
+    #[arg(long, alias = "split-memory")]
+    pub split_memory: bool,
+
+    /// Print the settled options [default: toml] and exit
+    #[arg(
+        long,
+        alias = "show-config",
+        value_name = "FORMAT",
+        value_parser = ["toml", "json"],
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "toml"
+    )]
+    pub show_config: Option<String>,
+
+    /// Print each sample's R1, R2, and single-end files [default: tsv] and
+    /// exit, without running anything
+    #[arg(
+        long,
+        alias = "list-samples",
+        value_name = "FORMAT",
+        value_parser = ["tsv", "json"],
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "tsv"
+    )]
+    pub list_samples: Option<String>,
+
+    /// Print the commands, write them (and any scheduler's scripts) to
+    /// out_dir/plan, and exit
+    #[arg(short = 'n', long, alias = "dry-run")]
+    pub dry_run: bool,
+
+    /// Print the jobs' commands, output directories, and resources as JSON
+    /// and exit
+    #[arg(long)]
+    pub plan: bool,
+
+    /// Show the samples and resources and ask before running
+    #[arg(long)]
+    pub confirm: bool,
+
+    /// Don't ask, even with --confirm
+    #[arg(short, long)]
+    pub yes: bool,
+
+    /// Assemble with every combination of these values (k_min, k_max,
+    /// k_step, min_count, min_contig_len, preset)
+    #[arg(long, value_name = "KEY=V1,V2", num_args = 1..)]
+    pub sweep: Vec<String>,
+
+    /// Write the batch as a workflow to out_dir/FORMAT and exit
+    #[arg(long, value_name = "FORMAT", value_parser = PossibleValuesParser::new(export::FORMATS))]
+    pub export: Option<String>,
+
+    /// MD5/SHA256 manifest to verify inputs against, listing them relative
+    /// to its own directory
+    #[arg(long, value_name = "FILE")]
+    pub checksums: Option<PathBuf>,
+
+    /// Fail or skip samples with bad checksums
+    #[arg(
+        long,
+        value_name = "ACTION",
+        value_parser = ["fail", "skip"],
+        default_value = "fail"
+    )]
+    pub checksum_mismatch: String,
+
+    /// Batch summary file(s) to write besides the console table
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        value_parser = ["console", "json", "html"],
+        value_delimiter = ',',
+        default_value = "console"
+    )]
+    pub report: Vec<String>,
+
+    /// Email a summary here when the batch ends
+    #[arg(long, alias = "notify-email", value_name = "ADDRESS")]
+    pub notify_email: Option<String>,
+
+    /// POST JSON status events here (Slack, Teams, ...)
+    #[arg(long, value_name = "URL")]
+    pub webhook: Vec<String>,
+
+    /// Send email via this SMTP server instead of sendmail
+    #[arg(long, alias = "smtp-url", value_name = "URL")]
+    pub smtp_url: Option<String>,
+
+    /// Sender of the summary email
+    #[arg(long, alias = "email-from", value_name = "ADDRESS")]
+    pub email_from: Option<String>,
+
+    /// Run even if out_dir looks too small for the output
+    #[arg(long)]
+    pub force: bool,
+
+    /// Named end-to-end workflow
+    #[arg(
+        long,
+        value_name = "NAME",
+        value_parser = PossibleValuesParser::new(pipeline::PIPELINE_NAMES),
+        conflicts_with = "stages"
+    )]
+    pub pipeline: Option<String>,
+
+    /// Stages to run for each sample [default: assemble]
+    #[arg(
+        long,
+        value_name = "STAGE",
+        value_parser = PossibleValuesParser::new(pipeline::STAGE_NAMES),
+        value_delimiter = ','
+    )]
+    pub stages: Vec<String>,
+
+    /// Trim each sample's reads with this tool before assembly (adds the
+    /// trim stage)
+    #[arg(
+        long,
+        value_name = "TOOL",
+        value_parser = PossibleValuesParser::new(trim::TOOLS)
+    )]
+    pub trim: Option<String>,
+
+    /// Drop the reads that map to this host genome before assembly, a
+    /// bowtie2 index prefix or a minimap2 index or FASTA (adds the
+    /// host-removal stage)
+    #[arg(long, alias = "host-index", value_name = "PATH")]
+    pub host_index: Option<PathBuf>,
+
+    /// Mapper for --host_index [default: minimap2 for a file, otherwise
+    /// bowtie2]
+    #[arg(
+        long,
+        alias = "host-mapper",
+        value_name = "MAPPER",
+        value_parser = PossibleValuesParser::new(host_removal::MAPPERS)
+    )]
+    pub host_mapper: Option<String>,
+
+    /// Map each sample's reads back to its contigs with this mapper
+    /// [default: bwa-mem2] for the depth of each contig and the percent of
+    /// reads assembled (adds the coverage stage)
+    #[arg(
+        long,
+        value_name = "MAPPER",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "bwa-mem2",
+        value_parser = PossibleValuesParser::new(coverage::MAPPERS)
+    )]
+    pub coverage: Option<String>,
+
+    /// Bin each assembly's contigs into genomes with this tool, by the
+    /// depth of the coverage stage (adds the coverage and bin stages)
+    #[arg(
+        long,
+        value_name = "TOOL",
+        value_parser = PossibleValuesParser::new(binning::TOOLS)
+    )]
+    pub bin: Option<String>,
+
+    /// Predict the genes of each assembly with Prodigal in metagenome
+    /// mode, the proteins and GFF next to the contigs (adds the genes
+    /// stage)
+    #[arg(long)]
+    pub genes: bool,
+
+    /// Classify the contigs of each assembly with this tool and --db
+    /// (adds the classify stage)
+    #[arg(
+        long,
+        value_name = "TOOL",
+        requires = "db",
+        value_parser = PossibleValuesParser::new(classify::TOOLS)
+    )]
+    pub classify: Option<String>,
+
+    /// Database for --classify, e.g., a Kraken2 database directory
+    #[arg(long, value_name = "PATH")]
+    pub db: Option<PathBuf>,
+
+    /// Write the assembly graph of each sample's final k as a FASTG file
+    /// for Bandage (adds the graph stage)
+    #[arg(long)]
+    pub fastg: bool,
+
+    /// Evaluate each assembly with QUAST, or metaQUAST with
+    /// --quast=metaquast (adds the qc stage)
+    #[arg(
+        long,
+        value_name = "TOOL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "quast",
+        value_parser = PossibleValuesParser::new(quast::TOOLS)
+    )]
+    pub quast: Option<String>,
+
+    /// Only assemble these samples
+    #[arg(long, value_name = "SAMPLE", value_delimiter = ',')]
+    pub samples: Vec<String>,
+
+    /// Only assemble the first INT samples
+    #[arg(long, value_name = "INT")]
+    pub limit: Option<usize>,
+
+    /// Skip one stage for one sample (repeatable)
+    #[arg(long, value_name = "SAMPLE:STAGE")]
+    pub skip_stage: Vec<String>,
+
+    /// Sample sheet (sample, r1, r2, single, group columns)
+    #[arg(long, value_name = "FILE", conflicts_with = "query")]
+    pub manifest: Option<PathBuf>,
+
+    /// Assemble S_unpaired-style orphan reads with pair S
+    #[arg(long)]
+    pub attach_orphans: bool,
+
+    /// Assemble S_L001, S_L002, ... together as libraries of S
+    #[arg(long, alias = "merge-lanes")]
+    pub merge_lanes: bool,
+
+    /// Run jobs with built-in threads, GNU parallel, or SSH
+    #[arg(
+        long,
+        value_name = "NAME",
+        value_parser = ["native", "parallel", "ssh"],
+        default_value = "native"
+    )]
+    pub executor: String,
+
+    /// Run here or write a batch script per sample
+    #[arg(
+        long,
+        value_name = "NAME",
+        value_parser = ["local", "slurm", "pbs", "sge", "aws-batch"],
+        default_value = "local"
+    )]
+    pub scheduler: String,
+
+    /// Submit the scheduler scripts and print the job IDs
+    #[arg(long)]
+    pub submit: bool,
+
+    /// Write one SLURM job array instead of a job per sample
+    #[arg(long, alias = "slurm-array")]
+    pub slurm_array: bool,
+
+    /// Nodes for --export launcher to spread the jobs over
+    #[arg(
+        long,
+        value_name = "INT",
+        default_value = "1",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    pub launcher_nodes: u32,
+
+    /// Grid Engine parallel environment for the job's CPUs
+    #[arg(long, value_name = "NAME", default_value = "smp")]
+    pub sge_pe: String,
+
+    /// S3 location for AWS Batch inputs and outputs
+    #[arg(long, value_name = "URI")]
+    pub s3_prefix: Option<String>,
+
+    /// AWS Batch job queue
+    #[arg(long, value_name = "NAME")]
+    pub aws_queue: Option<String>,
+
+    /// AWS Batch container with megahit and the AWS CLI
+    #[arg(long, value_name = "IMAGE", default_value = aws::DEFAULT_IMAGE)]
+    pub aws_image: String,
+
+    /// Run jobs over SSH on host[:slots],... (e.g., a,b:8)
+    #[arg(long, value_name = "HOSTS", value_parser = nodes)]
+    pub nodes: Option<::std::vec::Vec<Node>>,
+
+    /// Start jobs largest input first, by name, or as listed
+    #[arg(
+        long,
+        value_name = "ORDER",
+        value_parser = ["size", "name", "manifest-order"],
+        default_value = "size"
+    )]
+    pub schedule: String,
+
+    /// Run as many jobs (up to -J) as their inputs' RAM allows
+    #[arg(long, alias = "adaptive-concurrency")]
+    pub adaptive_concurrency: bool,
+
+    /// Kill a job that outgrows its share of memory
+    #[arg(long, alias = "cap-memory")]
+    pub cap_memory: bool,
+
+    /// Run jobs at this niceness (-20 to 19)
+    #[arg(
+        long,
+        value_name = "INT",
+        allow_hyphen_values = true,
+        value_parser = clap::value_parser!(i32).range(-20..=19)
+    )]
+    pub nice: Option<i32>,
+
+    /// Run jobs at this I/O class: idle or best-effort[:0-7]
+    #[arg(long, value_name = "CLASS", value_parser = ionice)]
+    pub ionice: Option<::std::vec::Vec<String>>,
+
+    /// Run megahit in this Docker image (e.g., vout/megahit)
+    #[arg(long, value_name = "IMAGE", conflicts_with = "singularity")]
+    pub docker: Option<String>,
+
+    /// Run megahit in this Apptainer/Singularity image
+    #[arg(long, value_name = "IMAGE")]
+    pub singularity: Option<String>,
+
+    /// Program that runs the --singularity image
+    #[arg(
+        long,
+        alias = "container-engine",
+        value_name = "NAME",
+        value_parser = ["apptainer", "singularity"],
+        default_value = "apptainer"
+    )]
+    pub container_engine: String,
+
+    /// Retry a failed job up to INT times
+    #[arg(long, value_name = "INT", default_value = "0")]
+    pub retries: u32,
+
+    /// Wait before the first retry, doubling each time
+    #[arg(long, value_name = "SECS", default_value = "60")]
+    pub retry_backoff: u64,
+
+    /// Kill jobs that run longer than this (e.g., 12h)
+    #[arg(long, value_name = "DURATION", value_parser = duration)]
+    pub job_timeout: Option<Duration>,
+
+    /// Skip samples already completed and continue partial runs
+    #[arg(long)]
+    pub resume: bool,
+
+    /// Say more about what is happening, -vv for even more
+    #[arg(short, long, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Say only warnings and errors
+    #[arg(short, long, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Log as text or as one JSON event per line
+    #[arg(
+        long,
+        alias = "log-format",
+        value_name = "FORMAT",
+        value_parser = PossibleValuesParser::new(logging::FORMATS),
+        default_value = "text"
+    )]
+    pub log_format: String,
+
+    /// Don't draw progress bars
+    #[arg(long, alias = "no-progress")]
+    pub no_progress: bool,
+
+    /// Echo megahit's output prefixed with the sample name
+    #[arg(long, alias = "stream-logs")]
+    pub stream_logs: bool,
+
+    /// Echo at most one line per job every SECS
+    #[arg(long, value_name = "SECS", default_value = "0")]
+    pub log_interval: u64,
+
+    /// Only consider files with these extensions as reads
+    #[arg(long, value_name = "EXT", value_delimiter = ',')]
+    pub extensions: Vec<String>,
+
+    /// Check that R1/R2 files start with the same read IDs
+    #[arg(long)]
+    pub verify_pairs: bool,
+
+    /// Assemble all reads together in one megahit run
+    #[arg(long, alias = "co-assembly")]
+    pub co_assembly: bool,
+}
+
+// --------------------------------------------------
+// clap shows a parser's error after the flag and value, and needs an error
+// it can send between threads
+fn adapt<T>(res: MyResult<T>) -> Result<T, String> {
+    res.map_err(|e| e.to_string())
+}
+
+// --------------------------------------------------
+fn failure_policy(val: &str) -> Result<FailurePolicy, String> {
+    adapt(FailurePolicy::parse(val))
+}
+
+// --------------------------------------------------
+fn k_list(val: &str) -> Result<Vec<u32>, String> {
+    adapt(crate::parse_k_list(val))
+}
+
+// --------------------------------------------------
+fn merge_level(val: &str) -> Result<String, String> {
+    adapt(crate::parse_merge_level(val))
+}
+
+// --------------------------------------------------
+fn words(val: &str) -> Result<Vec<String>, String> {
+    adapt(crate::split_words(val))
+}
+
+// --------------------------------------------------
+fn nodes(val: &str) -> Result<Vec<Node>, String> {
+    adapt(remote::parse_nodes(val))
+}
+
+// --------------------------------------------------
+fn ionice(val: &str) -> Result<Vec<String>, String> {
+    adapt(limits::parse_ionice(val))
+}
+
+// --------------------------------------------------
+fn duration(val: &str) -> Result<Duration, String> {
+    adapt(executor::parse_duration(val))
+}
+
+// --------------------------------------------------
+/// A ratio from 0 to 1
+fn ratio(val: &str) -> Result<f32, String> {
+    match val.trim().parse::<f32>() {
+        Ok(n) if (0. ..=1.).contains(&n) => Ok(n),
+        _ => Err("must be a number from 0 to 1".to_string()),
+    }
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cli() {
+        use clap::CommandFactory;
+        Cli::command().debug_assert();
+
+        let cli = Cli::try_parse_from([
+            "run_megahit",
+            "-Q",
+            "a",
+            "b",
+            "--k-min",
+            "27",
+            "--nodes",
+            "h1,h2:8",
+            "--stages",
+            "assemble,stats",
+            "-vv",
+            "--",
+            "--no-mercy",
+        ])
+        .unwrap();
+        assert!(cli.command.is_none());
+        assert_eq!(cli.run.query, vec!["a", "b"]);
+        assert_eq!(cli.run.k_min, Some(27));
+        assert_eq!(cli.run.nodes.map(|nodes| nodes.len()), Some(2));
+        assert_eq!(cli.run.stages, vec!["assemble", "stats"]);
+        assert_eq!(cli.run.verbose, 2);
+        assert_eq!(cli.run.megahit_extra, vec!["--no-mercy"]);
+        assert_eq!(cli.run.on_failure, FailurePolicy::Continue);
+        assert_eq!(cli.run.report, vec!["console"]);
+
+        for bad in [
+            vec!["--k_min", "oops"],
+            vec!["-J", "0"],
+            vec!["--prune_level", "4"],
+            vec!["--nice", "20"],
+            vec!["--low_local_ratio", "1.5"],
+            vec!["--memory", "lots"],
+            vec!["--k_list", "21,40"],
+            vec!["--k_list", "21", "--k_min", "21"],
+            vec!["--executor", "slurm"],
+        ] {
+            let args = std::iter::once("run_megahit").chain(bad.clone());
+            assert!(Cli::try_parse_from(args).is_err(), "{:?}", bad);
+        }
+
+        let cli =
+            Cli::try_parse_from(["run_megahit", "report", "out", "-f", "json"])
+                .unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Report { format, .. }) if format == vec!["json"]
+        ));
+    }
+}
@@ -0,0 +1,299 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::{fs, path::Path};
+
+type MyResult<T> = Result<T, Box<dyn Error>>;
+
+/// megahit options run_megahit sets itself, which a profile cannot
+const RESERVED: &[&str] = &[
+    "o",
+    "out-dir",
+    "1",
+    "2",
+    "r",
+    "12",
+    "read",
+    "continue",
+    "t",
+    "num-cpu-threads",
+];
+
+/// A TOML file of settings for run_megahit: any of its options, named as
+/// on the command line, and profiles of megahit options, e.g.,
+///
+/// ```toml
+/// query = ["reads/"]
+/// out_dir = "assemblies"
+/// k_min = 27
+/// executor = "native"
+///
+/// [profiles.soil]
+/// presets = "meta-large"
+/// min-count = 2
+///
+/// [profiles.isolate]
+/// k-list = [21, 41, 61, 81, 99]
+/// no-local = true
+///
+/// [smtp]
+/// url = "smtps://smtp.example.org:465"
+/// user = "me"
+/// password = "secret"
+/// from = "me@example.org"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+pub struct ConfigFile {
+    /// megahit options by profile name, keyed by the flag without "--"
+    #[serde(default)]
+    pub profiles: BTreeMap<String, BTreeMap<String, toml::Value>>,
+    /// The server --notify_email sends through, kept here rather than on
+    /// the command line for its login
+    #[serde(default)]
+    pub smtp: Smtp,
+    /// run_megahit options by name
+    #[serde(flatten)]
+    pub options: BTreeMap<String, toml::Value>,
+}
+
+/// The [smtp] table
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Smtp {
+    pub url: Option<String>,
+    pub user: Option<String>,
+    pub password: Option<String>,
+    /// Sender of the summary email
+    pub from: Option<String>,
+}
+
+impl Smtp {
+    /// "user:password" for curl
+    pub fn login(&self) -> Option<String> {
+        self.user.as_ref().map(|user| {
+            format!("{}:{}", user, self.password.as_deref().unwrap_or(""))
+        })
+    }
+}
+
+/// One megahit option of a profile, e.g., ("--min-count", Some("2")) or
+/// ("--no-local", None)
+pub type ProfileOption = (String, Option<String>);
+
+// --------------------------------------------------
+pub fn read(path: &Path) -> MyResult<ConfigFile> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    toml::from_str(&contents)
+        .map_err(|e| From::from(format!("{}: {}", path.display(), e)))
+}
+
+impl ConfigFile {
+    /// The options as command-line arguments: each name (with "_" for "-")
+    /// and its values, none for a switch that is on
+    pub fn args(&self) -> MyResult<Vec<(String, Vec<String>)>> {
+        let mut args = vec![];
+        for (key, val) in &self.options {
+            let name = key.replace('-', "_");
+            if name == "config" {
+                return Err(From::from("A config cannot name another config"));
+            }
+
+            let bad = || -> Box<dyn Error> {
+                From::from(format!("config {}: unsupported value", key))
+            };
+            let values = match val {
+                toml::Value::Boolean(false) => continue,
+                toml::Value::Boolean(true) => vec![],
+                toml::Value::Array(vals) => vals
+                    .iter()
+                    .map(scalar)
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(bad)?,
+                val => vec![scalar(val).ok_or_else(bad)?],
+            };
+            args.push((name, values));
+        }
+
+        Ok(args)
+    }
+
+    /// The megahit options of the named profile
+    pub fn profile(&self, name: &str) -> MyResult<Vec<ProfileOption>> {
+        let table = self.profiles.get(name).ok_or_else(|| {
+            format!(
+                "No profile \"{}\" in the config, choose from {}",
+                name,
+                self.profiles
+                    .keys()
+                    .map(|key| key.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })?;
+
+        let mut options: Vec<ProfileOption> = vec![];
+        for (key, val) in table {
+            let bad = |msg: &str| -> Box<dyn Error> {
+                From::from(format!("profile \"{}\" {}: {}", name, key, msg))
+            };
+            let key = key.trim_start_matches('-');
+            if key.is_empty()
+                || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            {
+                return Err(bad("not a megahit option"));
+            }
+            if RESERVED.contains(&key) {
+                return Err(bad("run_megahit sets this itself"));
+            }
+
+            let flag = format!("--{}", key);
+            let val = match val {
+                toml::Value::Boolean(false) => continue,
+                toml::Value::Boolean(true) => None,
+                toml::Value::Array(vals) => Some(
+                    vals.iter()
+                        .map(scalar)
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or_else(|| {
+                            bad("lists may hold only numbers and strings")
+                        })?
+                        .join(","),
+                ),
+                val => {
+                    Some(scalar(val).ok_or_else(|| bad("unsupported value"))?)
+                }
+            };
+            options.push((flag, val));
+        }
+
+        Ok(options)
+    }
+}
+
+// --------------------------------------------------
+fn scalar(val: &toml::Value) -> Option<String> {
+    match val {
+        toml::Value::String(val) => Some(val.to_string()),
+        toml::Value::Integer(val) => Some(val.to_string()),
+        toml::Value::Float(val) => Some(val.to_string()),
+        _ => None,
+    }
+}
+
+// --------------------------------------------------
+/// The batch's megahit options after those of a profile the batch doesn't
+/// set itself. Setting any k-mer option replaces all of the profile's.
+pub fn with_profile(
+    profile: &[ProfileOption],
+    options: &[String],
+) -> Vec<String> {
+    let set = |flag: &str| {
+        options.iter().any(|opt| {
+            opt == flag || (flag.starts_with("--k-") && opt.starts_with("--k-"))
+        })
+    };
+
+    let mut args: Vec<String> = vec![];
+    for (flag, val) in profile.iter().filter(|(flag, _)| !set(flag)) {
+        args.push(flag.to_string());
+        args.extend(val.iter().cloned());
+    }
+    args.extend(options.iter().cloned());
+    args
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile() {
+        let config: ConfigFile = toml::from_str(
+            r#"
+            [profiles.soil]
+            presets = "meta-large"
+            min-count = 2
+
+            [profiles.isolate]
+            k-list = [21, 41, 61]
+            no-local = true
+            no-mercy = false
+
+            [profiles.bad]
+            out-dir = "x"
+            "#,
+        )
+        .unwrap();
+
+        let soil = config.profile("soil").unwrap();
+        assert_eq!(
+            soil,
+            vec![
+                ("--min-count".to_string(), Some("2".to_string())),
+                ("--presets".to_string(), Some("meta-large".to_string())),
+            ]
+        );
+
+        let isolate = config.profile("isolate").unwrap();
+        let options: Vec<String> = ["--k-min", "27", "--memory", "0.5"]
+            .iter()
+            .map(|opt| opt.to_string())
+            .collect();
+        assert_eq!(
+            with_profile(&isolate, &options),
+            vec!["--no-local", "--k-min", "27", "--memory", "0.5"]
+        );
+        assert_eq!(
+            with_profile(&isolate, &[]),
+            vec!["--k-list", "21,41,61", "--no-local"]
+        );
+
+        assert!(config.profile("gut").is_err());
+        assert!(config.profile("bad").is_err());
+    }
+
+    #[test]
+    fn test_args() {
+        let config: ConfigFile = toml::from_str(
+            r#"
+            query = ["a", "b"]
+            out-dir = "out"
+            k_min = 27
+            dry_run = true
+            resume = false
+
+            [profiles.soil]
+            presets = "meta-large"
+            "#,
+        )
+        .unwrap();
+
+        let args = config.args().unwrap();
+        assert_eq!(
+            args,
+            vec![
+                ("dry_run".to_string(), vec![]),
+                ("k_min".to_string(), vec!["27".to_string()]),
+                ("out_dir".to_string(), vec!["out".to_string()]),
+                ("query".to_string(), vec!["a".to_string(), "b".to_string()]),
+            ]
+        );
+
+        let config: ConfigFile = toml::from_str(
+            "[smtp]\nurl = \"smtps://mail.org\"\nuser = \"me\"\n\
+             password = \"pw\"\n",
+        )
+        .unwrap();
+        assert!(config.args().unwrap().is_empty());
+        assert_eq!(config.smtp.url.as_deref(), Some("smtps://mail.org"));
+        assert_eq!(config.smtp.login().as_deref(), Some("me:pw"));
+        assert!(toml::from_str::<ConfigFile>("[smtp]\nport = 25\n").is_err());
+
+        let config: ConfigFile = toml::from_str("[profile.x]\n").unwrap();
+        assert!(config.args().is_err());
+        let config: ConfigFile = toml::from_str("config = \"x\"\n").unwrap();
+        assert!(config.args().is_err());
+    }
+}
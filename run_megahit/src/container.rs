@@ -0,0 +1,231 @@
+use crate::scheduler::file_name;
+use crate::Job;
+use log::warn;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Numbers the containers this process starts so their names are unique
+static CONTAINERS: AtomicUsize = AtomicUsize::new(0);
+
+// --------------------------------------------------
+/// The job run by `docker run` in `image`, as the current user so the
+/// output isn't owned by root. Every directory the job touches (its
+/// inputs, the parent of its out_dir, and the temp dir) is mounted at the
+/// same path inside the container so the arguments need no rewriting.
+/// The container is named for `kill` and has an init to reap megahit's
+/// processes.
+pub fn docker(job: &Job, image: &str) -> Job {
+    let name = format!(
+        "run_megahit-{}-{}-{}",
+        process::id(),
+        CONTAINERS.fetch_add(1, Ordering::SeqCst),
+        file_name(&job.sample)
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || "_.-".contains(c) {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>()
+    );
+    let mut args: Vec<String> = vec![
+        "run".to_string(),
+        "--name".to_string(),
+        name,
+        "--init".to_string(),
+        "--rm".to_string(),
+    ];
+
+    #[cfg(unix)]
+    {
+        // SAFETY: getuid and getgid cannot fail
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        args.push("--user".to_string());
+        args.push(format!("{}:{}", uid, gid));
+    }
+
+    let tmp_dir = env::temp_dir();
+    for dir in mounts(job, &tmp_dir) {
+        args.push("--volume".to_string());
+        args.push(format!("{0}:{0}", dir.display()));
+    }
+
+    args.push("--env".to_string());
+    args.push(format!("TMPDIR={}", tmp_dir.display()));
+    if let Ok(cwd) = env::current_dir() {
+        args.push("--workdir".to_string());
+        args.push(cwd.display().to_string());
+    }
+
+    args.push(image.to_string());
+    args.push(job.program.to_string());
+    args.extend(job.args.iter().cloned());
+
+    Job {
+        sample: job.sample.to_string(),
+        out_dir: job.out_dir.clone(),
+        program: "docker".to_string(),
+        args,
+    }
+}
+
+// --------------------------------------------------
+/// The name of the container a `docker` job runs in
+pub fn docker_name(job: &Job) -> Option<&str> {
+    if job.program != "docker" {
+        return None;
+    }
+
+    let pos = job.args.iter().position(|arg| arg == "--name")?;
+    job.args.get(pos + 1).map(|name| name.as_str())
+}
+
+// --------------------------------------------------
+/// Stops a container, which killing the docker client leaves running
+pub fn kill(name: &str) {
+    let output = Command::new("docker")
+        .args(["kill", name])
+        .stdin(Stdio::null())
+        .output();
+    match output {
+        Ok(output) if !output.status.success() => warn!(
+            "Cannot kill container {}: {}",
+            name,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+        Err(e) => warn!("Cannot run docker: {}", e),
+        _ => {}
+    }
+}
+
+// --------------------------------------------------
+/// The job run by `apptainer exec` (or `singularity exec`, as `engine`
+/// says) in the image, binding the same directories Docker would mount.
+/// Apptainer already runs as the user and passes TMPDIR through.
+pub fn apptainer(job: &Job, engine: &str, image: &str) -> Job {
+    let binds: Vec<String> = mounts(job, &env::temp_dir())
+        .iter()
+        .map(|dir| dir.display().to_string())
+        .collect();
+
+    let mut args: Vec<String> =
+        vec!["exec".to_string(), "--bind".to_string(), binds.join(",")];
+    if let Ok(cwd) = env::current_dir() {
+        args.push("--pwd".to_string());
+        args.push(cwd.display().to_string());
+    }
+
+    args.push(image.to_string());
+    args.push(job.program.to_string());
+    args.extend(job.args.iter().cloned());
+
+    Job {
+        sample: job.sample.to_string(),
+        out_dir: job.out_dir.clone(),
+        program: engine.to_string(),
+        args,
+    }
+}
+
+// --------------------------------------------------
+/// The absolute directories to mount for a job (including any megahit
+/// --tmp-dir), leaving out any inside another
+fn mounts(job: &Job, tmp_dir: &Path) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = job
+        .read_files()
+        .iter()
+        .filter_map(|file| {
+            absolute(Path::new(file)).parent().map(PathBuf::from)
+        })
+        .collect();
+    if let Some(parent) = absolute(&job.out_dir).parent() {
+        dirs.push(parent.to_path_buf());
+    }
+    dirs.push(absolute(tmp_dir));
+    if let Some(pos) = job.args.iter().position(|arg| arg == "--tmp-dir") {
+        if let Some(dir) = job.args.get(pos + 1) {
+            dirs.push(absolute(Path::new(dir)));
+        }
+    }
+
+    dirs.sort();
+    dirs.dedup();
+    dirs.iter()
+        .filter(|dir| {
+            !dirs
+                .iter()
+                .any(|other| other != *dir && dir.starts_with(other))
+        })
+        .cloned()
+        .collect()
+}
+
+// --------------------------------------------------
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_docker() {
+        let job = Job {
+            sample: "S1".to_string(),
+            out_dir: PathBuf::from("/out/S1"),
+            program: "megahit".to_string(),
+            args: ["-o", "/out/S1", "-1", "/in/a/1.fq,/in/2.fq"]
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect(),
+        };
+
+        assert_eq!(
+            mounts(&job, Path::new("/tmp")),
+            vec![
+                PathBuf::from("/in"),
+                PathBuf::from("/out"),
+                PathBuf::from("/tmp")
+            ]
+        );
+
+        let wrapped = docker(&job, "vout/megahit:latest");
+        assert_eq!(wrapped.program, "docker");
+        assert_eq!(&wrapped.args[3..5], ["--init", "--rm"]);
+        let name = docker_name(&wrapped).unwrap();
+        assert!(name.starts_with("run_megahit-"));
+        assert!(name.ends_with("-S1"));
+        assert_ne!(docker_name(&docker(&job, "megahit")), Some(name));
+        assert_eq!(docker_name(&job), None);
+        assert!(wrapped.args.contains(&"/in:/in".to_string()));
+        assert!(wrapped.command_line().ends_with(
+            "vout/megahit:latest megahit -o /out/S1 -1 /in/a/1.fq,/in/2.fq"
+        ));
+
+        let wrapped = apptainer(&job, "singularity", "megahit.sif");
+        assert_eq!(wrapped.program, "singularity");
+        assert_eq!(wrapped.args[0], "exec");
+        assert!(wrapped.args[2].starts_with("/in,/out"));
+        assert!(wrapped.command_line().ends_with(
+            "megahit.sif megahit -o /out/S1 -1 /in/a/1.fq,/in/2.fq"
+        ));
+
+        let mut job = job;
+        job.args.push("--tmp-dir".to_string());
+        job.args.push("/scratch/tmp".to_string());
+        assert!(mounts(&job, Path::new("/tmp"))
+            .contains(&PathBuf::from("/scratch/tmp")));
+
+        // A sweep's sample names its set after a '/'
+        job.sample = "S1/k21 x".to_string();
+        assert!(docker_name(&docker(&job, "megahit"))
+            .unwrap()
+            .ends_with("-S1__k21_x"));
+    }
+}
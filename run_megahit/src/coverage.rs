@@ -0,0 +1,280 @@
+use crate::report::Metric;
+use crate::trim::args;
+use crate::Sample;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+type MyResult<T> = Result<T, Box<dyn Error>>;
+
+/// The mappers --coverage takes
+pub const MAPPERS: &[&str] = &["bwa-mem2", "minimap2"];
+
+/// Where the coverage stage writes, relative to a sample's out_dir
+pub const COVERAGE_DIR: &str = "coverage";
+
+/// The sample's reads on its contigs, sorted and indexed
+pub const BAM_FILE: &str = "reads.sorted.bam";
+
+/// The depth of each contig, as `samtools coverage` has it
+pub const DEPTH_FILE: &str = "coverage.tsv";
+
+/// The depth table binners take, as jgi_summarize_bam_contig_depths has
+/// it
+pub const DEPTH_TABLE: &str = "depth.txt";
+
+/// The reads mapped to each contig, as `samtools idxstats` has it
+const IDXSTATS_FILE: &str = "idxstats.tsv";
+
+/// The coverage of the batch's samples side by side, relative to out_dir
+pub const SUMMARY_FILE: &str = "coverage_summary.tsv";
+
+// --------------------------------------------------
+/// The programs `mapper` needs
+pub fn programs(mapper: &str) -> Vec<&'static str> {
+    match mapper {
+        "minimap2" => vec!["minimap2", "samtools"],
+        _ => vec!["bwa-mem2", "samtools"],
+    }
+}
+
+// --------------------------------------------------
+/// The commands that map the sample's reads to the contigs in `dir` and
+/// tally the reads and depth of each contig, the depth table by way of
+/// `exe depth`
+pub fn commands(
+    mapper: &str,
+    sample: &Sample,
+    dir: &Path,
+    threads: Option<u32>,
+    exe: &str,
+) -> Vec<Vec<String>> {
+    let cov = dir.join(COVERAGE_DIR);
+    let path = |name: &str| cov.join(name).display().to_string();
+    let contigs = dir.join("final.contigs.fa").display().to_string();
+    let threads = threads.unwrap_or(1).to_string();
+    let bam = path(BAM_FILE);
+
+    let mut commands = vec![];
+    let reference = match mapper {
+        "minimap2" => {
+            commands.push(args(&["mkdir", "-p", &cov.display().to_string()]));
+            contigs
+        }
+        _ => {
+            let index = path("index/contigs");
+            commands.push(args(&["mkdir", "-p", &path("index")]));
+            commands.push(args(&["bwa-mem2", "index", "-p", &index, &contigs]));
+            index
+        }
+    };
+
+    // Each pair and single file maps on its own, then the parts merge
+    let reads = sample
+        .fwd
+        .iter()
+        .zip(&sample.rev)
+        .map(|(r1, r2)| vec![r1.as_str(), r2.as_str()])
+        .chain(sample.single.iter().map(|file| vec![file.as_str()]));
+    let mut parts = vec![];
+    for (i, files) in reads.enumerate() {
+        let sam = path(&format!("part{}.sam", i + 1));
+        let part = path(&format!("part{}.bam", i + 1));
+        let mut map = match mapper {
+            "minimap2" => args(&[
+                "minimap2",
+                "-ax",
+                "sr",
+                "--secondary=no",
+                "-t",
+                &threads,
+                "-o",
+                &sam,
+                &reference,
+            ]),
+            _ => args(&[
+                "bwa-mem2", "mem", "-t", &threads, "-o", &sam, &reference,
+            ]),
+        };
+        map.extend(args(&files));
+        commands.push(map);
+        commands.push(args(&[
+            "samtools", "sort", "-@", &threads, "-o", &part, &sam,
+        ]));
+        commands.push(args(&["rm", &sam]));
+        parts.push(part);
+    }
+
+    let mut merge = args(&["samtools", "merge", "-f", "-@", &threads, &bam]);
+    merge.extend(parts.iter().cloned());
+    commands.push(merge);
+    let mut rm = args(&["rm"]);
+    rm.extend(parts);
+    commands.push(rm);
+    if mapper != "minimap2" {
+        commands.push(args(&["rm", "-r", &path("index")]));
+    }
+
+    commands.push(args(&["samtools", "index", &bam]));
+    commands.push(args(&[
+        "samtools",
+        "idxstats",
+        &bam,
+        ">",
+        &path(IDXSTATS_FILE),
+    ]));
+    commands.push(args(&[
+        "samtools",
+        "coverage",
+        "-o",
+        &path(DEPTH_FILE),
+        &bam,
+    ]));
+    commands.push(args(&[exe, "depth", "-o", &path(DEPTH_TABLE), &bam]));
+    commands
+}
+
+// --------------------------------------------------
+/// How many reads mapped, what percent of the reads that is (i.e., how
+/// much of the sample the assembly accounts for), and the mean depth of
+/// the contigs weighted by their length
+pub fn read_summary(dir: &Path) -> MyResult<Vec<Metric>> {
+    let cov = dir.join(COVERAGE_DIR);
+    let read = |name: &str| {
+        let path = cov.join(name);
+        fs::read_to_string(&path)
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    };
+
+    // Name, length, mapped, unmapped; unplaced reads are "*"
+    let (mut mapped, mut unmapped) = (0u64, 0u64);
+    for line in read(IDXSTATS_FILE)?.lines() {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if let [_, _, m, u] = fields[..] {
+            mapped += m.parse::<u64>().unwrap_or(0);
+            unmapped += u.parse::<u64>().unwrap_or(0);
+        }
+    }
+
+    // #rname, startpos, endpos, numreads, covbases, coverage, meandepth...
+    let (mut bases, mut length) = (0f64, 0f64);
+    for line in read(DEPTH_FILE)?.lines().filter(|l| !l.starts_with('#')) {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() < 7 {
+            continue;
+        }
+        let start: f64 = fields[1].parse().unwrap_or(0.);
+        let end: f64 = fields[2].parse().unwrap_or(0.);
+        let depth: f64 = fields[6].parse().unwrap_or(0.);
+        bases += depth * (end - start + 1.);
+        length += end - start + 1.;
+    }
+
+    let total = mapped + unmapped;
+    let metric = |name: &str, value: String| Metric {
+        stage: "coverage".to_string(),
+        name: name.to_string(),
+        value,
+    };
+    Ok(vec![
+        metric("reads", total.to_string()),
+        metric("mapped_reads", mapped.to_string()),
+        metric(
+            "pct_reads_assembled",
+            if total == 0 {
+                "NA".to_string()
+            } else {
+                format!("{:.2}", 100. * mapped as f64 / total as f64)
+            },
+        ),
+        metric(
+            "mean_depth",
+            if length == 0. {
+                "NA".to_string()
+            } else {
+                format!("{:.2}", bases / length)
+            },
+        ),
+    ])
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coverage() {
+        let sample = Sample {
+            name: "S1".to_string(),
+            fwd: vec!["in/S1_R1.fq".to_string()],
+            rev: vec!["in/S1_R2.fq".to_string()],
+            single: vec!["in/S1.fq".to_string()],
+            ..Sample::default()
+        };
+        let dir = Path::new("out/S1");
+        let cmds = commands("bwa-mem2", &sample, dir, Some(4), "run_megahit");
+        let lines: Vec<String> = cmds.iter().map(|cmd| cmd.join(" ")).collect();
+        assert_eq!(
+            lines[1],
+            "bwa-mem2 index -p out/S1/coverage/index/contigs \
+             out/S1/final.contigs.fa"
+        );
+        assert_eq!(
+            lines[2],
+            "bwa-mem2 mem -t 4 -o out/S1/coverage/part1.sam \
+             out/S1/coverage/index/contigs in/S1_R1.fq in/S1_R2.fq"
+        );
+        assert_eq!(
+            lines[8],
+            "samtools merge -f -@ 4 out/S1/coverage/reads.sorted.bam \
+             out/S1/coverage/part1.bam out/S1/coverage/part2.bam"
+        );
+        assert_eq!(
+            lines.last().unwrap(),
+            "run_megahit depth -o out/S1/coverage/depth.txt \
+             out/S1/coverage/reads.sorted.bam"
+        );
+
+        let cmds = commands("minimap2", &sample, dir, None, "run_megahit");
+        assert!(cmds[1].join(" ").ends_with(
+            "-t 1 -o out/S1/coverage/part1.sam out/S1/final.contigs.fa \
+             in/S1_R1.fq in/S1_R2.fq"
+        ));
+
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let cov = dir.join(COVERAGE_DIR);
+        fs::create_dir_all(&cov).unwrap();
+        assert!(read_summary(dir).is_err());
+        fs::write(
+            cov.join(IDXSTATS_FILE),
+            "k141_1\t100\t30\t2\nk141_2\t300\t58\t0\n*\t0\t0\t10\n",
+        )
+        .unwrap();
+        // samtools depth didn't get to write its table
+        assert!(read_summary(dir).is_err());
+        fs::write(
+            cov.join(DEPTH_FILE),
+            "#rname\tstartpos\tendpos\tnumreads\tcovbases\tcoverage\t\
+             meandepth\tmeanbaseq\tmeanmapq\n\
+             k141_1\t1\t100\t30\t100\t100\t30\t36\t60\n\
+             k141_2\t1\t300\t58\t290\t96.7\t10\t36\t60\n",
+        )
+        .unwrap();
+        let values: Vec<String> = read_summary(dir)
+            .unwrap()
+            .into_iter()
+            .map(|metric| format!("{}={}", metric.name, metric.value))
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                "reads=100",
+                "mapped_reads=88",
+                "pct_reads_assembled=88.00",
+                "mean_depth=15.00"
+            ]
+        );
+    }
+}
@@ -0,0 +1,487 @@
+use crate::reads::{self, Compression};
+use flate2::read::MultiGzDecoder;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::path::Path;
+
+type MyResult<T> = Result<T, Box<dyn Error>>;
+
+/// Bases at each end of a contig left out of its depth, as
+/// jgi_summarize_bam_contig_depths does, for contigs long enough
+const EDGE_BASES: usize = 75;
+
+/// Least identity of an alignment for its read to count
+const MIN_IDENTITY: f64 = 0.97;
+
+/// Unmapped, secondary, QC failed, or duplicate
+const SKIP_FLAGS: u16 = 0x4 | 0x100 | 0x200 | 0x400;
+
+/// The depth of one contig in one BAM file
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContigDepth {
+    pub name: String,
+    pub length: usize,
+    pub mean: f64,
+    pub var: f64,
+}
+
+// --------------------------------------------------
+/// Writes the depth of each contig in the BAM files (sorted by position
+/// against the same contigs) as jgi_summarize_bam_contig_depths does, for
+/// binners such as MetaBAT2
+pub fn write_table(bams: &[&Path], out: &mut dyn Write) -> MyResult<()> {
+    let mut depths = vec![];
+    for bam in bams {
+        let found = contig_depths(bam)
+            .map_err(|e| format!("{}: {}", bam.display(), e))?;
+        if let Some(first) = depths.first() {
+            let same = |a: &Vec<ContigDepth>, b: &Vec<ContigDepth>| {
+                a.len() == b.len()
+                    && a.iter().zip(b).all(|(x, y)| x.name == y.name)
+            };
+            if !same(first, &found) {
+                return Err(From::from(format!(
+                    "\"{}\" is not mapped to the same contigs as \"{}\"",
+                    bam.display(),
+                    bams[0].display()
+                )));
+            }
+        }
+        depths.push(found);
+    }
+
+    let mut header = vec![
+        "contigName".to_string(),
+        "contigLen".to_string(),
+        "totalAvgDepth".to_string(),
+    ];
+    for bam in bams {
+        let name = bam.display().to_string();
+        header.push(name.clone());
+        header.push(format!("{}-var", name));
+    }
+    writeln!(out, "{}", header.join("\t"))?;
+
+    if let Some(first) = depths.first() {
+        for (i, contig) in first.iter().enumerate() {
+            let total: f64 = depths.iter().map(|found| found[i].mean).sum();
+            let mut row = vec![
+                contig.name.clone(),
+                contig.length.to_string(),
+                format!("{:.4}", total),
+            ];
+            for found in &depths {
+                row.push(format!("{:.4}", found[i].mean));
+                row.push(format!("{:.4}", found[i].var));
+            }
+            writeln!(out, "{}", row.join("\t"))?;
+        }
+    }
+
+    Ok(())
+}
+
+// --------------------------------------------------
+/// The mean and variance of the depth along each contig of a BAM file
+/// sorted by position
+pub fn contig_depths(path: &Path) -> MyResult<Vec<ContigDepth>> {
+    let file = File::open(path)?;
+    if reads::sniff(&path.display().to_string())? != Compression::Gzip {
+        return Err(From::from("not a BAM file"));
+    }
+    let mut bam = BufReader::new(MultiGzDecoder::new(file));
+
+    let mut magic = [0u8; 4];
+    bam.read_exact(&mut magic)?;
+    if &magic != b"BAM\x01" {
+        return Err(From::from("not a BAM file"));
+    }
+    let l_text = read_len(&mut bam)?;
+    read_bytes(&mut bam, l_text)?;
+
+    let mut contigs = vec![];
+    for _ in 0..read_len(&mut bam)? {
+        let l_name = read_len(&mut bam)?;
+        let name = read_bytes(&mut bam, l_name)?;
+        let name = String::from_utf8_lossy(&name)
+            .trim_end_matches('\0')
+            .to_string();
+        contigs.push(ContigDepth {
+            name,
+            length: read_len(&mut bam)?,
+            mean: 0.,
+            var: 0.,
+        });
+    }
+
+    // One contig at a time, as the reads come sorted
+    let mut current: Option<usize> = None;
+    let mut coverage: Vec<i64> = vec![];
+    while let Some(record) = read_record(&mut bam)? {
+        if record.flag & SKIP_FLAGS != 0 || record.ref_id < 0 {
+            continue;
+        }
+        let ref_id = record.ref_id as usize;
+        if ref_id >= contigs.len() {
+            return Err(From::from(format!("unknown contig {}", ref_id)));
+        }
+        if current != Some(ref_id) {
+            if let Some(prev) = current {
+                if ref_id < prev {
+                    return Err(From::from("not sorted by position"));
+                }
+                finish(&mut contigs[prev], &coverage);
+            }
+            current = Some(ref_id);
+            coverage = vec![0; contigs[ref_id].length + 1];
+        }
+
+        if record.identity().is_some_and(|id| id < MIN_IDENTITY) {
+            continue;
+        }
+
+        // What each aligned block covers, as +1 at its start and -1 past
+        // its end
+        let mut pos = record.pos.max(0) as usize;
+        for &(len, op) in &record.cigar {
+            let len = len as usize;
+            match op {
+                // M, =, X
+                0 | 7 | 8 => {
+                    let end = (pos + len).min(coverage.len() - 1);
+                    if pos < end {
+                        coverage[pos] += 1;
+                        coverage[end] -= 1;
+                    }
+                    pos += len;
+                }
+                // D, N
+                2 | 3 => pos += len,
+                _ => (),
+            }
+        }
+    }
+    if let Some(prev) = current {
+        finish(&mut contigs[prev], &coverage);
+    }
+
+    Ok(contigs)
+}
+
+// --------------------------------------------------
+/// Sets the contig's depth from the running +1/-1 tally of its coverage
+fn finish(contig: &mut ContigDepth, coverage: &[i64]) {
+    let mut depth = Vec::with_capacity(contig.length);
+    let mut running = 0;
+    for change in &coverage[..contig.length] {
+        running += change;
+        depth.push(running as f64);
+    }
+
+    let depth = if contig.length > 2 * EDGE_BASES {
+        &depth[EDGE_BASES..contig.length - EDGE_BASES]
+    } else {
+        &depth[..]
+    };
+    if depth.is_empty() {
+        return;
+    }
+
+    let n = depth.len() as f64;
+    contig.mean = depth.iter().sum::<f64>() / n;
+    contig.var =
+        depth.iter().map(|d| (d - contig.mean).powi(2)).sum::<f64>() / n;
+}
+
+/// The parts of an alignment the depth needs
+struct Record {
+    ref_id: i32,
+    pos: i32,
+    flag: u16,
+    /// Length and operation
+    cigar: Vec<(u32, u8)>,
+    /// Edit distance to the contig, when the aligner gave it
+    nm: Option<i64>,
+}
+
+impl Record {
+    /// The fraction of the aligned length that matches the contig
+    fn identity(&self) -> Option<f64> {
+        let nm = self.nm?;
+        let aligned: u32 = self
+            .cigar
+            .iter()
+            .filter(|(_, op)| matches!(op, 0 | 1 | 2 | 7 | 8))
+            .map(|(len, _)| len)
+            .sum();
+        if aligned == 0 {
+            return None;
+        }
+        Some(1. - nm as f64 / aligned as f64)
+    }
+}
+
+// --------------------------------------------------
+/// The next alignment, or None at the end of the file
+fn read_record(bam: &mut dyn Read) -> MyResult<Option<Record>> {
+    let mut size = [0u8; 4];
+    let mut got = 0;
+    while got < 4 {
+        match bam.read(&mut size[got..])? {
+            0 if got == 0 => return Ok(None),
+            0 => return Err(corrupt("truncated record")),
+            n => got += n,
+        }
+    }
+    let block_size = usize::try_from(i32::from_le_bytes(size))
+        .map_err(|_| corrupt("negative record length"))?;
+    let block = read_bytes(bam, block_size)?;
+    if block.len() < 32 {
+        return Err(corrupt("truncated record"));
+    }
+
+    let i32_at = |i: usize| {
+        i32::from_le_bytes([block[i], block[i + 1], block[i + 2], block[i + 3]])
+    };
+    let u16_at = |i: usize| u16::from_le_bytes([block[i], block[i + 1]]);
+    let l_read_name = block[8] as usize;
+    let n_cigar_op = u16_at(12) as usize;
+    let l_seq = usize::try_from(i32_at(16))
+        .map_err(|_| corrupt("negative sequence length"))?;
+    if l_seq > block.len() {
+        return Err(corrupt("truncated record"));
+    }
+
+    let cigar_start = 32 + l_read_name;
+    let aux_start = cigar_start + 4 * n_cigar_op + l_seq.div_ceil(2) + l_seq;
+    if aux_start > block.len() {
+        return Err(corrupt("truncated record"));
+    }
+    let cigar = (0..n_cigar_op)
+        .map(|i| {
+            let op = i32_at(cigar_start + 4 * i) as u32;
+            (op >> 4, (op & 0xf) as u8)
+        })
+        .collect();
+
+    Ok(Some(Record {
+        ref_id: i32_at(0),
+        pos: i32_at(4),
+        flag: u16_at(14),
+        cigar,
+        nm: nm_tag(&block[aux_start..]),
+    }))
+}
+
+// --------------------------------------------------
+/// The value of the NM tag among the optional fields of a record
+fn nm_tag(aux: &[u8]) -> Option<i64> {
+    let mut i = 0;
+    while i + 3 <= aux.len() {
+        let (tag, kind) = (&aux[i..i + 2], aux[i + 2]);
+        i += 3;
+        let size = match kind {
+            b'A' | b'c' | b'C' => 1,
+            b's' | b'S' => 2,
+            b'i' | b'I' | b'f' => 4,
+            b'Z' | b'H' => aux[i..].iter().position(|&b| b == 0)? + 1,
+            b'B' => {
+                let width = match *aux.get(i)? {
+                    b'c' | b'C' => 1,
+                    b's' | b'S' => 2,
+                    _ => 4,
+                };
+                let count = aux.get(i + 1..i + 5)?;
+                5 + width
+                    * u32::from_le_bytes([
+                        count[0], count[1], count[2], count[3],
+                    ]) as usize
+            }
+            _ => return None,
+        };
+        let value = aux.get(i..i + size)?;
+        if tag == b"NM" {
+            return match kind {
+                b'c' => Some(value[0] as i8 as i64),
+                b'C' => Some(value[0] as i64),
+                b's' => Some(i16::from_le_bytes([value[0], value[1]]) as i64),
+                b'S' => Some(u16::from_le_bytes([value[0], value[1]]) as i64),
+                b'i' => Some(i32::from_le_bytes([
+                    value[0], value[1], value[2], value[3],
+                ]) as i64),
+                b'I' => Some(u32::from_le_bytes([
+                    value[0], value[1], value[2], value[3],
+                ]) as i64),
+                _ => None,
+            };
+        }
+        i += size;
+    }
+    None
+}
+
+// --------------------------------------------------
+fn read_i32(bam: &mut dyn Read) -> MyResult<i32> {
+    let mut buf = [0u8; 4];
+    bam.read_exact(&mut buf)?;
+    Ok(i32::from_le_bytes(buf))
+}
+
+// --------------------------------------------------
+/// A length or count, which the format stores as an i32
+fn read_len(bam: &mut dyn Read) -> MyResult<usize> {
+    usize::try_from(read_i32(bam)?).map_err(|_| corrupt("negative length"))
+}
+
+// --------------------------------------------------
+/// The next `len` bytes, read as they come rather than all allocated up
+/// front for a length that may be corrupt
+fn read_bytes(bam: &mut dyn Read, len: usize) -> MyResult<Vec<u8>> {
+    let mut buf = vec![];
+    bam.take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() < len {
+        return Err(corrupt("truncated record"));
+    }
+    Ok(buf)
+}
+
+// --------------------------------------------------
+fn corrupt(what: &str) -> Box<dyn Error> {
+    From::from(format!("corrupt BAM, {}", what))
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    /// A mapped read at `pos` with one CIGAR operation and an NM tag
+    fn record(ref_id: i32, pos: i32, flag: u16, len: u32, nm: u8) -> Vec<u8> {
+        let name = b"r\0";
+        let mut block = vec![];
+        block.extend(ref_id.to_le_bytes());
+        block.extend(pos.to_le_bytes());
+        block.extend([name.len() as u8, 60]);
+        block.extend(0u16.to_le_bytes());
+        block.extend(1u16.to_le_bytes());
+        block.extend(flag.to_le_bytes());
+        block.extend(0i32.to_le_bytes());
+        block.extend((-1i32).to_le_bytes());
+        block.extend((-1i32).to_le_bytes());
+        block.extend(0i32.to_le_bytes());
+        block.extend(name);
+        block.extend((len << 4).to_le_bytes());
+        block.extend(b"NMC");
+        block.push(nm);
+
+        let mut rec = (block.len() as i32).to_le_bytes().to_vec();
+        rec.extend(block);
+        rec
+    }
+
+    /// The magic, text, and references of a BAM with two contigs
+    fn header() -> Vec<u8> {
+        let mut bam = b"BAM\x01".to_vec();
+        let text = b"@HD\tVN:1.6\tSO:coordinate\n";
+        bam.extend((text.len() as i32).to_le_bytes());
+        bam.extend(text);
+        bam.extend(2i32.to_le_bytes());
+        for (name, len) in [("k141_1", 10i32), ("k141_2", 200)].iter() {
+            bam.extend((name.len() as i32 + 1).to_le_bytes());
+            bam.extend(name.as_bytes());
+            bam.push(0);
+            bam.extend(len.to_le_bytes());
+        }
+        bam
+    }
+
+    /// Gzipped, as a BAM file is
+    fn write_bam(path: &Path, bam: &[u8]) {
+        let mut gz = GzEncoder::new(vec![], Compression::default());
+        gz.write_all(bam).unwrap();
+        std::fs::write(path, gz.finish().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_depth() {
+        let mut bam = header();
+        // Two reads on k141_1, one too divergent, one unmapped, and k141_2
+        // covered but for its edges
+        bam.extend(record(0, 0, 0, 10, 0));
+        bam.extend(record(0, 5, 0, 5, 0));
+        bam.extend(record(0, 0, 0, 10, 3));
+        bam.extend(record(0, 0, 0x4, 10, 0));
+        bam.extend(record(1, 75, 0, 50, 1));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("reads.sorted.bam");
+        assert!(contig_depths(&path).is_err());
+        write_bam(&path, &bam);
+
+        let depths = contig_depths(&path).unwrap();
+        assert_eq!(depths[0].name, "k141_1");
+        assert_eq!(depths[0].length, 10);
+        assert_eq!(depths[0].mean, 1.5);
+        assert_eq!(depths[0].var, 0.25);
+        assert_eq!(depths[1].mean, 1.);
+        assert_eq!(depths[1].var, 0.);
+
+        let mut out = vec![];
+        write_table(&[&path], &mut out).unwrap();
+        let name = path.display().to_string();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "contigName\tcontigLen\ttotalAvgDepth\t{}\t{}-var\n\
+                 k141_1\t10\t1.5000\t1.5000\t0.2500\n\
+                 k141_2\t200\t1.0000\t1.0000\t0.0000\n",
+                name, name
+            )
+        );
+
+        // SAM, say, in place of BAM
+        std::fs::write(&path, "@HD\tVN:1.6\n").unwrap();
+        assert!(contig_depths(&path).is_err());
+        write_bam(&path, b"@HD\tVN:1.6\n");
+        assert!(contig_depths(&path).is_err());
+    }
+
+    #[test]
+    fn test_corrupt() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("reads.sorted.bam");
+        let err = |bam: &[u8]| {
+            write_bam(&path, bam);
+            contig_depths(&path).unwrap_err().to_string()
+        };
+
+        // A record cut short, or longer than what's left of the file
+        let mut bam = header();
+        let rec = record(0, 0, 0, 10, 0);
+        bam.extend(&rec[..rec.len() - 2]);
+        assert_eq!(err(&bam), "corrupt BAM, truncated record");
+        let mut bam = header();
+        bam.extend(1000i32.to_le_bytes());
+        bam.extend(&rec[4..]);
+        assert_eq!(err(&bam), "corrupt BAM, truncated record");
+
+        // Negative lengths of a record, its sequence, and a contig name
+        let mut bam = header();
+        bam.extend((-1i32).to_le_bytes());
+        assert_eq!(err(&bam), "corrupt BAM, negative record length");
+        let mut bam = header();
+        let mut rec = record(0, 0, 0, 10, 0);
+        rec[20..24].copy_from_slice(&i32::MIN.to_le_bytes());
+        bam.extend(rec);
+        assert_eq!(err(&bam), "corrupt BAM, negative sequence length");
+        let mut bam = header();
+        // Past the magic, l_text, text, and n_ref
+        let l_name = 4 + 4 + 25 + 4;
+        bam[l_name..l_name + 4].copy_from_slice(&(-7i32).to_le_bytes());
+        assert_eq!(err(&bam), "corrupt BAM, negative length");
+    }
+}
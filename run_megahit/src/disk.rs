@@ -0,0 +1,156 @@
+use log::warn;
+use std::error::Error;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+type MyResult<T> = Result<T, Box<dyn Error>>;
+
+/// Rough ratio of megahit's peak disk use (intermediate k-mer files
+/// included) to the size of its input reads
+const OUTPUT_SIZE_FACTOR: u64 = 2;
+
+// --------------------------------------------------
+/// Warns when out_dir lives in $HOME and refuses to start (unless forced)
+/// when the filesystem cannot hold the estimated output
+pub fn check_out_dir(
+    out_dir: &Path,
+    input_bytes: u64,
+    force: bool,
+) -> MyResult<()> {
+    let existing = existing_ancestor(out_dir)?;
+    let estimate = input_bytes.saturating_mul(OUTPUT_SIZE_FACTOR);
+
+    if let Some(home) = env::var_os("HOME").map(PathBuf::from) {
+        if let Ok(home) = fs::canonicalize(home) {
+            if existing.starts_with(&home) {
+                warn!(
+                    "out_dir \"{}\" is in your home directory, \
+                     which is usually small and quota-limited; {}",
+                    out_dir.display(),
+                    suggestion()
+                );
+            }
+        }
+    }
+
+    if let Some(avail) = available_bytes(&existing) {
+        if avail < estimate {
+            let msg = format!(
+                "out_dir \"{}\" has {} free but the assemblies may need \
+                 about {}; {}",
+                out_dir.display(),
+                format_bytes(avail),
+                format_bytes(estimate),
+                suggestion()
+            );
+
+            if !force {
+                return Err(From::from(format!(
+                    "{} (use --force to run anyway)",
+                    msg
+                )));
+            }
+            warn!("{}", msg);
+        }
+    }
+
+    Ok(())
+}
+
+// --------------------------------------------------
+/// Free bytes available to an unprivileged user on the filesystem
+#[cfg(unix)]
+pub fn available_bytes(path: &Path) -> Option<u64> {
+    use std::ffi::CString;
+
+    let c_path = CString::new(path.to_string_lossy().as_bytes()).ok()?;
+    // SAFETY: statvfs is plain old data, for which all zeroes is a valid
+    // value, and it is only read after statvfs has filled it in
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+
+    // SAFETY: c_path is a NUL-terminated string that outlives the call,
+    // and stat is a valid, writable statvfs
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+/// Unknown elsewhere, so that the space check is skipped
+#[cfg(not(unix))]
+pub fn available_bytes(_path: &Path) -> Option<u64> {
+    None
+}
+
+// --------------------------------------------------
+/// The nearest directory on the way to `path` that already exists
+pub fn existing_ancestor(path: &Path) -> MyResult<PathBuf> {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir()?.join(path)
+    };
+
+    for dir in path.ancestors() {
+        if let Ok(real) = fs::canonicalize(dir) {
+            return Ok(real);
+        }
+    }
+
+    Ok(PathBuf::from("/"))
+}
+
+// --------------------------------------------------
+fn suggestion() -> String {
+    let scratch = ["SCRATCH", "WORK", "TMPDIR"]
+        .iter()
+        .find_map(|var| env::var(var).ok().filter(|v| !v.is_empty()));
+
+    match scratch {
+        Some(dir) => format!("consider \"-o {}/megahit-out\"", dir),
+        _ => "consider a scratch or project filesystem".to_string(),
+    }
+}
+
+// --------------------------------------------------
+pub fn format_bytes(bytes: u64) -> String {
+    let units = ["B", "K", "M", "G", "T", "P"];
+    let mut val = bytes as f64;
+    let mut unit = 0;
+    while val >= 1024. && unit < units.len() - 1 {
+        val /= 1024.;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{}B", bytes)
+    } else {
+        format!("{:.1}{}", val, units[unit])
+    }
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512), "512B");
+        assert_eq!(format_bytes(1536), "1.5K");
+        assert_eq!(format_bytes(64 * 1024 * 1024 * 1024), "64.0G");
+    }
+
+    #[test]
+    fn test_check_out_dir() {
+        let dir = env::temp_dir().join("run_megahit_disk").join("out");
+        assert!(available_bytes(&env::temp_dir()).is_some());
+        assert!(check_out_dir(&dir, 1, false).is_ok());
+        assert!(check_out_dir(&dir, u64::MAX / 4, false).is_err());
+        assert!(check_out_dir(&dir, u64::MAX / 4, true).is_ok());
+    }
+}
@@ -0,0 +1,159 @@
+use crate::{disk, host, megahit};
+use std::fs;
+use std::path::Path;
+use std::process::{self, Command};
+
+/// One thing `run_megahit doctor` looked at
+#[derive(Debug, PartialEq)]
+pub struct Check {
+    pub name: String,
+    /// Whether a batch can count on it
+    pub ok: bool,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: &str, result: Result<String, String>) -> Check {
+        let ok = result.is_ok();
+        Check {
+            name: name.to_string(),
+            ok,
+            detail: result.unwrap_or_else(|e| e),
+        }
+    }
+}
+
+// --------------------------------------------------
+/// Checks the programs the batch runs (GNU parallel only for that
+/// executor), that out_dir can be written, and what the machine has
+pub fn run(megahit: &str, executor: &str, out_dir: &Path) -> Vec<Check> {
+    let mut checks = vec![
+        Check::new("run_megahit", Ok(env!("CARGO_PKG_VERSION").to_string())),
+        Check::new(
+            "megahit",
+            megahit::check(megahit)
+                .map(|(path, version)| {
+                    format!("{} ({})", version, path.display())
+                })
+                .map_err(|e| e.to_string()),
+        ),
+    ];
+
+    if executor == "parallel" {
+        checks.push(Check::new("parallel", program_version("parallel")));
+    }
+
+    checks.push(Check::new("out_dir", writable(out_dir)));
+    checks.push(Check::new("host", Ok(describe(&host::Host::probe()))));
+    checks
+}
+
+// --------------------------------------------------
+/// The checks as a table, one per line
+pub fn format(checks: &[Check]) -> String {
+    let width = checks.iter().map(|check| check.name.len()).max();
+    checks
+        .iter()
+        .map(|check| {
+            format!(
+                "{:width$}  {:4}  {}\n",
+                check.name,
+                if check.ok { "ok" } else { "FAIL" },
+                check.detail,
+                width = width.unwrap_or(0)
+            )
+        })
+        .collect()
+}
+
+// --------------------------------------------------
+/// Where `program` is in PATH and the first line of its --version
+fn program_version(program: &str) -> Result<String, String> {
+    let path = megahit::locate(program)
+        .ok_or_else(|| format!("\"{}\" not found in PATH", program))?;
+    let output = Command::new(&path)
+        .arg("--version")
+        .output()
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    let version = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(|line| line.to_string());
+
+    match version {
+        Some(version) if output.status.success() => {
+            Ok(format!("{} ({})", version, path.display()))
+        }
+        _ => Err(format!(
+            "\"{} --version\" failed ({})",
+            path.display(),
+            output.status
+        )),
+    }
+}
+
+// --------------------------------------------------
+/// Whether a file can be made where out_dir is or will be, and the free
+/// space there
+fn writable(out_dir: &Path) -> Result<String, String> {
+    let dir = disk::existing_ancestor(out_dir).map_err(|e| e.to_string())?;
+    let probe = dir.join(format!(".run_megahit_doctor.{}", process::id()));
+    fs::write(&probe, "")
+        .map_err(|e| format!("\"{}\" is not writable: {}", dir.display(), e))?;
+    let _ = fs::remove_file(&probe);
+
+    Ok(format!(
+        "\"{}\" is writable, {} free",
+        dir.display(),
+        disk::available_bytes(&dir)
+            .map_or("unknown".to_string(), disk::format_bytes)
+    ))
+}
+
+// --------------------------------------------------
+fn describe(host: &host::Host) -> String {
+    format!(
+        "{} core{}, {} RAM",
+        host.cores,
+        if host.cores == 1 { "" } else { "s" },
+        host.memory
+            .map_or("unknown".to_string(), disk::format_bytes)
+    )
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_doctor() {
+        let dir = env::temp_dir().join("run_megahit_doctor");
+        let checks = run("no-such-megahit", "parallel", &dir.join("out"));
+        let names: Vec<&str> =
+            checks.iter().map(|check| check.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["run_megahit", "megahit", "parallel", "out_dir", "host"]
+        );
+        assert!(!checks[1].ok);
+        assert!(checks[3].ok);
+        assert!(checks[3].detail.contains("is writable"));
+
+        let checks = run("no-such-megahit", "native", &dir);
+        assert!(!checks.iter().any(|check| check.name == "parallel"));
+
+        let table = format(&[
+            Check::new("megahit", Err("not found".to_string())),
+            Check::new("host", Ok("1 core, 1.0G RAM".to_string())),
+        ]);
+        assert_eq!(
+            table,
+            "megahit  FAIL  not found\n\
+             host     ok    1 core, 1.0G RAM\n"
+        );
+    }
+}
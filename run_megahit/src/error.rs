@@ -0,0 +1,192 @@
+use crate::rerun;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Why run_megahit stopped, for programs that use it as a library to
+/// match on and for the command line to suggest a fix
+#[derive(Debug, Error)]
+pub enum RunMegahitError {
+    /// The query or manifest found no reads to assemble
+    #[error("No input files from query {query:?}")]
+    NoInputs { query: Vec<String> },
+
+    /// --verify_pairs found R1/R2 files whose reads don't match up
+    #[error("Mismatched read pairs:\n{}", .problems.join("\n"))]
+    Unpaired { problems: Vec<String> },
+
+    /// A k-mer option megahit would reject
+    #[error("{0}")]
+    InvalidK(String),
+
+    /// A program run_megahit needs could not be found or started
+    #[error("Cannot run \"{program}\": {reason}")]
+    SpawnFailed { program: String, reason: String },
+
+    /// The one job of the batch that failed
+    #[error("{sample} {}", failure(.code))]
+    JobFailed {
+        sample: String,
+        /// `None` when the job timed out
+        code: Option<i32>,
+        /// The job's output directory, with megahit's log
+        out_dir: PathBuf,
+    },
+
+    /// Some samples failed, timed out, or never ran
+    #[error(
+        "{failed} of {total} job{} did not finish successfully, see \"{}\"",
+        if *.total == 1 { "" } else { "s" },
+        .failed_file.display()
+    )]
+    Failed {
+        failed: usize,
+        total: usize,
+        out_dir: PathBuf,
+        /// Lists the samples to rerun
+        failed_file: PathBuf,
+    },
+
+    /// The user stopped the batch
+    #[error("Interrupted, rerun with --resume to finish the batch")]
+    Interrupted { out_dir: PathBuf },
+
+    /// Anything else, e.g., an unreadable file
+    #[error("{0}")]
+    Other(String),
+}
+
+impl RunMegahitError {
+    /// The process exit status, which tells scripts and workflow managers
+    /// apart a batch that ran with failures from one that couldn't run
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            RunMegahitError::JobFailed { .. }
+            | RunMegahitError::Failed { .. } => 2,
+            RunMegahitError::Interrupted { .. } => 130,
+            _ => 1,
+        }
+    }
+
+    /// What to try next, if there is something to say
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            RunMegahitError::NoInputs { .. } => Some(
+                "Check the --query paths and that --extensions, if given, \
+                 match the reads"
+                    .to_string(),
+            ),
+            RunMegahitError::Unpaired { .. } => Some(
+                "Check that each R1 goes with its R2, or leave out \
+                 --verify_pairs to assemble them anyway"
+                    .to_string(),
+            ),
+            RunMegahitError::InvalidK(_) => Some(
+                "megahit wants odd k-mer sizes up to 255 and an even step \
+                 up to 28"
+                    .to_string(),
+            ),
+            RunMegahitError::SpawnFailed { program, .. } => {
+                Some(if is_megahit(program) {
+                    "Install megahit or give its path with --megahit_path"
+                        .to_string()
+                } else {
+                    format!("Install {} or add it to PATH", program)
+                })
+            }
+            RunMegahitError::JobFailed { out_dir, .. } => Some(format!(
+                "See the job's log in \"{}\", then rerun the sample with \
+                 \"run_megahit rerun-failed {}\"",
+                out_dir.join("log").display(),
+                batch_dir(out_dir).display()
+            )),
+            RunMegahitError::Failed { out_dir, .. } => Some(format!(
+                "Rerun just those samples with \"run_megahit rerun-failed {}\"",
+                out_dir.display()
+            )),
+            RunMegahitError::Interrupted { out_dir } => Some(format!(
+                "Finish the batch with \"run_megahit resume {}\"",
+                out_dir.display()
+            )),
+            RunMegahitError::Other(_) => None,
+        }
+    }
+}
+
+/// The rest of the crate returns Box<dyn Error>, which is one of these or
+/// a message
+impl From<Box<dyn Error>> for RunMegahitError {
+    fn from(e: Box<dyn Error>) -> RunMegahitError {
+        match e.downcast::<RunMegahitError>() {
+            Ok(e) => *e,
+            Err(e) => RunMegahitError::Other(e.to_string()),
+        }
+    }
+}
+
+// --------------------------------------------------
+fn failure(code: &Option<i32>) -> String {
+    match code {
+        Some(code) => format!("failed with exit code {}", code),
+        _ => "timed out".to_string(),
+    }
+}
+
+// --------------------------------------------------
+/// The out_dir of the batch a job's directory is in, which is usually its
+/// parent but is further up for sweeps and the stages before assembly
+fn batch_dir(job_dir: &Path) -> &Path {
+    job_dir
+        .ancestors()
+        .skip(1)
+        .find(|dir| dir.join(rerun::ARGS_FILE).is_file())
+        .or(job_dir.parent())
+        .unwrap_or(job_dir)
+}
+
+// --------------------------------------------------
+fn is_megahit(program: &str) -> bool {
+    Path::new(program)
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with("megahit"))
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error() {
+        let boxed: Box<dyn Error> = Box::new(RunMegahitError::NoInputs {
+            query: vec!["in".into()],
+        });
+        let e = RunMegahitError::from(boxed);
+        assert!(matches!(e, RunMegahitError::NoInputs { .. }));
+        assert_eq!(e.to_string(), "No input files from query [\"in\"]");
+        assert_eq!(e.exit_code(), 1);
+
+        let e = RunMegahitError::from(Box::<dyn Error>::from("bad file"));
+        assert!(matches!(e, RunMegahitError::Other(_)));
+        assert_eq!(e.hint(), None);
+
+        let e = RunMegahitError::JobFailed {
+            sample: "S1".to_string(),
+            code: Some(1),
+            out_dir: PathBuf::from("out/S1"),
+        };
+        assert_eq!(e.to_string(), "S1 failed with exit code 1");
+        assert_eq!(e.exit_code(), 2);
+        assert_eq!(
+            e.hint().unwrap(),
+            "See the job's log in \"out/S1/log\", then rerun the sample \
+             with \"run_megahit rerun-failed out\""
+        );
+
+        let e = RunMegahitError::SpawnFailed {
+            program: "/opt/bin/megahit".to_string(),
+            reason: "not found".to_string(),
+        };
+        assert!(e.hint().unwrap().contains("--megahit_path"));
+    }
+}
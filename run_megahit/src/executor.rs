@@ -0,0 +1,1117 @@
+use crate::container;
+use crate::limits::MemoryCap;
+use crate::logging;
+use crate::progress::Progress;
+use crate::remote::{self, Node};
+use crate::report::{self, JobResult};
+use crate::{commands, parallel_args, Job, RunMegahitError};
+use log::{debug, info, warn};
+use serde::{Serialize, Serializer};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use std::{fs, thread};
+
+type MyResult<T> = Result<T, Box<dyn Error>>;
+
+/// Lines of a failed job's STDERR to echo
+const STDERR_TAIL: usize = 5;
+
+/// How often running jobs are checked for completion or timeout
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often a running job's memory is measured
+const RSS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Set once the user has asked the batch to stop
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// How one attempt at a job ended
+struct Outcome {
+    exit_code: Option<i32>,
+    secs: f64,
+    timed_out: bool,
+    interrupted: bool,
+    /// Most memory the job's processes held at once, in bytes
+    peak_rss: Option<u64>,
+}
+
+/// How the native executor schedules and retries jobs
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub num_concurrent_jobs: u32,
+    /// What failing jobs do to the rest of the batch
+    pub on_failure: FailurePolicy,
+    /// Extra attempts for a failing job
+    pub retries: u32,
+    /// Wait before the first retry, doubling for each one after
+    pub retry_backoff: Duration,
+    /// Kill jobs running longer than this
+    pub timeout: Option<Duration>,
+    /// Draw progress bars on a terminal
+    pub show_progress: bool,
+    /// Echo each job's output prefixed with its sample
+    pub stream_logs: bool,
+    /// Echo no more than one line per job this often
+    pub log_interval: Duration,
+    /// Run the jobs over SSH on these hosts instead of locally
+    pub nodes: Vec<Node>,
+    /// Bytes the running jobs' estimated memory may add up to
+    pub memory_budget: Option<u64>,
+    /// Estimated bytes each job needs, by index
+    pub job_memory: Vec<u64>,
+    /// Kill (or starve) a job that uses more memory than this
+    pub memory_cap: Option<MemoryCap>,
+    /// Set by the program running the batch to stop it, as Ctrl-C would
+    pub cancel: Option<Arc<AtomicBool>>,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            num_concurrent_jobs: 8,
+            on_failure: FailurePolicy::Continue,
+            retries: 0,
+            retry_backoff: Duration::from_secs(60),
+            timeout: None,
+            show_progress: false,
+            stream_logs: false,
+            log_interval: Duration::from_secs(0),
+            nodes: vec![],
+            memory_budget: None,
+            job_memory: vec![],
+            memory_cap: None,
+            cancel: None,
+        }
+    }
+}
+
+/// What the batch does when jobs fail
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FailurePolicy {
+    /// Run every job regardless
+    Continue,
+    /// Start no more jobs after the first failure and kill running ones
+    FailFast,
+    /// Start no more jobs once this many have failed
+    Threshold(u32),
+    /// Start no more jobs once this percentage of the batch has failed
+    Percent(f64),
+}
+
+impl FailurePolicy {
+    /// Parses "continue", "fail-fast", "threshold=N", or "percent=X"
+    pub fn parse(val: &str) -> MyResult<FailurePolicy> {
+        let bad = || {
+            From::from(format!(
+                "--on_failure \"{}\" must be continue, fail-fast, \
+                 threshold=N, or percent=X",
+                val
+            ))
+        };
+
+        match val.trim().split_once('=') {
+            None if val.trim() == "continue" => Ok(FailurePolicy::Continue),
+            None if val.trim() == "fail-fast" => Ok(FailurePolicy::FailFast),
+            Some(("threshold", n)) => match n.parse::<u32>() {
+                Ok(n) if n > 0 => Ok(FailurePolicy::Threshold(n)),
+                _ => Err(bad()),
+            },
+            Some(("percent", x)) => match x.trim_end_matches('%').parse() {
+                Ok(x) if x > 0. && x <= 100. => Ok(FailurePolicy::Percent(x)),
+                _ => Err(bad()),
+            },
+            _ => Err(bad()),
+        }
+    }
+
+    /// Whether no more of `num_jobs` should start after `failures`
+    pub fn halts(&self, failures: u32, num_jobs: usize) -> bool {
+        match *self {
+            FailurePolicy::Continue => false,
+            FailurePolicy::FailFast => failures > 0,
+            FailurePolicy::Threshold(n) => failures >= n,
+            FailurePolicy::Percent(x) => {
+                failures > 0 && failures as f64 * 100. >= x * num_jobs as f64
+            }
+        }
+    }
+
+    /// The same policy as GNU parallel's --halt
+    pub fn parallel_halt(&self) -> Option<String> {
+        match *self {
+            FailurePolicy::Continue => None,
+            FailurePolicy::FailFast => Some("now,fail=1".to_string()),
+            FailurePolicy::Threshold(n) => Some(format!("soon,fail={}", n)),
+            FailurePolicy::Percent(x) => Some(format!("soon,fail={}%", x)),
+        }
+    }
+}
+
+/// As --on_failure takes it, e.g., "threshold=3"
+impl fmt::Display for FailurePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FailurePolicy::Continue => write!(f, "continue"),
+            FailurePolicy::FailFast => write!(f, "fail-fast"),
+            FailurePolicy::Threshold(n) => write!(f, "threshold={}", n),
+            FailurePolicy::Percent(x) => write!(f, "percent={}", x),
+        }
+    }
+}
+
+impl Serialize for FailurePolicy {
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        ser.collect_str(self)
+    }
+}
+
+/// A way to run the batch's jobs, chosen with --executor (or --scheduler)
+/// so that a new backend needn't touch how the jobs are made
+pub trait Executor {
+    /// Runs the jobs, calling `on_start` as each one starts (or is handed
+    /// off, when the executor can't tell) and `on_done` as each one ends,
+    /// and returns how each finished in the order given
+    fn run(
+        &self,
+        jobs: &[Job],
+        on_start: &(dyn Fn(&Job) + Sync),
+        on_done: &(dyn Fn(&JobResult) + Sync),
+    ) -> MyResult<Vec<JobResult>>;
+}
+
+// --------------------------------------------------
+/// Threads on this machine
+pub struct LocalParallel {
+    pub opts: Options,
+}
+
+impl Executor for LocalParallel {
+    fn run(
+        &self,
+        jobs: &[Job],
+        on_start: &(dyn Fn(&Job) + Sync),
+        on_done: &(dyn Fn(&JobResult) + Sync),
+    ) -> MyResult<Vec<JobResult>> {
+        info!(
+            "Running Megahit (# {} @ {})",
+            num_jobs(jobs.len()),
+            self.opts.num_concurrent_jobs
+        );
+        Ok(run_native(jobs, &self.opts, on_start, on_done))
+    }
+}
+
+// --------------------------------------------------
+/// The slots of other hosts, over SSH
+pub struct Ssh {
+    pub opts: Options,
+}
+
+impl Executor for Ssh {
+    fn run(
+        &self,
+        jobs: &[Job],
+        on_start: &(dyn Fn(&Job) + Sync),
+        on_done: &(dyn Fn(&JobResult) + Sync),
+    ) -> MyResult<Vec<JobResult>> {
+        let nodes = &self.opts.nodes;
+        info!(
+            "Running Megahit (# {} @ {} slots on {})",
+            num_jobs(jobs.len()),
+            nodes.iter().map(|node| node.slots).sum::<u32>(),
+            nodes
+                .iter()
+                .map(|node| node.host.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        Ok(run_native(jobs, &self.opts, on_start, on_done))
+    }
+}
+
+// --------------------------------------------------
+/// GNU parallel, learning how each job ended from its joblog
+pub struct GnuParallel {
+    pub num_concurrent_jobs: u32,
+    pub on_failure: FailurePolicy,
+    pub timeout: Option<Duration>,
+    pub joblog: PathBuf,
+}
+
+impl Executor for GnuParallel {
+    fn run(
+        &self,
+        jobs: &[Job],
+        on_start: &(dyn Fn(&Job) + Sync),
+        on_done: &(dyn Fn(&JobResult) + Sync),
+    ) -> MyResult<Vec<JobResult>> {
+        info!(
+            "Running Megahit (# {} @ {})",
+            num_jobs(jobs.len()),
+            self.num_concurrent_jobs
+        );
+
+        let mut process = Command::new("parallel")
+            .args(parallel_args(self.num_concurrent_jobs, &self.on_failure))
+            .args(
+                self.timeout
+                    .map(|t| {
+                        vec!["--timeout".to_string(), t.as_secs().to_string()]
+                    })
+                    .unwrap_or_default(),
+            )
+            .arg("--joblog")
+            .arg(&self.joblog)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .map_err(|e| RunMegahitError::SpawnFailed {
+                program: "parallel".to_string(),
+                reason: e.to_string(),
+            })?;
+
+        {
+            let stdin = process.stdin.as_mut().expect("Failed to open stdin");
+            stdin
+                .write_all(commands(jobs).join("\n").as_bytes())
+                .expect("Failed to write to stdin");
+        }
+        jobs.iter().for_each(on_start);
+
+        // Failed jobs are reported from the joblog
+        process.wait()?;
+
+        let results = read_joblog(&self.joblog, jobs, self.timeout.is_some())?;
+        results.iter().for_each(on_done);
+        Ok(results)
+    }
+}
+
+// --------------------------------------------------
+/// Matches parallel's joblog (Seq, Host, Starttime, JobRuntime, Send,
+/// Receive, Exitval, Signal, Command) back to the jobs by sequence number
+pub fn read_joblog(
+    joblog: &Path,
+    jobs: &[Job],
+    has_timeout: bool,
+) -> MyResult<Vec<JobResult>> {
+    let mut results: Vec<JobResult> = jobs
+        .iter()
+        .map(|job| JobResult {
+            sample: job.sample.to_string(),
+            out_dir: job.out_dir.clone(),
+            ..JobResult::default()
+        })
+        .collect();
+
+    if let Ok(contents) = fs::read_to_string(joblog) {
+        for line in contents.lines().skip(1) {
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() < 8 {
+                continue;
+            }
+
+            if let Ok(seq) = fields[0].parse::<usize>() {
+                if let Some(res) = results.get_mut(seq.wrapping_sub(1)) {
+                    res.runtime_secs = fields[3].trim().parse().unwrap_or(0.);
+                    res.exit_code = fields[6].trim().parse().ok();
+                    res.attempts = 1;
+                    res.started = fields[2].trim().parse().ok();
+                    res.finished =
+                        res.started.map(|start| start + res.runtime_secs);
+                    // parallel kills jobs over --timeout with a signal
+                    res.timed_out = has_timeout
+                        && fields[7].trim().parse::<i32>().unwrap_or(0) > 0;
+                }
+            }
+        }
+    }
+
+    Ok(results)
+}
+
+// --------------------------------------------------
+/// "N job(s)"
+fn num_jobs(n: usize) -> String {
+    format!("{} job{}", n, if n == 1 { "" } else { "s" })
+}
+
+// --------------------------------------------------
+/// Runs the jobs on a pool of `num_concurrent_jobs` threads. Once the
+/// failure policy halts the batch no new jobs start (and with fail-fast
+/// the running ones are killed), and the jobs never started are reported
+/// as not run.
+/// `on_start` sees each job as it starts and `on_done` each result as its
+/// job finishes. With `nodes` there is
+/// instead a thread for each slot on each host. With a `memory_budget`
+/// a job also waits until its estimate fits beside the running ones.
+pub fn run_native(
+    jobs: &[Job],
+    opts: &Options,
+    on_start: &(dyn Fn(&Job) + Sync),
+    on_done: &(dyn Fn(&JobResult) + Sync),
+) -> Vec<JobResult> {
+    let next = AtomicUsize::new(0);
+    let failures = AtomicU32::new(0);
+    let aborted = AtomicBool::new(false);
+    let results: Mutex<Vec<JobResult>> = Mutex::new(
+        jobs.iter()
+            .map(|job| JobResult {
+                sample: job.sample.to_string(),
+                out_dir: job.out_dir.clone(),
+                ..JobResult::default()
+            })
+            .collect(),
+    );
+
+    let mut hosts: Vec<Option<&str>> = if opts.nodes.is_empty() {
+        vec![None; opts.num_concurrent_jobs.max(1) as usize]
+    } else {
+        opts.nodes
+            .iter()
+            .flat_map(|node| {
+                std::iter::repeat_n(
+                    Some(node.host.as_str()),
+                    node.slots as usize,
+                )
+            })
+            .collect()
+    };
+    hosts.truncate(jobs.len());
+
+    let gate = opts.memory_budget.map(MemoryGate::new);
+    let cancel = || {
+        interrupted()
+            || opts
+                .cancel
+                .as_ref()
+                .is_some_and(|c| c.load(Ordering::SeqCst))
+            || aborted.load(Ordering::SeqCst)
+    };
+    let stop = || {
+        cancel()
+            || opts
+                .on_failure
+                .halts(failures.load(Ordering::SeqCst), jobs.len())
+    };
+
+    let progress = Progress::new(jobs.len(), hosts.len(), opts.show_progress);
+    // The workers warn to the batch's handlers
+    let handlers = logging::warning_handlers();
+    thread::scope(|scope| {
+        let (next, failures, aborted, results, progress) =
+            (&next, &failures, &aborted, &results, &progress);
+        let (gate, cancel, stop) = (&gate, &cancel, &stop);
+        for host in hosts {
+            let handlers = handlers.clone();
+            scope.spawn(move || {
+                logging::forward_warnings(handlers);
+                loop {
+                    if stop() {
+                        break;
+                    }
+
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    let job = match jobs.get(i) {
+                        Some(job) => job,
+                        _ => break,
+                    };
+
+                    let need = opts.job_memory.get(i).copied().unwrap_or(0);
+                    if let Some(gate) = gate {
+                        if !gate.acquire(need, stop) {
+                            break;
+                        }
+                    }
+
+                    progress.started(i, job);
+                    on_start(job);
+                    debug!(
+                        event = "job_start",
+                        sample = job.sample.as_str();
+                        "Started {}", job.sample
+                    );
+                    let started = report::now_secs();
+                    let (outcome, attempts) =
+                        run_with_retries(job, host, opts, cancel);
+                    if outcome.exit_code != Some(0) && !outcome.interrupted {
+                        failures.fetch_add(1, Ordering::SeqCst);
+                        if opts.on_failure == FailurePolicy::FailFast {
+                            aborted.store(true, Ordering::SeqCst);
+                        }
+                    }
+
+                    let mut results = results.lock().unwrap();
+                    results[i].exit_code = outcome.exit_code;
+                    results[i].runtime_secs = outcome.secs;
+                    results[i].timed_out = outcome.timed_out;
+                    results[i].interrupted = outcome.interrupted;
+                    results[i].attempts = attempts;
+                    results[i].peak_rss = outcome.peak_rss;
+                    results[i].started = Some(started);
+                    results[i].finished = Some(report::now_secs());
+                    progress.finished(i, &results[i]);
+                    on_done(&results[i]);
+                    if let Some(gate) = gate {
+                        gate.release(need);
+                    }
+                }
+            });
+        }
+    });
+    progress.finish();
+
+    results.into_inner().unwrap()
+}
+
+/// Holds jobs back until their estimated memory fits in what the running
+/// jobs leave of the budget. A job bigger than the whole budget still
+/// runs, but alone.
+struct MemoryGate {
+    budget: u64,
+    in_use: Mutex<u64>,
+    freed: Condvar,
+}
+
+impl MemoryGate {
+    fn new(budget: u64) -> MemoryGate {
+        MemoryGate {
+            budget,
+            in_use: Mutex::new(0),
+            freed: Condvar::new(),
+        }
+    }
+
+    /// Waits for room for `need` bytes; false if `stop` says to give up
+    fn acquire(&self, need: u64, stop: &dyn Fn() -> bool) -> bool {
+        let mut in_use = self.in_use.lock().unwrap();
+        while *in_use > 0 && *in_use + need > self.budget {
+            if stop() {
+                return false;
+            }
+            in_use = self.freed.wait_timeout(in_use, POLL_INTERVAL).unwrap().0;
+        }
+
+        *in_use += need;
+        true
+    }
+
+    fn release(&self, need: u64) {
+        *self.in_use.lock().unwrap() -= need;
+        self.freed.notify_all();
+    }
+}
+
+// --------------------------------------------------
+/// Runs a job until it succeeds, times out, or is out of retries,
+/// clearing the failed attempt's output first because megahit won't reuse
+/// a directory (unless it is continuing a run there). Returns the last
+/// outcome with the total runtime, the peak memory of any attempt, and
+/// the attempts made.
+fn run_with_retries(
+    job: &Job,
+    host: Option<&str>,
+    opts: &Options,
+    cancel: &dyn Fn() -> bool,
+) -> (Outcome, u32) {
+    let mut total_secs = 0.;
+    let mut peak_rss = None;
+    let mut backoff = opts.retry_backoff;
+    let mut attempt = 1;
+
+    loop {
+        let mut outcome = match host {
+            Some(host) => run_remote(job, host, opts, cancel),
+            _ => run_job(job, opts, cancel),
+        };
+        total_secs += outcome.secs;
+        peak_rss = peak_rss.max(outcome.peak_rss);
+
+        if outcome.exit_code == Some(0)
+            || outcome.timed_out
+            || outcome.interrupted
+            || attempt > opts.retries
+        {
+            outcome.secs = total_secs;
+            outcome.peak_rss = peak_rss;
+            return (outcome, attempt);
+        }
+
+        warn!(
+            event = "job_retry",
+            sample = job.sample.as_str(),
+            attempt = attempt + 1;
+            "{}: retrying in {}s (attempt {} of {})",
+            job.sample,
+            backoff.as_secs(),
+            attempt + 1,
+            opts.retries + 1
+        );
+        let wake = Instant::now() + backoff;
+        while Instant::now() < wake && !cancel() {
+            thread::sleep(POLL_INTERVAL);
+        }
+        if cancel() {
+            outcome.secs = total_secs;
+            outcome.peak_rss = peak_rss;
+            return (outcome, attempt);
+        }
+        backoff *= 2;
+        attempt += 1;
+
+        if job.out_dir.is_dir() && !job.is_continuation() {
+            if let Err(e) = fs::remove_dir_all(&job.out_dir) {
+                warn!("{}: cannot clear output: {}", job.sample, e);
+            }
+        }
+    }
+}
+
+// --------------------------------------------------
+/// Copies the job's reads to `host`, runs it there over SSH, and copies
+/// the output back. A failed copy counts as a failed run (exit 255, as
+/// ssh itself uses).
+fn run_remote(
+    job: &Job,
+    host: &str,
+    opts: &Options,
+    cancel: &dyn Fn() -> bool,
+) -> Outcome {
+    let staging_failed = |secs: f64, e: Box<dyn Error>| {
+        warn!("{} on {}: {}", job.sample, host, e);
+        Outcome {
+            exit_code: Some(255),
+            secs,
+            timed_out: false,
+            interrupted: false,
+            peak_rss: None,
+        }
+    };
+
+    let start = Instant::now();
+    if let Err(e) = remote::stage_in(job, host) {
+        return staging_failed(start.elapsed().as_secs_f64(), e);
+    }
+
+    let mut outcome = run_job(&remote::remote_job(job, host), opts, cancel);
+    if outcome.timed_out || outcome.interrupted {
+        if let Err(e) = remote::kill_job(job, host) {
+            warn!("{}", e);
+        }
+    }
+    if outcome.exit_code == Some(0) {
+        if let Err(e) = remote::stage_out(job, host) {
+            return staging_failed(start.elapsed().as_secs_f64(), e);
+        }
+    }
+
+    // What was measured is ssh, not megahit
+    outcome.secs = start.elapsed().as_secs_f64();
+    outcome.peak_rss = None;
+    outcome
+}
+
+// --------------------------------------------------
+/// Runs the job in its own process group so that a timeout can kill
+/// megahit along with the megahit_core processes it starts. `cancel` says
+/// when to kill it because the batch is stopping.
+fn run_job(job: &Job, opts: &Options, cancel: &dyn Fn() -> bool) -> Outcome {
+    let start = Instant::now();
+    let mut cmd = match &opts.memory_cap {
+        Some(cap) => cap.command(&job.program, &job.args),
+        _ => {
+            let mut cmd = Command::new(&job.program);
+            cmd.args(&job.args);
+            cmd
+        }
+    };
+    cmd.stdin(Stdio::null())
+        .stdout(if opts.stream_logs {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stderr(Stdio::piped());
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        cmd.process_group(0);
+    }
+
+    let mut child = match cmd.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            warn!(
+                event = "job_failed",
+                sample = job.sample.as_str();
+                "{} failed to start \"{}\": {}",
+                job.sample, job.program, e
+            );
+            return Outcome {
+                exit_code: Some(127),
+                secs: start.elapsed().as_secs_f64(),
+                timed_out: false,
+                interrupted: false,
+                peak_rss: None,
+            };
+        }
+    };
+
+    let relay = LogRelay {
+        sample: &job.sample,
+        interval: opts.log_interval,
+        last: Mutex::new(None),
+    };
+    let relay = if opts.stream_logs { Some(&relay) } else { None };
+    let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+
+    thread::scope(|scope| {
+        // Drain the output as it comes so a chatty job can't fill the pipe
+        scope.spawn(move || tail_lines(stdout, relay));
+        let stderr_tail = scope.spawn(move || tail_lines(stderr, relay));
+
+        let mut timed_out = false;
+        let mut peak_rss = None;
+        let mut oom_kills = 0;
+        // Only the docker client runs in the group, not megahit
+        let measures_rss = container::docker_name(job).is_none();
+        // Measured once it has had time to start
+        let mut measured = Instant::now();
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break Some(status),
+                Ok(None) => {}
+                Err(_) => break None,
+            }
+
+            // Read while the job runs, as its cgroup goes with it
+            if let Some(kills) = opts
+                .memory_cap
+                .as_ref()
+                .and_then(|cap| cap.oom_kills(child.id()))
+            {
+                oom_kills = oom_kills.max(kills);
+            }
+
+            if measures_rss && measured.elapsed() >= RSS_INTERVAL {
+                peak_rss = peak_rss.max(group_rss(child.id()));
+                measured = Instant::now();
+            }
+
+            // The job is in its own process group, so the terminal's
+            // SIGINT never reached it
+            if cancel() {
+                kill_job(&mut child, job);
+                let _ = child.wait();
+                return Outcome {
+                    exit_code: None,
+                    secs: start.elapsed().as_secs_f64(),
+                    timed_out: false,
+                    interrupted: true,
+                    peak_rss: None,
+                };
+            }
+
+            if opts.timeout.is_some_and(|limit| start.elapsed() >= limit) {
+                timed_out = true;
+                kill_job(&mut child, job);
+                break child.wait().ok();
+            }
+
+            thread::sleep(POLL_INTERVAL);
+        };
+
+        let secs = start.elapsed().as_secs_f64();
+        let code = status.as_ref().map_or(1, exit_code);
+
+        if timed_out {
+            warn!(
+                event = "job_failed",
+                sample = job.sample.as_str();
+                "{} timed out after {:.0}s", job.sample, secs
+            );
+        } else if code != 0 {
+            // megahit exits on its own once the kernel kills a
+            // megahit_core for outgrowing the cgroup, so the exit code
+            // doesn't tell
+            let cause = match &opts.memory_cap {
+                Some(cap) if oom_kills > 0 => format!(
+                    ", for exceeding its {:.1} GB memory cap",
+                    cap.bytes as f64 / 1e9
+                ),
+                _ => "".to_string(),
+            };
+            warn!(
+                event = "job_failed",
+                sample = job.sample.as_str(),
+                exit_code = code;
+                "{} failed (exit {}{}){}",
+                job.sample,
+                code,
+                cause,
+                stderr_tail
+                    .join()
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|line| format!("\n  {}", line))
+                    .collect::<String>()
+            );
+        }
+
+        Outcome {
+            exit_code: Some(code),
+            secs,
+            timed_out,
+            interrupted: false,
+            peak_rss,
+        }
+    })
+}
+
+/// Echoes a job's output to the console prefixed with its sample name,
+/// at most one line per `interval`
+struct LogRelay<'a> {
+    sample: &'a str,
+    interval: Duration,
+    last: Mutex<Option<Instant>>,
+}
+
+impl LogRelay<'_> {
+    fn relay(&self, line: &str) {
+        let mut last = self.last.lock().unwrap();
+        if last.is_some_and(|last| last.elapsed() < self.interval) {
+            return;
+        }
+
+        *last = Some(Instant::now());
+        info!(
+            event = "job_output",
+            sample = self.sample;
+            "[{}] {}", self.sample, line
+        );
+    }
+}
+
+// --------------------------------------------------
+/// Reads a stream to its end, passing each line to `relay`, and returns
+/// the last few lines
+fn tail_lines<R: Read>(
+    stream: Option<R>,
+    relay: Option<&LogRelay>,
+) -> Vec<String> {
+    let mut tail: VecDeque<String> = VecDeque::new();
+    let mut reader = match stream {
+        Some(stream) => BufReader::new(stream),
+        _ => return vec![],
+    };
+
+    let mut buf = vec![];
+    while let Ok(len) = reader.read_until(b'\n', &mut buf) {
+        if len == 0 {
+            break;
+        }
+
+        let line = String::from_utf8_lossy(&buf).trim_end().to_string();
+        buf.clear();
+
+        if let Some(relay) = relay {
+            relay.relay(&line);
+        }
+
+        if tail.len() == STDERR_TAIL {
+            tail.pop_front();
+        }
+        tail.push_back(line);
+    }
+
+    tail.into()
+}
+
+// --------------------------------------------------
+/// Turns SIGINT and SIGTERM into a request to stop: running jobs are
+/// killed, no more are started, and the batch returns what it has
+#[cfg(unix)]
+pub fn catch_interrupts() {
+    extern "C" fn handle(_: libc::c_int) {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+    }
+
+    let handler = handle as extern "C" fn(libc::c_int);
+    unsafe {
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+        libc::signal(libc::SIGTERM, handler as libc::sighandler_t);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn catch_interrupts() {}
+
+// --------------------------------------------------
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+// --------------------------------------------------
+/// Kills the job's process group and any container it runs in, which the
+/// docker daemon started outside of the group
+fn kill_job(child: &mut Child, job: &Job) {
+    kill_group(child);
+    if let Some(name) = container::docker_name(job) {
+        container::kill(name);
+    }
+}
+
+// --------------------------------------------------
+#[cfg(unix)]
+fn kill_group(child: &mut Child) {
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_group(child: &mut Child) {
+    let _ = child.kill();
+}
+
+// --------------------------------------------------
+/// The resident memory of every process in a job's process group (megahit
+/// and the megahit_core it is running), from /proc
+#[cfg(target_os = "linux")]
+fn group_rss(pgid: u32) -> Option<u64> {
+    // SAFETY: sysconf only reads system configuration
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    if page_size <= 0 {
+        return None;
+    }
+
+    let mut pages = 0;
+    let mut found = false;
+    for entry in fs::read_dir("/proc").ok()?.flatten() {
+        let stat = match fs::read_to_string(entry.path().join("stat")) {
+            Ok(stat) => stat,
+            _ => continue,
+        };
+
+        // The fields after "(comm)" start with state, ppid, pgrp; rss is
+        // the 22nd
+        let fields: Vec<&str> = match stat.rfind(')') {
+            Some(i) => stat[i + 1..].split_whitespace().collect(),
+            _ => continue,
+        };
+        if fields.get(2).and_then(|pgrp| pgrp.parse().ok()) == Some(pgid) {
+            found = true;
+            pages += fields
+                .get(21)
+                .and_then(|rss| rss.parse::<u64>().ok())
+                .unwrap_or(0);
+        }
+    }
+
+    found.then_some(pages * page_size as u64)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn group_rss(_pgid: u32) -> Option<u64> {
+    None
+}
+
+// --------------------------------------------------
+/// Parses "90", "90s", "30m", "12h", or "2d" into a duration
+pub fn parse_duration(val: &str) -> MyResult<Duration> {
+    let val = val.trim();
+    let (num, unit) = match val.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => val.split_at(i),
+        _ => (val, "s"),
+    };
+
+    let secs_per = match unit.trim().to_lowercase().as_str() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => 0,
+    };
+
+    match num.parse::<u64>() {
+        Ok(num) if secs_per > 0 && num > 0 => {
+            Ok(Duration::from_secs(num * secs_per))
+        }
+        _ => Err(From::from(format!(
+            "Invalid duration \"{}\", expected e.g. 90s, 30m, 12h, 2d",
+            val
+        ))),
+    }
+}
+
+// --------------------------------------------------
+#[cfg(unix)]
+fn exit_code(status: &std::process::ExitStatus) -> i32 {
+    use std::os::unix::process::ExitStatusExt;
+    status
+        .code()
+        .or_else(|| status.signal().map(|sig| 128 + sig))
+        .unwrap_or(1)
+}
+
+#[cfg(not(unix))]
+fn exit_code(status: &std::process::ExitStatus) -> i32 {
+    status.code().unwrap_or(1)
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn job(sample: &str, script: &str) -> Job {
+        Job {
+            sample: sample.to_string(),
+            out_dir: PathBuf::from(sample),
+            program: "sh".to_string(),
+            args: vec!["-c".to_string(), script.to_string()],
+        }
+    }
+
+    #[test]
+    fn test_run_native() {
+        let opts = Options {
+            num_concurrent_jobs: 2,
+            retry_backoff: Duration::from_secs(0),
+            ..Default::default()
+        };
+
+        let jobs = vec![job("a", "exit 0"), job("b", "exit 3")];
+        let results = run_native(&jobs, &opts, &|_| (), &|_| ());
+        assert_eq!(results[0].exit_code, Some(0));
+        assert_eq!(results[1].exit_code, Some(3));
+        assert_eq!(results[1].attempts, 1);
+
+        let halt = Options {
+            num_concurrent_jobs: 1,
+            on_failure: FailurePolicy::Threshold(1),
+            ..opts.clone()
+        };
+        let jobs = vec![job("a", "exit 1"), job("b", "exit 0")];
+        let results = run_native(&jobs, &halt, &|_| (), &|_| ());
+        assert_eq!(results[0].exit_code, Some(1));
+        assert_eq!(results[1].exit_code, None);
+
+        // Fails the first time only
+        let dir = tempfile::tempdir().unwrap();
+        let flag = dir.path().join("retry_flag");
+        let script =
+            format!("test -e {0} && exit 0; touch {0}; exit 1", flag.display());
+        let retry = Options {
+            retries: 2,
+            ..opts.clone()
+        };
+        let results =
+            run_native(&[job("a", &script)], &retry, &|_| (), &|_| ());
+        assert_eq!(results[0].exit_code, Some(0));
+        assert_eq!(results[0].attempts, 2);
+
+        let results =
+            run_native(&[job("a", "sleep 1.5")], &opts, &|_| (), &|_| ());
+        #[cfg(target_os = "linux")]
+        assert!(results[0].peak_rss.is_some_and(|rss| rss > 0));
+
+        let timeout = Options {
+            timeout: Some(Duration::from_millis(300)),
+            ..retry
+        };
+        let start = Instant::now();
+        let results =
+            run_native(&[job("a", "sleep 30")], &timeout, &|_| (), &|_| ());
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert!(results[0].timed_out);
+        assert_eq!(results[0].attempts, 1);
+        assert_eq!(results[0].status(), "timeout");
+
+        // Two jobs that don't fit in the budget together run one by one
+        let budget = Options {
+            memory_budget: Some(10),
+            job_memory: vec![6, 6],
+            ..opts.clone()
+        };
+        let jobs = vec![job("a", "sleep 0.3"), job("b", "sleep 0.3")];
+        let start = Instant::now();
+        let results = run_native(&jobs, &budget, &|_| (), &|_| ());
+        assert!(start.elapsed() >= Duration::from_millis(600));
+        assert!(results.iter().all(|res| res.exit_code == Some(0)));
+    }
+
+    #[test]
+    fn test_failure_policy() {
+        assert_eq!(
+            FailurePolicy::parse("threshold=3").unwrap(),
+            FailurePolicy::Threshold(3)
+        );
+        assert_eq!(
+            FailurePolicy::parse("percent=25%").unwrap(),
+            FailurePolicy::Percent(25.)
+        );
+        assert!(FailurePolicy::parse("threshold=0").is_err());
+        assert!(FailurePolicy::parse("percent=120").is_err());
+        assert!(FailurePolicy::parse("halt").is_err());
+
+        assert!(!FailurePolicy::Continue.halts(10, 10));
+        assert!(FailurePolicy::FailFast.halts(1, 10));
+        assert!(!FailurePolicy::Threshold(2).halts(1, 10));
+        assert!(FailurePolicy::Percent(20.).halts(2, 10));
+        assert!(!FailurePolicy::Percent(20.).halts(1, 10));
+        assert_eq!(
+            FailurePolicy::Percent(20.).parallel_halt().unwrap(),
+            "soon,fail=20%"
+        );
+
+        // Fail-fast kills the job still running
+        let opts = Options {
+            num_concurrent_jobs: 2,
+            on_failure: FailurePolicy::FailFast,
+            ..Default::default()
+        };
+        let start = Instant::now();
+        let jobs = vec![job("a", "sleep 30"), job("b", "sleep 0.2; exit 1")];
+        let results = run_native(&jobs, &opts, &|_| (), &|_| ());
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert!(results[0].interrupted);
+        assert_eq!(results[1].exit_code, Some(1));
+    }
+
+    #[test]
+    fn test_tail_lines() {
+        let text = "1\n2\n3\n4\n5\n6\n7";
+        assert_eq!(
+            tail_lines(Some(text.as_bytes()), None),
+            vec!["3", "4", "5", "6", "7"]
+        );
+        assert!(tail_lines(None::<&[u8]>, None).is_empty());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("30m").unwrap(), Duration::from_secs(1800));
+        assert_eq!(
+            parse_duration("12h").unwrap(),
+            Duration::from_secs(12 * 3600)
+        );
+        assert_eq!(
+            parse_duration("2D").unwrap(),
+            Duration::from_secs(2 * 86400)
+        );
+        assert!(parse_duration("12x").is_err());
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("0s").is_err());
+    }
+}
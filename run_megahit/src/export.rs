@@ -0,0 +1,832 @@
+use crate::{scheduler, shell_quote, Job, Sample};
+use serde_json::{json, Value};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+type MyResult<T> = Result<T, Box<dyn Error>>;
+
+/// Workflow languages the batch can be written out as
+pub const FORMATS: &[&str] =
+    &["nextflow", "snakemake", "cwl", "wdl", "make", "launcher"];
+
+/// The batch as planned: its samples, the megahit options every sample
+/// shares, where the assemblies belong, the jobs that would run them, and
+/// how to fit them onto cluster nodes
+#[derive(Debug)]
+pub struct Plan<'a> {
+    pub samples: &'a [Sample],
+    pub options: &'a [String],
+    pub out_dir: &'a Path,
+    pub jobs: &'a [Job],
+    /// Jobs run at once on a node
+    pub num_concurrent_jobs: u32,
+    /// Nodes to spread the jobs over with TACC's launcher
+    pub launcher_nodes: u32,
+    pub job_timeout: Option<Duration>,
+}
+
+// --------------------------------------------------
+/// Writes the plan as a `format` workflow into its own directory in
+/// out_dir and returns that directory
+pub fn export(format: &str, plan: &Plan) -> MyResult<PathBuf> {
+    let write: fn(&Plan, &Path) -> MyResult<()> = match format {
+        "nextflow" => nextflow,
+        "snakemake" => snakemake,
+        "cwl" => cwl,
+        "wdl" => wdl,
+        "make" => make,
+        "launcher" => launcher,
+        _ => {
+            return Err(From::from(format!(
+                "Cannot export as \"{}\", choose from {}",
+                format,
+                FORMATS.join(", ")
+            )))
+        }
+    };
+
+    // These take the options once for every sample
+    if matches!(format, "nextflow" | "cwl" | "wdl") {
+        if let Some(sample) = plan
+            .samples
+            .iter()
+            .find(|sample| !sample.options.is_empty())
+        {
+            return Err(From::from(format!(
+                "Cannot export as \"{}\" with options set for sample \"{}\" \
+                 in the manifest",
+                format, sample.name
+            )));
+        }
+    }
+
+    let dir = plan.out_dir.join(format);
+    fs::create_dir_all(&dir)?;
+    write(plan, &dir)?;
+    Ok(dir)
+}
+
+// --------------------------------------------------
+/// main.nf with one MEGAHIT process over the samples in samples.csv
+fn nextflow(plan: &Plan, dir: &Path) -> MyResult<()> {
+    let mut csv = vec!["sample,reads1,reads2,single".to_string()];
+    for sample in plan.samples {
+        csv.push(
+            [
+                csv_field(&sample.name),
+                csv_field(&absolute_list(&sample.fwd, ";")),
+                csv_field(&absolute_list(&sample.rev, ";")),
+                csv_field(&absolute_list(&sample.single, ";")),
+            ]
+            .join(","),
+        );
+    }
+    fs::write(dir.join("samples.csv"), csv.join("\n") + "\n")?;
+
+    let main = NEXTFLOW_TEMPLATE
+        .replace("@OUT_DIR@", &groovy_string(&absolute(plan.out_dir)))
+        .replace("@OPTIONS@", &groovy_string(&plan.options.join(" ")));
+    fs::write(dir.join("main.nf"), main)?;
+
+    Ok(())
+}
+
+const NEXTFLOW_TEMPLATE: &str = r#"#!/usr/bin/env nextflow
+// Written by run_megahit: nextflow run main.nf
+
+nextflow.enable.dsl = 2
+
+params.samples = "${projectDir}/samples.csv"
+params.outdir = @OUT_DIR@
+params.megahit_args = @OPTIONS@
+
+// A ";"-separated list of reads, possibly empty
+def paths(list) {
+    list ? list.tokenize(';').collect { file(it) } : []
+}
+
+process MEGAHIT {
+    tag "${sample}"
+    publishDir params.outdir, mode: 'copy'
+
+    input:
+    tuple val(sample), path(reads1, stageAs: 'fwd/*'), path(reads2, stageAs: 'rev/*'), path(single, stageAs: 'single/*')
+
+    output:
+    path "${sample}"
+
+    script:
+    def paired = reads1 ? "-1 ${[reads1].flatten().join(',')} -2 ${[reads2].flatten().join(',')}" : ''
+    def unpaired = single ? "-r ${[single].flatten().join(',')}" : ''
+    """
+    megahit ${params.megahit_args} ${paired} ${unpaired} -o ${sample}
+    """
+}
+
+workflow {
+    Channel.fromPath(params.samples)
+        | splitCsv(header: true)
+        | map { row -> tuple(row.sample, paths(row.reads1), paths(row.reads2), paths(row.single)) }
+        | MEGAHIT
+}
+"#;
+
+// --------------------------------------------------
+/// A Snakefile with a rule per sample, its reads as the inputs and its
+/// contigs as the output, and an "all" rule asking for every sample
+fn snakemake(plan: &Plan, dir: &Path) -> MyResult<()> {
+    let mut rules = vec![];
+    let mut targets = vec![];
+    for sample in plan.samples {
+        let out_dir = absolute(&plan.out_dir.join(&sample.name));
+        let contigs = format!("{}/final.contigs.fa", out_dir);
+        let inputs: Vec<String> = sample
+            .files()
+            .map(|file| {
+                format!(
+                    "        {},",
+                    python_string(&absolute(Path::new(file)))
+                )
+            })
+            .collect();
+
+        // Snakemake makes the output's directory, which megahit refuses
+        let command = format!(
+            "rm -rf {} && {}",
+            shell_quote(&out_dir),
+            megahit_command(plan, sample, &out_dir)
+        );
+
+        rules.push(format!(
+            "rule megahit_{}:\n    input:\n{}\n    output:\n        {}\n    \
+             shell:\n        {}\n",
+            identifier(&sample.name),
+            inputs.join("\n"),
+            python_string(&contigs),
+            python_string(&command.replace('{', "{{").replace('}', "}}"))
+        ));
+        targets.push(format!("        {},", python_string(&contigs)));
+    }
+
+    let snakefile = format!(
+        "# Written by run_megahit: snakemake --cores N\n\n\
+         rule all:\n    input:\n{}\n\n{}",
+        targets.join("\n"),
+        rules.join("\n")
+    );
+    fs::write(dir.join("Snakefile"), snakefile)?;
+
+    Ok(())
+}
+
+// --------------------------------------------------
+/// A CommandLineTool for megahit (megahit.cwl), a Workflow scattering it
+/// over the samples (workflow.cwl), and the batch as the workflow's
+/// inputs (inputs.json), for `cwltool workflow.cwl inputs.json`
+fn cwl(plan: &Plan, dir: &Path) -> MyResult<()> {
+    let reads = |prefix: &str| {
+        json!({
+            "type": "File[]?",
+            "inputBinding": { "prefix": prefix, "itemSeparator": "," }
+        })
+    };
+    let tool = json!({
+        "cwlVersion": "v1.2",
+        "class": "CommandLineTool",
+        "baseCommand": "megahit",
+        "inputs": {
+            "out_name": {
+                "type": "string",
+                "inputBinding": { "prefix": "-o" }
+            },
+            "reads1": reads("-1"),
+            "reads2": reads("-2"),
+            "single": reads("-r"),
+            "options": {
+                "type": "string[]",
+                "default": [],
+                "inputBinding": { "position": 1 }
+            }
+        },
+        "outputs": {
+            "contigs": {
+                "type": "File",
+                "outputBinding": {
+                    "glob": "$(inputs.out_name)/final.contigs.fa"
+                }
+            },
+            "out_dir": {
+                "type": "Directory",
+                "outputBinding": { "glob": "$(inputs.out_name)" }
+            }
+        }
+    });
+
+    let file_lists = json!({
+        "type": "array",
+        "items": ["null", { "type": "array", "items": "File" }]
+    });
+    let workflow = json!({
+        "cwlVersion": "v1.2",
+        "class": "Workflow",
+        "requirements": { "ScatterFeatureRequirement": {} },
+        "inputs": {
+            "samples": "string[]",
+            "reads1": { "type": file_lists },
+            "reads2": { "type": file_lists },
+            "single": { "type": file_lists },
+            "options": "string[]"
+        },
+        "outputs": {
+            "assemblies": {
+                "type": "Directory[]",
+                "outputSource": "megahit/out_dir"
+            }
+        },
+        "steps": {
+            "megahit": {
+                "run": "megahit.cwl",
+                "scatter": ["out_name", "reads1", "reads2", "single"],
+                "scatterMethod": "dotproduct",
+                "in": {
+                    "out_name": "samples",
+                    "reads1": "reads1",
+                    "reads2": "reads2",
+                    "single": "single",
+                    "options": "options"
+                },
+                "out": ["contigs", "out_dir"]
+            }
+        }
+    });
+
+    let (mut reads1, mut reads2, mut single) = (vec![], vec![], vec![]);
+    for sample in plan.samples {
+        reads1.push(cwl_files(&sample.fwd));
+        reads2.push(cwl_files(&sample.rev));
+        single.push(cwl_files(&sample.single));
+    }
+    let names: Vec<&str> = plan
+        .samples
+        .iter()
+        .map(|sample| sample.name.as_str())
+        .collect();
+    let inputs = json!({
+        "samples": names,
+        "reads1": reads1,
+        "reads2": reads2,
+        "single": single,
+        "options": plan.options
+    });
+
+    for (name, doc) in [
+        ("megahit.cwl", tool),
+        ("workflow.cwl", workflow),
+        ("inputs.json", inputs),
+    ] {
+        fs::write(dir.join(name), serde_json::to_string_pretty(&doc)? + "\n")?;
+    }
+
+    Ok(())
+}
+
+// --------------------------------------------------
+/// A WDL workflow scattering a megahit task over the samples
+/// (workflow.wdl) and the batch as its inputs (inputs.json), for Terra,
+/// Cromwell, or miniwdl
+fn wdl(plan: &Plan, dir: &Path) -> MyResult<()> {
+    fs::write(dir.join("workflow.wdl"), WDL_WORKFLOW)?;
+
+    let samples: Vec<Value> = plan
+        .samples
+        .iter()
+        .map(|sample| {
+            json!({
+                "name": sample.name,
+                "reads1": absolute_files(&sample.fwd),
+                "reads2": absolute_files(&sample.rev),
+                "single": absolute_files(&sample.single),
+            })
+        })
+        .collect();
+    let options: Vec<String> =
+        plan.options.iter().map(|opt| shell_quote(opt)).collect();
+    let inputs = json!({
+        "run_megahit.samples": samples,
+        "run_megahit.options": options.join(" "),
+    });
+    fs::write(
+        dir.join("inputs.json"),
+        serde_json::to_string_pretty(&inputs)? + "\n",
+    )?;
+
+    Ok(())
+}
+
+const WDL_WORKFLOW: &str = r#"version 1.0
+
+# Written by run_megahit: miniwdl run workflow.wdl -i inputs.json
+
+struct Sample {
+    String name
+    Array[File] reads1
+    Array[File] reads2
+    Array[File] single
+}
+
+workflow run_megahit {
+    input {
+        Array[Sample] samples
+        String options = ""
+        String docker = "vout/megahit:release-v1.2.9"
+    }
+
+    scatter (sample in samples) {
+        call megahit {
+            input: sample = sample, options = options, docker = docker
+        }
+    }
+
+    output {
+        Array[File] contigs = megahit.contigs
+        Array[File] logs = megahit.log
+    }
+}
+
+task megahit {
+    input {
+        Sample sample
+        String options
+        String docker
+    }
+
+    command <<<
+        set -e
+        fwd="~{sep=',' sample.reads1}"
+        rev="~{sep=',' sample.reads2}"
+        single="~{sep=',' sample.single}"
+        megahit ~{options} \
+            ${fwd:+-1 "$fwd" -2 "$rev"} \
+            ${single:+-r "$single"} \
+            -o "~{sample.name}"
+    >>>
+
+    output {
+        File contigs = "~{sample.name}/final.contigs.fa"
+        File log = "~{sample.name}/log"
+    }
+
+    runtime {
+        docker: docker
+    }
+}
+"#;
+
+// --------------------------------------------------
+/// A Makefile with each sample's contigs as a target depending on its
+/// reads, so that `make -j N` runs the jobs and reruns only what is
+/// missing or older than its reads
+fn make(plan: &Plan, dir: &Path) -> MyResult<()> {
+    let work_dir = absolute(Path::new("."));
+    let mut targets = vec![];
+    let mut rules = vec![];
+    for job in plan.jobs {
+        let target =
+            make_path(&absolute(&job.out_dir.join("final.contigs.fa")));
+        let reads: Vec<String> = job
+            .read_files()
+            .iter()
+            .map(|file| make_path(&absolute(Path::new(file))))
+            .collect();
+
+        // megahit won't start in a directory left by a failed attempt
+        let clean = if job.is_continuation() {
+            "".to_string()
+        } else {
+            format!(
+                "rm -rf {} && ",
+                shell_quote(&job.out_dir.display().to_string())
+            )
+        };
+        rules.push(format!(
+            "{}: {}\n\tcd $(WORK_DIR) && {}{}\n",
+            target,
+            reads.join(" "),
+            clean,
+            job.command_line().replace('$', "$$")
+        ));
+        targets.push(target);
+    }
+
+    let makefile = format!(
+        "# Written by run_megahit: make -f Makefile -j N\n\n\
+         WORK_DIR := {}\n\n\
+         .PHONY: all\n\
+         all: {}\n\n{}",
+        make_path(&work_dir),
+        targets.join(" "),
+        rules.join("\n")
+    );
+    fs::write(dir.join("Makefile"), makefile)?;
+
+    Ok(())
+}
+
+// --------------------------------------------------
+/// A paramlist with each job's command for TACC's `launcher` module and a
+/// SLURM script (launcher.slurm) running it with --num_concurrent_jobs
+/// tasks on each of --launcher_nodes nodes
+fn launcher(plan: &Plan, dir: &Path) -> MyResult<()> {
+    let paramlist = dir.join("paramlist");
+    fs::write(
+        &paramlist,
+        plan.jobs
+            .iter()
+            .map(|job| job.command_line() + "\n")
+            .collect::<String>(),
+    )?;
+
+    let num_jobs = plan.jobs.len().max(1) as u32;
+    let nodes = plan.launcher_nodes.clamp(1, num_jobs);
+    let tasks = (nodes * plan.num_concurrent_jobs.max(1)).min(num_jobs);
+
+    let mut directives = vec![
+        "--job-name=megahit".to_string(),
+        format!("--nodes={}", nodes),
+        format!("--ntasks={}", tasks),
+        format!("--output={}", absolute(&dir.join("launcher_%j.out"))),
+    ];
+
+    // Enough time for every round of jobs to hit the limit
+    if let Some(limit) = plan.job_timeout {
+        let rounds = num_jobs.div_ceil(tasks);
+        directives
+            .push(format!("--time={}", scheduler::slurm_time(limit * rounds)));
+    }
+
+    let script = format!(
+        "#!/bin/bash\n{}\n\n\
+         module load launcher\n\n\
+         export LAUNCHER_WORKDIR={}\n\
+         export LAUNCHER_JOB_FILE={}\n\n\
+         $LAUNCHER_DIR/paramrun\n",
+        directives
+            .iter()
+            .map(|d| format!("#SBATCH {}", shell_quote(d)))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        shell_quote(&absolute(Path::new("."))),
+        shell_quote(&absolute(&paramlist))
+    );
+    fs::write(dir.join("launcher.slurm"), script)?;
+
+    Ok(())
+}
+
+// --------------------------------------------------
+/// A path for a Makefile's targets and prerequisites
+fn make_path(path: &str) -> String {
+    path.replace('$', "$$").replace(' ', "\\ ")
+}
+
+// --------------------------------------------------
+/// Reads as CWL File objects, or null for none so that megahit doesn't
+/// get the flag
+fn cwl_files(files: &[String]) -> Value {
+    if files.is_empty() {
+        return Value::Null;
+    }
+
+    files
+        .iter()
+        .map(|file| json!({ "class": "File", "path": absolute(Path::new(file)) }))
+        .collect()
+}
+
+// --------------------------------------------------
+/// The megahit command line to assemble a sample into out_dir
+fn megahit_command(plan: &Plan, sample: &Sample, out_dir: &str) -> String {
+    let mut args = vec!["megahit".to_string(), "-o".to_string()];
+    args.push(out_dir.to_string());
+    args.extend(sample.options.apply(plan.options));
+    if !sample.fwd.is_empty() {
+        args.push("-1".to_string());
+        args.push(absolute_list(&sample.fwd, ","));
+        args.push("-2".to_string());
+        args.push(absolute_list(&sample.rev, ","));
+    }
+    if !sample.single.is_empty() {
+        args.push("-r".to_string());
+        args.push(absolute_list(&sample.single, ","));
+    }
+
+    args.iter()
+        .map(|arg| shell_quote(arg))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// --------------------------------------------------
+fn absolute(path: &Path) -> String {
+    std::path::absolute(path)
+        .unwrap_or_else(|_| path.to_path_buf())
+        .display()
+        .to_string()
+}
+
+// --------------------------------------------------
+fn absolute_files(files: &[String]) -> Vec<String> {
+    files.iter().map(|file| absolute(Path::new(file))).collect()
+}
+
+// --------------------------------------------------
+/// Workflow tasks run in directories of their own, so the reads are given
+/// by absolute path
+fn absolute_list(files: &[String], sep: &str) -> String {
+    absolute_files(files).join(sep)
+}
+
+// --------------------------------------------------
+fn csv_field(val: &str) -> String {
+    if val.contains([',', '"', '\n']) {
+        format!("\"{}\"", val.replace('"', "\"\""))
+    } else {
+        val.to_string()
+    }
+}
+
+// --------------------------------------------------
+/// A sample name made fit to name a rule or task
+fn identifier(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+// --------------------------------------------------
+/// A Python string literal (JSON's escapes mean the same in Python)
+fn python_string(val: &str) -> String {
+    serde_json::Value::String(val.to_string()).to_string()
+}
+
+// --------------------------------------------------
+/// A Groovy string literal that interpolates nothing
+fn groovy_string(val: &str) -> String {
+    format!("'{}'", val.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nextflow() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().join("export");
+
+        let samples = vec![
+            Sample {
+                name: "S1".to_string(),
+                fwd: vec!["/in/S1_R1.fq".to_string()],
+                rev: vec!["/in/S1_R2.fq".to_string()],
+                single: vec![],
+                ..Default::default()
+            },
+            Sample {
+                name: "S2".to_string(),
+                fwd: vec![],
+                rev: vec![],
+                single: vec!["/in/a.fq".to_string(), "/in/b.fq".to_string()],
+                ..Default::default()
+            },
+        ];
+        let options = vec!["--k-min".to_string(), "27".to_string()];
+        let plan = Plan {
+            samples: &samples,
+            options: &options,
+            out_dir: &dir,
+            jobs: &[],
+            num_concurrent_jobs: 1,
+            launcher_nodes: 1,
+            job_timeout: None,
+        };
+
+        let nf_dir = export("nextflow", &plan).unwrap();
+        assert_eq!(nf_dir, dir.join("nextflow"));
+        assert_eq!(
+            fs::read_to_string(nf_dir.join("samples.csv")).unwrap(),
+            "sample,reads1,reads2,single\n\
+             S1,/in/S1_R1.fq,/in/S1_R2.fq,\n\
+             S2,,,/in/a.fq;/in/b.fq\n"
+        );
+
+        let main = fs::read_to_string(nf_dir.join("main.nf")).unwrap();
+        assert!(main.contains("params.megahit_args = '--k-min 27'"));
+        assert!(main.contains(&format!("params.outdir = '{}'", dir.display())));
+        assert!(export("galaxy", &plan).is_err());
+    }
+
+    #[test]
+    fn test_cwl() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().join("export");
+
+        let samples = vec![Sample {
+            name: "S1".to_string(),
+            fwd: vec![],
+            rev: vec![],
+            single: vec!["/in/S1.fq".to_string()],
+            ..Default::default()
+        }];
+        let plan = Plan {
+            samples: &samples,
+            options: &[],
+            out_dir: &dir,
+            jobs: &[],
+            num_concurrent_jobs: 1,
+            launcher_nodes: 1,
+            job_timeout: None,
+        };
+
+        let cwl_dir = export("cwl", &plan).unwrap();
+        let read = |name: &str| -> Value {
+            serde_json::from_str(
+                &fs::read_to_string(cwl_dir.join(name)).unwrap(),
+            )
+            .unwrap()
+        };
+        assert_eq!(read("megahit.cwl")["class"], "CommandLineTool");
+        assert_eq!(
+            read("workflow.cwl")["steps"]["megahit"]["run"],
+            "megahit.cwl"
+        );
+
+        let inputs = read("inputs.json");
+        assert_eq!(inputs["samples"], json!(["S1"]));
+        assert_eq!(inputs["reads1"], json!([null]));
+        assert_eq!(inputs["single"][0][0]["path"], "/in/S1.fq");
+    }
+
+    #[test]
+    fn test_wdl() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().join("export");
+
+        let samples = vec![Sample {
+            name: "S1".to_string(),
+            fwd: vec!["/in/S1_R1.fq".to_string()],
+            rev: vec!["/in/S1_R2.fq".to_string()],
+            single: vec![],
+            ..Default::default()
+        }];
+        let options = vec!["--presets".to_string(), "meta large".to_string()];
+        let plan = Plan {
+            samples: &samples,
+            options: &options,
+            out_dir: &dir,
+            jobs: &[],
+            num_concurrent_jobs: 1,
+            launcher_nodes: 1,
+            job_timeout: None,
+        };
+
+        let wdl_dir = export("wdl", &plan).unwrap();
+        let workflow =
+            fs::read_to_string(wdl_dir.join("workflow.wdl")).unwrap();
+        assert!(workflow.starts_with("version 1.0"));
+        // One argument to a line
+        assert!(workflow.contains("megahit ~{options} \\\n"));
+        assert!(workflow.contains("\\\n            -o \"~{sample.name}\"\n"));
+
+        let inputs: Value = serde_json::from_str(
+            &fs::read_to_string(wdl_dir.join("inputs.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            inputs["run_megahit.samples"],
+            json!([{
+                "name": "S1",
+                "reads1": ["/in/S1_R1.fq"],
+                "reads2": ["/in/S1_R2.fq"],
+                "single": []
+            }])
+        );
+        assert_eq!(inputs["run_megahit.options"], "--presets 'meta large'");
+    }
+
+    #[test]
+    fn test_make() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().join("export");
+
+        let jobs = vec![Job {
+            sample: "S1".to_string(),
+            out_dir: PathBuf::from("/out/S1"),
+            program: "megahit".to_string(),
+            args: ["-o", "/out/S1", "-r", "/in/my reads.fq"]
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect(),
+        }];
+        let plan = Plan {
+            samples: &[],
+            options: &[],
+            out_dir: &dir,
+            jobs: &jobs,
+            num_concurrent_jobs: 1,
+            launcher_nodes: 1,
+            job_timeout: None,
+        };
+
+        let makefile =
+            fs::read_to_string(export("make", &plan).unwrap().join("Makefile"))
+                .unwrap();
+        assert!(makefile.contains("all: /out/S1/final.contigs.fa\n"));
+        assert!(makefile.contains(
+            "/out/S1/final.contigs.fa: /in/my\\ reads.fq\n\t\
+             cd $(WORK_DIR) && rm -rf /out/S1 && \
+             megahit -o /out/S1 -r '/in/my reads.fq'\n"
+        ));
+    }
+
+    #[test]
+    fn test_launcher() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().join("export");
+
+        let jobs: Vec<Job> = (1..=10)
+            .map(|i| Job {
+                sample: format!("S{}", i),
+                out_dir: PathBuf::from(format!("/out/S{}", i)),
+                program: "megahit".to_string(),
+                args: vec!["-o".to_string(), format!("/out/S{}", i)],
+            })
+            .collect();
+        let plan = Plan {
+            samples: &[],
+            options: &[],
+            out_dir: &dir,
+            jobs: &jobs,
+            num_concurrent_jobs: 4,
+            launcher_nodes: 2,
+            job_timeout: Some(Duration::from_secs(3600)),
+        };
+
+        let launcher_dir = export("launcher", &plan).unwrap();
+        let paramlist =
+            fs::read_to_string(launcher_dir.join("paramlist")).unwrap();
+        assert_eq!(paramlist.lines().count(), 10);
+        assert!(paramlist.starts_with("megahit -o /out/S1\n"));
+
+        // 8 tasks take two rounds for 10 jobs
+        let script =
+            fs::read_to_string(launcher_dir.join("launcher.slurm")).unwrap();
+        assert!(script.contains("#SBATCH --nodes=2\n#SBATCH --ntasks=8\n"));
+        assert!(script.contains("#SBATCH --time=0-02:00:00\n"));
+        assert!(script.ends_with("$LAUNCHER_DIR/paramrun\n"));
+    }
+
+    #[test]
+    fn test_snakemake() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().join("export");
+
+        let samples = vec![Sample {
+            name: "S1".to_string(),
+            fwd: vec!["/in/S1_R1.fq".to_string()],
+            rev: vec!["/in/S1_R2.fq".to_string()],
+            single: vec![],
+            ..Default::default()
+        }];
+        let options = vec!["--k-min".to_string(), "27".to_string()];
+        let plan = Plan {
+            samples: &samples,
+            options: &options,
+            out_dir: &dir,
+            jobs: &[],
+            num_concurrent_jobs: 1,
+            launcher_nodes: 1,
+            job_timeout: None,
+        };
+
+        let snakefile = fs::read_to_string(
+            export("snakemake", &plan).unwrap().join("Snakefile"),
+        )
+        .unwrap();
+        let out_dir = dir.join("S1").display().to_string();
+        assert!(snakefile.contains(&format!(
+            "rule all:\n    input:\n        \"{}/final.contigs.fa\",",
+            out_dir
+        )));
+        assert!(snakefile.contains("rule megahit_S1:\n"));
+        assert!(snakefile
+            .contains("        \"/in/S1_R1.fq\",\n        \"/in/S1_R2.fq\","));
+        assert!(snakefile.contains(&format!(
+            "\"rm -rf {0} && megahit -o {0} --k-min 27 -1 /in/S1_R1.fq -2 /in/S1_R2.fq\"",
+            out_dir
+        )));
+    }
+}
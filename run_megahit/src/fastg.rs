@@ -0,0 +1,81 @@
+use crate::trim::args;
+use std::fs;
+use std::path::Path;
+
+/// Where megahit leaves the contigs of each k, relative to a sample's
+/// out_dir
+pub const INTERMEDIATE_DIR: &str = "intermediate_contigs";
+
+// --------------------------------------------------
+/// megahit_toolkit, next to megahit when that was given as a path
+pub fn toolkit(megahit: &str) -> String {
+    match Path::new(megahit).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => {
+            dir.join("megahit_toolkit").display().to_string()
+        }
+        _ => "megahit_toolkit".to_string(),
+    }
+}
+
+// --------------------------------------------------
+/// The largest k megahit left contigs of in `dir`, i.e., the final one
+pub fn final_k(dir: &Path) -> Option<u32> {
+    fs::read_dir(dir.join(INTERMEDIATE_DIR))
+        .ok()?
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name().to_string_lossy().to_string();
+            name.strip_prefix('k')?
+                .strip_suffix(".contigs.fa")?
+                .parse::<u32>()
+                .ok()
+        })
+        .max()
+}
+
+// --------------------------------------------------
+/// The assembly graph of the contigs of k in `dir` as dir/k{k}.fastg,
+/// which Bandage loads
+pub fn command(toolkit: &str, dir: &Path, k: u32) -> Vec<String> {
+    args(&[
+        toolkit,
+        "contig2fastg",
+        &k.to_string(),
+        &dir.join(INTERMEDIATE_DIR)
+            .join(format!("k{}.contigs.fa", k))
+            .display()
+            .to_string(),
+        ">",
+        &dir.join(format!("k{}.fastg", k)).display().to_string(),
+    ])
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fastg() {
+        assert_eq!(toolkit("megahit"), "megahit_toolkit");
+        assert_eq!(
+            toolkit("/opt/megahit/bin/megahit"),
+            "/opt/megahit/bin/megahit_toolkit"
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        assert_eq!(final_k(dir), None);
+        fs::create_dir_all(dir.join(INTERMEDIATE_DIR)).unwrap();
+        for name in ["k21.contigs.fa", "k99.contigs.fa", "k141.addi.fa"].iter()
+        {
+            fs::write(dir.join(INTERMEDIATE_DIR).join(name), "").unwrap();
+        }
+        assert_eq!(final_k(dir), Some(99));
+
+        assert_eq!(
+            command("megahit_toolkit", Path::new("out/S1"), 99).join(" "),
+            "megahit_toolkit contig2fastg 99 \
+             out/S1/intermediate_contigs/k99.contigs.fa > out/S1/k99.fastg"
+        );
+    }
+}
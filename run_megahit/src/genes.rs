@@ -0,0 +1,73 @@
+use crate::report::Metric;
+use crate::stats;
+use crate::trim::args;
+use std::error::Error;
+use std::path::Path;
+
+type MyResult<T> = Result<T, Box<dyn Error>>;
+
+/// The proteins Prodigal predicts, relative to a sample's out_dir
+pub const PROTEINS_FILE: &str = "final.contigs.faa";
+
+/// Where the genes are on the contigs, relative to a sample's out_dir
+pub const GFF_FILE: &str = "final.contigs.gff";
+
+/// The genes of the batch's samples side by side, relative to out_dir
+pub const SUMMARY_FILE: &str = "genes_summary.tsv";
+
+// --------------------------------------------------
+/// Prodigal in metagenome mode on the sample's final contigs, writing the
+/// proteins and GFF alongside them
+pub fn command(dir: &Path) -> Vec<String> {
+    let path = |name: &str| dir.join(name).display().to_string();
+    args(&[
+        "prodigal",
+        "-p",
+        "meta",
+        "-q",
+        "-i",
+        &path("final.contigs.fa"),
+        "-a",
+        &path(PROTEINS_FILE),
+        "-f",
+        "gff",
+        "-o",
+        &path(GFF_FILE),
+    ])
+}
+
+// --------------------------------------------------
+/// How many genes Prodigal found in the sample's contigs
+pub fn read_summary(dir: &Path) -> MyResult<Vec<Metric>> {
+    let proteins = stats::assembly_stats(&dir.join(PROTEINS_FILE))?;
+    Ok(vec![Metric {
+        stage: "genes".to_string(),
+        name: "genes".to_string(),
+        value: proteins.num_contigs.to_string(),
+    }])
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_genes() {
+        assert_eq!(
+            command(Path::new("out/S1")).join(" "),
+            "prodigal -p meta -q -i out/S1/final.contigs.fa \
+             -a out/S1/final.contigs.faa -f gff -o out/S1/final.contigs.gff"
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        assert!(read_summary(dir).is_err());
+        fs::write(dir.join(PROTEINS_FILE), ">k141_1_1\nMK*\n>k141_1_2\nML*\n")
+            .unwrap();
+        let metrics = read_summary(dir).unwrap();
+        assert_eq!(metrics[0].name, "genes");
+        assert_eq!(metrics[0].value, "2");
+    }
+}
@@ -0,0 +1,192 @@
+/// Jobs run at once when neither the user nor the machine says otherwise
+pub const DEFAULT_JOBS: u32 = 8;
+
+/// Fewest threads worth giving a megahit job when picking the concurrency
+const MIN_THREADS_PER_JOB: u32 = 4;
+
+/// Least memory worth giving a megahit job when picking the concurrency
+const MIN_MEMORY_PER_JOB: u64 = 4_000_000_000;
+
+/// Share of the RAM the jobs may use together, as megahit's own default
+const MEMORY_FRACTION: f64 = 0.9;
+
+/// The machine the jobs will run on
+#[derive(Debug, Clone, PartialEq)]
+pub struct Host {
+    pub cores: u32,
+    /// Physical memory in bytes, if it could be read
+    pub memory: Option<u64>,
+}
+
+impl Host {
+    pub fn probe() -> Host {
+        Host {
+            cores: cores(),
+            memory: memory(),
+        }
+    }
+
+    /// How many jobs fit at once: each gets `threads` (or a few) cores and
+    /// a few GB, and there are never more than the old default of eight
+    pub fn default_jobs(&self, threads: Option<u32>) -> u32 {
+        let threads = threads.unwrap_or(MIN_THREADS_PER_JOB).max(1);
+        let by_cores = self.cores / threads;
+        let by_memory = self
+            .memory
+            .map_or(u64::MAX, |mem| mem / MIN_MEMORY_PER_JOB)
+            .min(u32::MAX as u64) as u32;
+
+        by_cores.min(by_memory).clamp(1, DEFAULT_JOBS)
+    }
+
+    /// Bytes the jobs may use together
+    pub fn memory_budget(&self) -> Option<u64> {
+        self.memory.map(|mem| (mem as f64 * MEMORY_FRACTION) as u64)
+    }
+
+    /// A --memory value that asks for more bytes than the machine has
+    pub fn check_memory(&self, name: &str, mem: f64) -> Result<(), String> {
+        match self.memory {
+            Some(ram) if mem > 1. && mem > ram as f64 => Err(format!(
+                "--{} {:.0} bytes is more than this machine's {} bytes of RAM",
+                name, mem, ram
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Bytes for each of `jobs` concurrent jobs to share most of the RAM
+    pub fn memory_per_job(&self, jobs: u32) -> Option<f64> {
+        self.memory.map(|mem| {
+            (mem as f64 * MEMORY_FRACTION / jobs.max(1) as f64).floor()
+        })
+    }
+}
+
+// --------------------------------------------------
+/// A memory value as megahit's --memory takes it, a fraction of the RAM
+/// (e.g., "0.5" or "80%") or bytes (e.g., "64G", "512MB", "16GiB", or
+/// "64000000000")
+pub fn parse_memory(name: &str, val: &str) -> Result<f64, String> {
+    let bad = || {
+        format!(
+            "--{} \"{}\" must be a fraction of the RAM (0.5 or 50%) \
+             or a size (64G, 512M, or bytes)",
+            name, val
+        )
+    };
+
+    let val = val.trim();
+    if let Some(percent) = val.strip_suffix('%') {
+        return match percent.trim().parse::<f64>() {
+            Ok(p) if p > 0. && p <= 100. => Ok(p / 100.),
+            _ => Err(bad()),
+        };
+    }
+
+    let upper = val.to_ascii_uppercase();
+    let digits = upper.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let unit = &upper[digits.len()..];
+    let scale: f64 = match unit {
+        "" => 1.,
+        "K" | "KB" => 1e3,
+        "M" | "MB" => 1e6,
+        "G" | "GB" => 1e9,
+        "T" | "TB" => 1e12,
+        "KI" | "KIB" => 1024.,
+        "MI" | "MIB" => 1024f64.powi(2),
+        "GI" | "GIB" => 1024f64.powi(3),
+        "TI" | "TIB" => 1024f64.powi(4),
+        _ => return Err(bad()),
+    };
+
+    match digits.trim().parse::<f64>() {
+        Ok(n) if n > 0. && unit.is_empty() => Ok(n),
+        Ok(n) if n > 0. => Ok((n * scale).floor()),
+        _ => Err(bad()),
+    }
+}
+
+// --------------------------------------------------
+pub fn cores() -> u32 {
+    std::thread::available_parallelism()
+        .map(|n| n.get() as u32)
+        .unwrap_or(1)
+}
+
+// --------------------------------------------------
+#[cfg(unix)]
+fn memory() -> Option<u64> {
+    // SAFETY: sysconf only reads system configuration
+    let (pages, page_size) = unsafe {
+        (
+            libc::sysconf(libc::_SC_PHYS_PAGES),
+            libc::sysconf(libc::_SC_PAGESIZE),
+        )
+    };
+
+    if pages > 0 && page_size > 0 {
+        Some(pages as u64 * page_size as u64)
+    } else {
+        None
+    }
+}
+
+#[cfg(not(unix))]
+fn memory() -> Option<u64> {
+    None
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults() {
+        let big = Host {
+            cores: 64,
+            memory: Some(512_000_000_000),
+        };
+        assert_eq!(big.default_jobs(None), 8);
+        assert_eq!(big.default_jobs(Some(16)), 4);
+        assert_eq!(big.memory_per_job(8), Some(57_600_000_000.));
+
+        let laptop = Host {
+            cores: 8,
+            memory: Some(8_000_000_000),
+        };
+        assert_eq!(laptop.default_jobs(None), 2);
+        assert_eq!(laptop.default_jobs(Some(8)), 1);
+
+        let tiny = Host {
+            cores: 1,
+            memory: None,
+        };
+        assert_eq!(tiny.default_jobs(None), 1);
+        assert_eq!(tiny.memory_per_job(1), None);
+        assert!(Host::probe().cores >= 1);
+    }
+
+    #[test]
+    fn test_parse_memory() {
+        assert_eq!(parse_memory("memory", "0.5"), Ok(0.5));
+        assert_eq!(parse_memory("memory", "80%"), Ok(0.8));
+        assert_eq!(parse_memory("memory", "64G"), Ok(64e9));
+        assert_eq!(parse_memory("memory", "512mb"), Ok(512e6));
+        assert_eq!(parse_memory("memory", "1GiB"), Ok(1073741824.));
+        assert_eq!(parse_memory("memory", "1000000000"), Ok(1e9));
+        assert!(parse_memory("memory", "0").is_err());
+        assert!(parse_memory("memory", "150%").is_err());
+        assert!(parse_memory("memory", "64Q").is_err());
+        assert!(parse_memory("memory", "lots").is_err());
+
+        let host = Host {
+            cores: 8,
+            memory: Some(8_000_000_000),
+        };
+        assert!(host.check_memory("memory", 0.9).is_ok());
+        assert!(host.check_memory("memory", 4e9).is_ok());
+        assert!(host.check_memory("memory", 64e9).is_err());
+    }
+}
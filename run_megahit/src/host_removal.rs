@@ -0,0 +1,188 @@
+use crate::trim::{args, base_name};
+use crate::Sample;
+use std::path::Path;
+
+/// The mappers --host_mapper takes
+pub const MAPPERS: &[&str] = &["bowtie2", "minimap2"];
+
+// --------------------------------------------------
+/// The mapper for a host index: minimap2 for a file (.mmi or FASTA),
+/// bowtie2 for the prefix of an index's .bt2 files
+pub fn mapper_for(index: &Path) -> &'static str {
+    if index.is_file() {
+        "minimap2"
+    } else {
+        "bowtie2"
+    }
+}
+
+// --------------------------------------------------
+/// The programs `mapper` needs
+pub fn programs(mapper: &str) -> Vec<&'static str> {
+    match mapper {
+        "minimap2" => vec!["minimap2", "samtools"],
+        _ => vec!["bowtie2"],
+    }
+}
+
+// --------------------------------------------------
+/// The sample with its reads swapped for the ones that don't map to the
+/// host, which `dir` will hold, and the commands that make them
+pub fn plan(
+    mapper: &str,
+    index: &Path,
+    sample: &Sample,
+    dir: &Path,
+    threads: Option<u32>,
+) -> (Sample, Vec<Vec<String>>) {
+    let mut kept = Sample {
+        fwd: vec![],
+        rev: vec![],
+        single: vec![],
+        ..sample.clone()
+    };
+    let mut commands = vec![];
+    let index = index.display().to_string();
+    let threads = threads.unwrap_or(1).to_string();
+    let out = |file: &str, mate: &str| {
+        dir.join(format!("{}.nohost{}.fq.gz", base_name(file).0, mate))
+            .display()
+            .to_string()
+    };
+
+    for (r1, r2) in sample.fwd.iter().zip(&sample.rev) {
+        let (out1, out2) = (out(r1, ".1"), out(r1, ".2"));
+        match mapper {
+            "minimap2" => {
+                // Both mates unmapped
+                commands.push(args(&[
+                    "minimap2",
+                    "-ax",
+                    "sr",
+                    "-t",
+                    &threads,
+                    &index,
+                    r1,
+                    r2,
+                    "|",
+                    "samtools",
+                    "fastq",
+                    "-f",
+                    "12",
+                    "-n",
+                    "-1",
+                    &out1,
+                    "-2",
+                    &out2,
+                    "-0",
+                    "/dev/null",
+                    "-s",
+                    "/dev/null",
+                    "-",
+                ]));
+            }
+            _ => commands.push(args(&[
+                "bowtie2",
+                "-p",
+                &threads,
+                "-x",
+                &index,
+                "-1",
+                r1,
+                "-2",
+                r2,
+                "--un-conc-gz",
+                &out(r1, ".%"),
+                "-S",
+                "/dev/null",
+            ])),
+        }
+        kept.fwd.push(out1);
+        kept.rev.push(out2);
+    }
+
+    for file in &sample.single {
+        let out = out(file, "");
+        match mapper {
+            "minimap2" => {
+                commands.push(args(&[
+                    "minimap2", "-ax", "sr", "-t", &threads, &index, file, "|",
+                    "samtools", "fastq", "-f", "4", "-0", &out, "-",
+                ]));
+            }
+            _ => commands.push(args(&[
+                "bowtie2",
+                "-p",
+                &threads,
+                "-x",
+                &index,
+                "-U",
+                file,
+                "--un-gz",
+                &out,
+                "-S",
+                "/dev/null",
+            ])),
+        }
+        kept.single.push(out);
+    }
+
+    (kept, commands)
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan() {
+        let sample = Sample {
+            name: "S1".to_string(),
+            fwd: vec!["in/S1_R1.fq.gz".to_string()],
+            rev: vec!["in/S1_R2.fq.gz".to_string()],
+            single: vec!["in/S1.fastq".to_string()],
+            ..Sample::default()
+        };
+        let (dir, index) = (Path::new("work/S1"), Path::new("ref/hg38"));
+        assert_eq!(mapper_for(index), "bowtie2");
+
+        let (kept, commands) = plan("bowtie2", index, &sample, dir, Some(8));
+        assert_eq!(kept.fwd, vec!["work/S1/S1_R1.nohost.1.fq.gz"]);
+        assert_eq!(kept.rev, vec!["work/S1/S1_R1.nohost.2.fq.gz"]);
+        assert_eq!(kept.single, vec!["work/S1/S1.nohost.fq.gz"]);
+        assert_eq!(
+            commands[0].join(" "),
+            "bowtie2 -p 8 -x ref/hg38 -1 in/S1_R1.fq.gz -2 in/S1_R2.fq.gz \
+             --un-conc-gz work/S1/S1_R1.nohost.%.fq.gz -S /dev/null"
+        );
+        assert_eq!(
+            commands[1].join(" "),
+            "bowtie2 -p 8 -x ref/hg38 -U in/S1.fastq \
+             --un-gz work/S1/S1.nohost.fq.gz -S /dev/null"
+        );
+
+        let (kept, commands) = plan("minimap2", index, &sample, dir, None);
+        assert_eq!(kept.fwd, vec!["work/S1/S1_R1.nohost.1.fq.gz"]);
+        assert_eq!(commands.len(), 2);
+        assert_eq!(
+            commands[0].join(" "),
+            "minimap2 -ax sr -t 1 ref/hg38 in/S1_R1.fq.gz in/S1_R2.fq.gz \
+             | samtools fastq -f 12 -n -1 work/S1/S1_R1.nohost.1.fq.gz \
+             -2 work/S1/S1_R1.nohost.2.fq.gz -0 /dev/null -s /dev/null -"
+        );
+        assert_eq!(
+            commands[1].join(" "),
+            "minimap2 -ax sr -t 1 ref/hg38 in/S1.fastq \
+             | samtools fastq -f 4 -0 work/S1/S1.nohost.fq.gz -"
+        );
+        assert_eq!(programs("minimap2"), vec!["minimap2", "samtools"]);
+
+        // A minimap2 index is one file
+        let dir = tempfile::tempdir().unwrap();
+        let mmi = dir.path().join("hg38.mmi");
+        assert_eq!(mapper_for(&mmi), "bowtie2");
+        std::fs::write(&mmi, "").unwrap();
+        assert_eq!(mapper_for(&mmi), "minimap2");
+    }
+}
@@ -1,435 +1,3596 @@
 extern crate clap;
+extern crate flate2;
+extern crate libc;
+extern crate md5;
 extern crate regex;
+extern crate serde;
+extern crate serde_json;
+extern crate sha2;
 
-use clap::{App, Arg};
+#[cfg(feature = "async")]
+mod async_batch;
+mod aws;
+mod binning;
+mod builder;
+mod checksum;
+mod classify;
+mod cli;
+mod config_file;
+mod container;
+mod coverage;
+mod depth;
+mod disk;
+mod doctor;
+mod error;
+mod executor;
+mod export;
+mod fastg;
+mod genes;
+mod host;
+mod host_removal;
+mod limits;
+mod logging;
+mod manifest;
+mod megahit;
+mod outputs;
+mod pipeline;
+mod progress;
+mod provenance;
+mod quast;
+mod reads;
+mod remote;
+pub mod report;
+mod rerun;
+mod scheduler;
+mod state;
+mod stats;
+mod sweep;
+mod trim;
+mod verify;
+
+#[cfg(feature = "async")]
+pub use async_batch::AsyncBatch;
+pub use builder::ConfigBuilder;
+use clap::error::{ContextKind, ErrorKind};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, ColorChoice, CommandFactory, FromArgMatches};
+use cli::{Cli, Command, RunArgs};
+pub use error::RunMegahitError;
+use executor::Executor;
+use log::{debug, info, warn};
+use pipeline::Stage;
 use regex::Regex;
-use std::collections::HashMap;
+use report::{
+    ConsoleNotifier, ConsoleReporter, EmailNotifier, Event, Hooks,
+    HtmlReporter, JobResult, JsonReporter, Metric, RunSummary, SampleResult,
+    WebhookNotifier,
+};
+use serde::{Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::error::Error;
-use std::process::{Command, Stdio};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use std::{
     env, fs,
-    io::Write,
+    io::{self, BufRead, IsTerminal, Write},
     path::{Path, PathBuf},
 };
 
-#[derive(Debug)]
-struct SplitPath {
-    stem: String,
-    ext: Option<String>,
+/// The values megahit's --presets takes
+const PRESETS: &[&str] = &["meta-sensitive", "meta-large"];
+
+#[derive(Debug, PartialEq)]
+enum Action {
+    Run,
+    Verify,
+    RerunFailed,
+    Stats,
+    Depth,
+    Clean,
+    Report,
+    Doctor,
 }
 
-#[derive(Debug)]
+/// The batch as the command line, config file, and environment settled
+/// it. `--show_config` prints it.
+#[derive(Debug, Serialize)]
 pub struct Config {
+    #[serde(skip)]
+    action: Action,
+    /// The command line, kept so the batch can be rerun
+    #[serde(skip)]
+    args: Vec<String>,
+    /// Print the config in this format ("toml" or "json") instead of running
+    #[serde(skip)]
+    show_config: Option<String>,
+    /// List the samples in this format ("tsv" or "json") instead of running
+    #[serde(skip)]
+    list_samples: Option<String>,
+    /// The BAM files `run_megahit depth` reads, and where it writes
+    #[serde(skip)]
+    depth_bams: Vec<PathBuf>,
+    #[serde(skip)]
+    depth_file: Option<PathBuf>,
     query: Vec<String>,
     out_dir: PathBuf,
-    num_concurrent_jobs: Option<u32>,
-    num_halt: Option<u32>,
+    num_concurrent_jobs: u32,
+    num_cpu_threads: Option<u32>,
+    on_failure: executor::FailurePolicy,
+    /// megahit --presets, one of PRESETS
+    preset: Option<String>,
     min_count: Option<u32>,
     k_min: Option<u32>,
     k_max: Option<u32>,
     k_step: Option<u32>,
-    memory: Option<f32>,
+    /// Used instead of k_min, k_max, and k_step
+    k_list: Option<Vec<u32>>,
+    memory: Option<f64>,
+    memory_per_job: Option<f64>,
+    split_memory: bool,
     min_contig_length: Option<u32>,
+    prune_level: Option<u32>,
+    prune_depth: Option<u32>,
+    bubble_level: Option<u32>,
+    /// "l,s", the length and similarity thresholds for merging
+    merge_level: Option<String>,
+    low_local_ratio: Option<f32>,
+    cleaning_rounds: Option<u32>,
+    no_local: bool,
+    disconnect_ratio: Option<f32>,
+    kmin_1pass: bool,
+    /// Where megahit writes its temp files, e.g., node-local scratch
+    tmp_dir: Option<PathBuf>,
+    keep_tmp_files: bool,
+    /// The megahit program, a path or a name in PATH
+    megahit: String,
+    /// Appended as is to every megahit command
+    megahit_args: Vec<String>,
+    /// megahit options of the --profile, under the batch's own
+    #[serde(serialize_with = "profile_args")]
+    profile: Vec<config_file::ProfileOption>,
+    dry_run: bool,
+    /// Print the job plan as JSON instead of running
+    plan: bool,
+    /// Ask before running, after showing the samples and resources
+    confirm: bool,
+    /// How much run_megahit says on STDERR
+    #[serde(serialize_with = "lowercase")]
+    log_level: log::LevelFilter,
+    /// "text" or "json"
+    log_format: String,
+    /// Write the batch as this kind of workflow instead of running it
+    export: Option<String>,
+    /// Parameter sets to assemble every sample with
+    sweep: Vec<sweep::ParamSet>,
+    launcher_nodes: u32,
+    checksums: Option<PathBuf>,
+    skip_bad_checksums: bool,
+    reports: Vec<String>,
+    notify_email: Option<String>,
+    #[serde(serialize_with = "redacted_urls")]
+    webhooks: Vec<String>,
+    #[serde(serialize_with = "redacted_url")]
+    smtp_url: Option<String>,
+    /// "user:password" of the [smtp] table
+    #[serde(skip)]
+    smtp_login: Option<String>,
+    email_from: Option<String>,
+    force: bool,
+    stages: Vec<Stage>,
+    /// The trimmer of the trim stage, one of trim::TOOLS
+    trim: String,
+    /// The host genome of the host-removal stage
+    host_index: Option<PathBuf>,
+    /// One of host_removal::MAPPERS
+    host_mapper: String,
+    /// The evaluator of the qc stage, one of quast::TOOLS
+    quast: String,
+    /// The mapper of the coverage stage, one of coverage::MAPPERS
+    coverage: String,
+    /// The binner of the bin stage, one of binning::TOOLS
+    bin: String,
+    /// The classifier of the classify stage, one of classify::TOOLS
+    classify: String,
+    /// Its database
+    db: Option<PathBuf>,
+    samples: Vec<String>,
+    limit: Option<usize>,
+    skip_stages: BTreeMap<String, Vec<Stage>>,
+    co_assembly: bool,
+    manifest: Option<PathBuf>,
+    attach_orphans: bool,
+    /// Assemble the lanes of a sample (S1_L001, S1_L002) as its libraries
+    merge_lanes: bool,
+    verify_pairs: bool,
+    extensions: Vec<String>,
+    executor: String,
+    retries: u32,
+    retry_backoff: u64,
+    #[serde(rename = "job_timeout_secs", serialize_with = "secs")]
+    job_timeout: Option<Duration>,
+    resume: bool,
+    progress: bool,
+    stream_logs: bool,
+    log_interval: u64,
+    scheduler: String,
+    submit: bool,
+    slurm_array: bool,
+    sge_pe: String,
+    s3_prefix: Option<String>,
+    aws_queue: Option<String>,
+    aws_image: String,
+    nodes: Vec<remote::Node>,
+    docker: Option<String>,
+    schedule: String,
+    adaptive_concurrency: bool,
+    cap_memory: bool,
+    nice: Option<i32>,
+    ionice: Vec<String>,
+    singularity: Option<String>,
+    container_engine: String,
+    /// Stops the batch when set, for programs that run batches as a library
+    /// and so don't have run_megahit catch Ctrl-C
+    #[serde(skip)]
+    cancel: Option<Arc<AtomicBool>>,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash)]
-enum ReadDirection {
-    Forward,
-    Reverse,
+/// The reads assembled together in one megahit job
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Sample {
+    pub name: String,
+    pub fwd: Vec<String>,
+    pub rev: Vec<String>,
+    pub single: Vec<String>,
+    pub group: Option<String>,
+    /// What the manifest sets for this sample over the batch's options
+    pub options: SampleOptions,
 }
 
-type MyResult<T> = Result<T, Box<dyn Error>>;
-type ReadPair = HashMap<ReadDirection, String>;
-type ReadPairLookup = HashMap<String, ReadPair>;
-type SingleReads = Vec<String>;
+impl Sample {
+    fn files(&self) -> impl Iterator<Item = &String> {
+        self.fwd.iter().chain(&self.rev).chain(&self.single)
+    }
+}
 
-// --------------------------------------------------
-pub fn get_args() -> MyResult<Config> {
-    let matches = App::new("run_megahit")
-        .version("0.1.0")
-        .author("Ken Youens-Clark <kyclark@email.arizona.edu>")
-        .about("Runs TrimGalore")
-        .arg(
-            Arg::with_name("query")
-                .short("Q")
-                .long("query")
-                .value_name("FILE_OR_DIR")
-                .help("File input or directory")
-                .required(true)
-                .min_values(1),
-        )
-        .arg(
-            Arg::with_name("out_dir")
-                .short("o")
-                .long("out_dir")
-                .value_name("DIR")
-                .help("Output directory"),
-        )
-        .arg(
-            Arg::with_name("num_concurrent_jobs")
-                .short("J")
-                .long("num_concurrent_jobs")
-                .value_name("INT")
-                .default_value("8")
-                .help("Number of concurrent jobs for parallel"),
-        )
-        .arg(
-            Arg::with_name("num_halt")
-                .short("H")
-                .long("num_halt")
-                .value_name("INT")
-                .default_value("0")
-                .help("Halt after this many failing jobs"),
-        )
-        .arg(
-            Arg::with_name("min_count")
-                .long("min_count")
-                .value_name("INT")
-                .help("minimum multiplicity for filtering (k_min+1)-mers")
-        )
-        .arg(
-            Arg::with_name("k_min")
-                .long("k_min")
-                .value_name("INT")
-                .help("minimum kmer size (<= 255), must be odd number")
-        )
-        .arg(
-            Arg::with_name("k_max")
-                .long("k_max")
-                .value_name("INT")
-                .help("maximum kmer size (<= 255), must be odd number")
-        )
-        .arg(
-            Arg::with_name("k_step")
-                .long("k_step")
-                .value_name("INT")
-                .help("increment of kmer size of each iteration (<= 28), must be even number")
-        )
-        .arg(
-            Arg::with_name("min_contig_len")
-                .long("min_contig_len")
-                .value_name("INT")
-                .help("minimum length of contigs to output")
-        )
-        .arg(
-            Arg::with_name("memory")
-                .short("m")
-                .long("memory")
-                .value_name("FLOAT")
-                .default_value("1000000000")
-                .help("Amount/percentage of memory"),
-        )
-        .get_matches();
+/// The megahit options a manifest can set for one sample
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SampleOptions {
+    pub preset: Option<String>,
+    pub k_list: Option<Vec<u32>>,
+    pub min_count: Option<u32>,
+    pub memory: Option<f64>,
+}
 
-    let out_dir = match matches.value_of("out_dir") {
-        Some(x) => PathBuf::from(x),
-        _ => {
-            let cwd = env::current_dir()?;
-            cwd.join(PathBuf::from("megahit-out"))
+impl SampleOptions {
+    fn is_empty(&self) -> bool {
+        *self == SampleOptions::default()
+    }
+
+    /// The batch's megahit options with the ones the sample sets replaced
+    fn apply(&self, options: &[String]) -> Vec<String> {
+        let mut drop: Vec<&str> = vec![];
+        let mut add: Vec<String> = vec![];
+        if let Some(preset) = &self.preset {
+            drop.push("--presets");
+            add.extend(vec!["--presets".to_string(), preset.to_string()]);
+        }
+        if let Some(k_list) = &self.k_list {
+            drop.extend(vec!["--k-min", "--k-max", "--k-step", "--k-list"]);
+            let k_list: Vec<String> =
+                k_list.iter().map(|k| k.to_string()).collect();
+            add.extend(vec!["--k-list".to_string(), k_list.join(",")]);
+        }
+        if let Some(min_count) = self.min_count {
+            drop.push("--min-count");
+            add.extend(vec!["--min-count".to_string(), min_count.to_string()]);
+        }
+        if let Some(memory) = self.memory {
+            drop.push("--memory");
+            add.extend(vec!["--memory".to_string(), memory.to_string()]);
         }
-    };
 
-    let num_concurrent_jobs = matches
-        .value_of("num_concurrent_jobs")
-        .and_then(|x| x.trim().parse::<u32>().ok());
+        let mut args = vec![];
+        let mut iter = options.iter();
+        while let Some(opt) = iter.next() {
+            if drop.contains(&opt.as_str()) {
+                iter.next();
+            } else {
+                args.push(opt.to_string());
+            }
+        }
+        args.extend(add);
+        args
+    }
+}
 
-    let num_halt = matches
-        .value_of("num_halt")
-        .and_then(|x| x.trim().parse::<u32>().ok());
+/// One program invocation, kept as separate arguments so that it can be
+/// spawned directly or quoted safely for a shell
+#[derive(Debug)]
+struct Job {
+    sample: String,
+    out_dir: PathBuf,
+    program: String,
+    args: Vec<String>,
+}
 
-    let min_count = matches
-        .value_of("min_count")
-        .and_then(|x| x.trim().parse::<u32>().ok());
+impl Job {
+    /// Whether megahit is picking up a run already in `out_dir`
+    fn is_continuation(&self) -> bool {
+        self.args.iter().any(|arg| arg == "--continue")
+    }
 
-    let k_min = matches
-        .value_of("k_min")
-        .and_then(|x| x.trim().parse::<u32>().ok());
+    /// The command as a single line a POSIX shell will split back into
+    /// exactly `program` and `args`
+    fn command_line(&self) -> String {
+        std::iter::once(&self.program)
+            .chain(&self.args)
+            .map(|arg| shell_quote(arg))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
 
-    let k_max = matches
-        .value_of("k_max")
-        .and_then(|x| x.trim().parse::<u32>().ok());
+    /// The read files named in the megahit arguments
+    fn read_files(&self) -> Vec<&str> {
+        self.args
+            .windows(2)
+            .filter(|pair| READ_OPTIONS.contains(&pair[0].as_str()))
+            .flat_map(|pair| pair[1].split(','))
+            .collect()
+    }
+}
 
-    let k_step = matches
-        .value_of("k_step")
-        .and_then(|x| x.trim().parse::<u32>().ok());
+/// Options whose values may hold logins or tokens
+const SECRET_OPTIONS: &[&str] = &["--smtp_url", "--smtp-url", "--webhook"];
 
-    let min_contig_length = matches
-        .value_of("min_contig_len")
-        .and_then(|x| x.trim().parse::<u32>().ok());
+/// megahit options whose values are (comma-separated) read files
+const READ_OPTIONS: &[&str] = &["-1", "-2", "-r", "--12"];
 
-    let memory = matches
-        .value_of("memory")
-        .and_then(|x| x.trim().parse::<f32>().ok());
+/// The batch scripts written for a scheduler
+enum JobScripts {
+    Each(Vec<scheduler::Script>),
+    /// One SLURM array job for all of them
+    Array(PathBuf),
+}
 
-    Ok(Config {
-        query: matches.values_of_lossy("query").unwrap(),
-        out_dir,
-        num_concurrent_jobs,
-        num_halt,
-        min_count,
-        k_min,
-        k_max,
-        k_step,
-        min_contig_length,
-        memory,
-    })
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum ReadDirection {
+    Forward,
+    Reverse,
 }
 
-// --------------------------------------------------
-pub fn run(config: Config) -> MyResult<()> {
-    let files = find_files(&config.query)?;
+type MyResult<T> = Result<T, Box<dyn Error>>;
 
-    if files.is_empty() {
-        let msg = format!("No input files from query \"{:?}\"", &config.query);
-        return Err(From::from(msg));
-    }
+/// Reads what a stage after assembly measured in a sample's out_dir
+type MetricsReader = fn(&Path) -> MyResult<Vec<Metric>>;
 
-    let (pairs, singles) = classify(&files)?;
+/// Where the stages before assembly write each sample's reads, relative
+/// to out_dir
+const WORK_DIR: &str = "work";
 
-    println!(
-        "Processing {} pair, {} single.",
-        pairs.keys().len(),
-        singles.len()
-    );
+/// What the stages after assembly write, in each sample's out_dir
+const POST_LOG: &str = "stages.log";
 
-    let jobs = make_jobs(&config, pairs, singles)?;
+/// megahit's largest k unless told otherwise
+const DEFAULT_K_MAX: u32 = 141;
 
-    run_jobs(
-        &jobs,
-        "Running Megahit",
-        config.num_concurrent_jobs.unwrap_or(8),
-        config.num_halt.unwrap_or(0),
-    )?;
+/// Least memory a megahit job is assumed to need
+const MIN_JOB_MEMORY: u64 = 1_000_000_000;
 
-    println!("Done, see output in \"{}\"", &config.out_dir.display());
+/// How many leading reads --verify_pairs compares between R1 and R2
+const NUM_PAIR_CHECK_READS: usize = 20;
+type ReadPair = HashMap<ReadDirection, String>;
+type ReadPairLookup = HashMap<String, ReadPair>;
+type SingleReads = Vec<String>;
 
-    Ok(())
+// --------------------------------------------------
+pub fn get_args() -> Result<Config, RunMegahitError> {
+    Ok(parse_args(env::args().collect())?)
 }
 
 // --------------------------------------------------
-fn make_jobs(
-    config: &Config,
-    pairs: ReadPairLookup,
-    singles: SingleReads,
-) -> Result<Vec<String>, Box<dyn Error>> {
-    let mut args: Vec<String> = vec![];
-
-    if let Some(min_count) = config.min_count {
-        args.push(format!("--min-count {}", min_count));
-    }
+/// Options from RUN_MEGAHIT_<NAME> variables, e.g., RUN_MEGAHIT_OUT_DIR,
+/// as names and values like those of a config. A switch is on for
+/// "true", "yes", or "on" and off for "false", "no", "off", or nothing.
+fn env_options(
+    vars: impl Iterator<Item = (String, String)>,
+) -> MyResult<Vec<(String, Vec<String>)>> {
+    let mut options = vec![];
+    for (var, val) in vars {
+        let name = match var.strip_prefix("RUN_MEGAHIT_") {
+            Some(name) if !name.is_empty() => name.to_lowercase(),
+            _ => continue,
+        };
+        if name == "megahit_extra" {
+            options.push((name, split_words(&val)?));
+            continue;
+        }
 
-    if let Some(k_min) = config.k_min {
-        args.push(format!("--k-min {}", k_min));
+        match val.trim().to_lowercase().as_str() {
+            "true" | "yes" | "on" => options.push((name, vec![])),
+            "false" | "no" | "off" | "" => {}
+            _ => options.push((name, vec![val])),
+        }
     }
+    options.sort();
 
-    if let Some(k_max) = config.k_max {
-        args.push(format!("--k-max {}", k_max));
-    }
+    Ok(options)
+}
 
-    if let Some(k_step) = config.k_step {
-        args.push(format!("--k-step {}", k_step));
+// --------------------------------------------------
+/// Adds the `defaults` the command line doesn't set to `args` and parses
+/// them again
+fn with_defaults(
+    args: &mut Vec<String>,
+    matches: &ArgMatches,
+    defaults: Vec<(String, Vec<String>)>,
+) -> MyResult<ArgMatches> {
+    let command = Cli::command();
+    let mut extra: Vec<String> = vec![];
+    let mut extra_megahit: Vec<String> = vec![];
+    for (name, values) in defaults {
+        // An unknown name is left for clap to report
+        let given = command.get_arguments().any(|arg| arg.get_id() == &name)
+            && matches.value_source(&name) == Some(ValueSource::CommandLine);
+        if given {
+            continue;
+        }
+        if name == "megahit_extra" {
+            extra_megahit.extend(values);
+            continue;
+        }
+        if values.is_empty() {
+            extra.push(format!("--{}", name));
+        }
+        for val in values {
+            extra.push(format!("--{}={}", name, val));
+        }
     }
 
-    if let Some(min_contig_length) = config.min_contig_length {
-        args.push(format!("--min-contig-len {}", min_contig_length));
+    if extra.is_empty() && extra_megahit.is_empty() {
+        return Ok(matches.clone());
     }
 
-    if let Some(memory) = config.memory {
-        args.push(format!("--memory {}", memory));
+    let pos = args.iter().position(|arg| arg == "--");
+    let rest = args.split_off(pos.unwrap_or(args.len()));
+    args.extend(extra);
+    args.extend(rest);
+    if !extra_megahit.is_empty() {
+        if !args.iter().any(|arg| arg == "--") {
+            args.push("--".to_string());
+        }
+        args.extend(extra_megahit);
     }
 
-    let mut jobs: Vec<String> = vec![];
-    for (i, (sample, val)) in pairs.iter().enumerate() {
-        println!("{:3}: Pair {}", i + 1, sample);
+    command
+        .color(ColorChoice::Never)
+        .try_get_matches_from(args.iter())
+        .map_err(clap_error)
+}
 
-        if let (Some(fwd), Some(rev)) = (
-            val.get(&ReadDirection::Forward),
-            val.get(&ReadDirection::Reverse),
-        ) {
-            jobs.push(format!(
-                "megahit -o {} {} -1 {} -2 {}",
-                config.out_dir.display(),
-                args.join(" "),
-                fwd,
-                rev,
-            ));
+// --------------------------------------------------
+/// A clap error as one line, for options that didn't come from the
+/// command line
+fn clap_error(e: clap::Error) -> Box<dyn Error> {
+    From::from(match (e.kind(), e.get(ContextKind::InvalidArg)) {
+        (ErrorKind::UnknownArgument, Some(arg)) => {
+            format!("unknown option \"{}\"", arg)
         }
-    }
+        _ => e
+            .to_string()
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .trim_start_matches("error: ")
+            .to_string(),
+    })
+}
 
-    for (i, file) in singles.iter().enumerate() {
-        let path = Path::new(file);
-        let basename = path.file_name().expect("basename");
-        let basename = &basename.to_string_lossy().to_string();
+// --------------------------------------------------
+fn parse_args(args: Vec<String>) -> MyResult<Config> {
+    parse_command(args, Action::Run)
+}
+
+// --------------------------------------------------
+/// The config of a command line, which is `action` when it has no
+/// subcommand
+fn parse_command(args: Vec<String>, action: Action) -> MyResult<Config> {
+    let mut args = args;
+    let mut matches =
+        Cli::command().try_get_matches_from(&args).map_err(|e| {
+            match e.kind() {
+                ErrorKind::DisplayHelp | ErrorKind::DisplayVersion => e.exit(),
+                _ => clap_error(e),
+            }
+        })?;
 
-        println!("{:3}: Single {}", i + 1, basename);
+    // RUN_MEGAHIT_* variables and then a --config file fill in what the
+    // command line doesn't say
+    if matches.subcommand_name().is_none() {
+        let from_env = env_options(env::vars())?;
+        if !from_env.is_empty() {
+            matches = with_defaults(&mut args, &matches, from_env)
+                .map_err(|e| format!("RUN_MEGAHIT_* variables: {}", e))?;
+        }
 
-        jobs.push(format!(
-            "megahit -o {} {} -r {}",
-            config.out_dir.display(),
-            args.join(" "),
-            file,
-        ));
+        if let Some(path) = matches.get_one::<PathBuf>("config").cloned() {
+            let file = config_file::read(&path)?;
+            matches = with_defaults(&mut args, &matches, file.args()?)
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+        }
     }
 
-    Ok(jobs)
+    let cli = Cli::from_arg_matches(&matches)?;
+    let (action, out_dir) = match cli.command {
+        // "export --format F OPTIONS" is a run with "--export F"
+        Some(Command::Export { format, run_args }) => {
+            let mut export_args =
+                vec![args[0].to_string(), "--export".to_string(), format];
+            export_args.extend(run_args);
+            return parse_args(export_args);
+        }
+        Some(Command::Run { run_args }) => {
+            let mut run_args = run_args;
+            run_args.insert(0, args[0].to_string());
+            return parse_args(run_args);
+        }
+        Some(Command::Doctor { run_args }) => {
+            let mut run_args = run_args;
+            run_args.insert(0, args[0].to_string());
+            return parse_command(run_args, Action::Doctor);
+        }
+        Some(Command::RerunFailed { out_dir }) => {
+            return rerun_config(&out_dir);
+        }
+        Some(Command::Resume { out_dir }) => {
+            let (_, mut config) = saved_config(&out_dir)?;
+            config.resume = true;
+            return Ok(config);
+        }
+        Some(Command::Report { out_dir, format }) => {
+            let mut config =
+                config_from(Action::Report, Some(out_dir), cli.run, args)?;
+            config.reports = format;
+            return Ok(config);
+        }
+        Some(Command::Depth { output, bams }) => {
+            let mut config = config_from(Action::Depth, None, cli.run, args)?;
+            config.depth_bams = bams;
+            config.depth_file = output;
+            return Ok(config);
+        }
+        Some(Command::Verify { out_dir }) => (Action::Verify, Some(out_dir)),
+        Some(Command::Stats { out_dir }) => (Action::Stats, Some(out_dir)),
+        Some(Command::Clean { out_dir }) => (Action::Clean, Some(out_dir)),
+        None => (action, cli.run.out_dir.clone()),
+    };
+
+    config_from(action, out_dir, cli.run, args)
 }
 
 // --------------------------------------------------
-fn find_files(paths: &[String]) -> Result<Vec<String>, Box<dyn Error>> {
-    let mut files = vec![];
-    for path in paths {
-        let meta = fs::metadata(path)?;
-        if meta.is_file() {
-            files.push(path.to_owned());
-        } else {
-            for entry in fs::read_dir(path)? {
-                let entry = entry?;
-                let meta = entry.metadata()?;
-                if meta.is_file() {
-                    files.push(entry.path().display().to_string());
-                }
-            }
-        };
+/// The config of the parsed options, with what they leave out fitted to
+/// this machine when the jobs run here
+fn config_from(
+    action: Action,
+    out_dir: Option<PathBuf>,
+    run: RunArgs,
+    args: Vec<String>,
+) -> MyResult<Config> {
+    // Not required by clap so that a --config can give them
+    if action == Action::Run && run.query.is_empty() && run.manifest.is_none() {
+        return Err(From::from("--query or --manifest is required"));
     }
 
-    if files.is_empty() {
-        return Err(From::from("No input files"));
-    }
+    let out_dir = match out_dir {
+        Some(x) => x,
+        _ => {
+            let cwd = env::current_dir()?;
+            cwd.join(PathBuf::from("megahit-out"))
+        }
+    };
 
-    Ok(files)
-}
+    let on_failure = match run.num_halt {
+        Some(n) if n > 0 => {
+            executor::FailurePolicy::parse(&format!("threshold={}", n))?
+        }
+        _ => run.on_failure,
+    };
 
-// --------------------------------------------------
-fn classify(
-    paths: &[String],
-) -> Result<(ReadPairLookup, SingleReads), Box<dyn Error>> {
-    let paths = paths.iter().map(Path::new);
-    let mut exts: Vec<String> =
-        paths.clone().map(get_extension).filter_map(|x| x).collect();
-    exts.dedup();
+    check_kmers(run.k_min, run.k_max, run.k_step, run.min_count)?;
 
-    let dots = Regex::new(r"\.").unwrap();
-    let exts: Vec<String> = exts
+    let sweep: Vec<sweep::ParamSet> = sweep::parse(&run.sweep)?
         .into_iter()
-        .map(|x| dots.replace(&x, r"\.").to_string())
+        .filter(|set| !set.label.is_empty())
         .collect();
 
-    let pattern = format!(r"(.+)[_-][Rr]?([12])?\.(?:{})$", exts.join("|"));
-    let re = Regex::new(&pattern).unwrap();
-    let mut pairs: ReadPairLookup = HashMap::new();
-    let mut singles: Vec<String> = vec![];
+    if run.k_list.is_some()
+        && sweep
+            .iter()
+            .any(|set| set.flags.iter().any(|flag| flag.starts_with("--k-")))
+    {
+        return Err(From::from(
+            "--sweep cannot vary k_min, k_max, or k_step with --k_list",
+        ));
+    }
 
-    for path in paths.map(Path::new) {
-        let path_str = path.to_str().expect("Convert path");
+    // Each parameter set has to make sense with the options it doesn't vary
+    for set in &sweep {
+        let value = |flag: &str| set.value(flag).and_then(|v| v.parse().ok());
+        check_kmers(
+            value("--k-min").or(run.k_min),
+            value("--k-max").or(run.k_max),
+            value("--k-step").or(run.k_step),
+            value("--min-count").or(run.min_count),
+        )
+        .map_err(|e| {
+            RunMegahitError::InvalidK(format!("--sweep {}: {}", set.label, e))
+        })?;
+    }
 
-        if let Some(file_name) = path.file_name() {
-            let basename = file_name.to_string_lossy();
-            if let Some(cap) = re.captures(&basename) {
-                let sample_name = &cap[1];
-                let direction = if &cap[2] == "1" {
-                    ReadDirection::Forward
-                } else {
-                    ReadDirection::Reverse
-                };
+    let mut megahit_args = run.megahit_args.unwrap_or_default();
+    megahit_args.extend(run.megahit_extra);
 
-                if !pairs.contains_key(sample_name) {
-                    let mut pair: ReadPair = HashMap::new();
-                    pair.insert(direction, path_str.to_string());
-                    pairs.insert(sample_name.to_string(), pair);
-                } else if let Some(pair) = pairs.get_mut(sample_name) {
-                    pair.insert(direction, path_str.to_string());
-                }
-            } else {
-                singles.push(path_str.to_string());
-            }
+    let profile = match (&run.profile, &run.config) {
+        (Some(name), Some(path)) => config_file::read(path)?.profile(name)?,
+        _ => vec![],
+    };
+
+    // The options of the [smtp] table that the command line doesn't set
+    let smtp = match &run.config {
+        Some(path) => config_file::read(path)?.smtp,
+        _ => config_file::Smtp::default(),
+    };
+
+    let nodes = run.nodes.unwrap_or_default();
+
+    let mut stages = match &run.pipeline {
+        Some(name) => pipeline::preset(name)?,
+        _ if !run.stages.is_empty() => pipeline::parse_stages(&run.stages)?,
+        _ => vec![Stage::Assemble],
+    };
+    for (given, stage) in [
+        (run.trim.is_some(), Stage::Trim),
+        (run.host_index.is_some(), Stage::HostRemoval),
+        // Binning takes the depth table of the coverage stage
+        (run.coverage.is_some() || run.bin.is_some(), Stage::Coverage),
+        (run.bin.is_some(), Stage::Bin),
+        (run.genes, Stage::Genes),
+        (run.classify.is_some(), Stage::Classify),
+        (run.fastg, Stage::Graph),
+        (run.quast.is_some(), Stage::Qc),
+    ] {
+        if given && !stages.contains(&stage) {
+            stages.push(stage);
+            stages.sort();
         }
     }
 
-    let bad: Vec<String> = pairs
-        .iter()
-        .filter_map(|(k, v)| {
-            if !v.contains_key(&ReadDirection::Forward)
-                || !v.contains_key(&ReadDirection::Reverse)
-            {
-                Some(k.to_string())
-            } else {
-                None
-            }
-        })
-        .collect();
+    if stages.contains(&Stage::HostRemoval) && run.host_index.is_none() {
+        return Err(From::from("The host-removal stage requires --host_index"));
+    }
+    if stages.contains(&Stage::Classify) && run.db.is_none() {
+        return Err(From::from("The classify stage requires --db"));
+    }
+    if stages.contains(&Stage::Bin) && !stages.contains(&Stage::Coverage) {
+        return Err(From::from("The bin stage requires the coverage stage"));
+    }
+    let host_mapper = match (&run.host_mapper, &run.host_index) {
+        (Some(mapper), _) => mapper.to_string(),
+        (_, Some(index)) => host_removal::mapper_for(index).to_string(),
+        _ => "bowtie2".to_string(),
+    };
 
-    // Push unpaired samples to the singles
-    for key in bad {
-        if let Some(pair) = pairs.get(&key) {
-            for val in pair.values() {
-                singles.push(val.to_string());
+    let mut skip_stages: BTreeMap<String, Vec<Stage>> = BTreeMap::new();
+    for directive in &run.skip_stage {
+        let (sample, stage) = pipeline::parse_skip(directive, &stages)?;
+        skip_stages.entry(sample).or_default().push(stage);
+    }
+    for (sample, skipped) in &skip_stages {
+        if stages.contains(&Stage::Bin)
+            && skipped.contains(&Stage::Coverage)
+            && !skipped.contains(&Stage::Bin)
+        {
+            return Err(From::from(format!(
+                "Cannot skip \"coverage\" for sample \"{}\", \
+                 the bin stage needs it",
+                sample
+            )));
+        }
+    }
+
+    // Fit what wasn't given to this machine when the jobs run here
+    let local = nodes.is_empty() && run.scheduler == "local";
+    let host = host::Host::probe();
+    let num_concurrent_jobs = match run.num_concurrent_jobs {
+        Some(jobs) => jobs,
+        _ if local => host.default_jobs(run.num_cpu_threads),
+        _ => host::DEFAULT_JOBS,
+    };
+    if local {
+        for (name, mem) in [
+            ("memory", run.memory),
+            ("memory_per_job", run.memory_per_job),
+        ] {
+            if let Some(mem) = mem {
+                host.check_memory(name, mem)?;
             }
         }
-        pairs.remove(&key);
     }
+    let memory_per_job = match run.memory_per_job {
+        None if local && run.memory.is_none() => {
+            host.memory_per_job(num_concurrent_jobs)
+        }
+        mem => mem,
+    };
 
-    Ok((pairs, singles))
+    Ok(Config {
+        action,
+        args,
+        query: run.query,
+        out_dir,
+        num_concurrent_jobs,
+        num_cpu_threads: run.num_cpu_threads,
+        on_failure,
+        preset: run.preset,
+        min_count: run.min_count,
+        k_min: run.k_min,
+        k_max: run.k_max,
+        k_step: run.k_step,
+        k_list: run.k_list,
+        min_contig_length: run.min_contig_len,
+        prune_level: run.prune_level,
+        prune_depth: run.prune_depth,
+        bubble_level: run.bubble_level,
+        merge_level: run.merge_level,
+        low_local_ratio: run.low_local_ratio,
+        cleaning_rounds: run.cleaning_rounds,
+        no_local: run.no_local,
+        disconnect_ratio: run.disconnect_ratio,
+        kmin_1pass: run.kmin_1pass,
+        tmp_dir: run.tmp_dir,
+        keep_tmp_files: run.keep_tmp_files,
+        megahit: run.megahit_path.unwrap_or_else(|| "megahit".to_string()),
+        megahit_args,
+        profile,
+        memory: run.memory,
+        memory_per_job,
+        split_memory: run.split_memory,
+        dry_run: run.dry_run,
+        plan: run.plan,
+        confirm: run.confirm && !run.yes,
+        export: run.export,
+        sweep,
+        launcher_nodes: run.launcher_nodes,
+        checksums: run.checksums,
+        skip_bad_checksums: run.checksum_mismatch == "skip",
+        reports: run.report,
+        notify_email: run.notify_email,
+        smtp_url: run.smtp_url.or_else(|| smtp.url.clone()),
+        smtp_login: smtp.login(),
+        webhooks: run.webhook,
+        email_from: run.email_from.or(smtp.from),
+        force: run.force,
+        stages,
+        trim: run.trim.unwrap_or_else(|| "fastp".to_string()),
+        host_index: run.host_index,
+        host_mapper,
+        quast: run.quast.unwrap_or_else(|| "quast".to_string()),
+        coverage: run.coverage.unwrap_or_else(|| "bwa-mem2".to_string()),
+        bin: run.bin.unwrap_or_else(|| "metabat2".to_string()),
+        classify: run.classify.unwrap_or_else(|| "kraken2".to_string()),
+        db: run.db,
+        samples: run.samples,
+        limit: run.limit,
+        skip_stages,
+        show_config: run.show_config,
+        list_samples: run.list_samples,
+        depth_bams: vec![],
+        depth_file: None,
+        co_assembly: run.co_assembly,
+        manifest: run.manifest,
+        attach_orphans: run.attach_orphans,
+        merge_lanes: run.merge_lanes,
+        verify_pairs: run.verify_pairs,
+        executor: run.executor,
+        retries: run.retries,
+        retry_backoff: run.retry_backoff,
+        job_timeout: run.job_timeout,
+        resume: run.resume,
+        progress: !run.no_progress,
+        log_level: logging::level(run.quiet, run.verbose),
+        log_format: run.log_format,
+        stream_logs: run.stream_logs,
+        scheduler: run.scheduler,
+        submit: run.submit,
+        slurm_array: run.slurm_array,
+        sge_pe: run.sge_pe,
+        s3_prefix: run.s3_prefix,
+        aws_queue: run.aws_queue,
+        aws_image: run.aws_image,
+        nodes,
+        docker: run.docker,
+        schedule: run.schedule,
+        adaptive_concurrency: run.adaptive_concurrency,
+        cap_memory: run.cap_memory,
+        nice: run.nice,
+        ionice: run.ionice.unwrap_or_default(),
+        singularity: run.singularity,
+        container_engine: run.container_engine,
+        cancel: None,
+        log_interval: run.log_interval,
+        extensions: run
+            .extensions
+            .iter()
+            .map(|ext| ext.trim().trim_start_matches('.').to_lowercase())
+            .collect(),
+    })
 }
 
 // --------------------------------------------------
-/// Returns the extension plus optional ".gz"
-fn get_extension(path: &Path) -> Option<String> {
-    let re = Regex::new(r"\.([^.]+(?:\.gz)?)$").unwrap();
-    if let Some(basename) = path.file_name() {
-        let basename = basename.to_string_lossy();
-        if let Some(cap) = re.captures(&basename) {
-            return Some(cap[1].to_string());
+/// The batch that made `out_dir`, from where it was started and narrowed
+/// to the samples in its failed_samples.tsv
+fn rerun_config(out_dir: &Path) -> MyResult<Config> {
+    let (out_dir, mut config) = saved_config(out_dir)?;
+    let failed = rerun::read_failed(&out_dir)?;
+    config.action = Action::RerunFailed;
+    config.samples = failed;
+    config.limit = None;
+    Ok(config)
+}
+
+// --------------------------------------------------
+/// The config of the batch in out_dir as it was started, from the
+/// directory it was started in, with out_dir made absolute
+fn saved_config(out_dir: &Path) -> MyResult<(PathBuf, Config)> {
+    let out_dir = out_dir
+        .canonicalize()
+        .map_err(|e| format!("{}: {}", out_dir.display(), e))?;
+    let invocation = rerun::load_invocation(&out_dir)?;
+
+    env::set_current_dir(&invocation.cwd)
+        .map_err(|e| format!("{}: {}", invocation.cwd.display(), e))?;
+    Ok((out_dir, parse_args(invocation.args)?))
+}
+
+// --------------------------------------------------
+/// The command line without the options that may hold secrets, to keep in
+/// out_dir. A rerun takes them from the --config or environment again.
+fn public_args(args: &[String]) -> Vec<String> {
+    let mut public = vec![];
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--" {
+            public.push(arg.to_string());
+            public.extend(iter.cloned());
+            break;
         }
+
+        let (name, val) = match arg.split_once('=') {
+            Some((name, val)) => (name, Some(val)),
+            _ => (arg.as_str(), None),
+        };
+        if SECRET_OPTIONS.contains(&name) {
+            if val.is_none() {
+                iter.next();
+            }
+            continue;
+        }
+        public.push(arg.to_string());
     }
-    None
+    public
 }
 
 // --------------------------------------------------
-fn run_jobs(
-    jobs: &[String],
-    msg: &str,
-    num_concurrent_jobs: u32,
-    num_halt: u32,
-) -> MyResult<()> {
-    let num_jobs = jobs.len();
+/// Logs to STDERR at the level and in the format of the config. For the
+/// command line; a program using the library keeps its own logger, which
+/// hears run_megahit's messages under the "run_megahit" target.
+pub fn init_logging(config: &Config) {
+    logging::init(config.log_level, &config.log_format);
+}
 
-    if num_jobs > 0 {
-        println!(
-            "{} (# {} job{} @ {})",
-            msg,
-            num_jobs,
-            if num_jobs == 1 { "" } else { "s" },
-            num_concurrent_jobs,
-        );
+// --------------------------------------------------
+/// Has Ctrl-C (SIGINT) and SIGTERM stop the batch, killing its running jobs,
+/// for the rest of the process. For the command line; a program using the
+/// library keeps its own signal handling and stops a batch with `cancel`.
+pub fn catch_interrupts() {
+    executor::catch_interrupts();
+}
 
-        let mut args: Vec<String> =
-            vec!["-j".to_string(), num_concurrent_jobs.to_string()];
+// --------------------------------------------------
+/// Does what the config says, failing if any sample wasn't assembled
+pub fn run(config: Config) -> Result<(), RunMegahitError> {
+    let out_dir = config.out_dir.clone();
+    let results = run_collect(config)?;
+    Ok(check_results(&out_dir, &results)?)
+}
 
-        if num_halt > 0 {
-            args.push("--halt".to_string());
-            args.push(format!("soon,fail={}", num_halt.to_string()));
-        }
+// --------------------------------------------------
+/// Does what the config says and returns what became of each sample,
+/// failed ones included. Only a batch that ran has results.
+pub fn run_collect(
+    config: Config,
+) -> Result<Vec<SampleResult>, RunMegahitError> {
+    Ok(run_action(config)?)
+}
 
-        let mut process = Command::new("parallel")
-            .args(args)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::null())
-            .spawn()?;
+// --------------------------------------------------
+fn run_action(config: Config) -> MyResult<Vec<SampleResult>> {
+    if let Some(format) = &config.show_config {
+        print!("{}", show_config(&config, format)?);
+        return Ok(vec![]);
+    }
 
-        {
-            let stdin = process.stdin.as_mut().expect("Failed to open stdin");
-            stdin
-                .write_all(jobs.join("\n").as_bytes())
-                .expect("Failed to write to stdin");
+    if let Some(format) = &config.list_samples {
+        print!("{}", list_samples(&discover_samples(&config)?, format)?);
+        return Ok(vec![]);
+    }
+
+    match config.action {
+        Action::Verify => {
+            verify_out_dir(&config.out_dir)?;
+            return Ok(vec![]);
+        }
+        Action::Stats => {
+            print_stats(&config.out_dir)?;
+            return Ok(vec![]);
+        }
+        Action::Depth => {
+            let bams: Vec<&Path> =
+                config.depth_bams.iter().map(PathBuf::as_path).collect();
+            match &config.depth_file {
+                Some(path) => {
+                    let mut file = fs::File::create(path)
+                        .map_err(|e| format!("{}: {}", path.display(), e))?;
+                    depth::write_table(&bams, &mut file)?;
+                }
+                _ => depth::write_table(&bams, &mut io::stdout())?,
+            }
+            return Ok(vec![]);
+        }
+        Action::Clean => {
+            let freed = outputs::clean(&config.out_dir)?;
+            println!("Freed {}", disk::format_bytes(freed));
+            return Ok(vec![]);
+        }
+        Action::Doctor => {
+            let checks =
+                doctor::run(&config.megahit, &config.executor, &config.out_dir);
+            print!("{}", doctor::format(&checks));
+            let failed = checks.iter().filter(|check| !check.ok).count();
+            if failed > 0 {
+                return Err(From::from(format!(
+                    "{} check{} failed",
+                    failed,
+                    if failed == 1 { "" } else { "s" }
+                )));
+            }
+            return Ok(vec![]);
         }
+        Action::Report => {
+            let state =
+                state::State::load(&config.out_dir.join(state::STATE_FILE))?;
+            if state.samples.is_empty() {
+                return Err(From::from(format!(
+                    "No batch state in \"{}\"",
+                    config.out_dir.display()
+                )));
+            }
+            default_hooks(&config)
+                .report(&report::summary_from_state(&state))?;
+            return Ok(vec![]);
+        }
+        _ => {}
+    }
 
-        let result = process.wait()?;
-        if !result.success() {
-            return Err(From::from("Failed to run jobs in parallel"));
+    if config.action == Action::RerunFailed {
+        if config.samples.is_empty() {
+            info!("No failed samples to rerun");
+            return Ok(vec![]);
         }
+        info!("Rerunning {}", config.samples.join(", "));
     }
 
-    Ok(())
+    let hooks = default_hooks(&config);
+    Ok(run_with(config, &hooks)?)
+}
+
+// --------------------------------------------------
+/// Builds the notifiers and reporters requested on the command line
+pub fn default_hooks(config: &Config) -> Hooks {
+    let mut hooks = Hooks::new();
+    hooks.add_notifier(Box::new(ConsoleNotifier));
+    for url in &config.webhooks {
+        hooks.add_notifier(Box::new(WebhookNotifier {
+            url: url.to_string(),
+        }));
+    }
+    if let Some(to) = &config.notify_email {
+        hooks.add_notifier(Box::new(EmailNotifier {
+            to: to.to_string(),
+            smtp_url: config.smtp_url.clone(),
+            smtp_login: config.smtp_login.clone(),
+            from: config.email_from.clone(),
+        }));
+    }
+
+    // Every run ends with the status of each sample
+    hooks.add_reporter(Box::new(ConsoleReporter));
+
+    for report in &config.reports {
+        match report.as_str() {
+            "json" => hooks.add_reporter(Box::new(JsonReporter {
+                path: config.out_dir.join("report.json"),
+            })),
+            "html" => hooks.add_reporter(Box::new(HtmlReporter {
+                path: config.out_dir.join("report.html"),
+            })),
+            _ => &mut hooks,
+        };
+    }
+
+    hooks
+}
+
+// --------------------------------------------------
+/// Runs the batch, sending progress and the final summary to `hooks`, and
+/// returns what became of each sample
+pub fn run_with(
+    config: Config,
+    hooks: &Hooks,
+) -> Result<Vec<SampleResult>, RunMegahitError> {
+    let outer = logging::forward_warnings(hooks.progress_handlers());
+    let results = run_batch(config, hooks);
+    logging::forward_warnings(outer);
+    if let Err(e) = &results {
+        hooks.notify(&Event::BatchAborted {
+            error: e.to_string(),
+        });
+    }
+    Ok(results?)
+}
+
+// --------------------------------------------------
+fn run_batch(config: Config, hooks: &Hooks) -> MyResult<Vec<SampleResult>> {
+    if config.executor == "parallel" && config.retries > 0 {
+        return Err(From::from("--retries requires --executor native"));
+    }
+
+    if has_stage_jobs(&config) && !runs_here(&config) {
+        return Err(From::from(format!(
+            "Stages {} run on this machine, so they cannot be combined with \
+             --scheduler, --nodes, --docker or --singularity",
+            config
+                .stages
+                .iter()
+                .filter(|stage| !matches!(
+                    stage,
+                    Stage::Assemble | Stage::Stats
+                ))
+                .map(|stage| stage.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )));
+    }
+
+    if config.slurm_array && config.scheduler != "slurm" {
+        return Err(From::from("--slurm_array requires --scheduler slurm"));
+    }
+
+    if config.executor == "parallel" && config.stream_logs {
+        return Err(From::from("--stream_logs requires --executor native"));
+    }
+
+    if !config.nodes.is_empty()
+        && (config.executor == "parallel" || config.scheduler != "local")
+    {
+        return Err(From::from(
+            "--nodes requires --executor native or ssh and --scheduler local",
+        ));
+    }
+
+    if config.executor == "ssh" && config.nodes.is_empty() {
+        return Err(From::from("--executor ssh requires --nodes"));
+    }
+
+    if config.adaptive_concurrency
+        && (config.executor != "native"
+            || config.scheduler != "local"
+            || !config.nodes.is_empty())
+    {
+        return Err(From::from(
+            "--adaptive_concurrency requires the native executor on this \
+             machine",
+        ));
+    }
+
+    if config.cap_memory
+        && (config.executor != "native"
+            || config.scheduler != "local"
+            || !config.nodes.is_empty()
+            || config.docker.is_some())
+    {
+        return Err(From::from(
+            "--cap_memory requires the native executor on this machine \
+             and no Docker",
+        ));
+    }
+
+    if (config.docker.is_some() || config.singularity.is_some())
+        && (!config.nodes.is_empty() || config.scheduler == "aws-batch")
+    {
+        return Err(From::from(
+            "Containers cannot be combined with --nodes or AWS Batch",
+        ));
+    }
+
+    let samples = discover_samples(&config)?;
+    // For verify to check again, before the stages replace any
+    let inputs: Vec<String> =
+        samples.iter().flat_map(|s| s.files()).cloned().collect();
+
+    check_compression(&samples)?;
+
+    if config.verify_pairs {
+        verify_pairs(&samples)?;
+    }
+
+    info!(
+        "Processing {} pair, {} single.",
+        samples.iter().filter(|s| !s.fwd.is_empty()).count(),
+        samples.iter().filter(|s| s.fwd.is_empty()).count(),
+    );
+
+    // What the stages before assembly make replaces the reads
+    let (samples, prep) = prep_jobs(&config, samples);
+
+    let samples = if config.co_assembly {
+        vec![merge_samples("co-assembly", &samples)]
+    } else {
+        group_samples(samples)
+    };
+
+    let mut jobs = make_jobs(&config, &samples)?;
+    schedule_jobs(&mut jobs, &samples, &config.schedule);
+
+    if let Some(format) = &config.export {
+        if !config.sweep.is_empty() {
+            return Err(From::from("--sweep cannot be exported"));
+        }
+
+        if has_stage_jobs(&config) {
+            return Err(From::from(
+                "Stages other than assemble cannot be exported",
+            ));
+        }
+
+        let dir = export::export(
+            format,
+            &export::Plan {
+                samples: &samples,
+                options: &megahit_options(&config),
+                out_dir: &config.out_dir,
+                jobs: &jobs,
+                num_concurrent_jobs: config.num_concurrent_jobs,
+                launcher_nodes: config.launcher_nodes,
+                job_timeout: config.job_timeout,
+            },
+        )?;
+        info!("Exported the batch, see \"{}\"", dir.display());
+        return Ok(vec![]);
+    }
+
+    if config.plan {
+        print!("{}", job_plan(&config, &jobs)?);
+        return Ok(vec![]);
+    }
+
+    let scheduler = make_scheduler(&config)?;
+
+    if config.dry_run {
+        let post =
+            post_jobs(&config, &samples, &jobs.iter().collect::<Vec<_>>());
+        for job in prep.iter().chain(&jobs).chain(&post) {
+            println!("# {} => {}", job.sample, job.out_dir.display());
+            println!("{}", job.command_line());
+        }
+
+        let plan_dir = write_plan(
+            &[
+                ("prep_jobs.txt", &prep[..]),
+                ("jobs.txt", &jobs[..]),
+                ("post_jobs.txt", &post[..]),
+            ],
+            &config.out_dir,
+            config.num_concurrent_jobs,
+            &config.on_failure,
+        )?;
+        fs::write(plan_dir.join("plan.json"), job_plan(&config, &jobs)?)?;
+        if let Some(scheduler) = &scheduler {
+            let dir = plan_dir.join(scheduler.name());
+            let written =
+                write_job_scripts(&config, scheduler.as_ref(), &jobs, &dir)?;
+            if let JobScripts::Each(scripts) = written {
+                scheduler.write_requests(
+                    &scripts,
+                    &job_resources(&config),
+                    &dir,
+                )?;
+            }
+        }
+        info!("Dry run, see plan in \"{}\"", plan_dir.display());
+        return Ok(vec![]);
+    }
+
+    for program in stage_programs(&config) {
+        if megahit::locate(&program).is_none() {
+            return Err(Box::new(RunMegahitError::SpawnFailed {
+                program,
+                reason: "not found".to_string(),
+            }));
+        }
+    }
+
+    // A missing or broken megahit would otherwise fail every job
+    let megahit_version = if runs_here(&config) {
+        let (path, version) = megahit::check(&config.megahit)?;
+        info!("Using {} ({})", version, path.display());
+        Some(version)
+    } else {
+        None
+    };
+
+    // Fit the options to the megahit that will run them
+    if let Some(version) =
+        megahit_version.as_deref().and_then(megahit::parse_version)
+    {
+        let mut notes: Vec<String> = vec![];
+        for job in jobs.iter_mut() {
+            let (args, job_notes) = megahit::gate(version, &job.args)?;
+            job.args = args;
+            notes.extend(job_notes);
+        }
+        notes.sort();
+        notes.dedup();
+        for note in notes {
+            warn!("{}", note);
+        }
+    }
+
+    for job in &jobs {
+        debug!(
+            event = "job",
+            sample = job.sample.as_str();
+            "{}: {}", job.sample, job.command_line()
+        );
+    }
+
+    let input_bytes = samples
+        .iter()
+        .flat_map(|s| s.files())
+        .filter_map(|f| fs::metadata(f).ok())
+        .map(|meta| meta.len())
+        .sum();
+    disk::check_out_dir(&config.out_dir, input_bytes, config.force)?;
+
+    // Samples finished by an earlier, interrupted run of this batch
+    let state_path = config.out_dir.join(state::STATE_FILE);
+    let mut done: Vec<JobResult> = vec![];
+    if config.resume {
+        let previous = state::State::load(&state_path)?;
+        jobs.retain(|job| match previous.completed(job) {
+            Some(res) => {
+                done.push(JobResult {
+                    skipped: true,
+                    ..res.clone()
+                });
+                false
+            }
+            _ => true,
+        });
+
+        if !done.is_empty() {
+            info!(
+                "Skipping {} completed sample{}",
+                done.len(),
+                if done.len() == 1 { "" } else { "s" }
+            );
+        }
+    }
+
+    // A rerun keeps the rest of the batch in its state, reports, and
+    // output manifest
+    if config.action == Action::RerunFailed {
+        let previous = state::State::load(&state_path)?;
+        done.extend(
+            previous
+                .samples
+                .values()
+                .filter(|entry| entry.status == state::Status::Completed)
+                .filter_map(|entry| entry.result.clone())
+                .filter(|res| !config.samples.contains(&res.sample))
+                .map(|res| JobResult {
+                    skipped: true,
+                    ..res
+                }),
+        );
+    }
+
+    if config.confirm && !confirm(&config, &samples, &jobs)? {
+        info!("Nothing was run");
+        return Ok(vec![]);
+    }
+
+    fs::create_dir_all(&config.out_dir)?;
+    rerun::save_invocation(&config.out_dir, &public_args(&config.args))?;
+    let mut provenance =
+        provenance::Provenance::new(&config, megahit_version.clone())?;
+    provenance.write(&config.out_dir)?;
+
+    // A sample whose reads could not be prepared isn't assembled
+    let mut prep_failed: Vec<JobResult> = vec![];
+    if !prep.is_empty() {
+        info!(
+            "Preparing the reads of {} sample{}",
+            prep.len(),
+            if prep.len() == 1 { "" } else { "s" }
+        );
+    }
+    for res in run_local(&config, &prep, config.retries) {
+        if res.exit_code == Some(0) {
+            continue;
+        }
+        warn!(
+            "{} was not assembled, preparing its reads {}",
+            res.sample,
+            res.status()
+        );
+        jobs.retain(|job| {
+            !job.read_files()
+                .iter()
+                .any(|file| Path::new(file).starts_with(&res.out_dir))
+        });
+        prep_failed.push(res);
+    }
+
+    // megahit won't start in the output of the failed attempt
+    if config.action == Action::RerunFailed {
+        for job in jobs.iter().filter(|job| !job.is_continuation()) {
+            if job.out_dir.is_dir() {
+                fs::remove_dir_all(&job.out_dir)
+                    .map_err(|e| format!("{}: {}", job.out_dir.display(), e))?;
+            }
+        }
+    }
+
+    let submitted = match &scheduler {
+        Some(scheduler) if !jobs.is_empty() => {
+            match submit_scheduler(&config, scheduler.as_ref(), &jobs)? {
+                Some(ids) => ids,
+                _ => return Ok(vec![]),
+            }
+        }
+        _ => vec![],
+    };
+
+    let state = state::StateFile::create(&state_path, &jobs, &done)?;
+    let record = |res: &JobResult| {
+        hooks.job_finished(res);
+        if let Err(e) = state.record(res) {
+            warn!("Cannot update {}: {}", state_path.display(), e);
+        }
+        debug!(
+            event = "job_end",
+            sample = res.sample.as_str(),
+            status = res.status(),
+            exit_code = res.exit_code,
+            runtime_secs = res.runtime_secs,
+            attempts = res.attempts;
+            "{} {} in {}",
+            res.sample,
+            res.status(),
+            report::format_secs(res.runtime_secs)
+        );
+
+        if res.exit_code != Some(0) && !res.interrupted {
+            hooks.notify(&Event::SampleFailed {
+                sample: res.sample.to_string(),
+                status: res.status().to_string(),
+                exit_code: res.exit_code,
+            });
+        }
+    };
+
+    let executor =
+        make_executor(&config, &samples, &jobs, scheduler, submitted)?;
+    hooks.notify(&Event::BatchStarted {
+        num_jobs: jobs.len(),
+    });
+
+    let start = Instant::now();
+    let mut results = if jobs.is_empty() {
+        vec![]
+    } else {
+        executor.run(
+            &jobs,
+            &|job| hooks.job_started(&job.sample, &job.out_dir),
+            &record,
+        )?
+    };
+
+    // The stages after assembly go on from each new assembly
+    let assembled: Vec<&Job> = jobs
+        .iter()
+        .filter(|job| {
+            results
+                .iter()
+                .any(|res| res.sample == job.sample && res.exit_code == Some(0))
+        })
+        .collect();
+    let post = post_jobs(&config, &samples, &assembled);
+    if !post.is_empty() {
+        info!(
+            "Running the stages after assembly for {} sample{}",
+            post.len(),
+            if post.len() == 1 { "" } else { "s" }
+        );
+    }
+    // Without retries, which would clear the assembly
+    for res in run_local(&config, &post, 0) {
+        if res.exit_code != Some(0) {
+            warn!(
+                "{}: the stages after assembly {}, see \"{}\"",
+                res.sample,
+                res.status(),
+                res.out_dir.join(POST_LOG).display()
+            );
+        }
+    }
+
+    // Report the samples in their original order, skipped ones included
+    results.extend(done);
+    results.extend(prep_failed);
+    results
+        .sort_by_key(|res| samples.iter().position(|s| s.name == res.sample));
+
+    let summary = RunSummary {
+        metrics: stage_metrics(&config, &results)?,
+        results,
+        elapsed_secs: start.elapsed().as_secs_f64(),
+        megahit_version,
+    };
+
+    hooks.notify(&Event::BatchFinished {
+        num_ok: summary.num_ok(),
+        num_failed: summary.num_failed(),
+        elapsed_secs: summary.elapsed_secs,
+        failed: summary
+            .results
+            .iter()
+            .filter(|res| res.exit_code != Some(0))
+            .map(|res| res.sample.to_string())
+            .collect(),
+        interrupted: executor::interrupted()
+            || config
+                .cancel
+                .as_ref()
+                .is_some_and(|cancel| cancel.load(Ordering::SeqCst)),
+    });
+    hooks.report(&summary)?;
+    provenance.finish();
+    provenance.write(&config.out_dir)?;
+    verify::write_output_manifest(&config.out_dir, &summary.results)?;
+    if let Some(manifest) = &config.checksums {
+        verify::write_input_manifest(&config.out_dir, manifest, &inputs)?;
+    }
+    rerun::write_failed(&config.out_dir, &summary.results)?;
+    if !config.sweep.is_empty() {
+        let table = config.out_dir.join(sweep::TABLE_FILE);
+        sweep::write_table(&table, &config.sweep, &summary.results)?;
+        info!("Compare the parameter sets in \"{}\"", table.display());
+    }
+    report::write_timings(
+        &config.out_dir.join(report::TIMINGS_FILE),
+        &summary.results,
+        &samples
+            .iter()
+            .map(|sample| (sample.name.as_str(), input_size(sample)))
+            .collect(),
+    )?;
+
+    Ok(summary
+        .results
+        .iter()
+        .map(|res| sample_result(res, &jobs, &samples))
+        .collect())
+}
+
+// --------------------------------------------------
+/// The samples of the query or manifest, paired up and narrowed to the
+/// ones the batch is to run
+fn discover_samples(config: &Config) -> MyResult<Vec<Sample>> {
+    let mut samples = match &config.manifest {
+        Some(manifest) => manifest::read_samples(manifest)?,
+        _ => {
+            let files = find_files(&config.query, &config.extensions)?;
+            let (pairs, singles) = classify(&files)?;
+            to_samples(pairs, singles)
+        }
+    };
+
+    if samples.is_empty() {
+        return Err(Box::new(RunMegahitError::NoInputs {
+            query: config.query.clone(),
+        }));
+    }
+
+    if let Some(manifest) = &config.checksums {
+        let files: Vec<String> =
+            samples.iter().flat_map(|s| s.files()).cloned().collect();
+        let bad_files =
+            verify_checksums(&files, manifest, config.skip_bad_checksums)?;
+        samples.retain(|s| !s.files().any(|f| bad_files.contains(f)));
+    }
+
+    if config.attach_orphans {
+        samples = attach_orphans(samples);
+    }
+
+    if config.merge_lanes {
+        samples = merge_lanes(samples);
+    }
+
+    let names: Vec<&String> = config.skip_stages.keys().collect();
+    check_sample_names(&samples, &names, "--skip_stage")?;
+
+    select_samples(samples, &config.samples, config.limit)
+}
+
+// --------------------------------------------------
+/// What became of the job's sample, with the reads it was given
+fn sample_result(
+    res: &JobResult,
+    jobs: &[Job],
+    samples: &[Sample],
+) -> SampleResult {
+    let inputs: Vec<PathBuf> = match jobs
+        .iter()
+        .find(|job| job.sample == res.sample)
+    {
+        Some(job) => job.read_files().into_iter().map(PathBuf::from).collect(),
+        _ => samples
+            .iter()
+            .filter(|sample| sample.name == res.sample)
+            .flat_map(|sample| sample.files())
+            .map(PathBuf::from)
+            .collect(),
+    };
+    let contigs = res.out_dir.join("final.contigs.fa");
+
+    SampleResult {
+        sample: res.sample.to_string(),
+        inputs,
+        out_dir: res.out_dir.clone(),
+        status: res.status(),
+        exit_code: res.exit_code,
+        duration: Duration::from_secs_f64(res.runtime_secs),
+        contigs: Some(contigs).filter(|path| {
+            res.exit_code == Some(0) && !res.timed_out && path.is_file()
+        }),
+    }
+}
+
+// --------------------------------------------------
+/// Why a batch that ran didn't assemble every sample, if it didn't
+fn check_results(out_dir: &Path, results: &[SampleResult]) -> MyResult<()> {
+    if results.is_empty() {
+        return Ok(());
+    }
+
+    let ok = results.iter().filter(|res| res.is_ok()).count();
+    if executor::interrupted() {
+        let unfinished: Vec<&str> = results
+            .iter()
+            .filter(|res| !res.is_ok())
+            .map(|res| res.sample.as_str())
+            .collect();
+        warn!(
+            "Interrupted with {} of {} sample{} completed, unfinished: {}",
+            ok,
+            results.len(),
+            if results.len() == 1 { "" } else { "s" },
+            unfinished.join(", ")
+        );
+        return Err(Box::new(RunMegahitError::Interrupted {
+            out_dir: out_dir.to_path_buf(),
+        }));
+    }
+
+    // One failed job is reported as itself, e.g., a single-sample batch
+    let failed: Vec<&SampleResult> = results
+        .iter()
+        .filter(|res| matches!(res.status, "failed" | "timeout"))
+        .collect();
+    let num_failed = results.len() - ok;
+    if let ([res], 1) = (failed.as_slice(), num_failed) {
+        return Err(Box::new(RunMegahitError::JobFailed {
+            sample: res.sample.clone(),
+            code: res.exit_code.filter(|_| res.status != "timeout"),
+            out_dir: res.out_dir.clone(),
+        }));
+    }
+
+    if num_failed > 0 {
+        return Err(Box::new(RunMegahitError::Failed {
+            failed: num_failed,
+            total: results.len(),
+            out_dir: out_dir.to_path_buf(),
+            failed_file: out_dir.join(rerun::FAILED_FILE),
+        }));
+    }
+
+    info!("Done, see output in \"{}\"", out_dir.display());
+
+    Ok(())
+}
+
+// --------------------------------------------------
+/// The executor for the batch: the scheduler the jobs were submitted to,
+/// or --executor
+fn make_executor(
+    config: &Config,
+    samples: &[Sample],
+    jobs: &[Job],
+    scheduler: Option<Box<dyn scheduler::Scheduler>>,
+    submitted: Vec<(String, String)>,
+) -> MyResult<Box<dyn Executor>> {
+    if let Some(scheduler) = scheduler {
+        return Ok(Box::new(scheduler::Cluster {
+            scheduler,
+            submitted,
+        }));
+    }
+
+    if config.executor == "parallel" {
+        return Ok(Box::new(executor::GnuParallel {
+            num_concurrent_jobs: config.num_concurrent_jobs,
+            on_failure: config.on_failure,
+            timeout: config.job_timeout,
+            joblog: config.out_dir.join("joblog.tsv"),
+        }));
+    }
+
+    let memory_budget = if config.adaptive_concurrency {
+        Some(host::Host::probe().memory_budget().ok_or(
+            "Cannot tell this machine's memory for --adaptive_concurrency",
+        )?)
+    } else {
+        None
+    };
+    let memory_cap = if config.cap_memory {
+        let cap = limits::MemoryCap::new(job_cap(config)?);
+        info!("Capping memory at {}", cap.describe());
+        Some(cap)
+    } else {
+        None
+    };
+    let estimates: HashMap<&str, u64> = samples
+        .iter()
+        .map(|sample| (sample.name.as_str(), estimate_memory(sample)))
+        .collect();
+
+    let opts = executor::Options {
+        num_concurrent_jobs: config.num_concurrent_jobs,
+        on_failure: config.on_failure,
+        retries: config.retries,
+        retry_backoff: Duration::from_secs(config.retry_backoff),
+        timeout: config.job_timeout,
+        show_progress: config.progress,
+        stream_logs: config.stream_logs,
+        log_interval: Duration::from_secs(config.log_interval),
+        nodes: config.nodes.clone(),
+        memory_budget,
+        job_memory: jobs
+            .iter()
+            .map(|job| estimates.get(job.sample.as_str()).copied().unwrap_or(0))
+            .collect(),
+        memory_cap,
+        cancel: config.cancel.clone(),
+    };
+
+    if config.nodes.is_empty() {
+        Ok(Box::new(executor::LocalParallel { opts }))
+    } else {
+        Ok(Box::new(executor::Ssh { opts }))
+    }
+}
+
+// --------------------------------------------------
+/// The scheduler of --scheduler, or none to run the jobs from here
+fn make_scheduler(
+    config: &Config,
+) -> MyResult<Option<Box<dyn scheduler::Scheduler>>> {
+    match config.scheduler.as_str() {
+        "local" => Ok(None),
+        name => Ok(Some(scheduler::by_name(
+            name,
+            &scheduler::Options {
+                sge_pe: config.sge_pe.to_string(),
+                s3_prefix: config.s3_prefix.clone(),
+                aws_queue: config.aws_queue.clone(),
+                aws_image: config.aws_image.to_string(),
+            },
+        )?)),
+    }
+}
+
+// --------------------------------------------------
+/// What each scheduler job asks for
+fn job_resources(config: &Config) -> scheduler::Resources {
+    scheduler::Resources::new(
+        cpu_threads(config).unwrap_or(scheduler::DEFAULT_CPUS),
+        job_memory(config),
+        config.job_timeout,
+    )
+}
+
+// --------------------------------------------------
+/// Writes the batch scripts for the jobs to `dir`: one per job, or one
+/// array job with --slurm_array
+fn write_job_scripts(
+    config: &Config,
+    scheduler: &dyn scheduler::Scheduler,
+    jobs: &[Job],
+    dir: &Path,
+) -> MyResult<JobScripts> {
+    let resources = job_resources(config);
+    if config.slurm_array {
+        Ok(JobScripts::Array(scheduler::write_slurm_array(
+            jobs,
+            dir,
+            &resources,
+            config.num_concurrent_jobs,
+        )?))
+    } else {
+        Ok(JobScripts::Each(scheduler::write_scripts(
+            scheduler, jobs, dir, &resources,
+        )?))
+    }
+}
+
+// --------------------------------------------------
+/// Writes batch scripts for the jobs and, with --submit, queues them and
+/// returns the (sample, job ID) pairs to track
+fn submit_scheduler(
+    config: &Config,
+    scheduler: &dyn scheduler::Scheduler,
+    jobs: &[Job],
+) -> MyResult<Option<Vec<(String, String)>>> {
+    let script_dir = config.out_dir.join(scheduler.name());
+
+    let ids = match write_job_scripts(config, scheduler, jobs, &script_dir)? {
+        JobScripts::Array(script) => {
+            if !config.submit {
+                info!(
+                    "Wrote \"{}\", submit with --submit or \"sbatch\"",
+                    script.display()
+                );
+                return Ok(None);
+            }
+            scheduler::submit_slurm_array(&script, jobs)?
+        }
+        JobScripts::Each(scripts) => {
+            if !config.submit {
+                info!(
+                    "Wrote {} {} script{} to \"{}\", submit them with --submit",
+                    scripts.len(),
+                    scheduler.name(),
+                    if scripts.len() == 1 { "" } else { "s" },
+                    script_dir.display()
+                );
+                return Ok(None);
+            }
+            scheduler.prepare(jobs, &job_resources(config))?;
+            scheduler::submit_all(scheduler, &scripts)?
+        }
+    };
+
+    for (sample, id) in &ids {
+        println!("{}\t{}", sample, id);
+    }
+
+    Ok(Some(ids))
+}
+
+// --------------------------------------------------
+/// The size and contiguity of each finished assembly in out_dir
+fn print_stats(out_dir: &Path) -> MyResult<()> {
+    let assemblies = outputs::assemblies(out_dir)?;
+    if assemblies.is_empty() {
+        return Err(From::from(format!(
+            "No finished assemblies in \"{}\"",
+            out_dir.display()
+        )));
+    }
+
+    for (i, (sample, dir)) in assemblies.iter().enumerate() {
+        let stats = stats::assembly_stats(&dir.join("final.contigs.fa"))?;
+        let (names, values): (Vec<_>, Vec<_>) =
+            stats.columns().into_iter().unzip();
+        if i == 0 {
+            println!("sample\t{}", names.join("\t"));
+        }
+        println!("{}\t{}", sample, values.join("\t"));
+    }
+
+    Ok(())
+}
+
+// --------------------------------------------------
+/// Prints every discrepancy in a finished out_dir
+fn verify_out_dir(out_dir: &Path) -> MyResult<()> {
+    let problems = verify::verify(out_dir)?;
+
+    if problems.is_empty() {
+        println!("\"{}\" verified OK", out_dir.display());
+        return Ok(());
+    }
+
+    for problem in &problems {
+        println!("{}", problem);
+    }
+
+    Err(From::from(format!(
+        "Found {} problem{} in \"{}\"",
+        problems.len(),
+        if problems.len() == 1 { "" } else { "s" },
+        out_dir.display()
+    )))
+}
+
+// --------------------------------------------------
+/// The kmer sizes of --k_list, which megahit wants odd, ascending, and no
+/// more than 255
+fn parse_k_list(val: &str) -> MyResult<Vec<u32>> {
+    let mut k_list: Vec<u32> = vec![];
+    for k in val.split(',').map(str::trim) {
+        let k = match k.parse::<u32>() {
+            Ok(k) if k % 2 == 1 && k <= 255 => k,
+            _ => {
+                return Err(From::from(format!(
+                    "--k_list \"{}\" must be odd numbers up to 255",
+                    val
+                )))
+            }
+        };
+        if k_list.last().is_some_and(|&last| k <= last) {
+            return Err(From::from(format!(
+                "--k_list \"{}\" must be in ascending order",
+                val
+            )));
+        }
+        k_list.push(k);
+    }
+
+    Ok(k_list)
+}
+
+// --------------------------------------------------
+/// The k-mer options as megahit would reject them, so a bad value stops
+/// the batch before any job is dispatched rather than failing every job
+fn check_kmers(
+    k_min: Option<u32>,
+    k_max: Option<u32>,
+    k_step: Option<u32>,
+    min_count: Option<u32>,
+) -> MyResult<()> {
+    for (name, k) in [("k_min", k_min), ("k_max", k_max)] {
+        if let Some(k) = k {
+            if k % 2 == 0 || k > 255 {
+                return Err(Box::new(RunMegahitError::InvalidK(format!(
+                    "--{} {} must be an odd number no more than 255 (e.g., {})",
+                    name,
+                    k,
+                    (k | 1).min(255)
+                ))));
+            }
+        }
+    }
+
+    if let (Some(k_min), Some(k_max)) = (k_min, k_max) {
+        if k_min >= k_max {
+            return Err(Box::new(RunMegahitError::InvalidK(format!(
+                "--k_min {} must be less than --k_max {}",
+                k_min, k_max
+            ))));
+        }
+    }
+
+    if let Some(k_step) = k_step {
+        if k_step % 2 == 1 || k_step > 28 {
+            return Err(Box::new(RunMegahitError::InvalidK(format!(
+                "--k_step {} must be an even number no more than 28 (e.g., {})",
+                k_step,
+                (k_step + k_step % 2).min(28)
+            ))));
+        }
+    }
+
+    if min_count == Some(0) {
+        return Err(From::from("--min_count must be at least 1"));
+    }
+
+    Ok(())
+}
+
+// --------------------------------------------------
+/// The "l,s" of --merge_level, a positive integer length and a similarity
+/// from 0 to 1
+fn parse_merge_level(val: &str) -> MyResult<String> {
+    let bad = || {
+        From::from(format!(
+            "--merge_level \"{}\" must look like l,s (e.g., 20,0.95) with \
+             l a positive integer and s from 0 to 1",
+            val
+        ))
+    };
+    let (len, sim) = val.split_once(',').ok_or_else(bad)?;
+    let len = len.trim().parse::<u32>().map_err(|_| bad())?;
+    let sim = sim.trim().parse::<f32>().map_err(|_| bad())?;
+    if len == 0 || !(0. ..=1.).contains(&sim) {
+        return Err(bad());
+    }
+
+    Ok(format!("{},{}", len, sim))
+}
+
+// --------------------------------------------------
+/// The config with the megahit options it makes, as TOML or JSON
+fn show_config(config: &Config, format: &str) -> MyResult<String> {
+    #[derive(Serialize)]
+    struct Settled<'a> {
+        #[serde(flatten)]
+        config: &'a Config,
+        /// What every megahit command gets before the sample's reads
+        megahit_options: Vec<String>,
+    }
+
+    let settled = Settled {
+        config,
+        megahit_options: megahit_options(config),
+    };
+    Ok(match format {
+        "json" => serde_json::to_string_pretty(&settled)? + "\n",
+        _ => toml::to_string(&settled)?,
+    })
+}
+
+// --------------------------------------------------
+/// Each sample with the files it was given, as TSV or JSON, for checking
+/// how the reads were paired before running anything
+fn list_samples(samples: &[Sample], format: &str) -> MyResult<String> {
+    #[derive(Serialize)]
+    struct Listed<'a> {
+        sample: &'a str,
+        r1: &'a [String],
+        r2: &'a [String],
+        single: &'a [String],
+        group: Option<&'a str>,
+    }
+
+    let listed: Vec<Listed> = samples
+        .iter()
+        .map(|sample| Listed {
+            sample: &sample.name,
+            r1: &sample.fwd,
+            r2: &sample.rev,
+            single: &sample.single,
+            group: sample.group.as_deref(),
+        })
+        .collect();
+
+    if format == "json" {
+        return Ok(serde_json::to_string_pretty(&listed)? + "\n");
+    }
+
+    let mut out = String::from("sample\tr1\tr2\tsingle\tgroup\n");
+    for row in listed {
+        out += &format!(
+            "{}\t{}\t{}\t{}\t{}\n",
+            row.sample,
+            row.r1.join(","),
+            row.r2.join(","),
+            row.single.join(","),
+            row.group.unwrap_or_default()
+        );
+    }
+    Ok(out)
+}
+
+// --------------------------------------------------
+fn profile_args<S: Serializer>(
+    profile: &[config_file::ProfileOption],
+    ser: S,
+) -> Result<S::Ok, S::Error> {
+    config_file::with_profile(profile, &[]).serialize(ser)
+}
+
+// --------------------------------------------------
+fn redacted_url<S: Serializer>(
+    url: &Option<String>,
+    ser: S,
+) -> Result<S::Ok, S::Error> {
+    url.as_deref().map(report::redact_url).serialize(ser)
+}
+
+// --------------------------------------------------
+fn redacted_urls<S: Serializer>(
+    urls: &[String],
+    ser: S,
+) -> Result<S::Ok, S::Error> {
+    urls.iter()
+        .map(|url| report::redact_url(url))
+        .collect::<Vec<_>>()
+        .serialize(ser)
+}
+
+// --------------------------------------------------
+fn lowercase<T: fmt::Display, S: Serializer>(
+    val: &T,
+    ser: S,
+) -> Result<S::Ok, S::Error> {
+    ser.collect_str(&val.to_string().to_lowercase())
+}
+
+// --------------------------------------------------
+fn secs<S: Serializer>(
+    val: &Option<Duration>,
+    ser: S,
+) -> Result<S::Ok, S::Error> {
+    val.map(|dur| dur.as_secs()).serialize(ser)
+}
+
+// --------------------------------------------------
+/// The megahit options every sample of the batch shares
+fn megahit_options(config: &Config) -> Vec<String> {
+    let mut args: Vec<String> = vec![];
+
+    if let Some(preset) = &config.preset {
+        args.push("--presets".to_string());
+        args.push(preset.to_string());
+    }
+
+    if let Some(min_count) = config.min_count {
+        args.push("--min-count".to_string());
+        args.push(min_count.to_string());
+    }
+
+    if let Some(k_min) = config.k_min {
+        args.push("--k-min".to_string());
+        args.push(k_min.to_string());
+    }
+
+    if let Some(k_max) = config.k_max {
+        args.push("--k-max".to_string());
+        args.push(k_max.to_string());
+    }
+
+    if let Some(k_step) = config.k_step {
+        args.push("--k-step".to_string());
+        args.push(k_step.to_string());
+    }
+
+    if let Some(k_list) = &config.k_list {
+        args.push("--k-list".to_string());
+        args.push(
+            k_list
+                .iter()
+                .map(|k| k.to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+        );
+    }
+
+    if let Some(min_contig_length) = config.min_contig_length {
+        args.push("--min-contig-len".to_string());
+        args.push(min_contig_length.to_string());
+    }
+
+    if let Some(prune_level) = config.prune_level {
+        args.push("--prune-level".to_string());
+        args.push(prune_level.to_string());
+    }
+
+    if let Some(prune_depth) = config.prune_depth {
+        args.push("--prune-depth".to_string());
+        args.push(prune_depth.to_string());
+    }
+
+    if let Some(bubble_level) = config.bubble_level {
+        args.push("--bubble-level".to_string());
+        args.push(bubble_level.to_string());
+    }
+
+    if let Some(merge_level) = &config.merge_level {
+        args.push("--merge-level".to_string());
+        args.push(merge_level.to_string());
+    }
+
+    if let Some(ratio) = config.low_local_ratio {
+        args.push("--low-local-ratio".to_string());
+        args.push(ratio.to_string());
+    }
+
+    if let Some(rounds) = config.cleaning_rounds {
+        args.push("--cleaning-rounds".to_string());
+        args.push(rounds.to_string());
+    }
+
+    if config.no_local {
+        args.push("--no-local".to_string());
+    }
+
+    if let Some(ratio) = config.disconnect_ratio {
+        args.push("--disconnect-ratio".to_string());
+        args.push(ratio.to_string());
+    }
+
+    if config.kmin_1pass {
+        args.push("--kmin-1pass".to_string());
+    }
+
+    if let Some(tmp_dir) = &config.tmp_dir {
+        args.push("--tmp-dir".to_string());
+        args.push(tmp_dir.display().to_string());
+    }
+
+    if config.keep_tmp_files {
+        args.push("--keep-tmp-files".to_string());
+    }
+
+    if let Some(memory) = job_memory(config) {
+        args.push("--memory".to_string());
+        args.push(memory.to_string());
+    }
+
+    if let Some(threads) = cpu_threads(config) {
+        args.push("--num-cpu-threads".to_string());
+        args.push(threads.to_string());
+    }
+
+    args.extend(config.megahit_args.iter().cloned());
+
+    config_file::with_profile(&config.profile, &args)
+}
+
+// --------------------------------------------------
+fn make_jobs(config: &Config, samples: &[Sample]) -> MyResult<Vec<Job>> {
+    let batch_args = megahit_options(config);
+    // An image has its own megahit
+    let program = if config.docker.is_some() || config.singularity.is_some() {
+        "megahit"
+    } else {
+        &config.megahit
+    };
+    let mut jobs: Vec<Job> = vec![];
+    for (i, sample) in samples.iter().enumerate() {
+        let args = sample.options.apply(&batch_args);
+        if sample.options.k_list.is_some()
+            && config.sweep.iter().any(|set| {
+                set.flags.iter().any(|flag| flag.starts_with("--k-"))
+            })
+        {
+            return Err(From::from(format!(
+                "--sweep cannot vary k_min, k_max, or k_step for sample \"{}\" \
+                 with a k_list in the manifest",
+                sample.name
+            )));
+        }
+
+        let out_dir = config.out_dir.join(&sample.name);
+        let resume = config.resume && is_megahit_dir(&out_dir);
+
+        info!(
+            event = "sample",
+            sample = sample.name.as_str(),
+            kind = if sample.fwd.is_empty() { "single" } else { "pair" },
+            resume = resume;
+            "{:3}: {} {}{}{}",
+            i + 1,
+            if sample.fwd.is_empty() {
+                "Single"
+            } else {
+                "Pair"
+            },
+            sample.name,
+            skip_note(config, &sample.name),
+            if resume { " (resuming)" } else { "" }
+        );
+
+        // megahit takes everything else from the run's options.json
+        if resume {
+            jobs.push(Job {
+                sample: sample.name.to_string(),
+                out_dir: out_dir.clone(),
+                program: program.to_string(),
+                args: vec![
+                    "--continue".to_string(),
+                    "-o".to_string(),
+                    out_dir.display().to_string(),
+                ],
+            });
+            continue;
+        }
+
+        // A sweep assembles the sample once for each parameter set
+        let variants: Vec<(String, PathBuf, Vec<String>)> =
+            if config.sweep.is_empty() {
+                vec![(sample.name.to_string(), out_dir, args.clone())]
+            } else {
+                config
+                    .sweep
+                    .iter()
+                    .map(|set| {
+                        (
+                            format!("{}/{}", set.dir, sample.name),
+                            config.out_dir.join(&set.dir).join(&sample.name),
+                            set.apply(&args),
+                        )
+                    })
+                    .collect()
+            };
+
+        for (name, out_dir, options) in variants {
+            let mut job_args =
+                vec!["-o".to_string(), out_dir.display().to_string()];
+            job_args.extend(options);
+
+            if !sample.fwd.is_empty() {
+                job_args.push("-1".to_string());
+                job_args.push(file_list(&sample.fwd)?);
+                job_args.push("-2".to_string());
+                job_args.push(file_list(&sample.rev)?);
+            }
+
+            if !sample.single.is_empty() {
+                job_args.push("-r".to_string());
+                job_args.push(file_list(&sample.single)?);
+            }
+
+            jobs.push(Job {
+                sample: name,
+                out_dir,
+                program: program.to_string(),
+                args: job_args,
+            });
+        }
+    }
+
+    if config.nice.is_some() || !config.ionice.is_empty() {
+        jobs = jobs
+            .iter()
+            .map(|job| limits::prioritize(job, config.nice, &config.ionice))
+            .collect();
+    }
+
+    if let Some(image) = &config.docker {
+        jobs = jobs
+            .iter()
+            .map(|job| container::docker(job, image))
+            .collect();
+    } else if let Some(image) = &config.singularity {
+        jobs = jobs
+            .iter()
+            .map(|job| {
+                container::apptainer(job, &config.container_engine, image)
+            })
+            .collect();
+    }
+
+    Ok(jobs)
+}
+
+// --------------------------------------------------
+/// The samples with their reads swapped for the ones the stages before
+/// assembly will make in out_dir/work, and a job for each sample that
+/// makes them (unless a resumed batch already has)
+fn prep_jobs(config: &Config, samples: Vec<Sample>) -> (Vec<Sample>, Vec<Job>) {
+    let mut prepared = vec![];
+    let mut jobs = vec![];
+    for sample in samples {
+        let stages = sample_stages(config, &sample.name);
+        let dir = config.out_dir.join(WORK_DIR).join(&sample.name);
+        let mut sample = sample;
+        let mut commands: Vec<Vec<String>> = vec![];
+
+        if stages.contains(&Stage::Trim) {
+            let (trimmed, trim) =
+                trim::plan(&config.trim, &sample, &dir, cpu_threads(config));
+            sample = trimmed;
+            commands.extend(trim);
+        }
+
+        if let (true, Some(index)) =
+            (stages.contains(&Stage::HostRemoval), &config.host_index)
+        {
+            let (kept, remove) = host_removal::plan(
+                &config.host_mapper,
+                index,
+                &sample,
+                &dir,
+                cpu_threads(config),
+            );
+            sample = kept;
+            commands.extend(remove);
+        }
+
+        let made = config.resume
+            && sample.files().all(|file| Path::new(file).is_file());
+        if !commands.is_empty() && !made {
+            jobs.push(chain_job(&sample.name, dir, "log", &commands));
+        }
+        prepared.push(sample);
+    }
+
+    (prepared, jobs)
+}
+
+// --------------------------------------------------
+/// A job for each assembled sample that runs the stages after assembly
+/// in its out_dir
+fn post_jobs(config: &Config, samples: &[Sample], jobs: &[&Job]) -> Vec<Job> {
+    // For the steps run_megahit does itself
+    let exe = env::current_exe()
+        .map_or("run_megahit".to_string(), |exe| exe.display().to_string());
+    let mut post = vec![];
+    for job in jobs {
+        let stages = sample_stages(config, &job.sample);
+        let mut commands: Vec<Vec<String>> = vec![];
+
+        if let (true, Some(sample)) = (
+            stages.contains(&Stage::Coverage),
+            samples.iter().find(|sample| sample.name == job.sample),
+        ) {
+            commands.extend(coverage::commands(
+                &config.coverage,
+                sample,
+                &job.out_dir,
+                cpu_threads(config),
+                &exe,
+            ));
+        }
+
+        if stages.contains(&Stage::Bin) {
+            let depth = job
+                .out_dir
+                .join(coverage::COVERAGE_DIR)
+                .join(coverage::DEPTH_TABLE);
+            commands.extend(binning::command(
+                &job.out_dir,
+                &depth,
+                cpu_threads(config),
+            ));
+        }
+
+        if stages.contains(&Stage::Genes) {
+            commands.push(genes::command(&job.out_dir));
+        }
+
+        if let (true, Some(db)) =
+            (stages.contains(&Stage::Classify), &config.db)
+        {
+            commands.extend(classify::command(
+                &job.out_dir,
+                db,
+                cpu_threads(config),
+            ));
+        }
+
+        if stages.contains(&Stage::Graph) {
+            // Before the assembly is there to look at in a dry run, the
+            // largest k it was asked for
+            let k = match fastg::final_k(&job.out_dir) {
+                Some(k) => Some(k),
+                _ if config.dry_run => Some(
+                    match (&config.k_list, config.k_max) {
+                        (Some(k_list), _) => k_list.iter().max().copied(),
+                        (_, k_max) => k_max,
+                    }
+                    .unwrap_or(DEFAULT_K_MAX),
+                ),
+                _ => None,
+            };
+            match k {
+                Some(k) => commands.push(fastg::command(
+                    &fastg::toolkit(&config.megahit),
+                    &job.out_dir,
+                    k,
+                )),
+                _ => warn!(
+                    "{}: no contigs of any k in \"{}\" to graph, skipping it",
+                    job.sample,
+                    job.out_dir.join(fastg::INTERMEDIATE_DIR).display()
+                ),
+            }
+        }
+
+        if stages.contains(&Stage::Qc) {
+            commands.push(quast::command(
+                &config.quast,
+                &job.out_dir,
+                cpu_threads(config),
+            ));
+        }
+
+        if !commands.is_empty() {
+            post.push(chain_job(
+                &job.sample,
+                job.out_dir.clone(),
+                POST_LOG,
+                &commands,
+            ));
+        }
+    }
+    post
+}
+
+// --------------------------------------------------
+/// What the stages after assembly measured for each assembled sample,
+/// also written side by side into out_dir. The stats stage needs no job,
+/// it is worked out here.
+fn stage_metrics(
+    config: &Config,
+    results: &[JobResult],
+) -> MyResult<BTreeMap<String, Vec<Metric>>> {
+    // Each stage's reader of a sample's out_dir, and the table of the
+    // batch it fills
+    let readers: [(Stage, MetricsReader, &str, &str); 6] = [
+        (
+            Stage::Stats,
+            stats::read_summary,
+            stats::STATS_FILE,
+            "Assembly stats",
+        ),
+        (
+            Stage::Coverage,
+            coverage::read_summary,
+            coverage::SUMMARY_FILE,
+            "Coverage summary",
+        ),
+        (
+            Stage::Bin,
+            binning::read_summary,
+            binning::SUMMARY_FILE,
+            "Binning summary",
+        ),
+        (
+            Stage::Genes,
+            genes::read_summary,
+            genes::SUMMARY_FILE,
+            "Gene summary",
+        ),
+        (
+            Stage::Classify,
+            classify::read_summary,
+            classify::SUMMARY_FILE,
+            "Taxonomy summary",
+        ),
+        (
+            Stage::Qc,
+            quast::read_report,
+            quast::SUMMARY_FILE,
+            "QUAST summary",
+        ),
+    ];
+
+    let mut metrics: BTreeMap<String, Vec<Metric>> = BTreeMap::new();
+    for (stage, read, file, what) in &readers {
+        let mut rows = vec![];
+        for res in results {
+            let ran = res.status() == "ok";
+            if !(ran || res.skipped)
+                || !sample_stages(config, &res.sample).contains(stage)
+            {
+                continue;
+            }
+
+            match read(&res.out_dir) {
+                Ok(found) => {
+                    metrics
+                        .entry(res.sample.clone())
+                        .or_default()
+                        .extend(found.clone());
+                    rows.push((res.sample.clone(), found));
+                }
+                Err(e) if ran => warn!("{}: {}", res.sample, e),
+                _ => (),
+            }
+        }
+
+        if !rows.is_empty() {
+            let path =
+                report::write_metrics(&config.out_dir.join(file), &rows)?;
+            info!("{} in \"{}\"", what, path.display());
+        }
+    }
+
+    Ok(metrics)
+}
+
+// --------------------------------------------------
+/// Whether the batch runs jobs for stages other than assemble, which only
+/// run on this machine (stats needs none)
+fn has_stage_jobs(config: &Config) -> bool {
+    config
+        .stages
+        .iter()
+        .any(|stage| !matches!(stage, Stage::Assemble | Stage::Stats))
+}
+
+// --------------------------------------------------
+/// The programs the stages other than assemble run
+fn stage_programs(config: &Config) -> Vec<String> {
+    let mut programs: Vec<String> = vec![];
+    if config.stages.contains(&Stage::Trim) {
+        programs.push(config.trim.clone());
+    }
+    if config.stages.contains(&Stage::HostRemoval) {
+        programs.extend(
+            host_removal::programs(&config.host_mapper)
+                .into_iter()
+                .map(String::from),
+        );
+    }
+    if config.stages.contains(&Stage::Coverage) {
+        for program in coverage::programs(&config.coverage) {
+            if !programs.iter().any(|p| p == program) {
+                programs.push(program.to_string());
+            }
+        }
+    }
+    if config.stages.contains(&Stage::Bin) {
+        programs.push(config.bin.clone());
+    }
+    if config.stages.contains(&Stage::Genes) {
+        programs.push("prodigal".to_string());
+    }
+    if config.stages.contains(&Stage::Classify) {
+        programs.push(config.classify.clone());
+    }
+    if config.stages.contains(&Stage::Graph) {
+        programs.push(fastg::toolkit(&config.megahit));
+    }
+    if config.stages.contains(&Stage::Qc) {
+        programs.push(quast::program(&config.quast));
+    }
+    programs
+}
+
+// --------------------------------------------------
+/// A job that creates `out_dir` and runs `commands` one after the other,
+/// stopping at the first to fail, with their output in out_dir/`log`. A
+/// lone ">" sends the rest of a command's output to the file after it, and
+/// a lone "|" pipes it to the command after it.
+fn chain_job(
+    sample: &str,
+    out_dir: PathBuf,
+    log: &str,
+    commands: &[Vec<String>],
+) -> Job {
+    let dir = shell_quote(&out_dir.display().to_string());
+    let script: Vec<String> = commands
+        .iter()
+        .map(|cmd| {
+            cmd.iter()
+                .map(|word| {
+                    if word == ">" || word == "|" {
+                        word.to_string()
+                    } else {
+                        shell_quote(word)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect();
+
+    // A pipeline fails with any of its commands only in bash
+    let piped = commands.iter().flatten().any(|word| word == "|");
+    let (program, pipefail) = if piped {
+        ("bash", "set -o pipefail && ")
+    } else {
+        ("sh", "")
+    };
+
+    Job {
+        sample: sample.to_string(),
+        program: program.to_string(),
+        args: vec![
+            "-c".to_string(),
+            format!(
+                "{}mkdir -p {} && {{ {}; }} > {}/{} 2>&1",
+                pipefail,
+                dir,
+                script.join(" && "),
+                dir,
+                log
+            ),
+        ],
+        out_dir,
+    }
+}
+
+// --------------------------------------------------
+/// Runs the jobs of the stages other than assemble on this machine and
+/// returns how each ended
+fn run_local(config: &Config, jobs: &[Job], retries: u32) -> Vec<JobResult> {
+    if jobs.is_empty() {
+        return vec![];
+    }
+
+    let opts = executor::Options {
+        num_concurrent_jobs: config.num_concurrent_jobs,
+        on_failure: config.on_failure,
+        retries,
+        retry_backoff: Duration::from_secs(config.retry_backoff),
+        timeout: config.job_timeout,
+        show_progress: config.progress,
+        cancel: config.cancel.clone(),
+        ..Default::default()
+    };
+    executor::run_native(jobs, &opts, &|_| (), &|_| ())
+}
+
+// --------------------------------------------------
+/// Whether megahit runs on this machine outside of a container
+fn runs_here(config: &Config) -> bool {
+    config.scheduler == "local"
+        && config.nodes.is_empty()
+        && config.docker.is_none()
+        && config.singularity.is_none()
+}
+
+// --------------------------------------------------
+/// Memory for each megahit job: --memory_per_job, or --memory shared among
+/// the jobs that run at once with --split_memory (bytes or a fraction of
+/// the machine alike), or --memory as is. An SSH node only runs its own
+/// slots.
+fn job_memory(config: &Config) -> Option<f64> {
+    if config.memory_per_job.is_some() {
+        return config.memory_per_job;
+    }
+
+    let memory = config.memory?;
+    if !config.split_memory {
+        return Some(memory);
+    }
+
+    let jobs = match config.nodes.iter().map(|node| node.slots).max() {
+        Some(slots) => slots,
+        _ => config.num_concurrent_jobs.max(1),
+    };
+    Some(split_memory(memory, jobs))
+}
+
+// --------------------------------------------------
+/// The bytes each job may use before --cap_memory stops it: its memory
+/// share, with a fraction taken of this machine's RAM
+fn job_cap(config: &Config) -> MyResult<u64> {
+    let memory = job_memory(config).unwrap_or(1.);
+    if memory > 1. {
+        return Ok(memory as u64);
+    }
+
+    let ram = host::Host::probe()
+        .memory
+        .ok_or("Cannot tell this machine's memory for --cap_memory")?;
+    Ok((ram as f64 * memory) as u64)
+}
+
+// --------------------------------------------------
+/// Splits megahit's --memory among `jobs`, rounding bytes down
+fn split_memory(memory: f64, jobs: u32) -> f64 {
+    let share = memory / jobs as f64;
+    if memory > 1. {
+        share.floor()
+    } else {
+        share
+    }
+}
+
+// --------------------------------------------------
+/// Shows what the batch will run and asks on the terminal whether to
+fn confirm(
+    config: &Config,
+    samples: &[Sample],
+    jobs: &[Job],
+) -> MyResult<bool> {
+    if !io::stdin().is_terminal() {
+        return Err(From::from(
+            "--confirm needs a terminal to ask on, give --yes to run anyway",
+        ));
+    }
+
+    eprint!(
+        "{}Run {} job{}? [y/N] ",
+        preview(config, samples, jobs),
+        jobs.len(),
+        if jobs.len() == 1 { "" } else { "s" }
+    );
+    io::stderr().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+// --------------------------------------------------
+/// The samples with their reads, then how the jobs will run
+fn preview(config: &Config, samples: &[Sample], jobs: &[Job]) -> String {
+    let width = samples
+        .iter()
+        .map(|sample| sample.name.len())
+        .chain(Some("Sample".len()))
+        .max()
+        .unwrap_or(0);
+
+    let mut lines = vec![format!("{:width$}  {:6}  Reads", "Sample", "Type")];
+    for sample in samples {
+        lines.push(format!(
+            "{:width$}  {:6}  {}",
+            sample.name,
+            if sample.fwd.is_empty() {
+                "single"
+            } else {
+                "pair"
+            },
+            sample
+                .files()
+                .map(|file| file.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+
+    let threads = match cpu_threads(config) {
+        Some(threads) => {
+            format!("{} thread{}", threads, if threads == 1 { "" } else { "s" })
+        }
+        _ => "megahit's threads".to_string(),
+    };
+    let memory = match job_memory(config) {
+        Some(memory) if memory > 1. => disk::format_bytes(memory as u64),
+        Some(memory) => format!("{}% of memory", memory * 100.),
+        _ => "megahit's memory".to_string(),
+    };
+    let place = if config.scheduler != "local" {
+        format!("through {}", config.scheduler)
+    } else if !config.nodes.is_empty() {
+        format!("on {} SSH nodes", config.nodes.len())
+    } else {
+        "on this machine".to_string()
+    };
+
+    lines.push("".to_string());
+    lines.push(format!(
+        "{} job{}, {} at a time {}, with {} and {} each",
+        jobs.len(),
+        if jobs.len() == 1 { "" } else { "s" },
+        config.num_concurrent_jobs,
+        place,
+        threads,
+        memory
+    ));
+    lines.push(format!("Output in \"{}\"", config.out_dir.display()));
+    lines.join("\n") + "\n"
+}
+
+// --------------------------------------------------
+/// Threads for each megahit job: as given, what a scheduler job is
+/// allotted, or this machine's cores shared among the concurrent jobs.
+/// The cores of SSH nodes aren't known, so there megahit decides.
+fn cpu_threads(config: &Config) -> Option<u32> {
+    if config.num_cpu_threads.is_some() {
+        return config.num_cpu_threads;
+    }
+
+    if config.scheduler != "local" {
+        return Some(scheduler::DEFAULT_CPUS);
+    }
+
+    if !config.nodes.is_empty() {
+        return None;
+    }
+
+    Some((host::cores() / config.num_concurrent_jobs.max(1)).max(1))
+}
+
+// --------------------------------------------------
+/// Orders the jobs to start: the biggest inputs first so the longest
+/// assemblies don't hold up the end of the batch ("size"), by sample name
+/// ("name"), or as the samples were listed ("manifest-order")
+fn schedule_jobs(jobs: &mut [Job], samples: &[Sample], order: &str) {
+    match order {
+        "size" => {
+            let sizes: HashMap<&str, u64> = samples
+                .iter()
+                .map(|sample| (sample.name.as_str(), input_size(sample)))
+                .collect();
+            jobs.sort_by_key(|job| {
+                std::cmp::Reverse(
+                    sizes.get(job.sample.as_str()).copied().unwrap_or(0),
+                )
+            });
+        }
+        "name" => jobs.sort_by(|a, b| a.sample.cmp(&b.sample)),
+        _ => {}
+    }
+}
+
+// --------------------------------------------------
+/// Rough peak memory megahit needs for a sample: its reads decompressed
+/// and half as much again for the graph, but at least a gigabyte
+fn estimate_memory(sample: &Sample) -> u64 {
+    let reads: u64 = sample
+        .files()
+        .filter_map(|file| {
+            fs::metadata(file).ok().map(|meta| {
+                meta.len() * reads::Compression::from_name(file).expansion()
+            })
+        })
+        .sum();
+
+    (reads + reads / 2).max(MIN_JOB_MEMORY)
+}
+
+// --------------------------------------------------
+/// Bytes of (possibly compressed) reads in a sample
+fn input_size(sample: &Sample) -> u64 {
+    sample
+        .files()
+        .filter_map(|file| fs::metadata(file).ok())
+        .map(|meta| meta.len())
+        .sum()
+}
+
+// --------------------------------------------------
+/// Whether `dir` holds a megahit run that `--continue` can pick up
+fn is_megahit_dir(dir: &Path) -> bool {
+    dir.join("options.json").is_file() && dir.join("checkpoints.txt").is_file()
+}
+
+// --------------------------------------------------
+/// Joins files into one of megahit's comma-separated input lists
+fn file_list(files: &[String]) -> MyResult<String> {
+    if files.len() > 1 {
+        if let Some(bad) = files.iter().find(|f| f.contains(',')) {
+            return Err(From::from(format!(
+                "Cannot combine \"{}\", megahit file lists are \
+                 comma-separated",
+                bad
+            )));
+        }
+    }
+
+    Ok(files.join(","))
+}
+
+// --------------------------------------------------
+/// Pairs in name order followed by singles in the order found
+fn to_samples(pairs: ReadPairLookup, singles: SingleReads) -> Vec<Sample> {
+    let mut names: Vec<&String> = pairs.keys().collect();
+    names.sort();
+
+    let mut samples: Vec<Sample> = vec![];
+    for name in names {
+        let pair = &pairs[name];
+        if let (Some(fwd), Some(rev)) = (
+            pair.get(&ReadDirection::Forward),
+            pair.get(&ReadDirection::Reverse),
+        ) {
+            samples.push(Sample {
+                name: name.to_string(),
+                fwd: vec![fwd.to_string()],
+                rev: vec![rev.to_string()],
+                ..Default::default()
+            });
+        }
+    }
+
+    for file in singles {
+        samples.push(Sample {
+            name: sample_name(Path::new(&file)),
+            single: vec![file],
+            ..Default::default()
+        });
+    }
+
+    samples
+}
+
+// --------------------------------------------------
+/// Moves the reads of single-end samples named like orphans of a pair
+/// (e.g., "S1_unpaired", "S1_R1_unpaired_1", "S1_orphans") into that pair
+fn attach_orphans(samples: Vec<Sample>) -> Vec<Sample> {
+    let re = Regex::new(
+        r"^(.+?)(?:[_-][Rr]?[12])?[_.-](?:unpaired|orphans?|singles?|se|U|R0)(?:[_-][Rr]?[12])?$",
+    )
+    .unwrap();
+
+    let paired: HashSet<String> = samples
+        .iter()
+        .filter(|s| !s.fwd.is_empty())
+        .map(|s| s.name.to_string())
+        .collect();
+
+    let mut orphans: Vec<(String, Vec<String>)> = vec![];
+    let mut kept: Vec<Sample> = vec![];
+    for sample in samples {
+        let pair_name = re
+            .captures(&sample.name)
+            .map(|cap| cap[1].to_string())
+            .filter(|name| sample.fwd.is_empty() && paired.contains(name));
+
+        match pair_name {
+            Some(name) => {
+                info!("Attaching \"{}\" to pair \"{}\"", sample.name, name);
+                orphans.push((name, sample.single));
+            }
+            _ => kept.push(sample),
+        }
+    }
+
+    for (name, files) in orphans {
+        if let Some(pair) = kept.iter_mut().find(|s| s.name == name) {
+            pair.single.extend(files);
+        }
+    }
+
+    kept
+}
+
+// --------------------------------------------------
+/// Combines the lanes of a sample, named like "S1_L001", into one sample
+/// "S1" with a library per lane, keeping the samples' order
+fn merge_lanes(samples: Vec<Sample>) -> Vec<Sample> {
+    let re = Regex::new(r"^(.+?)[_-]L\d{3}$").unwrap();
+
+    let mut merged: Vec<Sample> = vec![];
+    for sample in samples {
+        let name = match re.captures(&sample.name) {
+            Some(cap) => cap[1].to_string(),
+            _ => sample.name.to_string(),
+        };
+
+        match merged.iter_mut().find(|s| s.name == name) {
+            Some(prev) => {
+                info!("Adding \"{}\" to \"{}\"", sample.name, name);
+                prev.fwd.extend(sample.fwd);
+                prev.rev.extend(sample.rev);
+                prev.single.extend(sample.single);
+            }
+            _ => merged.push(Sample { name, ..sample }),
+        }
+    }
+
+    merged
+}
+
+// --------------------------------------------------
+/// Combines several samples into one for co-assembly
+fn merge_samples(name: &str, samples: &[Sample]) -> Sample {
+    info!(
+        "Co-assembling {} sample{} as \"{}\"",
+        samples.len(),
+        if samples.len() == 1 { "" } else { "s" },
+        name
+    );
+
+    let mut merged = Sample {
+        name: name.to_string(),
+        ..Default::default()
+    };
+
+    for sample in samples {
+        merged.fwd.extend(sample.fwd.iter().cloned());
+        merged.rev.extend(sample.rev.iter().cloned());
+        merged.single.extend(sample.single.iter().cloned());
+    }
+
+    // Per-sample options only carry over when the samples agree on them
+    if let Some(first) = samples.first() {
+        if samples.iter().all(|sample| sample.options == first.options) {
+            merged.options = first.options.clone();
+        } else {
+            warn!(
+                "The samples of \"{}\" set different options in the \
+                 manifest, using the batch's",
+                name
+            );
+        }
+    }
+
+    merged
+}
+
+// --------------------------------------------------
+/// Co-assembles the samples sharing a group, leaving ungrouped ones be
+fn group_samples(samples: Vec<Sample>) -> Vec<Sample> {
+    let mut grouped: Vec<Sample> = vec![];
+    let mut seen: HashSet<String> = HashSet::new();
+
+    for sample in &samples {
+        match &sample.group {
+            Some(group) => {
+                if seen.insert(group.to_string()) {
+                    let members: Vec<Sample> = samples
+                        .iter()
+                        .filter(|s| s.group.as_ref() == Some(group))
+                        .cloned()
+                        .collect();
+                    grouped.push(merge_samples(group, &members));
+                }
+            }
+            _ => grouped.push(sample.clone()),
+        }
+    }
+
+    grouped
+}
+
+// --------------------------------------------------
+/// The stages to run for one sample after its skip directives
+fn sample_stages(config: &Config, sample: &str) -> Vec<Stage> {
+    let skip = config.skip_stages.get(sample);
+    config
+        .stages
+        .iter()
+        .filter(|stage| skip.is_none_or(|s| !s.contains(stage)))
+        .cloned()
+        .collect()
+}
+
+// --------------------------------------------------
+fn skip_note(config: &Config, sample: &str) -> String {
+    let stages = sample_stages(config, sample);
+    if stages.len() == config.stages.len() {
+        return "".to_string();
+    }
+
+    let skipped: Vec<String> = config
+        .stages
+        .iter()
+        .filter(|stage| !stages.contains(stage))
+        .map(|stage| stage.to_string())
+        .collect();
+    format!(" (skipping {})", skipped.join(", "))
+}
+
+// --------------------------------------------------
+/// Warns about files whose contents and name disagree on compression.
+/// Gzip is read transparently either way, but bzip2 is only recognized
+/// by its ".bz2" extension.
+fn check_compression(samples: &[Sample]) -> MyResult<()> {
+    for file in samples.iter().flat_map(|s| s.files()) {
+        let actual = reads::sniff(file)?;
+        let named = reads::Compression::from_name(file);
+
+        if actual != named {
+            let msg = format!(
+                "\"{}\" is named as {:?} but contains {:?} data",
+                file, named, actual
+            );
+
+            if actual == reads::Compression::Bzip2 {
+                return Err(From::from(format!(
+                    "{}; rename it to end with \".bz2\"",
+                    msg
+                )));
+            }
+            warn!("{}", msg);
+        }
+    }
+
+    Ok(())
+}
+
+// --------------------------------------------------
+/// Confirms each R1/R2 file pair describes the same fragments by
+/// comparing their first read IDs
+fn verify_pairs(samples: &[Sample]) -> MyResult<()> {
+    let mut problems = vec![];
+    for sample in samples {
+        for (fwd, rev) in sample.fwd.iter().zip(&sample.rev) {
+            if let Some(problem) =
+                reads::check_pair(fwd, rev, NUM_PAIR_CHECK_READS)?
+            {
+                problems.push(format!("{}: {}", sample.name, problem));
+            }
+        }
+    }
+
+    if !problems.is_empty() {
+        return Err(Box::new(RunMegahitError::Unpaired { problems }));
+    }
+
+    Ok(())
+}
+
+// --------------------------------------------------
+/// Fails on any name that isn't one of the samples
+fn check_sample_names(
+    samples: &[Sample],
+    names: &[&String],
+    flag: &str,
+) -> MyResult<()> {
+    let unknown: Vec<&str> = names
+        .iter()
+        .filter(|name| !samples.iter().any(|s| &&s.name == *name))
+        .map(|name| name.as_str())
+        .collect();
+
+    if !unknown.is_empty() {
+        return Err(From::from(format!(
+            "Unknown sample(s) in {}: {}",
+            flag,
+            unknown.join(", ")
+        )));
+    }
+
+    Ok(())
+}
+
+// --------------------------------------------------
+/// Keeps only the named samples (all when empty), then at most `limit`
+fn select_samples(
+    mut samples: Vec<Sample>,
+    names: &[String],
+    limit: Option<usize>,
+) -> MyResult<Vec<Sample>> {
+    if !names.is_empty() {
+        let wanted: Vec<&String> = names.iter().collect();
+        check_sample_names(&samples, &wanted, "--samples")?;
+        samples.retain(|s| names.contains(&s.name));
+    }
+
+    if let Some(limit) = limit {
+        samples.truncate(limit);
+    }
+
+    Ok(samples)
+}
+
+// --------------------------------------------------
+/// Replaces a "-" query with the newline-delimited paths on STDIN
+fn read_stdin_paths(paths: &[String]) -> MyResult<Vec<String>> {
+    let mut expanded = vec![];
+    for path in paths {
+        if path == "-" {
+            for line in io::stdin().lock().lines() {
+                let line = line?;
+                let line = line.trim();
+                if !line.is_empty() {
+                    expanded.push(line.to_string());
+                }
+            }
+        } else {
+            expanded.push(path.to_string());
+        }
+    }
+
+    Ok(expanded)
+}
+
+// --------------------------------------------------
+fn find_files(
+    paths: &[String],
+    extensions: &[String],
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut files = vec![];
+    for path in &read_stdin_paths(paths)? {
+        let meta = fs::metadata(path)?;
+        if meta.is_file() {
+            files.push(path.to_owned());
+        } else {
+            for entry in fs::read_dir(path)? {
+                let entry = entry?;
+                // Follow symlinks, unlike entry.metadata()
+                let meta = fs::metadata(entry.path())?;
+                if meta.is_file() {
+                    files.push(entry.path().display().to_string());
+                }
+            }
+        };
+    }
+
+    // Keep the first name seen for each real file so that symlinks and
+    // repeated arguments don't assemble the same reads twice
+    let mut seen = HashSet::new();
+    let mut unique = vec![];
+    for file in files {
+        if !has_extension(&file, extensions) {
+            warn!("Skipping non-read file \"{}\"", file);
+            continue;
+        }
+
+        let real = fs::canonicalize(&file)?;
+        if seen.insert(real) {
+            unique.push(file);
+        } else {
+            warn!("Skipping duplicate input \"{}\"", file);
+        }
+    }
+
+    if unique.is_empty() {
+        return Err(Box::new(RunMegahitError::NoInputs {
+            query: paths.to_vec(),
+        }));
+    }
+    info!(
+        event = "discovery",
+        files = unique.len();
+        "Found {} input file{}",
+        unique.len(),
+        if unique.len() == 1 { "" } else { "s" }
+    );
+
+    Ok(unique)
+}
+
+// --------------------------------------------------
+/// Checks every input against the manifest and returns the failing files
+/// when skipping, otherwise errors on the first bad file
+fn verify_checksums(
+    files: &[String],
+    manifest: &Path,
+    skip: bool,
+) -> MyResult<HashSet<String>> {
+    let lookup = checksum::read_manifest(manifest)?;
+    let mut bad = HashSet::new();
+
+    info!(
+        "Verifying {} file{} against \"{}\"",
+        files.len(),
+        if files.len() == 1 { "" } else { "s" },
+        manifest.display()
+    );
+
+    for file in files {
+        if let Some(problem) = checksum::verify(file, &lookup)? {
+            let msg = match problem {
+                checksum::Mismatch::Missing => {
+                    format!("\"{}\" is not in the checksum manifest", file)
+                }
+                checksum::Mismatch::Differs { expected, actual } => format!(
+                    "\"{}\" checksum {} does not match expected {}",
+                    file, actual, expected
+                ),
+            };
+
+            if !skip {
+                return Err(From::from(msg));
+            }
+
+            warn!("Skipping {}", msg);
+            bad.insert(file.to_string());
+        }
+    }
+
+    Ok(bad)
+}
+
+// --------------------------------------------------
+fn classify(
+    paths: &[String],
+) -> Result<(ReadPairLookup, SingleReads), Box<dyn Error>> {
+    let paths = paths.iter().map(Path::new);
+    let mut exts: Vec<String> =
+        paths.clone().filter_map(get_extension).collect();
+    exts.dedup();
+
+    let dots = Regex::new(r"\.").unwrap();
+    let exts: Vec<String> = exts
+        .into_iter()
+        .map(|x| dots.replace(&x, r"\.").to_string())
+        .collect();
+
+    let pattern = format!(r"(.+)[_-][Rr]?([12])?\.(?:{})$", exts.join("|"));
+    let re = Regex::new(&pattern).unwrap();
+    let mut pairs: ReadPairLookup = HashMap::new();
+    let mut singles: Vec<String> = vec![];
+
+    for path in paths.map(Path::new) {
+        let path_str = path.to_str().expect("Convert path");
+
+        if let Some(file_name) = path.file_name() {
+            let basename = file_name.to_string_lossy();
+            if let Some(cap) = re.captures(&basename) {
+                let sample_name = &cap[1];
+                let direction = if &cap[2] == "1" {
+                    ReadDirection::Forward
+                } else {
+                    ReadDirection::Reverse
+                };
+
+                if !pairs.contains_key(sample_name) {
+                    let mut pair: ReadPair = HashMap::new();
+                    pair.insert(direction, path_str.to_string());
+                    pairs.insert(sample_name.to_string(), pair);
+                } else if let Some(pair) = pairs.get_mut(sample_name) {
+                    if let Some(prev) = pair.get(&direction) {
+                        return Err(From::from(format!(
+                            "Sample \"{}\" has two {} read files: \"{}\" \
+                             and \"{}\"",
+                            sample_name,
+                            match direction {
+                                ReadDirection::Forward => "forward",
+                                ReadDirection::Reverse => "reverse",
+                            },
+                            prev,
+                            path_str
+                        )));
+                    }
+                    pair.insert(direction, path_str.to_string());
+                }
+            } else {
+                singles.push(path_str.to_string());
+            }
+        }
+    }
+
+    let mut bad: Vec<String> = pairs
+        .iter()
+        .filter_map(|(k, v)| {
+            if !v.contains_key(&ReadDirection::Forward)
+                || !v.contains_key(&ReadDirection::Reverse)
+            {
+                Some(k.to_string())
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    // Push unpaired samples to the singles in a stable order
+    bad.sort();
+    for key in bad {
+        if let Some(mut pair) = pairs.remove(&key) {
+            for dir in &[ReadDirection::Forward, ReadDirection::Reverse] {
+                if let Some(val) = pair.remove(dir) {
+                    warn!(
+                        event = "unpaired",
+                        file = val.as_str();
+                        "\"{}\" has no mate, assembling it as single", val
+                    );
+                    singles.push(val);
+                }
+            }
+        }
+    }
+
+    Ok((pairs, singles))
+}
+
+// --------------------------------------------------
+/// Whether the file name ends with one of the (lowercase) extensions;
+/// an empty list allows everything
+fn has_extension(path: &str, extensions: &[String]) -> bool {
+    if extensions.is_empty() {
+        return true;
+    }
+
+    let basename = Path::new(path)
+        .file_name()
+        .map(|x| x.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    extensions
+        .iter()
+        .any(|ext| basename.ends_with(&format!(".{}", ext)))
+}
+
+// --------------------------------------------------
+/// Returns the file name without its extension
+fn sample_name(path: &Path) -> String {
+    let basename = path
+        .file_name()
+        .map(|x| x.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    match get_extension(path) {
+        Some(ext) => basename[..basename.len() - ext.len() - 1].to_string(),
+        _ => basename,
+    }
+}
+
+// --------------------------------------------------
+/// Returns the extension plus optional ".gz"
+fn get_extension(path: &Path) -> Option<String> {
+    let re = Regex::new(r"\.([^.]+(?:\.gz)?)$").unwrap();
+    if let Some(basename) = path.file_name() {
+        let basename = basename.to_string_lossy();
+        if let Some(cap) = re.captures(&basename) {
+            return Some(cap[1].to_string());
+        }
+    }
+    None
+}
+
+// --------------------------------------------------
+/// Writes each batch of jobs to its file in `out_dir/plan`, and the exact
+/// `parallel` invocations that run them in turn
+fn write_plan(
+    batches: &[(&str, &[Job])],
+    out_dir: &Path,
+    num_concurrent_jobs: u32,
+    on_failure: &executor::FailurePolicy,
+) -> MyResult<PathBuf> {
+    let plan_dir = out_dir.join("plan");
+    fs::create_dir_all(&plan_dir)?;
+
+    let parallel = |file: &Path| {
+        format!(
+            "parallel {} < {}\n",
+            parallel_args(num_concurrent_jobs, on_failure).join(" "),
+            shell_quote(&file.display().to_string())
+        )
+    };
+    let mut script = "#!/bin/bash\n\n".to_string();
+
+    // Each batch starts once the one before it has ended
+    for (name, jobs) in batches {
+        if jobs.is_empty() && *name != "jobs.txt" {
+            continue;
+        }
+        let file = plan_dir.join(name);
+        fs::write(&file, format!("{}\n", commands(jobs).join("\n")))?;
+        script += &parallel(&file);
+    }
+    fs::write(plan_dir.join("run_jobs.sh"), script)?;
+
+    Ok(plan_dir)
+}
+
+// --------------------------------------------------
+/// The jobs as JSON, with each one's argv and output directory and the
+/// resources they get, for other schedulers and for keeping with results
+fn job_plan(config: &Config, jobs: &[Job]) -> MyResult<String> {
+    #[derive(Serialize)]
+    struct Plan<'a> {
+        out_dir: &'a Path,
+        num_concurrent_jobs: u32,
+        num_cpu_threads: Option<u32>,
+        memory: Option<f64>,
+        on_failure: &'a executor::FailurePolicy,
+        executor: &'a str,
+        scheduler: &'a str,
+        retries: u32,
+        job_timeout_secs: Option<u64>,
+        jobs: Vec<PlannedJob<'a>>,
+    }
+
+    #[derive(Serialize)]
+    struct PlannedJob<'a> {
+        sample: &'a str,
+        out_dir: &'a Path,
+        argv: Vec<&'a str>,
+    }
+
+    let plan = Plan {
+        out_dir: &config.out_dir,
+        num_concurrent_jobs: config.num_concurrent_jobs,
+        num_cpu_threads: cpu_threads(config),
+        memory: job_memory(config),
+        on_failure: &config.on_failure,
+        executor: &config.executor,
+        scheduler: &config.scheduler,
+        retries: config.retries,
+        job_timeout_secs: config.job_timeout.map(|dur| dur.as_secs()),
+        jobs: jobs
+            .iter()
+            .map(|job| PlannedJob {
+                sample: &job.sample,
+                out_dir: &job.out_dir,
+                argv: std::iter::once(&job.program)
+                    .chain(&job.args)
+                    .map(String::as_str)
+                    .collect(),
+            })
+            .collect(),
+    };
+    Ok(serde_json::to_string_pretty(&plan)? + "\n")
+}
+
+// --------------------------------------------------
+fn parallel_args(
+    num_concurrent_jobs: u32,
+    on_failure: &executor::FailurePolicy,
+) -> Vec<String> {
+    let mut args: Vec<String> =
+        vec!["-j".to_string(), num_concurrent_jobs.to_string()];
+
+    if let Some(halt) = on_failure.parallel_halt() {
+        args.push("--halt".to_string());
+        args.push(halt);
+    }
+
+    args
+}
+
+// --------------------------------------------------
+fn commands(jobs: &[Job]) -> Vec<String> {
+    jobs.iter().map(|job| job.command_line()).collect()
+}
+
+// --------------------------------------------------
+/// Single-quotes a word for a POSIX shell unless it is plainly safe
+fn shell_quote(word: &str) -> String {
+    let safe = !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./,:=+@%".contains(c));
+
+    if safe {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
+
+// --------------------------------------------------
+/// The words of a command line as a POSIX shell would split them, minding
+/// quotes and backslashes but expanding nothing
+fn split_words(line: &str) -> MyResult<Vec<String>> {
+    let mut words: Vec<String> = vec![];
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(From::from("unclosed quote")),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c) => {
+                                if !"\\\"$`".contains(c) {
+                                    word.push('\\');
+                                }
+                                word.push(c)
+                            }
+                            None => return Err(From::from("unclosed quote")),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err(From::from("unclosed quote")),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some(c) => word.get_or_insert_with(String::new).push(c),
+                None => return Err(From::from("trailing backslash")),
+            },
+            c if c.is_whitespace() => {
+                if let Some(word) = word.take() {
+                    words.push(word);
+                }
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+
+    Ok(words)
 }
 
 // --------------------------------------------------
@@ -519,4 +3680,1050 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_find_files_dedupes() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let reads = dir.join("S1_R1.fastq");
+        fs::write(&reads, "").unwrap();
+        std::os::unix::fs::symlink(&reads, dir.join("S1_link.fastq")).unwrap();
+
+        let dir = dir.display().to_string();
+        let reads = reads.display().to_string();
+        let files = find_files(&[dir.clone(), reads], &[]).unwrap();
+        assert_eq!(files.len(), 1);
+
+        let exts = vec!["fq".to_string(), "fq.gz".to_string()];
+        assert!(find_files(&[dir], &exts).is_err());
+    }
+
+    #[test]
+    fn test_has_extension() {
+        let exts = vec!["fastq.gz".to_string(), "fq".to_string()];
+        assert!(has_extension("/foo/S1_R1.fastq.gz", &exts));
+        assert!(has_extension("/foo/S1_R1.FQ", &exts));
+        assert!(!has_extension("/foo/S1_R1.fastq", &exts));
+        assert!(!has_extension("/foo/run.log", &exts));
+        assert!(has_extension("/foo/run.log", &[]));
+    }
+
+    #[test]
+    fn test_select_samples() {
+        let (pairs, singles) = classify(&[
+            "/foo/S2_1.fq".to_string(),
+            "/foo/S2_2.fq".to_string(),
+            "/foo/S1_1.fq".to_string(),
+            "/foo/S1_2.fq".to_string(),
+            "/foo/S3.fq".to_string(),
+        ])
+        .unwrap();
+        let samples = to_samples(pairs, singles);
+
+        let res = select_samples(samples.clone(), &[], Some(1)).unwrap();
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].name, "S1");
+
+        let wanted = vec!["S3".to_string(), "S2".to_string()];
+        let res = select_samples(samples.clone(), &wanted, None).unwrap();
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[1].single, vec!["/foo/S3.fq"]);
+
+        let wanted = vec!["S4".to_string()];
+        assert!(select_samples(samples, &wanted, None).is_err());
+    }
+
+    #[test]
+    fn test_classify_collision() {
+        let res = classify(&[
+            "/run1/S1_R1.fastq.gz".to_string(),
+            "/run1/S1_R2.fastq.gz".to_string(),
+            "/run2/S1_R1.fastq.gz".to_string(),
+        ]);
+        assert!(res.is_err());
+
+        if let Err(e) = res {
+            let msg = e.to_string();
+            assert!(msg.contains("/run1/S1_R1.fastq.gz"));
+            assert!(msg.contains("/run2/S1_R1.fastq.gz"));
+        }
+    }
+
+    #[test]
+    fn test_attach_orphans() {
+        let (pairs, singles) = classify(&[
+            "/foo/S1_R1.fq".to_string(),
+            "/foo/S1_R2.fq".to_string(),
+            "/foo/S1_R1_unpaired_1.fq".to_string(),
+            "/foo/S1_R2_unpaired_2.fq".to_string(),
+            "/foo/S2_unpaired.fq".to_string(),
+        ])
+        .unwrap();
+
+        let samples = attach_orphans(to_samples(pairs, singles));
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0].name, "S1");
+        assert_eq!(
+            samples[0].single,
+            vec!["/foo/S1_R1_unpaired_1.fq", "/foo/S1_R2_unpaired_2.fq"]
+        );
+        assert_eq!(samples[1].name, "S2_unpaired");
+    }
+
+    #[test]
+    fn test_merge_lanes() {
+        let (pairs, singles) = classify(&[
+            "/foo/S1_L001_R1.fq".to_string(),
+            "/foo/S1_L001_R2.fq".to_string(),
+            "/foo/S1_L002_R1.fq".to_string(),
+            "/foo/S1_L002_R2.fq".to_string(),
+            "/foo/S2_R1.fq".to_string(),
+            "/foo/S2_R2.fq".to_string(),
+        ])
+        .unwrap();
+
+        let samples = merge_lanes(to_samples(pairs, singles));
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0].name, "S1");
+        assert_eq!(
+            file_list(&samples[0].fwd).unwrap(),
+            "/foo/S1_L001_R1.fq,/foo/S1_L002_R1.fq"
+        );
+        assert_eq!(
+            file_list(&samples[0].rev).unwrap(),
+            "/foo/S1_L001_R2.fq,/foo/S1_L002_R2.fq"
+        );
+        assert_eq!(samples[1].name, "S2");
+    }
+
+    #[test]
+    fn test_group_samples() {
+        let sample = |name: &str, group: Option<&str>| Sample {
+            name: name.to_string(),
+            fwd: vec![format!("{}_1.fq", name)],
+            rev: vec![format!("{}_2.fq", name)],
+            group: group.map(|g| g.to_string()),
+            ..Default::default()
+        };
+
+        let grouped = group_samples(vec![
+            sample("A", Some("site1")),
+            sample("B", None),
+            sample("C", Some("site1")),
+        ]);
+        assert_eq!(grouped.len(), 2);
+        assert_eq!(grouped[0].name, "site1");
+        assert_eq!(grouped[0].fwd, vec!["A_1.fq", "C_1.fq"]);
+        assert_eq!(grouped[1].name, "B");
+
+        assert_eq!(file_list(&grouped[0].rev).unwrap(), "A_2.fq,C_2.fq");
+        assert!(file_list(&["a,b.fq".to_string(), "c.fq".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_usage_errors() {
+        // Returned to be reported like any other error, not exited on
+        let args = |words: &[&str]| -> Vec<String> {
+            words.iter().map(|word| word.to_string()).collect()
+        };
+        let res = parse_args(args(&["run_megahit", "--query", "x", "--nope"]));
+        assert_eq!(res.unwrap_err().to_string(), "unknown option \"--nope\"");
+        assert!(parse_args(args(&["run_megahit", "--query"])).is_err());
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("/data/S1_R1.fq.gz"), "/data/S1_R1.fq.gz");
+        assert_eq!(shell_quote("my reads (1).fq"), "'my reads (1).fq'");
+        assert_eq!(shell_quote("a&b;rm -rf ~"), "'a&b;rm -rf ~'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        assert_eq!(shell_quote(""), "''");
+
+        let job = Job {
+            sample: "S1".to_string(),
+            out_dir: PathBuf::from("out/S1"),
+            program: "megahit".to_string(),
+            args: vec!["-r".to_string(), "S 1.fq".to_string()],
+        };
+        assert_eq!(job.command_line(), "megahit -r 'S 1.fq'");
+    }
+
+    #[test]
+    fn test_env_options() {
+        let vars = vec![
+            ("RUN_MEGAHIT_OUT_DIR", "/scratch/out"),
+            ("RUN_MEGAHIT_DRY_RUN", "yes"),
+            ("RUN_MEGAHIT_RESUME", "false"),
+            ("RUN_MEGAHIT_MEGAHIT_EXTRA", "--no-mercy --k-list 21,41"),
+            ("MEGAHIT_PATH", "/opt/megahit"),
+            ("HOME", "/home/me"),
+        ];
+        let options = env_options(
+            vars.into_iter()
+                .map(|(k, v)| (k.to_string(), v.to_string())),
+        )
+        .unwrap();
+        assert_eq!(
+            options,
+            vec![
+                ("dry_run".to_string(), vec![]),
+                (
+                    "megahit_extra".to_string(),
+                    vec![
+                        "--no-mercy".to_string(),
+                        "--k-list".to_string(),
+                        "21,41".to_string()
+                    ]
+                ),
+                ("out_dir".to_string(), vec!["/scratch/out".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_public_args() {
+        let args: Vec<String> = [
+            "run_megahit",
+            "--smtp_url",
+            "smtps://me:pw@smtp.org",
+            "-o",
+            "out",
+            "--smtp-url=smtps://me:pw@smtp.org",
+            "--webhook",
+            "https://hooks.slack.com/services/T0/B0/XXXX",
+            "--",
+            "--smtp_url",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+        assert_eq!(
+            public_args(&args),
+            vec!["run_megahit", "-o", "out", "--", "--smtp_url"]
+        );
+    }
+
+    #[test]
+    fn test_split_words() {
+        assert_eq!(
+            split_words("  --no-mercy --min-count 2 ").unwrap(),
+            vec!["--no-mercy", "--min-count", "2"]
+        );
+        assert_eq!(
+            split_words(r#"--out-prefix 'my run' --x "a \"b\" \c" d\ e ''"#)
+                .unwrap(),
+            vec!["--out-prefix", "my run", "--x", r#"a "b" \c"#, "d e", ""]
+        );
+        assert!(split_words("--x 'oops").is_err());
+        assert!(split_words("--x \\").is_err());
+    }
+
+    #[test]
+    fn test_parallel_args() {
+        use executor::FailurePolicy;
+
+        assert_eq!(parallel_args(8, &FailurePolicy::Continue), vec!["-j", "8"]);
+        assert_eq!(
+            parallel_args(4, &FailurePolicy::Threshold(2)),
+            vec!["-j", "4", "--halt", "soon,fail=2"]
+        );
+        assert_eq!(
+            parallel_args(4, &FailurePolicy::FailFast),
+            vec!["-j", "4", "--halt", "now,fail=1"]
+        );
+    }
+
+    #[test]
+    fn test_parse_k_list() {
+        assert_eq!(
+            parse_k_list("21,41, 61,81,99").unwrap(),
+            vec![21, 41, 61, 81, 99]
+        );
+        assert!(parse_k_list("21,40").is_err());
+        assert!(parse_k_list("41,21").is_err());
+        assert!(parse_k_list("21,21").is_err());
+        assert!(parse_k_list("21,257").is_err());
+        assert!(parse_k_list("21,").is_err());
+    }
+
+    #[test]
+    fn test_check_kmers() {
+        assert!(check_kmers(Some(21), Some(141), Some(12), Some(2)).is_ok());
+        assert!(check_kmers(None, None, None, None).is_ok());
+
+        let err = check_kmers(Some(20), None, None, None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "--k_min 20 must be an odd number no more than 255 (e.g., 21)"
+        );
+        assert!(check_kmers(None, Some(257), None, None).is_err());
+        assert!(check_kmers(Some(99), Some(77), None, None).is_err());
+        assert!(check_kmers(Some(77), Some(77), None, None).is_err());
+        assert!(check_kmers(None, None, Some(11), None).is_err());
+        assert!(check_kmers(None, None, Some(30), None).is_err());
+        assert!(check_kmers(None, None, None, Some(0)).is_err());
+    }
+
+    #[test]
+    fn test_parse_merge_level() {
+        assert_eq!(parse_merge_level("20,0.95").unwrap(), "20,0.95");
+        assert_eq!(parse_merge_level(" 10 , 1 ").unwrap(), "10,1");
+        assert!(parse_merge_level("20").is_err());
+        assert!(parse_merge_level("0,0.9").is_err());
+        assert!(parse_merge_level("20,1.5").is_err());
+        assert!(parse_merge_level("20,0.9,1").is_err());
+        assert!(parse_merge_level("x,0.9").is_err());
+    }
+
+    #[test]
+    fn test_sample_options() {
+        let batch: Vec<String> = [
+            "--k-min",
+            "27",
+            "--no-local",
+            "--memory",
+            "0.5",
+            "--min-count",
+            "2",
+        ]
+        .iter()
+        .map(|opt| opt.to_string())
+        .collect();
+        assert_eq!(SampleOptions::default().apply(&batch), batch);
+
+        let options = SampleOptions {
+            k_list: Some(vec![21, 41]),
+            memory: Some(64e9),
+            ..Default::default()
+        };
+        assert!(!options.is_empty());
+        assert_eq!(
+            options.apply(&batch),
+            vec![
+                "--no-local",
+                "--min-count",
+                "2",
+                "--k-list",
+                "21,41",
+                "--memory",
+                "64000000000"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_memory() {
+        assert_eq!(split_memory(8_000_000_000., 4), 2_000_000_000.);
+        assert_eq!(split_memory(0.8, 4), 0.2);
+    }
+
+    #[test]
+    fn test_schedule_jobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+
+        let mut samples = vec![];
+        for (name, size) in [("b", 10), ("a", 1), ("c", 100)] {
+            let file = dir.join(format!("{}.fq", name));
+            fs::write(&file, vec![b'A'; size]).unwrap();
+            samples.push(Sample {
+                name: name.to_string(),
+                single: vec![file.display().to_string()],
+                ..Sample::default()
+            });
+        }
+
+        let jobs = || -> Vec<Job> {
+            samples
+                .iter()
+                .map(|sample| Job {
+                    sample: sample.name.to_string(),
+                    out_dir: dir.join(&sample.name),
+                    program: "megahit".to_string(),
+                    args: vec![],
+                })
+                .collect()
+        };
+        let order = |jobs: &[Job]| -> Vec<String> {
+            jobs.iter().map(|job| job.sample.to_string()).collect()
+        };
+
+        for (schedule, expected) in [
+            ("size", ["c", "b", "a"]),
+            ("name", ["a", "b", "c"]),
+            ("manifest-order", ["b", "a", "c"]),
+        ] {
+            let mut jobs = jobs();
+            schedule_jobs(&mut jobs, &samples, schedule);
+            assert_eq!(order(&jobs), expected);
+        }
+    }
+
+    #[test]
+    fn test_sample_results() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        fs::create_dir_all(dir.join("S1")).unwrap();
+        fs::write(dir.join("S1/final.contigs.fa"), ">c\nACGT\n").unwrap();
+
+        let samples = vec![
+            Sample {
+                name: "S1".to_string(),
+                fwd: vec!["S1_R1.fq".to_string()],
+                rev: vec!["S1_R2.fq".to_string()],
+                ..Sample::default()
+            },
+            Sample {
+                name: "S2".to_string(),
+                single: vec!["S2.fq".to_string()],
+                ..Sample::default()
+            },
+        ];
+        let jobs = vec![Job {
+            sample: "S2".to_string(),
+            out_dir: dir.join("S2"),
+            program: "megahit".to_string(),
+            args: vec!["-r".to_string(), "S2.fq".to_string()],
+        }];
+        let result = |sample: &str, exit_code: Option<i32>| JobResult {
+            sample: sample.to_string(),
+            out_dir: dir.join(sample),
+            exit_code,
+            runtime_secs: 1.5,
+            attempts: 1,
+            skipped: exit_code == Some(0),
+            ..JobResult::default()
+        };
+
+        let ok = sample_result(&result("S1", Some(0)), &jobs, &samples);
+        assert_eq!(ok.status, "skipped");
+        assert!(ok.is_ok());
+        assert_eq!(
+            ok.inputs,
+            vec![PathBuf::from("S1_R1.fq"), PathBuf::from("S1_R2.fq")]
+        );
+        assert_eq!(ok.contigs, Some(dir.join("S1/final.contigs.fa")));
+        assert_eq!(ok.duration, Duration::from_millis(1500));
+
+        let failed = sample_result(&result("S2", Some(1)), &jobs, &samples);
+        assert_eq!(failed.status, "failed");
+        assert_eq!(failed.inputs, vec![PathBuf::from("S2.fq")]);
+        assert_eq!(failed.contigs, None);
+
+        assert!(check_results(dir, std::slice::from_ref(&ok)).is_ok());
+        let err = check_results(dir, &[ok, failed]).unwrap_err();
+        assert!(matches!(
+            RunMegahitError::from(err),
+            RunMegahitError::JobFailed { code: Some(1), .. }
+        ));
+    }
+
+    #[test]
+    fn test_show_config() {
+        let args: Vec<String> = [
+            "run_megahit",
+            "--query",
+            "reads",
+            "-o",
+            "out",
+            "--k_min",
+            "27",
+            "--skip_stage",
+            "S1:stats",
+            "--stages",
+            "assemble,stats",
+            "--show_config=json",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+        let config = parse_args(args).unwrap();
+        assert_eq!(config.show_config.as_deref(), Some("json"));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&show_config(&config, "json").unwrap())
+                .unwrap();
+        assert_eq!(json["k_min"], 27);
+        assert_eq!(json["skip_stages"]["S1"][0], "stats");
+        assert_eq!(json["megahit_options"][0], "--k-min");
+        assert!(json.get("args").is_none());
+
+        let toml = show_config(&config, "toml").unwrap();
+        assert!(toml.contains("k_min = 27\n"));
+    }
+
+    #[test]
+    fn test_list_samples() {
+        let samples = vec![
+            Sample {
+                name: "S1".to_string(),
+                fwd: vec!["S1_R1.fq".to_string(), "S1_L2_R1.fq".to_string()],
+                rev: vec!["S1_R2.fq".to_string(), "S1_L2_R2.fq".to_string()],
+                ..Default::default()
+            },
+            Sample {
+                name: "S2".to_string(),
+                single: vec!["S2.fq".to_string()],
+                group: Some("G".to_string()),
+                ..Default::default()
+            },
+        ];
+
+        assert_eq!(
+            list_samples(&samples, "tsv").unwrap(),
+            "sample\tr1\tr2\tsingle\tgroup\n\
+             S1\tS1_R1.fq,S1_L2_R1.fq\tS1_R2.fq,S1_L2_R2.fq\t\t\n\
+             S2\t\t\tS2.fq\tG\n"
+        );
+
+        let json: serde_json::Value =
+            serde_json::from_str(&list_samples(&samples, "json").unwrap())
+                .unwrap();
+        assert_eq!(json[0]["r2"][1], "S1_L2_R2.fq");
+        assert_eq!(json[1]["single"][0], "S2.fq");
+        assert_eq!(json[1]["group"], "G");
+    }
+
+    #[test]
+    fn test_preview() {
+        let args: Vec<String> = [
+            "run_megahit",
+            "--query",
+            "reads",
+            "-o",
+            "out",
+            "-J",
+            "2",
+            "-t",
+            "4",
+            "-m",
+            "0.5",
+            "--confirm",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+        let config = parse_args(args).unwrap();
+        assert!(config.confirm);
+
+        let samples = vec![
+            Sample {
+                name: "S1".to_string(),
+                fwd: vec!["S1_R1.fq".to_string()],
+                rev: vec!["S1_R2.fq".to_string()],
+                ..Sample::default()
+            },
+            Sample {
+                name: "sample2".to_string(),
+                single: vec!["sample2.fq".to_string()],
+                ..Sample::default()
+            },
+        ];
+        let jobs: Vec<Job> = samples
+            .iter()
+            .map(|sample| Job {
+                sample: sample.name.to_string(),
+                out_dir: PathBuf::from("out").join(&sample.name),
+                program: "megahit".to_string(),
+                args: vec![],
+            })
+            .collect();
+
+        assert_eq!(
+            preview(&config, &samples, &jobs),
+            "Sample   Type    Reads\n\
+             S1       pair    S1_R1.fq, S1_R2.fq\n\
+             sample2  single  sample2.fq\n\
+             \n\
+             2 jobs, 2 at a time on this machine, with 4 threads and 50% \
+             of memory each\n\
+             Output in \"out\"\n"
+        );
+    }
+
+    #[test]
+    fn test_job_plan() {
+        let args: Vec<String> = [
+            "run_megahit",
+            "--query",
+            "reads",
+            "-o",
+            "out",
+            "-J",
+            "2",
+            "-t",
+            "4",
+            "-m",
+            "0.5",
+            "--retries",
+            "1",
+            "--plan",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+        let config = parse_args(args).unwrap();
+        assert!(config.plan);
+
+        let samples = vec![Sample {
+            name: "S1".to_string(),
+            single: vec!["S1.fq".to_string()],
+            ..Sample::default()
+        }];
+        let jobs = make_jobs(&config, &samples).unwrap();
+
+        let plan: serde_json::Value =
+            serde_json::from_str(&job_plan(&config, &jobs).unwrap()).unwrap();
+        assert_eq!(plan["num_concurrent_jobs"], 2);
+        assert_eq!(plan["num_cpu_threads"], 4);
+        assert_eq!(plan["memory"], 0.5);
+        assert_eq!(plan["retries"], 1);
+        assert_eq!(plan["on_failure"], "continue");
+        assert_eq!(plan["jobs"][0]["sample"], "S1");
+        assert_eq!(plan["jobs"][0]["out_dir"], "out/S1");
+
+        let argv: Vec<&str> = plan["jobs"][0]["argv"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|arg| arg.as_str().unwrap())
+            .collect();
+        assert_eq!(argv[..3], ["megahit", "-o", "out/S1"]);
+        assert_eq!(argv[argv.len() - 2..], ["-r", "S1.fq"]);
+    }
+
+    #[test]
+    fn test_prep_jobs() {
+        let args: Vec<String> = [
+            "run_megahit",
+            "--query",
+            "reads",
+            "-o",
+            "out",
+            "-t",
+            "4",
+            "--trim",
+            "fastp",
+            "--skip_stage",
+            "S2:trim",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+        let config = parse_args(args).unwrap();
+        assert_eq!(config.stages, vec![Stage::Trim, Stage::Assemble]);
+        assert_eq!(stage_programs(&config), vec!["fastp"]);
+
+        let samples = vec![
+            Sample {
+                name: "S1".to_string(),
+                single: vec!["in/S1.fq".to_string()],
+                ..Sample::default()
+            },
+            Sample {
+                name: "S2".to_string(),
+                single: vec!["in/S2.fq".to_string()],
+                ..Sample::default()
+            },
+        ];
+        let (prepared, jobs) = prep_jobs(&config, samples.clone());
+        assert_eq!(prepared[0].single, vec!["out/work/S1/S1.fq"]);
+        assert_eq!(prepared[1].single, vec!["in/S2.fq"]);
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].out_dir, PathBuf::from("out/work/S1"));
+        assert_eq!(
+            jobs[0].command_line(),
+            "sh -c 'mkdir -p out/work/S1 && { fastp --json \
+             out/work/S1/S1.fastp.json --html out/work/S1/S1.fastp.html \
+             --thread 4 --in1 in/S1.fq --out1 out/work/S1/S1.fq; } > \
+             out/work/S1/log 2>&1'"
+        );
+
+        // Host removal maps the trimmed reads
+        let args: Vec<String> = [
+            "run_megahit",
+            "--query",
+            "reads",
+            "-o",
+            "out",
+            "--trim",
+            "fastp",
+            "--host_index",
+            "ref/hg38",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+        let config = parse_args(args).unwrap();
+        assert_eq!(
+            config.stages,
+            vec![Stage::Trim, Stage::HostRemoval, Stage::Assemble]
+        );
+        assert_eq!(stage_programs(&config), vec!["fastp", "bowtie2"]);
+
+        let (prepared, jobs) = prep_jobs(&config, samples);
+        assert_eq!(prepared[0].single, vec!["out/work/S1/S1.nohost.fq.gz"]);
+        assert!(jobs[0].args[1].contains("-U out/work/S1/S1.fq"));
+
+        let args: Vec<String> = [
+            "run_megahit",
+            "--query",
+            "reads",
+            "--stages",
+            "host-removal,assemble",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+        assert!(parse_args(args).is_err());
+    }
+
+    #[test]
+    fn test_chain_job() {
+        let dir = tempfile::tempdir().unwrap();
+        let words = |cmd: &str| -> Vec<String> {
+            cmd.split(' ').map(|word| word.to_string()).collect()
+        };
+
+        let job = chain_job(
+            "S1",
+            dir.path().join("ok"),
+            "log.txt",
+            &[words("printf a|b | sed s/|/-/")],
+        );
+        assert_eq!(job.program, "bash");
+        assert!(job.args[1].starts_with("set -o pipefail && mkdir -p"));
+        assert!(job.args[1].contains("printf 'a|b' | sed 's/|/-/'"));
+        let status = std::process::Command::new(&job.program)
+            .args(&job.args)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        assert_eq!(
+            fs::read_to_string(dir.path().join("ok/log.txt")).unwrap(),
+            "a-b"
+        );
+
+        // The pipeline fails with its first command
+        let job = chain_job(
+            "S1",
+            dir.path().join("fail"),
+            "log.txt",
+            &[words("false | cat")],
+        );
+        let status = std::process::Command::new(&job.program)
+            .args(&job.args)
+            .status()
+            .unwrap();
+        assert!(!status.success());
+
+        let job = chain_job("S1", dir.path().join("x"), "log.txt", &[]);
+        assert_eq!(job.program, "sh");
+    }
+
+    #[test]
+    fn test_stages_here() {
+        let dir = tempfile::tempdir().unwrap();
+        let reads = dir.path().join("reads");
+        fs::create_dir(&reads).unwrap();
+        fs::write(reads.join("S1.fastq"), "@r1\nACGT\n+\nIIII\n").unwrap();
+
+        for opts in [["--docker", "megahit"], ["--nodes", "n1"]].iter() {
+            let mut args: Vec<String> = [
+                "run_megahit",
+                "--query",
+                &reads.display().to_string(),
+                "-o",
+                &dir.path().join("out").display().to_string(),
+                "--trim",
+                "fastp",
+                "--dry_run",
+            ]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+            args.extend(opts.iter().map(|opt| opt.to_string()));
+            let res = run_batch(parse_args(args).unwrap(), &Hooks::new());
+            assert!(res.unwrap_err().to_string().contains("trim"));
+        }
+
+        let args: Vec<String> = [
+            "run_megahit",
+            "--query",
+            &reads.display().to_string(),
+            "-o",
+            &dir.path().join("out").display().to_string(),
+            "--stages",
+            "assemble,stats,genes",
+            "--singularity",
+            "megahit.sif",
+            "--dry_run",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+        let res = run_batch(parse_args(args).unwrap(), &Hooks::new());
+        assert!(res
+            .unwrap_err()
+            .to_string()
+            .starts_with("Stages genes run on this machine"));
+    }
+
+    #[test]
+    fn test_post_jobs() {
+        let args: Vec<String> = [
+            "run_megahit",
+            "--query",
+            "reads",
+            "-o",
+            "out",
+            "-t",
+            "4",
+            "--quast=metaquast",
+            "--skip_stage",
+            "S2:qc",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+        let config = parse_args(args).unwrap();
+        assert_eq!(config.stages, vec![Stage::Assemble, Stage::Qc]);
+        assert_eq!(stage_programs(&config), vec!["metaquast.py"]);
+
+        let jobs: Vec<Job> = ["S1", "S2"]
+            .iter()
+            .map(|name| Job {
+                sample: name.to_string(),
+                program: "megahit".to_string(),
+                args: vec![],
+                out_dir: PathBuf::from("out").join(name),
+            })
+            .collect();
+        let post = post_jobs(&config, &[], &jobs.iter().collect::<Vec<_>>());
+        assert_eq!(post.len(), 1);
+        assert_eq!(post[0].out_dir, PathBuf::from("out/S1"));
+        assert_eq!(
+            post[0].command_line(),
+            "sh -c 'mkdir -p out/S1 && { metaquast.py -o out/S1/quast \
+             --threads 4 out/S1/final.contigs.fa; } > out/S1/stages.log 2>&1'"
+        );
+
+        // Coverage maps the reads the sample was assembled from
+        let args: Vec<String> =
+            ["run_megahit", "--query", "reads", "-o", "out", "--coverage"]
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect();
+        let config = parse_args(args).unwrap();
+        assert_eq!(stage_programs(&config), vec!["bwa-mem2", "samtools"]);
+        let samples = vec![Sample {
+            name: "S1".to_string(),
+            single: vec!["out/work/S1/S1.fq".to_string()],
+            ..Sample::default()
+        }];
+        let post = post_jobs(&config, &samples, &[&jobs[0]]);
+        assert!(post[0].args[1].contains(
+            "-o out/S1/coverage/part1.sam out/S1/coverage/index/contigs \
+             out/work/S1/S1.fq && "
+        ));
+        assert!(post[0].args[1].contains(
+            "samtools idxstats out/S1/coverage/reads.sorted.bam > \
+             out/S1/coverage/idxstats.tsv"
+        ));
+
+        // Binning takes the depth table along with it
+        let args: Vec<String> =
+            ["run_megahit", "--query", "reads", "--bin", "metabat2"]
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect();
+        let config = parse_args(args).unwrap();
+        assert_eq!(
+            config.stages,
+            vec![Stage::Assemble, Stage::Coverage, Stage::Bin]
+        );
+        let post = post_jobs(&config, &samples, &[&jobs[0]]);
+        assert!(post[0].args[1]
+            .contains("-o out/S1/bins/bin -a out/S1/coverage/depth.txt"));
+
+        // Not without the depth table
+        for stages in [
+            ["--stages", "assemble,bin"],
+            ["--stages", "assemble,coverage,bin --skip_stage S1:coverage"],
+        ] {
+            let args: Vec<String> = ["run_megahit", "--query", "reads"]
+                .iter()
+                .chain(&stages)
+                .flat_map(|arg| arg.split(' '))
+                .map(|arg| arg.to_string())
+                .collect();
+            let err = parse_args(args).unwrap_err().to_string();
+            assert!(err.contains("coverage"));
+        }
+
+        // The graph of the final k, which only a dry run guesses at, and
+        // none for an assembly without any
+        let args: Vec<String> = [
+            "run_megahit",
+            "--query",
+            "reads",
+            "--fastg",
+            "--k_max",
+            "99",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+        let mut config = parse_args(args).unwrap();
+        assert!(post_jobs(&config, &samples, &[&jobs[0]]).is_empty());
+        config.dry_run = true;
+        let post = post_jobs(&config, &samples, &[&jobs[0]]);
+        assert!(post[0].args[1].contains("contig2fastg 99"));
+    }
+
+    #[test]
+    fn test_dry_run_scripts() {
+        let dir = tempfile::tempdir().unwrap();
+        let reads = dir.path().join("reads");
+        fs::create_dir(&reads).unwrap();
+        for name in ["S1_R1.fastq", "S1_R2.fastq", "S2.fastq"].iter() {
+            fs::write(reads.join(name), "@r1\nACGT\n+\nIIII\n").unwrap();
+        }
+
+        for name in ["slurm", "pbs", "sge"].iter() {
+            let out = dir.path().join(name);
+            let args: Vec<String> = [
+                "run_megahit",
+                "--query",
+                &reads.display().to_string(),
+                "-o",
+                &out.display().to_string(),
+                "--scheduler",
+                name,
+                "--dry_run",
+            ]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+            run_batch(parse_args(args).unwrap(), &Hooks::new()).unwrap();
+
+            // The scripts to review, and nothing submitted
+            let scripts = out.join("plan").join(name);
+            assert!(scripts.join("S1.sh").is_file());
+            assert!(scripts.join("S2.sh").is_file());
+            assert!(out.join("plan/jobs.txt").is_file());
+            assert!(!out.join(name).exists());
+        }
+
+        // AWS Batch also has the requests it would make
+        let out = dir.path().join("aws");
+        let args: Vec<String> = [
+            "run_megahit",
+            "--query",
+            &reads.display().to_string(),
+            "-o",
+            &out.display().to_string(),
+            "--scheduler",
+            "aws-batch",
+            "--s3_prefix",
+            "s3://bucket/run",
+            "--aws_queue",
+            "q",
+            "--dry_run",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+        run_batch(parse_args(args).unwrap(), &Hooks::new()).unwrap();
+        let plan = out.join("plan/aws-batch");
+        assert!(plan.join("S1.sh").is_file());
+        assert!(plan.join("job_definition.json").is_file());
+        let submit: serde_json::Value = serde_json::from_str(
+            &fs::read_to_string(plan.join("S2.submit.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(submit["jobQueue"], "q");
+    }
+
+    #[test]
+    fn test_rerun_dry_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let reads = dir.path().join("reads");
+        fs::create_dir(&reads).unwrap();
+        fs::write(reads.join("S1.fastq"), "@r1\nACGT\n+\nIIII\n").unwrap();
+        let out = dir.path().join("out");
+        let failed = out.join("S1");
+        fs::create_dir_all(&failed).unwrap();
+        fs::write(failed.join("log"), "failed attempt").unwrap();
+
+        let args: Vec<String> = [
+            "run_megahit",
+            "--query",
+            &reads.display().to_string(),
+            "-o",
+            &out.display().to_string(),
+            "--dry_run",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+        let mut config = parse_args(args).unwrap();
+        config.action = Action::RerunFailed;
+        config.samples = vec!["S1".to_string()];
+        run_action(config).unwrap();
+
+        // Only the plan is written, the failed attempt is kept
+        assert!(out.join("plan/jobs.txt").is_file());
+        assert!(failed.join("log").is_file());
+    }
+
+    #[test]
+    fn test_sweep_scripts() {
+        let dir = tempfile::tempdir().unwrap();
+        let reads = dir.path().join("reads");
+        fs::create_dir(&reads).unwrap();
+        fs::write(reads.join("S1.fastq"), "@r1\nACGT\n+\nIIII\n").unwrap();
+
+        let out = dir.path().join("out");
+        let args: Vec<String> = [
+            "run_megahit",
+            "--query",
+            &reads.display().to_string(),
+            "-o",
+            &out.display().to_string(),
+            "--sweep",
+            "k_min=21,27",
+            "--scheduler",
+            "slurm",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+        let config = parse_args(args).unwrap();
+        let samples = discover_samples(&config).unwrap();
+        let jobs = make_jobs(&config, &samples).unwrap();
+        assert_eq!(jobs[0].sample, "k_min-21/S1");
+
+        // Written, not submitted, without --submit
+        assert!(submit_scheduler(&config, &scheduler::Slurm, &jobs)
+            .unwrap()
+            .is_none());
+        let script = out.join("slurm/k_min-21__S1.sh");
+        assert!(fs::read_to_string(script)
+            .unwrap()
+            .contains("--job-name=megahit-k_min-21__S1"));
+        assert!(out.join("slurm/k_min-27__S1.sh").is_file());
+    }
+
+    #[test]
+    fn test_is_megahit_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        assert!(!is_megahit_dir(dir));
+
+        fs::write(dir.join("options.json"), "{}").unwrap();
+        fs::write(dir.join("checkpoints.txt"), "1\tdone\n").unwrap();
+        assert!(is_megahit_dir(dir));
+    }
 }
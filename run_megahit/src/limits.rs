@@ -0,0 +1,276 @@
+use crate::Job;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::process::{self, Command, Stdio};
+
+type MyResult<T> = Result<T, Box<dyn Error>>;
+
+/// How a job's memory cap is enforced
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Method {
+    /// A transient systemd scope with MemoryMax, i.e., a cgroup v2 limit
+    /// on the job's resident memory, megahit_core processes included
+    Cgroup,
+    /// RLIMIT_AS on each process, which counts virtual memory
+    Rlimit,
+}
+
+/// A limit on the memory of each job, past which the job is killed (or
+/// its allocations fail) instead of the kernel OOM killer picking a victim
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryCap {
+    pub bytes: u64,
+    method: Method,
+}
+
+impl MemoryCap {
+    /// Uses a cgroup when this user can start systemd scopes, else rlimit
+    pub fn new(bytes: u64) -> MemoryCap {
+        let method = if has_user_scopes() {
+            Method::Cgroup
+        } else {
+            Method::Rlimit
+        };
+
+        MemoryCap { bytes, method }
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "{:.1} GB per job ({})",
+            self.bytes as f64 / 1e9,
+            match self.method {
+                Method::Cgroup => "cgroup",
+                Method::Rlimit => "rlimit",
+            }
+        )
+    }
+
+    /// The command to run `program` with `args` under the cap
+    pub fn command(&self, program: &str, args: &[String]) -> Command {
+        match self.method {
+            Method::Cgroup => {
+                let mut cmd = Command::new("systemd-run");
+                cmd.args(["--user", "--scope", "--quiet", "-p"])
+                    .arg(format!("MemoryMax={}", self.bytes))
+                    .arg("--")
+                    .arg(program)
+                    .args(args);
+                cmd
+            }
+            Method::Rlimit => {
+                let mut cmd = Command::new(program);
+                cmd.args(args);
+                set_rlimit(&mut cmd, self.bytes);
+                cmd
+            }
+        }
+    }
+
+    /// How many of a running job's processes the kernel has killed for
+    /// outgrowing its cgroup, from the cgroup's memory.events. None before
+    /// the job is in a cgroup of its own, or under rlimit, where
+    /// allocations fail instead.
+    pub fn oom_kills(&self, pid: u32) -> Option<u64> {
+        if self.method != Method::Cgroup {
+            return None;
+        }
+
+        let cgroup = cgroup_of(pid)?;
+        if Some(&cgroup) == cgroup_of(process::id()).as_ref() {
+            return None;
+        }
+
+        let events = Path::new("/sys/fs/cgroup")
+            .join(cgroup.trim_start_matches('/'))
+            .join("memory.events");
+        oom_kill_count(&fs::read_to_string(events).ok()?)
+    }
+}
+
+// --------------------------------------------------
+/// The cgroup v2 path of a process
+fn cgroup_of(pid: u32) -> Option<String> {
+    fs::read_to_string(format!("/proc/{}/cgroup", pid))
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .map(|path| path.to_string())
+}
+
+// --------------------------------------------------
+/// The oom_kill counter of a memory.events file
+fn oom_kill_count(events: &str) -> Option<u64> {
+    events
+        .lines()
+        .find_map(|line| line.strip_prefix("oom_kill "))
+        .and_then(|count| count.trim().parse().ok())
+}
+
+// --------------------------------------------------
+/// The job run through `nice` and/or `ionice` so a batch on a shared
+/// server leaves CPU and disk for everyone else. Both are inherited by
+/// the megahit_core processes megahit starts.
+pub fn prioritize(job: &Job, nice: Option<i32>, ionice: &[String]) -> Job {
+    let mut command: Vec<String> = vec![];
+    if let Some(nice) = nice {
+        command.extend(["nice".to_string(), "-n".to_string()]);
+        command.push(nice.to_string());
+    }
+    if !ionice.is_empty() {
+        command.push("ionice".to_string());
+        command.extend(ionice.iter().cloned());
+    }
+    command.push(job.program.to_string());
+    command.extend(job.args.iter().cloned());
+
+    Job {
+        sample: job.sample.to_string(),
+        out_dir: job.out_dir.clone(),
+        program: command.remove(0),
+        args: command,
+    }
+}
+
+// --------------------------------------------------
+/// Parses --ionice "idle" or "best-effort[:LEVEL]" into `ionice` options
+pub fn parse_ionice(val: &str) -> MyResult<Vec<String>> {
+    let (class, level) = match val.split_once(':') {
+        Some((class, level)) => (class, Some(level)),
+        _ => (val, None),
+    };
+
+    match (class, level) {
+        ("idle", None) => Ok(vec!["-c".to_string(), "3".to_string()]),
+        ("best-effort", None) => Ok(vec!["-c".to_string(), "2".to_string()]),
+        ("best-effort", Some(level))
+            if level.parse::<u8>().is_ok_and(|n| n <= 7) =>
+        {
+            Ok(vec![
+                "-c".to_string(),
+                "2".to_string(),
+                "-n".to_string(),
+                level.to_string(),
+            ])
+        }
+        _ => Err(From::from(format!(
+            "--ionice \"{}\" must be \"idle\" or \"best-effort[:0-7]\"",
+            val
+        ))),
+    }
+}
+
+// --------------------------------------------------
+/// Whether a job can be put in its own cgroup through the user's systemd
+fn has_user_scopes() -> bool {
+    std::path::Path::new("/sys/fs/cgroup/cgroup.controllers").exists()
+        && Command::new("systemd-run")
+            .args(["--user", "--scope", "--quiet", "true"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+}
+
+// --------------------------------------------------
+#[cfg(unix)]
+fn set_rlimit(cmd: &mut Command, bytes: u64) {
+    use std::os::unix::process::CommandExt;
+
+    let limit = libc::rlimit {
+        rlim_cur: bytes as libc::rlim_t,
+        rlim_max: bytes as libc::rlim_t,
+    };
+
+    // SAFETY: setrlimit is async-signal-safe, so it may run between fork
+    // and exec
+    unsafe {
+        cmd.pre_exec(move || {
+            if libc::setrlimit(libc::RLIMIT_AS, &limit) == 0 {
+                Ok(())
+            } else {
+                Err(std::io::Error::last_os_error())
+            }
+        });
+    }
+}
+
+#[cfg(not(unix))]
+fn set_rlimit(_cmd: &mut Command, _bytes: u64) {}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prioritize() {
+        let job = Job {
+            sample: "S1".to_string(),
+            out_dir: "out/S1".into(),
+            program: "megahit".to_string(),
+            args: vec!["-o".to_string(), "out/S1".to_string()],
+        };
+
+        let ionice = parse_ionice("best-effort:7").unwrap();
+        assert_eq!(
+            prioritize(&job, Some(10), &ionice).command_line(),
+            "nice -n 10 ionice -c 2 -n 7 megahit -o out/S1"
+        );
+        assert_eq!(
+            prioritize(&job, None, &parse_ionice("idle").unwrap())
+                .command_line(),
+            "ionice -c 3 megahit -o out/S1"
+        );
+        assert!(parse_ionice("best-effort:8").is_err());
+        assert!(parse_ionice("realtime").is_err());
+    }
+
+    #[test]
+    fn test_oom_kills() {
+        assert_eq!(
+            oom_kill_count("low 0\nhigh 0\nmax 12\noom 1\noom_kill 1\n"),
+            Some(1)
+        );
+        assert_eq!(oom_kill_count("low 0\n"), None);
+
+        // A job still in run_megahit's cgroup isn't counted
+        let mut child = Command::new("sleep").arg("5").spawn().unwrap();
+        for method in [Method::Cgroup, Method::Rlimit] {
+            let cap = MemoryCap { bytes: 1, method };
+            assert_eq!(cap.oom_kills(child.id()), None);
+        }
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn test_rlimit() {
+        let cap = MemoryCap {
+            bytes: 50_000_000,
+            method: Method::Rlimit,
+        };
+
+        // Allocating past the cap fails instead of succeeding
+        let small = cap
+            .command("sh", &["-c".to_string(), "true".to_string()])
+            .status()
+            .unwrap();
+        assert!(small.success());
+
+        let big = cap
+            .command(
+                "sh",
+                &[
+                    "-c".to_string(),
+                    "x=$(head -c 100000000 /dev/zero | tr '\\0' a)".to_string(),
+                ],
+            )
+            .stderr(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(!big.success());
+    }
+}
@@ -0,0 +1,207 @@
+use crate::report::{self, ProgressHandler};
+use indicatif::MultiProgress;
+use log::kv::{self, Key, Value, VisitSource};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// The ways the log can be written
+pub const FORMATS: &[&str] = &["text", "json"];
+
+/// Writes run_megahit's messages to STDERR, apart from the results it
+/// prints, so warnings stand out in job output and can be silenced with
+/// the rest
+struct Logger;
+
+static LOGGER: Logger = Logger;
+
+/// One JSON object per line instead of text
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// The progress bars being drawn, which the messages must go above
+static BARS: Mutex<Option<MultiProgress>> = Mutex::new(None);
+
+thread_local! {
+    /// Also told of each warning logged on this thread, while it works for
+    /// a batch, so that batches running side by side hear only their own
+    static HANDLERS: RefCell<Vec<Arc<dyn ProgressHandler>>> =
+        const { RefCell::new(Vec::new()) };
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+            && metadata.target().starts_with("run_megahit")
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        if record.level() == Level::Warn {
+            // Released before calling them, in case they log too
+            let handlers = warning_handlers();
+            if !handlers.is_empty() {
+                let message = record.args().to_string();
+                for handler in handlers {
+                    handler.on_warning(&message);
+                }
+            }
+        }
+
+        let line = if JSON.load(Ordering::Relaxed) {
+            format_json(record, report::now_secs())
+        } else {
+            format_text(record)
+        };
+        match BARS.lock().unwrap().as_ref() {
+            Some(bars) => {
+                let _ = bars.println(line);
+            }
+            _ => eprintln!("{}", line),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+// --------------------------------------------------
+/// Sends the log to STDERR: warnings and errors with --quiet, more with
+/// each -v. The JSON log has the job events that are debug in the text.
+pub fn init(level: LevelFilter, format: &str) {
+    let json = format == "json";
+    JSON.store(json, Ordering::Relaxed);
+
+    // Only the first call installs the logger, later ones change the level
+    let _ = log::set_logger(&LOGGER);
+    log::set_max_level(if json && level == LevelFilter::Info {
+        LevelFilter::Debug
+    } else {
+        level
+    });
+}
+
+// --------------------------------------------------
+/// The level for -q (quiet) or the number of -v given
+pub fn level(quiet: bool, verbose: u8) -> LevelFilter {
+    match (quiet, verbose) {
+        (true, _) => LevelFilter::Warn,
+        (_, 0) => LevelFilter::Info,
+        (_, 1) => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+// --------------------------------------------------
+/// Writes the log above these progress bars until given None
+pub fn attach(bars: Option<MultiProgress>) {
+    *BARS.lock().unwrap() = bars;
+}
+
+// --------------------------------------------------
+/// Tells these handlers of each warning logged on this thread, returning
+/// the ones they replace so that they can be put back
+pub fn forward_warnings(
+    handlers: Vec<Arc<dyn ProgressHandler>>,
+) -> Vec<Arc<dyn ProgressHandler>> {
+    HANDLERS.with(|current| current.replace(handlers))
+}
+
+// --------------------------------------------------
+/// The handlers told of this thread's warnings, for the threads it starts
+/// to forward theirs to as well
+pub fn warning_handlers() -> Vec<Arc<dyn ProgressHandler>> {
+    HANDLERS.with(|current| current.borrow().clone())
+}
+
+// --------------------------------------------------
+fn format_text(record: &Record) -> String {
+    match record.level() {
+        Level::Error => format!("Error: {}", record.args()),
+        Level::Warn => format!("Warning: {}", record.args()),
+        Level::Info => record.args().to_string(),
+        Level::Debug | Level::Trace => {
+            format!("[{}] {}", record.level(), record.args())
+        }
+    }
+}
+
+// --------------------------------------------------
+/// The record as one line of JSON with its time, level, message, and
+/// fields, e.g., "event" and "sample"
+fn format_json(record: &Record, time: f64) -> String {
+    let mut obj = serde_json::Map::new();
+    obj.insert("time".to_string(), serde_json::json!(time));
+    obj.insert(
+        "level".to_string(),
+        serde_json::json!(record.level().as_str().to_lowercase()),
+    );
+    obj.insert(
+        "message".to_string(),
+        serde_json::json!(record.args().to_string()),
+    );
+    let _ = record.key_values().visit(&mut Fields(&mut obj));
+    serde_json::Value::Object(obj).to_string()
+}
+
+/// Copies a record's fields into a JSON object
+struct Fields<'a>(&'a mut serde_json::Map<String, serde_json::Value>);
+
+impl<'kvs> VisitSource<'kvs> for Fields<'_> {
+    fn visit_pair(
+        &mut self,
+        key: Key<'kvs>,
+        value: Value<'kvs>,
+    ) -> Result<(), kv::Error> {
+        let value = if let Some(val) = value.to_u64() {
+            serde_json::json!(val)
+        } else if let Some(val) = value.to_i64() {
+            serde_json::json!(val)
+        } else if let Some(val) = value.to_f64() {
+            serde_json::json!(val)
+        } else if let Some(val) = value.to_bool() {
+            serde_json::json!(val)
+        } else {
+            serde_json::json!(value.to_string())
+        };
+        self.0.insert(key.as_str().to_string(), value);
+        Ok(())
+    }
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level() {
+        assert_eq!(level(false, 0), LevelFilter::Info);
+        assert_eq!(level(false, 1), LevelFilter::Debug);
+        assert_eq!(level(false, 3), LevelFilter::Trace);
+        assert_eq!(level(true, 2), LevelFilter::Warn);
+    }
+
+    #[test]
+    fn test_format() {
+        let fields: &[(&str, Value)] = &[
+            ("event", Value::from("job_end")),
+            ("sample", Value::from("S1")),
+            ("exit_code", Value::from(1)),
+            ("runtime_secs", Value::from(2.5)),
+        ];
+        let record = Record::builder()
+            .args(format_args!("S1 failed"))
+            .level(Level::Warn)
+            .key_values(&fields)
+            .build();
+
+        assert_eq!(format_text(&record), "Warning: S1 failed");
+        assert_eq!(
+            format_json(&record, 10.),
+            r#"{"event":"job_end","exit_code":1,"level":"warn","message":"S1 failed","runtime_secs":2.5,"sample":"S1","time":10.0}"#
+        );
+    }
+}
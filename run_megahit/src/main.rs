@@ -2,16 +2,16 @@ extern crate run_megahit;
 use std::process;
 
 fn main() {
-    let config = match run_megahit::get_args() {
-        Ok(c) => c,
-        Err(e) => {
-            println!("Error: {}", e);
-            process::exit(1);
-        }
-    };
-
-    if let Err(e) = run_megahit::run(config) {
+    let result = run_megahit::get_args().and_then(|config| {
+        run_megahit::init_logging(&config);
+        run_megahit::catch_interrupts();
+        run_megahit::run(config)
+    });
+    if let Err(e) = result {
         println!("Error: {}", e);
-        process::exit(1);
+        if let Some(hint) = e.hint() {
+            println!("Hint: {}", hint);
+        }
+        process::exit(e.exit_code());
     }
 }
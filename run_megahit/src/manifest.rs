@@ -0,0 +1,247 @@
+use crate::{host, parse_k_list, Sample, SampleOptions, PRESETS};
+use std::collections::HashMap;
+use std::error::Error;
+use std::{fs, path::Path};
+
+type MyResult<T> = Result<T, Box<dyn Error>>;
+
+/// Recognized column names, first being the canonical one
+const COLUMNS: &[&[&str]] = &[
+    &["sample", "name", "sample_id"],
+    &["r1", "fwd", "forward", "read1"],
+    &["r2", "rev", "reverse", "read2"],
+    &["single", "r", "unpaired", "reads"],
+    &["group"],
+    &["preset", "presets"],
+    &["k_list", "k-list"],
+    &["min_count", "min-count"],
+    &["memory", "mem"],
+];
+
+// --------------------------------------------------
+/// Reads a tab- (or, for *.csv, comma-) separated sample sheet whose
+/// header names the columns sample, r1, r2, single, and group, and
+/// optionally preset, k_list, min_count, and memory to override the
+/// batch's megahit options for a sample.
+/// Relative paths are taken from the sheet's directory and repeated
+/// sample names are merged into one multi-library sample.
+pub fn read_samples(path: &Path) -> MyResult<Vec<Sample>> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    let delim = match path.extension().and_then(|x| x.to_str()) {
+        Some("csv") => ',',
+        _ => '\t',
+    };
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut lines = contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'));
+
+    let header: Vec<String> = match lines.next() {
+        Some((_, line)) => {
+            line.split(delim).map(|x| x.trim().to_lowercase()).collect()
+        }
+        _ => return Err(From::from(format!("{} is empty", path.display()))),
+    };
+
+    let mut index: HashMap<&str, usize> = HashMap::new();
+    for (i, name) in header.iter().enumerate() {
+        if let Some(aliases) = COLUMNS.iter().find(|c| c.contains(&&**name)) {
+            index.insert(aliases[0], i);
+        }
+    }
+
+    if !index.contains_key("sample") {
+        return Err(From::from(format!(
+            "{} has no \"sample\" column",
+            path.display()
+        )));
+    }
+
+    let mut samples: Vec<Sample> = vec![];
+    for (line_num, line) in lines {
+        let fields: Vec<&str> = line.split(delim).map(|x| x.trim()).collect();
+        let get = |col: &str| {
+            index
+                .get(col)
+                .and_then(|&i| fields.get(i))
+                .filter(|val| !val.is_empty())
+                .map(|val| val.to_string())
+        };
+        let err = |msg: &str| -> Box<dyn Error> {
+            From::from(format!(
+                "{} line {}: {}",
+                path.display(),
+                line_num + 1,
+                msg
+            ))
+        };
+
+        let name = get("sample").ok_or_else(|| err("missing sample name"))?;
+        let resolve = |col: &str| -> MyResult<Option<String>> {
+            match get(col) {
+                Some(file) => {
+                    let file = base_dir.join(file);
+                    if !file.is_file() {
+                        return Err(err(&format!(
+                            "\"{}\" is not a file",
+                            file.display()
+                        )));
+                    }
+                    Ok(Some(file.display().to_string()))
+                }
+                _ => Ok(None),
+            }
+        };
+
+        let (fwd, rev, single) =
+            (resolve("r1")?, resolve("r2")?, resolve("single")?);
+        if fwd.is_some() != rev.is_some() {
+            return Err(err("r1 and r2 must be given together"));
+        }
+        if fwd.is_none() && single.is_none() {
+            return Err(err("no reads, need r1/r2 or single"));
+        }
+
+        let options = SampleOptions {
+            preset: match get("preset") {
+                Some(preset) if PRESETS.contains(&preset.as_str()) => {
+                    Some(preset)
+                }
+                Some(preset) => {
+                    return Err(err(&format!(
+                        "preset \"{}\" must be one of {}",
+                        preset,
+                        PRESETS.join(", ")
+                    )))
+                }
+                _ => None,
+            },
+            k_list: match get("k_list") {
+                Some(val) => Some(
+                    parse_k_list(&val.replace(';', ","))
+                        .map_err(|e| err(&e.to_string()))?,
+                ),
+                _ => None,
+            },
+            min_count: match get("min_count") {
+                Some(val) => match val.parse::<u32>() {
+                    Ok(n) if n > 0 => Some(n),
+                    _ => {
+                        return Err(err(&format!(
+                            "min_count \"{}\" must be a positive integer",
+                            val
+                        )))
+                    }
+                },
+                _ => None,
+            },
+            memory: match get("memory") {
+                Some(val) => Some(
+                    host::parse_memory("memory", &val).map_err(|e| err(&e))?,
+                ),
+                _ => None,
+            },
+        };
+
+        let group = get("group");
+        let pos = match samples.iter().position(|s| s.name == name) {
+            Some(pos) => pos,
+            _ => {
+                samples.push(Sample {
+                    name: name.to_string(),
+                    group: group.clone(),
+                    options: options.clone(),
+                    ..Default::default()
+                });
+                samples.len() - 1
+            }
+        };
+
+        let sample = &mut samples[pos];
+        if sample.group != group {
+            return Err(err(&format!(
+                "sample \"{}\" is in more than one group",
+                name
+            )));
+        }
+        if sample.options != options {
+            return Err(err(&format!(
+                "sample \"{}\" has different options on another line",
+                name
+            )));
+        }
+        sample.fwd.extend(fwd);
+        sample.rev.extend(rev);
+        sample.single.extend(single);
+    }
+
+    Ok(samples)
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_read_samples() {
+        let dir = env::temp_dir().join("run_megahit_manifest");
+        fs::create_dir_all(&dir).unwrap();
+        for file in &["a_1.fq", "a_2.fq", "b_1.fq", "b_2.fq", "c.fq"] {
+            fs::write(dir.join(file), "").unwrap();
+        }
+
+        let sheet = dir.join("samples.csv");
+        fs::write(
+            &sheet,
+            "Sample,R1,R2,Single,Group\n\
+             A,a_1.fq,a_2.fq,,site1\n\
+             B,b_1.fq,b_2.fq,,site1\n\
+             C,,,c.fq,\n",
+        )
+        .unwrap();
+
+        let samples = read_samples(&sheet).unwrap();
+        assert_eq!(samples.len(), 3);
+        assert_eq!(samples[0].name, "A");
+        assert_eq!(samples[0].group, Some("site1".to_string()));
+        assert!(samples[0].fwd[0].ends_with("a_1.fq"));
+        assert_eq!(samples[2].group, None);
+        assert_eq!(samples[2].single.len(), 1);
+
+        fs::write(&sheet, "sample,r1\nA,a_1.fq\n").unwrap();
+        assert!(read_samples(&sheet).is_err());
+
+        fs::write(&sheet, "sample,single\nA,missing.fq\n").unwrap();
+        assert!(read_samples(&sheet).is_err());
+
+        let sheet = dir.join("options.tsv");
+        fs::write(
+            &sheet,
+            "sample\tsingle\tpreset\tk_list\tmin_count\tmemory\n\
+             soil\tc.fq\tmeta-large\t21,41\t2\t80%\n\
+             isolate\tc.fq\t\t\t\t\n",
+        )
+        .unwrap();
+        let samples = read_samples(&sheet).unwrap();
+        assert_eq!(
+            samples[0].options,
+            SampleOptions {
+                preset: Some("meta-large".to_string()),
+                k_list: Some(vec![21, 41]),
+                min_count: Some(2),
+                memory: Some(0.8),
+            }
+        );
+        assert_eq!(samples[1].options, SampleOptions::default());
+
+        fs::write(&sheet, "sample\tsingle\tk_list\nA\tc.fq\t21,40\n").unwrap();
+        assert!(read_samples(&sheet).is_err());
+        fs::write(&sheet, "sample\tsingle\tpreset\nA\tc.fq\tfast\n").unwrap();
+        assert!(read_samples(&sheet).is_err());
+    }
+}
@@ -0,0 +1,262 @@
+use crate::RunMegahitError;
+use regex::Regex;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+type MyResult<T> = Result<T, Box<dyn Error>>;
+
+/// A megahit release, e.g., (1, 2, 9)
+pub type Version = (u32, u32, u32);
+
+/// What to do with a flag the installed megahit doesn't take
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Otherwise {
+    /// Leave it out, it only mattered to other releases
+    Drop,
+    /// Stop, the batch would not be what was asked for
+    Refuse,
+}
+
+/// A flag only some megahit releases take
+struct Gated {
+    flag: &'static str,
+    takes_value: bool,
+    /// The first release with it
+    since: Option<Version>,
+    /// The first release without it
+    until: Option<Version>,
+    otherwise: Otherwise,
+}
+
+const GATED: &[Gated] = &[
+    // 1.2 finds the read length itself and dropped GPU support
+    Gated {
+        flag: "--max-read-len",
+        takes_value: true,
+        since: None,
+        until: Some((1, 2, 0)),
+        otherwise: Otherwise::Drop,
+    },
+    Gated {
+        flag: "--cpu-only",
+        takes_value: false,
+        since: None,
+        until: Some((1, 2, 0)),
+        otherwise: Otherwise::Drop,
+    },
+    Gated {
+        flag: "--use-gpu",
+        takes_value: false,
+        since: None,
+        until: Some((1, 2, 0)),
+        otherwise: Otherwise::Refuse,
+    },
+    Gated {
+        flag: "--gpu-mem",
+        takes_value: true,
+        since: None,
+        until: Some((1, 2, 0)),
+        otherwise: Otherwise::Refuse,
+    },
+    // Hardware acceleration became optional in 1.2
+    Gated {
+        flag: "--no-hw-accel",
+        takes_value: false,
+        since: Some((1, 2, 0)),
+        until: None,
+        otherwise: Otherwise::Drop,
+    },
+];
+
+// --------------------------------------------------
+/// Where `program` (a path, or a name to look up in PATH) is and the
+/// version it reports, e.g., "MEGAHIT v1.2.9"
+pub fn check(program: &str) -> MyResult<(PathBuf, String)> {
+    let path = locate(program).ok_or_else(|| RunMegahitError::SpawnFailed {
+        program: program.to_string(),
+        reason: "not found".to_string(),
+    })?;
+
+    let output =
+        Command::new(&path).arg("--version").output().map_err(|e| {
+            RunMegahitError::SpawnFailed {
+                program: path.display().to_string(),
+                reason: e.to_string(),
+            }
+        })?;
+    let version = [&output.stdout, &output.stderr]
+        .iter()
+        .filter_map(|out| {
+            String::from_utf8_lossy(out)
+                .lines()
+                .map(str::trim)
+                .find(|line| !line.is_empty())
+                .map(|line| line.to_string())
+        })
+        .next();
+
+    match version {
+        Some(version) if output.status.success() => Ok((path, version)),
+        _ => Err(From::from(format!(
+            "\"{} --version\" failed ({}), is it megahit?",
+            path.display(),
+            output.status
+        ))),
+    }
+}
+
+// --------------------------------------------------
+/// The release in a version string like "MEGAHIT v1.2.9"
+pub fn parse_version(version: &str) -> Option<Version> {
+    let re = Regex::new(r"v?(\d+)\.(\d+)(?:\.(\d+))?").unwrap();
+    let caps = re.captures(version)?;
+    let num = |i: usize| {
+        caps.get(i)
+            .map_or(Some(0), |m| m.as_str().parse::<u32>().ok())
+    };
+    Some((num(1)?, num(2)?, num(3)?))
+}
+
+// --------------------------------------------------
+/// The megahit arguments the `version` release takes, leaving out the
+/// flags it doesn't need and refusing those it can't do without. Also
+/// returns a note for each flag left out.
+pub fn gate(
+    version: Version,
+    args: &[String],
+) -> MyResult<(Vec<String>, Vec<String>)> {
+    let (mut kept, mut notes) = (vec![], vec![]);
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let gated = GATED.iter().find(|gated| {
+            arg == gated.flag
+                && (gated.since.is_some_and(|since| version < since)
+                    || gated.until.is_some_and(|until| version >= until))
+        });
+
+        match gated {
+            Some(gated) if gated.otherwise == Otherwise::Refuse => {
+                return Err(From::from(format!(
+                    "megahit {} does not support {}",
+                    format_version(version),
+                    gated.flag
+                )))
+            }
+            Some(gated) => {
+                if gated.takes_value {
+                    iter.next();
+                }
+                notes.push(format!(
+                    "Leaving out {}, which megahit {} does not take",
+                    gated.flag,
+                    format_version(version)
+                ));
+            }
+            _ => kept.push(arg.to_string()),
+        }
+    }
+
+    Ok((kept, notes))
+}
+
+// --------------------------------------------------
+fn format_version((major, minor, patch): Version) -> String {
+    format!("{}.{}.{}", major, minor, patch)
+}
+
+// --------------------------------------------------
+/// The executable `program` names, as a path when it has a slash and
+/// otherwise from PATH as a shell would find it
+pub fn locate(program: &str) -> Option<PathBuf> {
+    if program.contains('/') {
+        let path = PathBuf::from(program);
+        return is_executable(&path).then_some(path);
+    }
+
+    std::env::var_os("PATH").and_then(|paths| {
+        std::env::split_paths(&paths)
+            .map(|dir| dir.join(program))
+            .find(|path| is_executable(path))
+    })
+}
+
+// --------------------------------------------------
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gate() {
+        assert_eq!(parse_version("MEGAHIT v1.2.9"), Some((1, 2, 9)));
+        assert_eq!(parse_version("megahit 1.1"), Some((1, 1, 0)));
+        assert_eq!(parse_version("megahit"), None);
+
+        let args: Vec<String> = [
+            "-o",
+            "out",
+            "--max-read-len",
+            "250",
+            "--no-hw-accel",
+            "-r",
+            "a",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+
+        let (kept, notes) = gate((1, 2, 9), &args).unwrap();
+        assert_eq!(kept, vec!["-o", "out", "--no-hw-accel", "-r", "a"]);
+        assert_eq!(notes.len(), 1);
+
+        let (kept, _) = gate((1, 1, 3), &args).unwrap();
+        assert_eq!(kept, vec!["-o", "out", "--max-read-len", "250", "-r", "a"]);
+
+        let gpu = vec!["--use-gpu".to_string()];
+        assert!(gate((1, 1, 3), &gpu).is_ok());
+        assert!(gate((1, 2, 0), &gpu).is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_check() {
+        use std::fs;
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join("run_megahit_megahit");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let fake = dir.join("megahit");
+        fs::write(&fake, "#!/bin/sh\necho 'MEGAHIT v1.2.9'\n").unwrap();
+        fs::set_permissions(&fake, fs::Permissions::from_mode(0o755)).unwrap();
+        let (path, version) = check(&fake.display().to_string()).unwrap();
+        assert_eq!(path, fake);
+        assert_eq!(version, "MEGAHIT v1.2.9");
+
+        let broken = dir.join("broken");
+        fs::write(&broken, "#!/bin/sh\nexit 1\n").unwrap();
+        fs::set_permissions(&broken, fs::Permissions::from_mode(0o755))
+            .unwrap();
+        assert!(check(&broken.display().to_string()).is_err());
+
+        let data = dir.join("data");
+        fs::write(&data, "").unwrap();
+        assert!(check(&data.display().to_string()).is_err());
+        assert!(check("run_megahit_no_such_program").is_err());
+    }
+}
@@ -0,0 +1,134 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+type MyResult<T> = Result<T, Box<dyn Error>>;
+
+/// What megahit leaves in a sample's out_dir that the assembly no longer
+/// needs once it is done
+const INTERMEDIATES: &[&str] = &["intermediate_contigs", "tmp"];
+
+// --------------------------------------------------
+/// The finished megahit runs in out_dir (a sweep's one level deeper) by
+/// sample name, e.g., "S1" or "k_min-21/S1", in name order
+pub fn assemblies(out_dir: &Path) -> MyResult<Vec<(String, PathBuf)>> {
+    if !out_dir.is_dir() {
+        return Err(From::from(format!(
+            "\"{}\" is not a directory",
+            out_dir.display()
+        )));
+    }
+
+    let mut found = vec![];
+    for dir in subdirs(out_dir)? {
+        if is_finished(&dir) {
+            found.push((name(out_dir, &dir), dir));
+        } else {
+            for dir in subdirs(&dir)? {
+                if is_finished(&dir) {
+                    found.push((name(out_dir, &dir), dir));
+                }
+            }
+        }
+    }
+    found.sort();
+
+    Ok(found)
+}
+
+// --------------------------------------------------
+/// Removes the intermediate files of every finished assembly in out_dir
+/// and returns how many bytes that freed
+pub fn clean(out_dir: &Path) -> MyResult<u64> {
+    let mut freed = 0;
+    for (sample, dir) in assemblies(out_dir)? {
+        for name in INTERMEDIATES {
+            let path = dir.join(name);
+            if path.is_dir() {
+                let size = dir_size(&path);
+                fs::remove_dir_all(&path)
+                    .map_err(|e| format!("{}: {}", path.display(), e))?;
+                println!("Removed {}/{}", sample, name);
+                freed += size;
+            }
+        }
+    }
+
+    Ok(freed)
+}
+
+// --------------------------------------------------
+fn is_finished(dir: &Path) -> bool {
+    dir.join("done").is_file() && dir.join("final.contigs.fa").is_file()
+}
+
+// --------------------------------------------------
+fn name(out_dir: &Path, dir: &Path) -> String {
+    dir.strip_prefix(out_dir)
+        .unwrap_or(dir)
+        .to_string_lossy()
+        .to_string()
+}
+
+// --------------------------------------------------
+fn subdirs(dir: &Path) -> MyResult<Vec<PathBuf>> {
+    let entries =
+        fs::read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    Ok(entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect())
+}
+
+// --------------------------------------------------
+fn dir_size(dir: &Path) -> u64 {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| match entry.metadata() {
+                    Ok(meta) if meta.is_dir() => dir_size(&entry.path()),
+                    Ok(meta) => meta.len(),
+                    _ => 0,
+                })
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assemblies_and_clean() {
+        let out_dir = std::env::temp_dir().join("run_megahit_outputs");
+        let _ = fs::remove_dir_all(&out_dir);
+
+        for sample in &["S1", "k_min-21/S2", "partial"] {
+            let dir = out_dir.join(sample);
+            fs::create_dir_all(dir.join("intermediate_contigs")).unwrap();
+            fs::write(dir.join("intermediate_contigs/k21.contigs.fa"), "ACGT")
+                .unwrap();
+            if *sample != "partial" {
+                fs::write(dir.join("done"), "").unwrap();
+                fs::write(dir.join("final.contigs.fa"), ">c\nACGT\n").unwrap();
+            }
+        }
+
+        let found: Vec<String> = assemblies(&out_dir)
+            .unwrap()
+            .into_iter()
+            .map(|(sample, _)| sample)
+            .collect();
+        assert_eq!(found, vec!["S1", "k_min-21/S2"]);
+
+        assert_eq!(clean(&out_dir).unwrap(), 8);
+        assert!(!out_dir.join("S1/intermediate_contigs").exists());
+        assert!(out_dir.join("S1/final.contigs.fa").exists());
+        assert!(out_dir.join("partial/intermediate_contigs").exists());
+        assert!(assemblies(&out_dir.join("missing")).is_err());
+    }
+}
@@ -0,0 +1,195 @@
+use serde::{Serialize, Serializer};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+type MyResult<T> = Result<T, Box<dyn Error>>;
+
+/// One step of the per-sample workflow, listed in execution order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Stage {
+    Trim,
+    HostRemoval,
+    Assemble,
+    Stats,
+    Coverage,
+    Bin,
+    Genes,
+    Classify,
+    Graph,
+    Qc,
+}
+
+pub const STAGE_NAMES: &[&str] = &[
+    "trim",
+    "host-removal",
+    "assemble",
+    "stats",
+    "coverage",
+    "bin",
+    "genes",
+    "classify",
+    "graph",
+    "qc",
+];
+
+pub const PIPELINE_NAMES: &[&str] =
+    &["reads-to-contigs", "reads-to-mags", "viral-discovery"];
+
+impl FromStr for Stage {
+    type Err = String;
+
+    fn from_str(val: &str) -> Result<Stage, String> {
+        match val {
+            "trim" => Ok(Stage::Trim),
+            "host-removal" => Ok(Stage::HostRemoval),
+            "assemble" => Ok(Stage::Assemble),
+            "stats" => Ok(Stage::Stats),
+            "coverage" => Ok(Stage::Coverage),
+            "bin" => Ok(Stage::Bin),
+            "genes" => Ok(Stage::Genes),
+            "classify" => Ok(Stage::Classify),
+            "graph" => Ok(Stage::Graph),
+            "qc" => Ok(Stage::Qc),
+            _ => Err(format!("Unknown stage \"{}\"", val)),
+        }
+    }
+}
+
+impl Serialize for Stage {
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        ser.collect_str(self)
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Stage::Trim => "trim",
+            Stage::HostRemoval => "host-removal",
+            Stage::Assemble => "assemble",
+            Stage::Stats => "stats",
+            Stage::Coverage => "coverage",
+            Stage::Bin => "bin",
+            Stage::Genes => "genes",
+            Stage::Classify => "classify",
+            Stage::Graph => "graph",
+            Stage::Qc => "qc",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// --------------------------------------------------
+/// The stages behind each named end-to-end pipeline
+pub fn preset(name: &str) -> MyResult<Vec<Stage>> {
+    let stages = match name {
+        "reads-to-contigs" => {
+            vec![Stage::Trim, Stage::Assemble, Stage::Stats, Stage::Qc]
+        }
+        "reads-to-mags" => vec![
+            Stage::Trim,
+            Stage::HostRemoval,
+            Stage::Assemble,
+            Stage::Stats,
+            Stage::Coverage,
+            Stage::Bin,
+            Stage::Qc,
+        ],
+        "viral-discovery" => vec![
+            Stage::Trim,
+            Stage::HostRemoval,
+            Stage::Assemble,
+            Stage::Stats,
+            Stage::Genes,
+            Stage::Classify,
+        ],
+        _ => return Err(From::from(format!("Unknown pipeline \"{}\"", name))),
+    };
+
+    Ok(stages)
+}
+
+// --------------------------------------------------
+/// Parses a comma-separated stage list into execution order
+pub fn parse_stages(names: &[String]) -> MyResult<Vec<Stage>> {
+    let mut stages = names
+        .iter()
+        .map(|name| name.trim().parse::<Stage>())
+        .collect::<Result<Vec<_>, _>>()?;
+    stages.sort();
+    stages.dedup();
+
+    if !stages.contains(&Stage::Assemble) {
+        return Err(From::from("The stage list must include \"assemble\""));
+    }
+
+    Ok(stages)
+}
+
+// --------------------------------------------------
+/// Parses a `sample:stage` directive that drops one stage for one sample
+pub fn parse_skip(val: &str, stages: &[Stage]) -> MyResult<(String, Stage)> {
+    let (sample, stage) = match val.rsplit_once(':') {
+        Some((sample, stage)) if !sample.is_empty() => (sample, stage),
+        _ => {
+            return Err(From::from(format!(
+                "Skip directive \"{}\" must look like \"sample:stage\"",
+                val
+            )))
+        }
+    };
+
+    let stage: Stage = stage.trim().parse()?;
+    if stage == Stage::Assemble {
+        return Err(From::from(format!(
+            "Cannot skip \"assemble\" for sample \"{}\"",
+            sample
+        )));
+    }
+
+    if !stages.contains(&stage) {
+        return Err(From::from(format!(
+            "Cannot skip \"{}\" for sample \"{}\", it is not part of this run",
+            stage, sample
+        )));
+    }
+
+    Ok((sample.to_string(), stage))
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stages() {
+        let names: Vec<String> =
+            vec!["stats".into(), "assemble".into(), "trim".into()];
+        assert_eq!(
+            parse_stages(&names).unwrap(),
+            vec![Stage::Trim, Stage::Assemble, Stage::Stats]
+        );
+
+        assert!(parse_stages(&["trim".to_string()]).is_err());
+        assert!(parse_stages(&["assembly".to_string()]).is_err());
+
+        for name in PIPELINE_NAMES {
+            assert!(preset(name).unwrap().contains(&Stage::Assemble));
+        }
+
+        let stages = vec![Stage::Trim, Stage::Assemble];
+        assert_eq!(
+            parse_skip("S1:trim", &stages).unwrap(),
+            ("S1".to_string(), Stage::Trim)
+        );
+        assert!(parse_skip("S1:assemble", &stages).is_err());
+        assert!(parse_skip("S1:stats", &stages).is_err());
+        assert!(parse_skip("trim", &stages).is_err());
+
+        for name in STAGE_NAMES {
+            assert_eq!(&name.parse::<Stage>().unwrap().to_string(), name);
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use crate::logging;
+use crate::report::{format_secs, JobResult};
+use crate::Job;
+use indicatif::{
+    MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle,
+};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// How often the running jobs' clocks are redrawn
+const TICK: Duration = Duration::from_millis(500);
+
+/// Live view of a batch on STDERR: one bar for the batch with its ETA and
+/// a spinner under it for each running job. Nothing is drawn when STDERR
+/// is not a terminal, so logs only get the plain messages.
+pub struct Progress {
+    multi: MultiProgress,
+    batch: ProgressBar,
+    num_concurrent: usize,
+    state: Mutex<Tally>,
+}
+
+#[derive(Default)]
+struct Tally {
+    /// Spinners by job index
+    running: HashMap<usize, ProgressBar>,
+    /// Runtimes of the finished jobs
+    durations: Vec<f64>,
+}
+
+impl Progress {
+    pub fn new(num_jobs: usize, num_concurrent: usize, show: bool) -> Progress {
+        let target = if show {
+            ProgressDrawTarget::stderr()
+        } else {
+            ProgressDrawTarget::hidden()
+        };
+        let multi = MultiProgress::with_draw_target(target);
+        if !multi.is_hidden() {
+            logging::attach(Some(multi.clone()));
+        }
+        let batch = multi.add(ProgressBar::new(num_jobs as u64));
+        batch.set_style(
+            ProgressStyle::with_template(
+                "[{elapsed_precise}] {bar:30} {pos}/{len} done, {msg}",
+            )
+            .unwrap(),
+        );
+
+        let progress = Progress {
+            multi,
+            batch,
+            num_concurrent: num_concurrent.max(1),
+            state: Mutex::new(Tally::default()),
+        };
+        progress.update(&progress.state.lock().unwrap());
+        progress
+    }
+
+    /// Adds a spinner for job `i`
+    pub fn started(&self, i: usize, job: &Job) {
+        let mut state = self.state.lock().unwrap();
+        let spinner = self.multi.add(ProgressBar::new_spinner());
+        spinner.set_style(
+            ProgressStyle::with_template(
+                "  {spinner} {prefix} [{elapsed_precise}] {msg}",
+            )
+            .unwrap(),
+        );
+        spinner.set_prefix(job.sample.to_string());
+        if let Some(mean) = mean(&state.durations) {
+            spinner.set_message(format!("~{} per job", format_secs(mean)));
+        }
+        spinner.enable_steady_tick(TICK);
+        state.running.insert(i, spinner);
+        self.update(&state);
+    }
+
+    /// Removes job `i`'s spinner and counts its runtime toward the ETA
+    pub fn finished(&self, i: usize, res: &JobResult) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(spinner) = state.running.remove(&i) {
+            spinner.finish_and_clear();
+            self.multi.remove(&spinner);
+        }
+
+        if res.exit_code == Some(0) {
+            state.durations.push(res.runtime_secs);
+        }
+        self.batch.inc(1);
+        self.update(&state);
+    }
+
+    /// Clears the display once the batch is over
+    pub fn finish(&self) {
+        let mut state = self.state.lock().unwrap();
+        for (_, spinner) in state.running.drain() {
+            spinner.finish_and_clear();
+        }
+        self.batch.finish_and_clear();
+        logging::attach(None);
+    }
+
+    fn update(&self, state: &Tally) {
+        let num_jobs = self.batch.length().unwrap_or(0) as usize;
+        let num_done = self.batch.position() as usize;
+        let num_running = state.running.len();
+        let num_queued = num_jobs.saturating_sub(num_done + num_running);
+
+        let eta = match mean(&state.durations) {
+            Some(mean) => format!(
+                ", ETA {}",
+                format_secs(eta(
+                    mean,
+                    num_running + num_queued,
+                    self.num_concurrent
+                ))
+            ),
+            _ => "".to_string(),
+        };
+
+        self.batch.set_message(format!(
+            "{} running, {} queued{}",
+            num_running, num_queued, eta
+        ));
+    }
+}
+
+// --------------------------------------------------
+fn mean(vals: &[f64]) -> Option<f64> {
+    if vals.is_empty() {
+        None
+    } else {
+        Some(vals.iter().sum::<f64>() / vals.len() as f64)
+    }
+}
+
+// --------------------------------------------------
+/// Time to finish `remaining` jobs of `mean` seconds run `concurrent` at a
+/// time
+fn eta(mean: f64, remaining: usize, concurrent: usize) -> f64 {
+    let rounds = remaining.div_ceil(concurrent);
+    mean * rounds as f64
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eta() {
+        assert_eq!(mean(&[]), None);
+        assert_eq!(mean(&[10., 20.]), Some(15.));
+        assert_eq!(eta(60., 0, 4), 0.);
+        assert_eq!(eta(60., 4, 4), 60.);
+        assert_eq!(eta(60., 5, 4), 120.);
+    }
+}
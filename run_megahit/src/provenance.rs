@@ -0,0 +1,164 @@
+use crate::report;
+use crate::{public_args, Config};
+use serde::Serialize;
+use std::error::Error;
+#[cfg(unix)]
+use std::ffi::CStr;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+type MyResult<T> = Result<T, Box<dyn Error>>;
+
+/// What made the assemblies, relative to out_dir
+pub const PROVENANCE_FILE: &str = "provenance.json";
+
+/// Who ran the batch where, with what, and when, so the assemblies can be
+/// traced back long after the fact
+#[derive(Debug, Serialize)]
+pub struct Provenance<'a> {
+    pub run_megahit_version: &'static str,
+    /// What megahit said its version was, if it runs here
+    pub megahit_version: Option<String>,
+    pub hostname: Option<String>,
+    pub user: Option<String>,
+    /// Relative paths in the command line and config are from here
+    pub cwd: PathBuf,
+    /// Without the options that may hold secrets
+    pub command_line: Vec<String>,
+    /// ISO 8601 UTC
+    pub started: String,
+    /// Not set until the batch ends
+    pub finished: Option<String>,
+    pub config: &'a Config,
+}
+
+impl<'a> Provenance<'a> {
+    /// The batch as it starts now
+    pub fn new(
+        config: &'a Config,
+        megahit_version: Option<String>,
+    ) -> MyResult<Provenance<'a>> {
+        Ok(Provenance {
+            run_megahit_version: env!("CARGO_PKG_VERSION"),
+            megahit_version,
+            hostname: hostname(),
+            user: user(),
+            cwd: env::current_dir()?,
+            command_line: public_args(&config.args),
+            started: report::format_timestamp(report::now_secs()),
+            finished: None,
+            config,
+        })
+    }
+
+    /// Marks the batch as having ended now
+    pub fn finish(&mut self) {
+        self.finished = Some(report::format_timestamp(report::now_secs()));
+    }
+
+    pub fn write(&self, out_dir: &Path) -> MyResult<()> {
+        let path = out_dir.join(PROVENANCE_FILE);
+        fs::write(&path, serde_json::to_string_pretty(self)? + "\n")
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(())
+    }
+}
+
+// --------------------------------------------------
+#[cfg(unix)]
+fn hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    // SAFETY: gethostname writes at most len bytes into buf, and the last
+    // byte is left zero so the name is NUL-terminated even if truncated
+    let res =
+        unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len() - 1) };
+    if res != 0 {
+        return None;
+    }
+
+    CStr::from_bytes_until_nul(&buf)
+        .ok()
+        .map(|name| name.to_string_lossy().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+#[cfg(not(unix))]
+fn hostname() -> Option<String> {
+    Some("unknown".to_string())
+}
+
+// --------------------------------------------------
+/// $USER, or the name of the uid when it isn't set (e.g., under cron)
+fn user() -> Option<String> {
+    if let Some(name) = ["USER", "LOGNAME"]
+        .iter()
+        .find_map(|var| env::var(var).ok().filter(|v| !v.is_empty()))
+    {
+        return Some(name);
+    }
+
+    uid_name()
+}
+
+// --------------------------------------------------
+#[cfg(unix)]
+fn uid_name() -> Option<String> {
+    // SAFETY: getuid cannot fail, and getpwuid returns null or a pointer
+    // to a static entry that stays valid until its next call
+    let entry = unsafe { libc::getpwuid(libc::getuid()) };
+    if entry.is_null() {
+        return None;
+    }
+    // SAFETY: entry is not null, its pw_name is NUL-terminated, and the
+    // name is copied out before anything could call getpwuid again
+    let name = unsafe { CStr::from_ptr((*entry).pw_name) };
+    Some(name.to_string_lossy().to_string())
+}
+
+#[cfg(not(unix))]
+fn uid_name() -> Option<String> {
+    Some("unknown".to_string())
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ConfigBuilder;
+
+    #[test]
+    fn test_provenance() {
+        let dir = env::temp_dir().join("run_megahit_provenance");
+        fs::create_dir_all(&dir).unwrap();
+        let config = ConfigBuilder::new()
+            .query("reads")
+            .out_dir(&dir)
+            .k_list(&[21, 41])
+            .build()
+            .unwrap();
+
+        let mut provenance =
+            Provenance::new(&config, Some("MEGAHIT v1.2.9".to_string()))
+                .unwrap();
+        provenance.write(&dir).unwrap();
+        let read = || -> serde_json::Value {
+            let contents =
+                fs::read_to_string(dir.join(PROVENANCE_FILE)).unwrap();
+            serde_json::from_str(&contents).unwrap()
+        };
+
+        let json = read();
+        assert_eq!(json["run_megahit_version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(json["megahit_version"], "MEGAHIT v1.2.9");
+        assert_eq!(json["command_line"][0], "run_megahit");
+        assert_eq!(json["config"]["k_list"][1], 41);
+        assert!(json["started"].as_str().unwrap().ends_with('Z'));
+        assert!(json["finished"].is_null());
+
+        provenance.finish();
+        provenance.write(&dir).unwrap();
+        assert!(read()["finished"].is_string());
+    }
+}
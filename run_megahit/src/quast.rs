@@ -0,0 +1,95 @@
+use crate::report::Metric;
+use crate::trim::args;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+type MyResult<T> = Result<T, Box<dyn Error>>;
+
+/// The evaluators --quast takes
+pub const TOOLS: &[&str] = &["quast", "metaquast"];
+
+/// Where QUAST writes its reports, relative to a sample's out_dir
+pub const QUAST_DIR: &str = "quast";
+
+/// The QUAST reports of the batch side by side, relative to out_dir
+pub const SUMMARY_FILE: &str = "quast_summary.tsv";
+
+// --------------------------------------------------
+/// The program of a --quast value
+pub fn program(tool: &str) -> String {
+    format!("{}.py", tool)
+}
+
+// --------------------------------------------------
+/// QUAST on the sample's final contigs
+pub fn command(tool: &str, dir: &Path, threads: Option<u32>) -> Vec<String> {
+    let mut cmd = args(&[
+        &program(tool),
+        "-o",
+        &dir.join(QUAST_DIR).display().to_string(),
+    ]);
+    if let Some(threads) = threads {
+        cmd.extend(args(&["--threads", &threads.to_string()]));
+    }
+    cmd.push(dir.join("final.contigs.fa").display().to_string());
+    cmd
+}
+
+// --------------------------------------------------
+/// The metrics of the report QUAST left in a sample's out_dir, in its
+/// order. metaQUAST puts the one that covers every reference in
+/// combined_reference.
+pub fn read_report(dir: &Path) -> MyResult<Vec<Metric>> {
+    let quast_dir = dir.join(QUAST_DIR);
+    let path = [
+        quast_dir.join("report.tsv"),
+        quast_dir.join("combined_reference").join("report.tsv"),
+    ]
+    .iter()
+    .find(|path| path.is_file())
+    .cloned()
+    .ok_or_else(|| format!("No QUAST report in \"{}\"", quast_dir.display()))?;
+
+    let contents = fs::read_to_string(&path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(contents
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .filter(|(name, _)| *name != "Assembly")
+        .map(|(name, value)| Metric {
+            stage: "qc".to_string(),
+            name: name.to_string(),
+            value: value.trim().to_string(),
+        })
+        .collect())
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quast() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            command("quast", Path::new("out/S1"), Some(4)).join(" "),
+            "quast.py -o out/S1/quast --threads 4 out/S1/final.contigs.fa"
+        );
+
+        let s1 = dir.path().join("S1");
+        assert!(read_report(&s1).is_err());
+        fs::create_dir_all(s1.join("quast/combined_reference")).unwrap();
+        fs::write(
+            s1.join("quast/combined_reference/report.tsv"),
+            "Assembly\tfinal.contigs\n# contigs\t12\nN50\t2048\n",
+        )
+        .unwrap();
+
+        let metrics = read_report(&s1).unwrap();
+        assert_eq!(metrics.len(), 2);
+        assert_eq!(metrics[1].name, "N50");
+        assert_eq!(metrics[1].value, "2048");
+    }
+}
@@ -0,0 +1,193 @@
+use flate2::read::MultiGzDecoder;
+use std::error::Error;
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::Path,
+};
+
+type MyResult<T> = Result<T, Box<dyn Error>>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    Plain,
+    Gzip,
+    Bzip2,
+}
+
+impl Compression {
+    /// The compression implied by the file name
+    pub fn from_name(path: &str) -> Compression {
+        let ext = Path::new(path)
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase());
+
+        match ext.as_deref() {
+            Some("gz") => Compression::Gzip,
+            Some("bz2") => Compression::Bzip2,
+            _ => Compression::Plain,
+        }
+    }
+
+    /// Typical size of FASTQ once decompressed, relative to the file
+    pub fn expansion(&self) -> u64 {
+        match self {
+            Compression::Plain => 1,
+            Compression::Gzip => 4,
+            Compression::Bzip2 => 5,
+        }
+    }
+}
+
+// --------------------------------------------------
+/// The compression actually used, judged by the file's magic bytes
+pub fn sniff(path: &str) -> MyResult<Compression> {
+    let mut magic = [0u8; 3];
+    let mut file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    let len = file.read(&mut magic)?;
+
+    Ok(match &magic[..len] {
+        [0x1f, 0x8b, ..] => Compression::Gzip,
+        [b'B', b'Z', b'h'] => Compression::Bzip2,
+        _ => Compression::Plain,
+    })
+}
+
+// --------------------------------------------------
+/// Opens a FASTA/FASTQ file, decompressing it if its contents are gzipped
+/// whatever the file is called
+pub fn open(path: &str) -> MyResult<Box<dyn BufRead>> {
+    let compression = sniff(path)?;
+    let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+
+    match compression {
+        Compression::Gzip => {
+            Ok(Box::new(BufReader::new(MultiGzDecoder::new(file))))
+        }
+        Compression::Bzip2 => Err(From::from(format!(
+            "\"{}\" is bzip2-compressed, which cannot be read here",
+            path
+        ))),
+        Compression::Plain => Ok(Box::new(BufReader::new(file))),
+    }
+}
+
+// --------------------------------------------------
+/// The first `num` read IDs of a FASTA/FASTQ file, without any "/1" or
+/// "/2" suffix or trailing comment
+pub fn read_ids(path: &str, num: usize) -> MyResult<Vec<String>> {
+    let mut ids = vec![];
+    let mut fastq_line = 0;
+
+    for line in open(path)?.lines() {
+        if ids.len() == num {
+            break;
+        }
+
+        let line = line?;
+        let is_header = if line.starts_with('@') {
+            // In FASTQ, quality lines can start with "@" too
+            fastq_line % 4 == 0
+        } else {
+            line.starts_with('>')
+        };
+
+        if is_header {
+            ids.push(normalize_id(&line[1..]));
+        }
+
+        if line.starts_with('>') {
+            fastq_line = 0;
+        } else {
+            fastq_line += 1;
+        }
+    }
+
+    Ok(ids)
+}
+
+// --------------------------------------------------
+fn normalize_id(header: &str) -> String {
+    let id = header.split_whitespace().next().unwrap_or("");
+    id.strip_suffix("/1")
+        .or_else(|| id.strip_suffix("/2"))
+        .unwrap_or(id)
+        .to_string()
+}
+
+// --------------------------------------------------
+/// Compares the leading read IDs of a forward and reverse file, returning
+/// a description of the first disagreement
+pub fn check_pair(
+    fwd: &str,
+    rev: &str,
+    num: usize,
+) -> MyResult<Option<String>> {
+    let fwd_ids = read_ids(fwd, num)?;
+    let rev_ids = read_ids(rev, num)?;
+
+    for (i, (f, r)) in fwd_ids.iter().zip(&rev_ids).enumerate() {
+        if f != r {
+            return Ok(Some(format!(
+                "read {} is \"{}\" in \"{}\" but \"{}\" in \"{}\"",
+                i + 1,
+                f,
+                fwd,
+                r,
+                rev
+            )));
+        }
+    }
+
+    if fwd_ids.len() != rev_ids.len() {
+        return Ok(Some(format!(
+            "\"{}\" has {} reads but \"{}\" has {}",
+            fwd,
+            fwd_ids.len(),
+            rev,
+            rev_ids.len()
+        )));
+    }
+
+    Ok(None)
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use std::{env, fs, io::Write};
+
+    #[test]
+    fn test_check_pair() {
+        let dir = env::temp_dir().join("run_megahit_reads");
+        fs::create_dir_all(&dir).unwrap();
+
+        let fwd = dir.join("S1_R1.fastq");
+        fs::write(
+            &fwd,
+            "@r1/1\nACGT\n+\n@III\n@r2 1:N:0:ACGT\nACGT\n+\nIIII\n",
+        )
+        .unwrap();
+
+        // Gzipped but not named so
+        let rev = dir.join("S1_R2.fastq");
+        let mut gz = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(b"@r1/2\nACGT\n+\nIIII\n@r2 2:N:0:ACGT\nACGT\n+\nIIII\n")
+            .unwrap();
+        fs::write(&rev, gz.finish().unwrap()).unwrap();
+
+        let (fwd, rev) = (fwd.to_str().unwrap(), rev.to_str().unwrap());
+        assert_eq!(sniff(fwd).unwrap(), Compression::Plain);
+        assert_eq!(sniff(rev).unwrap(), Compression::Gzip);
+        assert_eq!(Compression::from_name(rev), Compression::Plain);
+        assert_eq!(read_ids(fwd, 10).unwrap(), vec!["r1", "r2"]);
+        assert_eq!(check_pair(fwd, rev, 10).unwrap(), None);
+
+        let other = dir.join("S2_R2.fa");
+        fs::write(&other, ">r1\nACGT\n>r3\nACGT\n").unwrap();
+        let res = check_pair(fwd, other.to_str().unwrap(), 10).unwrap();
+        assert!(res.unwrap().starts_with("read 2 is \"r2\""));
+    }
+}
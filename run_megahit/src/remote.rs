@@ -0,0 +1,223 @@
+use crate::scheduler::file_name;
+use crate::{shell_quote, Job};
+use serde::Serialize;
+use std::error::Error;
+use std::process::{Command, Stdio};
+
+type MyResult<T> = Result<T, Box<dyn Error>>;
+
+/// Directory under the remote login's home that jobs are staged in
+const WORK_DIR: &str = "run_megahit_work";
+
+/// A machine to run jobs on over SSH and how many at once
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Node {
+    pub host: String,
+    pub slots: u32,
+}
+
+// --------------------------------------------------
+/// Parses "host1,host2:8,user@host3" (one slot unless given)
+pub fn parse_nodes(val: &str) -> MyResult<Vec<Node>> {
+    val.split(',')
+        .map(|node| node.trim())
+        .filter(|node| !node.is_empty())
+        .map(|node| {
+            let (host, slots) = match node.rsplit_once(':') {
+                Some((host, slots)) => {
+                    let slots = slots.parse::<u32>().ok().filter(|&n| n > 0);
+                    match slots {
+                        Some(slots) => (host, slots),
+                        _ => {
+                            return Err(From::from(format!(
+                                "Node \"{}\" needs a positive slot count",
+                                node
+                            )))
+                        }
+                    }
+                }
+                _ => (node, 1),
+            };
+
+            Ok(Node {
+                host: host.to_string(),
+                slots,
+            })
+        })
+        .collect()
+}
+
+// --------------------------------------------------
+/// Copies the job's reads (and, when continuing, its out_dir) to the same
+/// relative place under the node's work directory
+pub fn stage_in(job: &Job, host: &str) -> MyResult<()> {
+    let mut paths: Vec<String> =
+        job.read_files().iter().map(|f| f.to_string()).collect();
+    if job.is_continuation() {
+        paths.push(job.out_dir.display().to_string());
+    }
+
+    let mut args: Vec<String> = vec!["-aR".to_string()];
+    args.extend(paths);
+    args.push(format!("{}:{}/", host, WORK_DIR));
+    rsync(&args)
+}
+
+// --------------------------------------------------
+/// Copies a finished job's output back to its out_dir
+pub fn stage_out(job: &Job, host: &str) -> MyResult<()> {
+    let out_dir = job.out_dir.display().to_string();
+    rsync(&[
+        "-a".to_string(),
+        format!("{}:{}/{}/", host, WORK_DIR, relative(&out_dir)),
+        format!("{}/", out_dir),
+    ])
+}
+
+// --------------------------------------------------
+/// The job as run through ssh in the node's work directory, where every
+/// absolute path becomes relative to mirror the staged files
+pub fn remote_job(job: &Job, host: &str) -> Job {
+    let args: Vec<String> = job
+        .args
+        .iter()
+        .map(|arg| arg.split(',').map(relative).collect::<Vec<_>>().join(","))
+        .collect();
+    let out_dir = relative(&job.out_dir.display().to_string()).to_string();
+
+    // sshd starts the job's shell in a session of its own, so its pid is
+    // the process group that kill_job ends
+    let mut script = format!(
+        "cd {} && echo $$ > {} && ",
+        WORK_DIR,
+        shell_quote(&pid_file(job))
+    );
+    if !job.is_continuation() {
+        // megahit won't reuse a directory left by a failed attempt
+        script += &format!("rm -rf {} && ", shell_quote(&out_dir));
+    }
+    script += &std::iter::once(&job.program)
+        .chain(&args)
+        .map(|arg| shell_quote(arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    Job {
+        sample: job.sample.to_string(),
+        out_dir: job.out_dir.clone(),
+        program: "ssh".to_string(),
+        args: vec![
+            "-o".to_string(),
+            "BatchMode=yes".to_string(),
+            host.to_string(),
+            script,
+        ],
+    }
+}
+
+// --------------------------------------------------
+/// Kills the job on the node, which outlives the local ssh that a timeout
+/// or a cancel kills
+pub fn kill_job(job: &Job, host: &str) -> MyResult<()> {
+    let pid_file = shell_quote(&pid_file(job));
+    let script = format!(
+        "cd {} && kill -s KILL -- -\"$(cat {})\" && rm -f {}",
+        WORK_DIR, pid_file, pid_file
+    );
+    let output = Command::new("ssh")
+        .args(["-o", "BatchMode=yes", host, &script])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Cannot run ssh: {}", e))?;
+
+    if !output.status.success() {
+        return Err(From::from(format!(
+            "Cannot kill {} on {}: {}",
+            job.sample,
+            host,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(())
+}
+
+// --------------------------------------------------
+/// File in the work directory with the process group of the job's shell
+fn pid_file(job: &Job) -> String {
+    format!(".run_megahit.{}.pid", file_name(&job.sample))
+}
+
+// --------------------------------------------------
+fn relative(path: &str) -> &str {
+    path.trim_start_matches('/')
+}
+
+// --------------------------------------------------
+fn rsync(args: &[String]) -> MyResult<()> {
+    let output = Command::new("rsync")
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Cannot run rsync: {}", e))?;
+
+    if !output.status.success() {
+        return Err(From::from(format!(
+            "rsync failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(())
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_parse_nodes() {
+        assert_eq!(
+            parse_nodes("h1, ken@h2:8,h3").unwrap(),
+            vec![
+                Node {
+                    host: "h1".to_string(),
+                    slots: 1
+                },
+                Node {
+                    host: "ken@h2".to_string(),
+                    slots: 8
+                },
+                Node {
+                    host: "h3".to_string(),
+                    slots: 1
+                },
+            ]
+        );
+        assert!(parse_nodes("h1:0").is_err());
+        assert!(parse_nodes("h1:x").is_err());
+    }
+
+    #[test]
+    fn test_remote_job() {
+        let job = Job {
+            sample: "S1".to_string(),
+            out_dir: PathBuf::from("/out/S1"),
+            program: "megahit".to_string(),
+            args: ["-o", "/out/S1", "-1", "/in/a_1.fq,/in/b_1.fq"]
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect(),
+        };
+
+        let remote = remote_job(&job, "h2");
+        assert_eq!(remote.program, "ssh");
+        assert_eq!(
+            remote.args[3],
+            "cd run_megahit_work && echo $$ > .run_megahit.S1.pid && \
+             rm -rf out/S1 && megahit -o out/S1 -1 in/a_1.fq,in/b_1.fq"
+        );
+    }
+}
@@ -0,0 +1,1084 @@
+use crate::disk;
+use crate::state::State;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{
+    collections::{BTreeMap, HashMap},
+    env, fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+type MyResult<T> = Result<T, Box<dyn Error>>;
+
+/// The per-sample timings of the batch, relative to out_dir
+pub const TIMINGS_FILE: &str = "timings.tsv";
+
+/// Outcome of a single megahit job
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct JobResult {
+    pub sample: String,
+    pub out_dir: PathBuf,
+    /// `None` when the job never ran (e.g., the batch halted first)
+    pub exit_code: Option<i32>,
+    pub runtime_secs: f64,
+    /// Times the job was started, counting retries
+    #[serde(default)]
+    pub attempts: u32,
+    /// Killed for exceeding the per-job time limit
+    #[serde(default)]
+    pub timed_out: bool,
+    /// Killed because the batch was interrupted
+    #[serde(default)]
+    pub interrupted: bool,
+    /// Finished by an earlier run of the batch, so not run this time
+    #[serde(default)]
+    pub skipped: bool,
+    /// When the job started, in seconds since the Unix epoch
+    #[serde(default)]
+    pub started: Option<f64>,
+    /// When the job ended, in seconds since the Unix epoch
+    #[serde(default)]
+    pub finished: Option<f64>,
+    /// Most memory the job held at once, in bytes, when it was measured
+    #[serde(default)]
+    pub peak_rss: Option<u64>,
+}
+
+impl JobResult {
+    pub fn status(&self) -> &'static str {
+        if self.timed_out {
+            return "timeout";
+        }
+
+        if self.interrupted {
+            return "interrupted";
+        }
+
+        if self.skipped {
+            return "skipped";
+        }
+
+        match self.exit_code {
+            Some(0) => "ok",
+            Some(_) => "failed",
+            None => "not run",
+        }
+    }
+}
+
+/// What became of one sample of a batch, for programs that run batches
+/// and act on the outcome
+#[derive(Debug, Clone, Serialize)]
+pub struct SampleResult {
+    pub sample: String,
+    /// The read files assembled
+    pub inputs: Vec<PathBuf>,
+    pub out_dir: PathBuf,
+    /// "ok", "failed", "timeout", "interrupted", "skipped", or "not run"
+    pub status: &'static str,
+    /// `None` when the job never ran
+    pub exit_code: Option<i32>,
+    pub duration: Duration,
+    /// The assembled contigs, when the sample finished
+    pub contigs: Option<PathBuf>,
+}
+
+impl SampleResult {
+    /// Assembled by this run or an earlier one
+    pub fn is_ok(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+}
+
+/// Everything known about a batch once it has finished
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunSummary {
+    pub results: Vec<JobResult>,
+    pub elapsed_secs: f64,
+    /// As megahit reported it, when it ran on this machine
+    #[serde(default)]
+    pub megahit_version: Option<String>,
+    /// What the stages after assembly measured, by sample
+    #[serde(default)]
+    pub metrics: BTreeMap<String, Vec<Metric>>,
+}
+
+/// One value a stage after assembly measured, e.g., QUAST's N50
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Metric {
+    pub stage: String,
+    pub name: String,
+    pub value: String,
+}
+
+impl RunSummary {
+    pub fn num_ok(&self) -> usize {
+        self.results
+            .iter()
+            .filter(|r| r.exit_code == Some(0))
+            .count()
+    }
+
+    pub fn num_failed(&self) -> usize {
+        self.results.len() - self.num_ok()
+    }
+}
+
+/// Progress events sent to every registered `Notifier`
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    BatchStarted {
+        num_jobs: usize,
+    },
+    SampleFailed {
+        sample: String,
+        status: String,
+        exit_code: Option<i32>,
+    },
+    BatchFinished {
+        num_ok: usize,
+        num_failed: usize,
+        elapsed_secs: f64,
+        /// Samples that didn't finish
+        #[serde(default)]
+        failed: Vec<String>,
+        /// Stopped by the user before all jobs ran
+        #[serde(default)]
+        interrupted: bool,
+    },
+    /// The batch ended early on an error
+    BatchAborted {
+        error: String,
+    },
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::BatchStarted { num_jobs } => write!(
+                f,
+                "run_megahit started {} job{}",
+                num_jobs,
+                if *num_jobs == 1 { "" } else { "s" }
+            ),
+            Event::SampleFailed {
+                sample,
+                status,
+                exit_code,
+            } => write!(
+                f,
+                "run_megahit sample {} {}{}",
+                sample,
+                match status.as_str() {
+                    "timeout" => "timed out",
+                    "not run" => "did not run",
+                    _ => "failed",
+                },
+                exit_code
+                    .map_or("".to_string(), |code| format!(" (exit {})", code))
+            ),
+            Event::BatchFinished {
+                num_ok,
+                num_failed,
+                elapsed_secs,
+                interrupted,
+                ..
+            } => write!(
+                f,
+                "run_megahit {}: {} ok, {} failed in {}",
+                if *interrupted {
+                    "interrupted"
+                } else {
+                    "finished"
+                },
+                num_ok,
+                num_failed,
+                format_secs(*elapsed_secs)
+            ),
+            Event::BatchAborted { error } => {
+                write!(f, "run_megahit stopped: {}", error)
+            }
+        }
+    }
+}
+
+/// Receives progress events as the batch runs, from any of its threads
+pub trait Notifier: Send + Sync {
+    fn notify(&self, event: &Event) -> MyResult<()>;
+}
+
+/// Renders the summary of a finished batch
+pub trait Reporter: Send + Sync {
+    fn report(&self, summary: &RunSummary) -> MyResult<()>;
+}
+
+/// Follows the jobs of a batch as they run, e.g., to show them in a GUI
+/// or a pipeline manager. Called from the threads that run the jobs, so
+/// each method should return quickly.
+pub trait ProgressHandler: Send + Sync {
+    /// A job started, or was handed to a scheduler or GNU parallel
+    fn on_job_start(&self, _sample: &str, _out_dir: &Path) {}
+
+    fn on_job_finish(&self, _result: &JobResult) {}
+
+    /// Something run_megahit warned about while the batch ran, as it would
+    /// be logged, when `init_logging` installed run_megahit's logger
+    fn on_warning(&self, _message: &str) {}
+}
+
+/// The notifiers, reporters, and progress handlers attached to a run
+#[derive(Default)]
+pub struct Hooks {
+    notifiers: Vec<Box<dyn Notifier>>,
+    reporters: Vec<Box<dyn Reporter>>,
+    handlers: Vec<Arc<dyn ProgressHandler>>,
+}
+
+impl Hooks {
+    pub fn new() -> Hooks {
+        Hooks::default()
+    }
+
+    pub fn add_notifier(&mut self, notifier: Box<dyn Notifier>) -> &mut Self {
+        self.notifiers.push(notifier);
+        self
+    }
+
+    pub fn add_reporter(&mut self, reporter: Box<dyn Reporter>) -> &mut Self {
+        self.reporters.push(reporter);
+        self
+    }
+
+    pub fn add_progress_handler(
+        &mut self,
+        handler: Box<dyn ProgressHandler>,
+    ) -> &mut Self {
+        self.handlers.push(Arc::from(handler));
+        self
+    }
+
+    /// The progress handlers, which are also told of warnings
+    pub fn progress_handlers(&self) -> Vec<Arc<dyn ProgressHandler>> {
+        self.handlers.clone()
+    }
+
+    pub fn job_started(&self, sample: &str, out_dir: &Path) {
+        for handler in &self.handlers {
+            handler.on_job_start(sample, out_dir);
+        }
+    }
+
+    pub fn job_finished(&self, result: &JobResult) {
+        for handler in &self.handlers {
+            handler.on_job_finish(result);
+        }
+    }
+
+    /// A failing notifier only warns; it should never sink the batch
+    pub fn notify(&self, event: &Event) {
+        for notifier in &self.notifiers {
+            if let Err(e) = notifier.notify(event) {
+                warn!("Notification failed: {}", e);
+            }
+        }
+    }
+
+    pub fn report(&self, summary: &RunSummary) -> MyResult<()> {
+        for reporter in &self.reporters {
+            reporter.report(summary)?;
+        }
+        Ok(())
+    }
+}
+
+// --------------------------------------------------
+pub struct ConsoleNotifier;
+
+impl Notifier for ConsoleNotifier {
+    fn notify(&self, event: &Event) -> MyResult<()> {
+        match event {
+            // The executor has already shown why a sample failed, and
+            // the caller why the batch stopped
+            Event::SampleFailed { .. } | Event::BatchAborted { .. } => {}
+            Event::BatchStarted { num_jobs } => info!(
+                event = "batch_started",
+                num_jobs = *num_jobs;
+                "{}", event
+            ),
+            Event::BatchFinished {
+                num_ok,
+                num_failed,
+                elapsed_secs,
+                interrupted,
+                ..
+            } => info!(
+                event = "batch_finished",
+                num_ok = *num_ok,
+                num_failed = *num_failed,
+                elapsed_secs = *elapsed_secs,
+                interrupted = *interrupted;
+                "{}", event
+            ),
+        }
+        Ok(())
+    }
+}
+
+// --------------------------------------------------
+pub struct ConsoleReporter;
+
+impl Reporter for ConsoleReporter {
+    fn report(&self, summary: &RunSummary) -> MyResult<()> {
+        let width = summary
+            .results
+            .iter()
+            .map(|r| r.sample.len())
+            .max()
+            .unwrap_or(0)
+            .max(6);
+
+        println!(
+            "{:width$}  {:11}  {:8}  {:8}  Tries",
+            "Sample",
+            "Status",
+            "Time",
+            "Peak RSS",
+            width = width
+        );
+        for res in &summary.results {
+            println!(
+                "{:width$}  {:11}  {:8}  {:8}  {}",
+                res.sample,
+                res.status(),
+                format_secs(res.runtime_secs),
+                res.peak_rss.map_or("-".to_string(), disk::format_bytes),
+                res.attempts,
+                width = width
+            );
+        }
+
+        Ok(())
+    }
+}
+
+// --------------------------------------------------
+/// Writes the summary as JSON
+pub struct JsonReporter {
+    pub path: PathBuf,
+}
+
+impl Reporter for JsonReporter {
+    fn report(&self, summary: &RunSummary) -> MyResult<()> {
+        fs::write(&self.path, serde_json::to_string_pretty(summary)?)?;
+        Ok(())
+    }
+}
+
+// --------------------------------------------------
+/// Writes the summary as a standalone HTML table
+pub struct HtmlReporter {
+    pub path: PathBuf,
+}
+
+impl Reporter for HtmlReporter {
+    fn report(&self, summary: &RunSummary) -> MyResult<()> {
+        let rows: Vec<String> = summary
+            .results
+            .iter()
+            .map(|r| {
+                format!(
+                    "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td>\
+                     <td>{}</td><td>{}</td></tr>",
+                    html_escape(&r.sample),
+                    r.status(),
+                    format_secs(r.runtime_secs),
+                    r.peak_rss.map_or("-".to_string(), disk::format_bytes),
+                    r.attempts,
+                    html_escape(&r.out_dir.display().to_string()),
+                )
+            })
+            .collect();
+
+        // What the stages after assembly measured, e.g., the taxonomy
+        let metrics: Vec<String> = summary
+            .metrics
+            .iter()
+            .flat_map(|(sample, metrics)| {
+                metrics.iter().map(move |m| {
+                    format!(
+                        "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                        html_escape(sample),
+                        m.stage,
+                        html_escape(&m.name),
+                        html_escape(&m.value)
+                    )
+                })
+            })
+            .collect();
+        let metrics = if metrics.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "<h2>Stages</h2>\n<table>\n<tr><th>Sample</th><th>Stage</th>\
+                 <th>Metric</th><th>Value</th></tr>\n{}\n</table>\n",
+                metrics.join("\n")
+            )
+        };
+
+        let html = format!(
+            "<!DOCTYPE html>\n<html>\n<head><title>run_megahit report\
+             </title></head>\n<body>\n<h1>run_megahit report</h1>\n\
+             <p>{} ok, {} failed in {}</p>\n<table>\n<tr><th>Sample</th>\
+             <th>Status</th><th>Time</th><th>Peak RSS</th><th>Tries</th>\
+             <th>Output</th></tr>\n{}\n\
+             </table>\n{}</body>\n</html>\n",
+            summary.num_ok(),
+            summary.num_failed(),
+            format_secs(summary.elapsed_secs),
+            rows.join("\n"),
+            metrics
+        );
+
+        fs::write(&self.path, html)?;
+        Ok(())
+    }
+}
+
+// --------------------------------------------------
+/// Posts every event as JSON, with its message as "text" so that Slack and
+/// Teams incoming webhooks show it as well as generic receivers
+pub struct WebhookNotifier {
+    pub url: String,
+}
+
+impl Notifier for WebhookNotifier {
+    fn notify(&self, event: &Event) -> MyResult<()> {
+        post_json(&self.url, &webhook_payload(event)?.to_string())
+    }
+}
+
+// --------------------------------------------------
+fn webhook_payload(event: &Event) -> MyResult<serde_json::Value> {
+    let mut payload = serde_json::to_value(event)?;
+    payload["text"] = serde_json::Value::String(event.to_string());
+    Ok(payload)
+}
+
+// --------------------------------------------------
+/// Mails a summary to a recipient once the batch is over, using the local
+/// `sendmail` or, given `smtp_url`, an SMTP server through `curl`
+pub struct EmailNotifier {
+    pub to: String,
+    /// e.g., "smtps://smtp.example.org:465"
+    pub smtp_url: Option<String>,
+    /// "user:password" for the SMTP server
+    pub smtp_login: Option<String>,
+    /// Sender, when the SMTP server needs one
+    pub from: Option<String>,
+}
+
+impl Notifier for EmailNotifier {
+    fn notify(&self, event: &Event) -> MyResult<()> {
+        let no_failures = vec![];
+        let failed = match event {
+            Event::BatchFinished { failed, .. } => failed,
+            Event::BatchAborted { .. } => &no_failures,
+            _ => return Ok(()),
+        };
+
+        let mut body = format!("{}\n", event);
+        if !failed.is_empty() {
+            body += &format!("\nFailed samples:\n{}\n", failed.join("\n"));
+        }
+
+        // sendmail fills in the local user as the sender
+        let from = self
+            .from
+            .as_ref()
+            .map_or("".to_string(), |from| format!("From: {}\n", from));
+        let message = format!(
+            "{}To: {}\nSubject: {}\n\n{}",
+            from,
+            self.to,
+            mail_subject(event),
+            body
+        );
+        match &self.smtp_url {
+            Some(url) => send_smtp(
+                url,
+                self.smtp_login.as_deref(),
+                self.from.as_deref().unwrap_or(&self.to),
+                &self.to,
+                &message,
+            ),
+            _ => send_mail(&message),
+        }
+    }
+}
+
+// --------------------------------------------------
+/// The first line of the event, as the rest of a message that spans lines
+/// (e.g., the error that aborted the batch) would be read as headers
+fn mail_subject(event: &Event) -> String {
+    event
+        .to_string()
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .replace('\r', "")
+}
+
+// --------------------------------------------------
+/// Posts the payload through `curl`, which is given the URL on STDIN as
+/// its token is often in the path
+pub fn post_json(url: &str, payload: &str) -> MyResult<()> {
+    let posted = run_curl(&curl_config(&[
+        ("silent", None),
+        ("show-error", None),
+        ("fail", None),
+        ("request", Some("POST")),
+        ("header", Some("Content-Type: application/json")),
+        ("data", Some(payload)),
+        ("url", Some(url)),
+    ]))?;
+
+    if !posted {
+        return Err(From::from(format!(
+            "Failed to POST to \"{}\"",
+            redact_url(url)
+        )));
+    }
+
+    Ok(())
+}
+
+// --------------------------------------------------
+pub fn send_mail(message: &str) -> MyResult<()> {
+    let mut process = Command::new("sendmail")
+        .arg("-t")
+        .stdin(Stdio::piped())
+        .spawn()?;
+
+    {
+        let stdin = process.stdin.as_mut().expect("Failed to open stdin");
+        stdin.write_all(message.as_bytes())?;
+    }
+
+    if !process.wait()?.success() {
+        return Err(From::from("sendmail failed"));
+    }
+
+    Ok(())
+}
+
+// --------------------------------------------------
+/// Sends the message through `curl`, which is given the server and login
+/// on STDIN so that they never show in the process list
+pub fn send_smtp(
+    url: &str,
+    login: Option<&str>,
+    from: &str,
+    to: &str,
+    message: &str,
+) -> MyResult<()> {
+    // STDIN carries the config, so the message goes through a file only
+    // the user can read
+    let path = env::temp_dir().join(format!(
+        "run_megahit-mail-{}-{}.eml",
+        std::process::id(),
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |dur| dur.as_nanos())
+    ));
+    let mut open = fs::OpenOptions::new();
+    open.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        open.mode(0o600);
+    }
+    open.open(&path)?.write_all(message.as_bytes())?;
+
+    let path_str = path.display().to_string();
+    let mut options = vec![
+        ("silent", None),
+        ("show-error", None),
+        ("ssl", None),
+        ("url", Some(url)),
+        ("mail-from", Some(from)),
+        ("mail-rcpt", Some(to)),
+        ("upload-file", Some(path_str.as_str())),
+    ];
+    if let Some(login) = login {
+        options.push(("user", Some(login)));
+    }
+    let sent = run_curl(&curl_config(&options));
+    let _ = fs::remove_file(&path);
+
+    if !sent? {
+        return Err(From::from(format!(
+            "Failed to send mail via \"{}\"",
+            redact_url(url)
+        )));
+    }
+
+    Ok(())
+}
+
+// --------------------------------------------------
+/// Runs `curl` with the config on STDIN, returning whether it succeeded
+fn run_curl(config: &str) -> MyResult<bool> {
+    let mut process = Command::new("curl")
+        .args(["-K", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()?;
+
+    {
+        let stdin = process.stdin.as_mut().expect("Failed to open stdin");
+        stdin.write_all(config.as_bytes())?;
+    }
+
+    Ok(process.wait()?.success())
+}
+
+// --------------------------------------------------
+/// A `curl -K` config of the options, by long name, with their values
+/// quoted
+fn curl_config(options: &[(&str, Option<&str>)]) -> String {
+    options
+        .iter()
+        .map(|(name, val)| match val {
+            Some(val) => format!(
+                "{} = \"{}\"\n",
+                name,
+                val.replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('\n', "\\n")
+            ),
+            _ => format!("{}\n", name),
+        })
+        .collect()
+}
+
+// --------------------------------------------------
+/// The scheme and host of a URL, without the login, path, or query that
+/// may hold secrets, e.g., "https://hooks.slack.com/..."
+pub fn redact_url(url: &str) -> String {
+    let (scheme, rest) = match url.split_once("://") {
+        Some((scheme, rest)) => (format!("{}://", scheme), rest),
+        _ => (String::new(), url),
+    };
+    let (host, path) = match rest.find(['/', '?', '#']) {
+        Some(i) => (&rest[..i], &rest[i..]),
+        _ => (rest, ""),
+    };
+    let host = host.rsplit('@').next().unwrap_or(host);
+
+    format!(
+        "{}{}{}",
+        scheme,
+        host,
+        if path.is_empty() || path == "/" {
+            ""
+        } else {
+            "/..."
+        }
+    )
+}
+
+// --------------------------------------------------
+pub fn format_secs(secs: f64) -> String {
+    let secs = secs.round() as u64;
+    format!("{}:{:02}:{:02}", secs / 3600, (secs % 3600) / 60, secs % 60)
+}
+
+// --------------------------------------------------
+/// One row per sample with its input size, when its job started and
+/// ended, how long it ran, and how it finished, for modeling runtimes
+pub fn write_timings(
+    path: &Path,
+    results: &[JobResult],
+    input_sizes: &HashMap<&str, u64>,
+) -> MyResult<()> {
+    let mut lines = vec!["sample\tinput_bytes\tstart\tend\tduration_secs\t\
+                          peak_rss_bytes\tstatus"
+        .to_string()];
+    for res in results {
+        lines.push(format!(
+            "{}\t{}\t{}\t{}\t{:.1}\t{}\t{}",
+            res.sample,
+            input_sizes
+                .get(res.sample.as_str())
+                .map_or("NA".to_string(), |size| size.to_string()),
+            res.started.map_or("NA".to_string(), format_timestamp),
+            res.finished.map_or("NA".to_string(), format_timestamp),
+            res.runtime_secs,
+            res.peak_rss.map_or("NA".to_string(), |rss| rss.to_string()),
+            res.status()
+        ));
+    }
+
+    fs::write(path, lines.join("\n") + "\n")?;
+    Ok(())
+}
+
+// --------------------------------------------------
+/// One row of metrics for each sample, the columns in the order they
+/// first come up
+pub fn write_metrics(
+    path: &Path,
+    reports: &[(String, Vec<Metric>)],
+) -> MyResult<PathBuf> {
+    let mut names: Vec<&str> = vec![];
+    for metric in reports.iter().flat_map(|(_, metrics)| metrics) {
+        if !names.contains(&metric.name.as_str()) {
+            names.push(&metric.name);
+        }
+    }
+
+    let mut lines = vec![format!("sample\t{}", names.join("\t"))];
+    for (sample, metrics) in reports {
+        let values: Vec<&str> = names
+            .iter()
+            .map(|name| {
+                metrics
+                    .iter()
+                    .find(|metric| metric.name == *name)
+                    .map_or("NA", |metric| metric.value.as_str())
+            })
+            .collect();
+        lines.push(format!("{}\t{}", sample, values.join("\t")));
+    }
+
+    fs::write(path, lines.join("\n") + "\n")
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(path.to_path_buf())
+}
+
+// --------------------------------------------------
+/// Seconds since the Unix epoch
+pub fn now_secs() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0., |elapsed| elapsed.as_secs_f64())
+}
+
+// --------------------------------------------------
+/// Seconds since the Unix epoch as an ISO 8601 UTC timestamp
+pub fn format_timestamp(secs: f64) -> String {
+    let secs = secs.max(0.) as i64;
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+
+    // Days to a civil date, from Howard Hinnant's date algorithms
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60
+    )
+}
+
+// --------------------------------------------------
+/// The summary of a batch as its state file left it, for reporting again
+/// after the fact
+pub(crate) fn summary_from_state(state: &State) -> RunSummary {
+    let results: Vec<JobResult> = state
+        .samples
+        .values()
+        .filter_map(|entry| entry.result.clone())
+        .collect();
+
+    // From the first start to the last finish when the times are known
+    let started = results
+        .iter()
+        .filter_map(|res| res.started)
+        .reduce(f64::min);
+    let finished = results
+        .iter()
+        .filter_map(|res| res.finished)
+        .reduce(f64::max);
+    let elapsed_secs = match (started, finished) {
+        (Some(started), Some(finished)) => finished - started,
+        _ => results.iter().map(|res| res.runtime_secs).sum(),
+    };
+
+    RunSummary {
+        results,
+        elapsed_secs,
+        megahit_version: None,
+        metrics: BTreeMap::new(),
+    }
+}
+
+// --------------------------------------------------
+fn html_escape(val: &str) -> String {
+    val.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    struct Collect(Arc<Mutex<Vec<String>>>);
+
+    impl Notifier for Collect {
+        fn notify(&self, event: &Event) -> MyResult<()> {
+            self.0.lock().unwrap().push(event.to_string());
+            Ok(())
+        }
+    }
+
+    impl ProgressHandler for Collect {
+        fn on_job_start(&self, sample: &str, _out_dir: &Path) {
+            self.0.lock().unwrap().push(format!("start {}", sample));
+        }
+
+        fn on_job_finish(&self, result: &JobResult) {
+            self.0.lock().unwrap().push(format!(
+                "{} {}",
+                result.sample,
+                result.status()
+            ));
+        }
+
+        fn on_warning(&self, message: &str) {
+            self.0.lock().unwrap().push(format!("warning {}", message));
+        }
+    }
+
+    #[test]
+    fn test_webhook_payload() {
+        let payload = webhook_payload(&Event::SampleFailed {
+            sample: "S1".to_string(),
+            status: "failed".to_string(),
+            exit_code: Some(2),
+        })
+        .unwrap();
+        assert_eq!(payload["event"], "sample_failed");
+        assert_eq!(payload["sample"], "S1");
+        assert_eq!(payload["text"], "run_megahit sample S1 failed (exit 2)");
+
+        let payload = webhook_payload(&Event::BatchAborted {
+            error: "No inputs".to_string(),
+        })
+        .unwrap();
+        assert_eq!(payload["event"], "batch_aborted");
+        assert_eq!(payload["text"], "run_megahit stopped: No inputs");
+    }
+
+    #[test]
+    fn test_mail_subject() {
+        let event = Event::BatchAborted {
+            error: "bad input\r\nBcc: someone@example.org\n".to_string(),
+        };
+        assert_eq!(mail_subject(&event), "run_megahit stopped: bad input");
+        let event = Event::BatchAborted {
+            error: "bad\rinput".to_string(),
+        };
+        assert_eq!(mail_subject(&event), "run_megahit stopped: badinput");
+    }
+
+    #[test]
+    fn test_curl_config() {
+        assert_eq!(
+            curl_config(&[
+                ("silent", None),
+                ("url", Some("smtps://smtp.org")),
+                ("user", Some("me:p\"w\\d")),
+            ]),
+            "silent\nurl = \"smtps://smtp.org\"\nuser = \"me:p\\\"w\\\\d\"\n"
+        );
+
+        assert_eq!(
+            redact_url("smtps://me:pw@smtp.org:465"),
+            "smtps://smtp.org:465"
+        );
+        assert_eq!(
+            redact_url("https://hooks.slack.com/services/T0/B0/XXXX"),
+            "https://hooks.slack.com/..."
+        );
+        assert_eq!(redact_url("http://host/?token=x"), "http://host/...");
+    }
+
+    #[test]
+    fn test_timings() {
+        assert_eq!(format_timestamp(0.), "1970-01-01T00:00:00Z");
+        assert_eq!(format_timestamp(1_709_294_400.), "2024-03-01T12:00:00Z");
+        assert_eq!(format_timestamp(951_782_400.), "2000-02-29T00:00:00Z");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(TIMINGS_FILE);
+        let res = JobResult {
+            sample: "S1".to_string(),
+            out_dir: PathBuf::from("out/S1"),
+            exit_code: Some(0),
+            runtime_secs: 90.,
+            attempts: 1,
+            started: Some(1_709_294_400.),
+            finished: Some(1_709_294_490.),
+            peak_rss: Some(2_500_000_000),
+            ..JobResult::default()
+        };
+        let not_run = JobResult {
+            sample: "S2".to_string(),
+            exit_code: None,
+            runtime_secs: 0.,
+            started: None,
+            finished: None,
+            peak_rss: None,
+            ..res.clone()
+        };
+        let sizes: HashMap<&str, u64> =
+            vec![("S1", 1024)].into_iter().collect();
+        write_timings(&path, &[res, not_run], &sizes).unwrap();
+
+        let tsv = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = tsv.lines().collect();
+        assert_eq!(
+            lines[1],
+            "S1\t1024\t2024-03-01T12:00:00Z\t2024-03-01T12:01:30Z\t90.0\t\
+             2500000000\tok"
+        );
+        assert_eq!(lines[2], "S2\tNA\tNA\tNA\t0.0\tNA\tnot run");
+    }
+
+    #[test]
+    fn test_write_metrics() {
+        let metric = |name: &str, value: &str| Metric {
+            stage: "qc".to_string(),
+            name: name.to_string(),
+            value: value.to_string(),
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("metrics.tsv");
+        write_metrics(
+            &path,
+            &[
+                (
+                    "S1".to_string(),
+                    vec![metric("# contigs", "12"), metric("N50", "2048")],
+                ),
+                ("S2".to_string(), vec![metric("N50", "512")]),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "sample\t# contigs\tN50\nS1\t12\t2048\nS2\tNA\t512\n"
+        );
+    }
+
+    #[test]
+    fn test_status() {
+        let res = JobResult {
+            sample: "S1".to_string(),
+            out_dir: PathBuf::from("out/S1"),
+            exit_code: Some(0),
+            runtime_secs: 1.,
+            attempts: 1,
+            ..JobResult::default()
+        };
+        assert_eq!(res.status(), "ok");
+        assert_eq!(
+            JobResult {
+                skipped: true,
+                ..res.clone()
+            }
+            .status(),
+            "skipped"
+        );
+        assert_eq!(
+            JobResult {
+                exit_code: Some(137),
+                timed_out: true,
+                ..res.clone()
+            }
+            .status(),
+            "timeout"
+        );
+        assert_eq!(
+            JobResult {
+                exit_code: None,
+                ..res
+            }
+            .status(),
+            "not run"
+        );
+    }
+
+    #[test]
+    fn test_hooks() {
+        let seen = Arc::new(Mutex::new(vec![]));
+        let mut hooks = Hooks::new();
+        hooks.add_notifier(Box::new(Collect(Arc::clone(&seen))));
+        hooks.notify(&Event::BatchStarted { num_jobs: 1 });
+        hooks.notify(&Event::BatchFinished {
+            num_ok: 1,
+            num_failed: 0,
+            elapsed_secs: 3725.,
+            failed: vec![],
+            interrupted: false,
+        });
+
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![
+                "run_megahit started 1 job",
+                "run_megahit finished: 1 ok, 0 failed in 1:02:05"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_progress_handler() {
+        let seen = Arc::new(Mutex::new(vec![]));
+        let mut hooks = Hooks::new();
+        hooks.add_progress_handler(Box::new(Collect(Arc::clone(&seen))));
+        hooks.job_started("S1", Path::new("out/S1"));
+        hooks.job_finished(&JobResult {
+            sample: "S1".to_string(),
+            out_dir: PathBuf::from("out/S1"),
+            exit_code: Some(1),
+            runtime_secs: 1.,
+            attempts: 1,
+            ..JobResult::default()
+        });
+
+        crate::logging::init(log::LevelFilter::Warn, "text");
+        let outer = crate::logging::forward_warnings(hooks.progress_handlers());
+        warn!("S1 looks odd");
+        // Nor do the threads of other batches reach them
+        std::thread::spawn(|| warn!("elsewhere")).join().unwrap();
+        crate::logging::forward_warnings(outer);
+        warn!("not forwarded");
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen[..], ["start S1", "S1 failed", "warning S1 looks odd"]);
+    }
+}
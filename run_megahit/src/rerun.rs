@@ -0,0 +1,121 @@
+use crate::report::JobResult;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+type MyResult<T> = Result<T, Box<dyn Error>>;
+
+/// The command line of the batch, relative to out_dir
+pub const ARGS_FILE: &str = ".run_megahit_args.json";
+
+/// The samples that didn't finish, relative to out_dir
+pub const FAILED_FILE: &str = "failed_samples.tsv";
+
+/// How run_megahit was started, so the batch can be run again as it was
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Invocation {
+    /// Relative paths in `args` are from here
+    pub cwd: PathBuf,
+    /// Including the program name
+    pub args: Vec<String>,
+}
+
+// --------------------------------------------------
+pub fn save_invocation(out_dir: &Path, args: &[String]) -> MyResult<()> {
+    let invocation = Invocation {
+        cwd: std::env::current_dir()?,
+        args: args.to_vec(),
+    };
+    fs::write(
+        out_dir.join(ARGS_FILE),
+        serde_json::to_string_pretty(&invocation)?,
+    )?;
+    Ok(())
+}
+
+// --------------------------------------------------
+pub fn load_invocation(out_dir: &Path) -> MyResult<Invocation> {
+    let path = out_dir.join(ARGS_FILE);
+    let contents = fs::read_to_string(&path).map_err(|e| {
+        format!("{}: {} (not a run_megahit out_dir?)", path.display(), e)
+    })?;
+    serde_json::from_str(&contents)
+        .map_err(|e| From::from(format!("{}: {}", path.display(), e)))
+}
+
+// --------------------------------------------------
+/// Lists every sample that didn't finish with its status, exit code, and
+/// megahit log (the header alone when all went well)
+pub fn write_failed(out_dir: &Path, results: &[JobResult]) -> MyResult<()> {
+    let mut lines = vec!["sample\tstatus\texit_code\tlog".to_string()];
+    for res in results.iter().filter(|res| res.exit_code != Some(0)) {
+        lines.push(format!(
+            "{}\t{}\t{}\t{}",
+            res.sample,
+            res.status(),
+            res.exit_code
+                .map_or("NA".to_string(), |code| code.to_string()),
+            res.out_dir.join("log").display()
+        ));
+    }
+
+    fs::write(out_dir.join(FAILED_FILE), lines.join("\n") + "\n")?;
+    Ok(())
+}
+
+// --------------------------------------------------
+/// The names of the samples in out_dir's failed_samples.tsv
+pub fn read_failed(out_dir: &Path) -> MyResult<Vec<String>> {
+    let path = out_dir.join(FAILED_FILE);
+    let contents = fs::read_to_string(&path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    Ok(contents
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.split('\t').next())
+        .filter(|sample| !sample.is_empty())
+        .map(|sample| sample.to_string())
+        .collect())
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failed_samples() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+
+        let result = |sample: &str, exit_code: Option<i32>| JobResult {
+            sample: sample.to_string(),
+            out_dir: dir.join(sample),
+            exit_code,
+            runtime_secs: 1.,
+            attempts: 1,
+            ..JobResult::default()
+        };
+        let results = vec![
+            result("A", Some(0)),
+            result("B", Some(1)),
+            result("C", None),
+        ];
+        write_failed(dir, &results).unwrap();
+
+        let tsv = fs::read_to_string(dir.join(FAILED_FILE)).unwrap();
+        assert!(tsv.contains(&format!(
+            "B\tfailed\t1\t{}\n",
+            dir.join("B").join("log").display()
+        )));
+        assert_eq!(read_failed(dir).unwrap(), vec!["B", "C"]);
+
+        let args = vec!["run_megahit".to_string(), "-Q".to_string()];
+        save_invocation(dir, &args).unwrap();
+        assert_eq!(load_invocation(dir).unwrap().args, args);
+    }
+}
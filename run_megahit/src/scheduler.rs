@@ -0,0 +1,951 @@
+use crate::aws::AwsBatch;
+use crate::executor::{self, Executor};
+use crate::report::{self, JobResult};
+use crate::{shell_quote, Job, RunMegahitError};
+use log::{info, warn};
+use std::collections::HashSet;
+use std::error::Error;
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+type MyResult<T> = Result<T, Box<dyn Error>>;
+
+/// CPUs requested per job
+pub const DEFAULT_CPUS: u32 = 8;
+
+/// How often the scheduler is asked about submitted jobs
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// What each job asks of the cluster
+#[derive(Debug, Clone)]
+pub struct Resources {
+    pub cpus: u32,
+    pub mem_mb: Option<u64>,
+    pub time: Option<Duration>,
+}
+
+impl Resources {
+    /// `memory` is megahit's value: bytes, or a fraction of the node's RAM
+    /// that can't be turned into a request
+    pub fn new(
+        cpus: u32,
+        memory: Option<f64>,
+        time: Option<Duration>,
+    ) -> Resources {
+        Resources {
+            cpus,
+            mem_mb: memory
+                .filter(|&mem| mem > 1.)
+                .map(|mem| (mem / 1_000_000.).ceil() as u64),
+            time,
+        }
+    }
+}
+
+/// A job script written for a scheduler
+#[derive(Debug)]
+pub struct Script {
+    pub sample: String,
+    pub path: PathBuf,
+}
+
+/// What the scheduler last said about a submitted job
+#[derive(Debug, PartialEq)]
+pub struct Task {
+    pub id: String,
+    pub state: String,
+    pub finished: bool,
+    /// `None` when the scheduler no longer knows the job's exit status
+    pub exit_code: Option<i32>,
+    pub timed_out: bool,
+    pub elapsed_secs: f64,
+}
+
+/// A batch system that jobs can be written for, submitted to, and
+/// followed on
+pub trait Scheduler {
+    /// Also the name of the script directory in out_dir
+    fn name(&self) -> &'static str;
+
+    /// The header lines requesting `res` for a job
+    fn directives(&self, job_name: &str, res: &Resources, log: &Path)
+        -> String;
+
+    /// Queues a script and returns its job ID
+    fn submit(&self, script: &Path) -> MyResult<String>;
+
+    /// The state of each of these jobs that the scheduler reports on
+    fn poll(&self, ids: &[&str]) -> MyResult<Vec<Task>>;
+
+    /// Gets the batch ready to submit, e.g., by staging its inputs
+    fn prepare(&self, _jobs: &[Job], _res: &Resources) -> MyResult<()> {
+        Ok(())
+    }
+
+    /// The body of a job's script
+    fn command(&self, job: &Job) -> String {
+        job.command_line()
+    }
+
+    /// Writes what `prepare` and `submit` would send the scheduler for the
+    /// scripts to `dir`, beyond the scripts themselves, for review
+    fn write_requests(
+        &self,
+        _scripts: &[Script],
+        _res: &Resources,
+        _dir: &Path,
+    ) -> MyResult<()> {
+        Ok(())
+    }
+
+    /// Brings a finished job's output back to its out_dir
+    fn fetch(&self, _job: &Job) -> MyResult<()> {
+        Ok(())
+    }
+}
+
+/// Settings of the individual schedulers
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Grid Engine parallel environment
+    pub sge_pe: String,
+    /// Where AWS Batch jobs stage their inputs and outputs
+    pub s3_prefix: Option<String>,
+    pub aws_queue: Option<String>,
+    /// Container with megahit and the AWS CLI
+    pub aws_image: String,
+}
+
+// --------------------------------------------------
+/// The `Scheduler` for `--scheduler NAME`
+pub fn by_name(name: &str, opts: &Options) -> MyResult<Box<dyn Scheduler>> {
+    match name {
+        "slurm" => Ok(Box::new(Slurm)),
+        "pbs" => Ok(Box::new(Pbs)),
+        "sge" => Ok(Box::new(Sge {
+            pe: opts.sge_pe.to_string(),
+        })),
+        "aws-batch" => match (&opts.s3_prefix, &opts.aws_queue) {
+            (Some(prefix), Some(queue)) => {
+                Ok(Box::new(AwsBatch::new(prefix, queue, &opts.aws_image)))
+            }
+            _ => Err(From::from(
+                "--scheduler aws-batch needs --s3_prefix and --aws_queue",
+            )),
+        },
+        _ => Err(From::from(format!("Unknown scheduler \"{}\"", name))),
+    }
+}
+
+// --------------------------------------------------
+/// Writes one script per job to `dir`
+pub fn write_scripts(
+    scheduler: &dyn Scheduler,
+    jobs: &[Job],
+    dir: &Path,
+    res: &Resources,
+) -> MyResult<Vec<Script>> {
+    fs::create_dir_all(dir)?;
+
+    let mut scripts = vec![];
+    for job in jobs {
+        let name = file_name(&job.sample);
+        let path = dir.join(format!("{}.sh", name));
+        let log = dir.join(format!("{}.out", name));
+        fs::write(&path, job_script(scheduler, job, res, &log))?;
+        scripts.push(Script {
+            sample: job.sample.to_string(),
+            path,
+        });
+    }
+
+    Ok(scripts)
+}
+
+// --------------------------------------------------
+fn job_script(
+    scheduler: &dyn Scheduler,
+    job: &Job,
+    res: &Resources,
+    log: &Path,
+) -> String {
+    format!(
+        "#!/bin/bash\n{}\n\nset -euo pipefail\n\n{}\n",
+        scheduler.directives(
+            &format!("megahit-{}", file_name(&job.sample)),
+            res,
+            log
+        ),
+        scheduler.command(job)
+    )
+}
+
+// --------------------------------------------------
+/// A job's name as a file name: a sweep's "SET/SAMPLE" becomes
+/// "SET__SAMPLE"
+pub fn file_name(sample: &str) -> String {
+    sample.replace('/', "__")
+}
+
+// --------------------------------------------------
+/// Submits each script, returning (sample, job ID) pairs
+pub fn submit_all(
+    scheduler: &dyn Scheduler,
+    scripts: &[Script],
+) -> MyResult<Vec<(String, String)>> {
+    scripts
+        .iter()
+        .map(|script| {
+            Ok((script.sample.to_string(), scheduler.submit(&script.path)?))
+        })
+        .collect()
+}
+
+// --------------------------------------------------
+/// The jobs submitted to a cluster's scheduler, followed until they end
+pub struct Cluster {
+    pub scheduler: Box<dyn Scheduler>,
+    /// (sample, job ID) of each submitted job
+    pub submitted: Vec<(String, String)>,
+}
+
+impl Executor for Cluster {
+    fn run(
+        &self,
+        jobs: &[Job],
+        on_start: &(dyn Fn(&Job) + Sync),
+        on_done: &(dyn Fn(&JobResult) + Sync),
+    ) -> MyResult<Vec<JobResult>> {
+        // The jobs are already in the scheduler's hands
+        jobs.iter().for_each(on_start);
+        info!("Waiting for the jobs to finish (Ctrl-C stops waiting)");
+        let results = track(self.scheduler.as_ref(), &self.submitted, jobs)?;
+        results.iter().for_each(on_done);
+        Ok(results)
+    }
+}
+
+// --------------------------------------------------
+/// Polls the scheduler until every submitted job has ended, or the user
+/// interrupts, and returns how each one finished
+pub fn track(
+    scheduler: &dyn Scheduler,
+    ids: &[(String, String)],
+    jobs: &[Job],
+) -> MyResult<Vec<JobResult>> {
+    let mut results: Vec<JobResult> = jobs
+        .iter()
+        .map(|job| JobResult {
+            sample: job.sample.to_string(),
+            out_dir: job.out_dir.clone(),
+            ..JobResult::default()
+        })
+        .collect();
+
+    let mut pending: HashSet<&str> = ids.iter().map(|(_, id)| &**id).collect();
+
+    while !pending.is_empty() {
+        if executor::interrupted() {
+            warn!("Stopped waiting, the jobs are still queued");
+            for res in results.iter_mut().filter(|r| r.exit_code.is_none()) {
+                res.interrupted = true;
+            }
+            break;
+        }
+
+        let asking: Vec<&str> = pending.iter().copied().collect();
+        for task in scheduler.poll(&asking)? {
+            if !task.finished || !pending.remove(&*task.id) {
+                continue;
+            }
+
+            let sample = match ids.iter().find(|(_, id)| *id == task.id) {
+                Some((sample, _)) => sample,
+                _ => continue,
+            };
+
+            if let Some(res) = results.iter_mut().find(|r| &r.sample == sample)
+            {
+                if task.exit_code == Some(0) {
+                    if let Some(job) = jobs.iter().find(|j| &j.sample == sample)
+                    {
+                        scheduler.fetch(job)?;
+                    }
+                }
+
+                // With no word on the exit status, go by megahit's marker
+                let done = res.out_dir.join("done").is_file();
+                res.exit_code =
+                    task.exit_code.or(Some(if done { 0 } else { 1 }));
+                res.runtime_secs = task.elapsed_secs;
+                res.timed_out = task.timed_out;
+                res.attempts = 1;
+                // Seen as finished at the latest poll
+                let finished = report::now_secs();
+                res.finished = Some(finished);
+                res.started = (task.elapsed_secs > 0.)
+                    .then_some(finished - task.elapsed_secs);
+                info!("{} ({}) {}", sample, task.id, task.state);
+            }
+        }
+
+        let wake = Instant::now() + POLL_INTERVAL;
+        while !pending.is_empty()
+            && Instant::now() < wake
+            && !executor::interrupted()
+        {
+            thread::sleep(Duration::from_secs(1));
+        }
+    }
+
+    Ok(results)
+}
+
+// --------------------------------------------------
+/// Runs a submission command and returns its STDOUT
+fn run_submit(program: &str, args: &[&str], script: &Path) -> MyResult<String> {
+    let output = Command::new(program)
+        .args(args)
+        .arg(script)
+        .output()
+        .map_err(|e| RunMegahitError::SpawnFailed {
+            program: program.to_string(),
+            reason: e.to_string(),
+        })?;
+
+    if !output.status.success() {
+        return Err(From::from(format!(
+            "{} failed for \"{}\": {}",
+            program,
+            script.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// --------------------------------------------------
+/// Formats a limit as HH:MM:SS, hours running past 24
+fn hms(time: Duration) -> String {
+    let secs = time.as_secs();
+    format!(
+        "{:02}:{:02}:{:02}",
+        secs / 3600,
+        (secs % 3600) / 60,
+        secs % 60
+    )
+}
+
+// --------------------------------------------------
+/// Parses HH:MM:SS (or MM:SS, or SS) into seconds
+fn parse_hms(val: &str) -> f64 {
+    val.trim()
+        .split(':')
+        .fold(0., |secs, part| secs * 60. + part.parse().unwrap_or(0.))
+}
+
+// --------------------------------------------------
+/// SLURM, submitting with sbatch and following jobs with sacct
+pub struct Slurm;
+
+impl Scheduler for Slurm {
+    fn name(&self) -> &'static str {
+        "slurm"
+    }
+
+    fn directives(
+        &self,
+        job_name: &str,
+        res: &Resources,
+        log: &Path,
+    ) -> String {
+        slurm_directives(job_name, res, log, None)
+    }
+
+    fn submit(&self, script: &Path) -> MyResult<String> {
+        // --parsable prints "jobid" or "jobid;cluster"
+        let stdout = run_submit("sbatch", &["--parsable"], script)?;
+        Ok(stdout.split(';').next().unwrap_or("").to_string())
+    }
+
+    fn poll(&self, ids: &[&str]) -> MyResult<Vec<Task>> {
+        // sacct lists every task of an array asked about by its parent ID
+        let parents: HashSet<&str> = ids
+            .iter()
+            .map(|id| id.split('_').next().unwrap_or(id))
+            .collect();
+
+        let output = Command::new("sacct")
+            .args(["-n", "-P", "-X", "-o", "JobID,State,ExitCode,ElapsedRaw"])
+            .arg("-j")
+            .arg(parents.into_iter().collect::<Vec<_>>().join(","))
+            .output()
+            .map_err(|e| format!("Cannot run sacct: {}", e))?;
+
+        Ok(parse_sacct(&String::from_utf8_lossy(&output.stdout)))
+    }
+}
+
+// --------------------------------------------------
+/// Writes one array script running the jobs as tasks 1..N, at most
+/// `max_running` at a time (0 = no limit), and the tasks.tsv it reads
+/// each task's command from
+pub fn write_slurm_array(
+    jobs: &[Job],
+    dir: &Path,
+    res: &Resources,
+    max_running: u32,
+) -> MyResult<PathBuf> {
+    fs::create_dir_all(dir)?;
+
+    let tasks = dir.join("tasks.tsv");
+    fs::write(
+        &tasks,
+        jobs.iter()
+            .enumerate()
+            .map(|(i, job)| {
+                format!("{}\t{}\t{}\n", i + 1, job.sample, job.command_line())
+            })
+            .collect::<String>(),
+    )?;
+
+    let mut array = format!("1-{}", jobs.len());
+    if max_running > 0 {
+        array = format!("{}%{}", array, max_running);
+    }
+
+    let path = dir.join("array.sh");
+    fs::write(
+        &path,
+        format!(
+            "#!/bin/bash\n{}\n\nset -euo pipefail\n\n\
+             cmd=$(sed -n \"${{SLURM_ARRAY_TASK_ID}}p\" {} | cut -f3-)\n\
+             eval \"$cmd\"\n",
+            slurm_directives(
+                "megahit",
+                res,
+                &dir.join("array_%A_%a.out"),
+                Some(&array)
+            ),
+            shell_quote(&tasks.display().to_string())
+        ),
+    )?;
+
+    Ok(path)
+}
+
+// --------------------------------------------------
+/// Submits the array script, returning (sample, task ID) pairs
+pub fn submit_slurm_array(
+    path: &Path,
+    jobs: &[Job],
+) -> MyResult<Vec<(String, String)>> {
+    let id = Slurm.submit(path)?;
+    Ok(jobs
+        .iter()
+        .enumerate()
+        .map(|(i, job)| (job.sample.to_string(), format!("{}_{}", id, i + 1)))
+        .collect())
+}
+
+// --------------------------------------------------
+fn slurm_directives(
+    name: &str,
+    res: &Resources,
+    log: &Path,
+    array: Option<&str>,
+) -> String {
+    let mut directives = vec![format!("--job-name={}", name)];
+
+    if let Some(array) = array {
+        directives.push(format!("--array={}", array));
+    }
+
+    directives.push(format!("--cpus-per-task={}", res.cpus));
+
+    if let Some(mem_mb) = res.mem_mb {
+        directives.push(format!("--mem={}M", mem_mb));
+    }
+
+    if let Some(time) = res.time {
+        directives.push(format!("--time={}", slurm_time(time)));
+    }
+
+    directives.push(format!("--output={}", log.display()));
+
+    directives
+        .iter()
+        .map(|d| format!("#SBATCH {}", shell_quote(d)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// --------------------------------------------------
+/// Formats a limit as SLURM's D-HH:MM:SS
+pub fn slurm_time(time: Duration) -> String {
+    let secs = time.as_secs();
+    format!(
+        "{}-{:02}:{:02}:{:02}",
+        secs / 86400,
+        (secs % 86400) / 3600,
+        (secs % 3600) / 60,
+        secs % 60
+    )
+}
+
+// --------------------------------------------------
+/// Parses `sacct -n -P -o JobID,State,ExitCode,ElapsedRaw`
+fn parse_sacct(output: &str) -> Vec<Task> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split('|').collect();
+            let (id, state, exit, elapsed) = match fields.as_slice() {
+                [id, state, exit, elapsed, ..] => (id, state, exit, elapsed),
+                _ => return None,
+            };
+
+            // e.g., "CANCELLED by 1234"
+            let state = state.split_whitespace().next().unwrap_or("");
+            let finished = !matches!(
+                state,
+                "PENDING"
+                    | "RUNNING"
+                    | "REQUEUED"
+                    | "RESIZING"
+                    | "SUSPENDED"
+                    | "CONFIGURING"
+                    | "COMPLETING"
+            );
+
+            // The script's exit code, nonzero for any state but COMPLETED
+            let code = exit
+                .split(':')
+                .next()
+                .and_then(|code| code.parse::<i32>().ok())
+                .unwrap_or(1);
+            let code = match (state, code) {
+                ("COMPLETED", _) => code,
+                (_, 0) => 1,
+                _ => code,
+            };
+
+            Some(Task {
+                id: id.to_string(),
+                state: state.to_string(),
+                finished,
+                exit_code: Some(code),
+                timed_out: state == "TIMEOUT",
+                elapsed_secs: elapsed.parse().unwrap_or(0.),
+            })
+        })
+        .collect()
+}
+
+// --------------------------------------------------
+/// PBS/Torque, submitting with qsub and following jobs with qstat
+pub struct Pbs;
+
+impl Scheduler for Pbs {
+    fn name(&self) -> &'static str {
+        "pbs"
+    }
+
+    fn directives(
+        &self,
+        job_name: &str,
+        res: &Resources,
+        log: &Path,
+    ) -> String {
+        let mut directives = vec![
+            format!("-N {}", job_name),
+            format!("-l nodes=1:ppn={}", res.cpus),
+        ];
+
+        if let Some(mem_mb) = res.mem_mb {
+            directives.push(format!("-l mem={}mb", mem_mb));
+        }
+
+        if let Some(time) = res.time {
+            directives.push(format!("-l walltime={}", hms(time)));
+        }
+
+        directives.push("-j oe".to_string());
+        directives
+            .push(format!("-o {}", shell_quote(&log.display().to_string())));
+
+        directives
+            .iter()
+            .map(|d| format!("#PBS {}", d))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn submit(&self, script: &Path) -> MyResult<String> {
+        // qsub prints the ID, e.g., "1234.server"
+        run_submit("qsub", &[], script)
+    }
+
+    fn poll(&self, ids: &[&str]) -> MyResult<Vec<Task>> {
+        let output = Command::new("qstat")
+            .arg("-f")
+            .args(ids)
+            .output()
+            .map_err(|e| format!("Cannot run qstat: {}", e))?;
+
+        let mut tasks = parse_qstat(&String::from_utf8_lossy(&output.stdout));
+
+        // Finished jobs drop out of qstat once the server forgets them
+        for id in ids {
+            if !tasks.iter().any(|task| task.id == *id) {
+                tasks.push(Task {
+                    id: id.to_string(),
+                    state: "gone".to_string(),
+                    finished: true,
+                    exit_code: None,
+                    timed_out: false,
+                    elapsed_secs: 0.,
+                });
+            }
+        }
+
+        Ok(tasks)
+    }
+}
+
+// --------------------------------------------------
+/// Parses the "Job Id:" blocks of `qstat -f`
+fn parse_qstat(output: &str) -> Vec<Task> {
+    let mut tasks: Vec<Task> = vec![];
+
+    for line in output.lines() {
+        if let Some(id) = line.strip_prefix("Job Id:") {
+            tasks.push(Task {
+                id: id.trim().to_string(),
+                state: "".to_string(),
+                finished: false,
+                exit_code: None,
+                timed_out: false,
+                elapsed_secs: 0.,
+            });
+            continue;
+        }
+
+        let (task, key, val) = match (tasks.last_mut(), line.split_once('=')) {
+            (Some(task), Some((key, val))) => (task, key.trim(), val.trim()),
+            _ => continue,
+        };
+
+        match key {
+            "job_state" => {
+                task.state = val.to_string();
+                // Torque says C(ompleted), PBS Pro F(inished)
+                task.finished = val == "C" || val == "F";
+            }
+            "exit_status" | "Exit_status" => {
+                task.exit_code = val.parse().ok();
+            }
+            "resources_used.walltime" => {
+                task.elapsed_secs = parse_hms(val);
+            }
+            _ => {}
+        }
+    }
+
+    tasks
+}
+
+// --------------------------------------------------
+/// Grid Engine (SGE/UGE), submitting with qsub, watching qstat, and
+/// reading the exit status of finished jobs from qacct
+pub struct Sge {
+    /// Parallel environment that grants the job its slots
+    pub pe: String,
+}
+
+impl Scheduler for Sge {
+    fn name(&self) -> &'static str {
+        "sge"
+    }
+
+    fn directives(
+        &self,
+        job_name: &str,
+        res: &Resources,
+        log: &Path,
+    ) -> String {
+        let mut directives = vec![
+            format!("-N {}", job_name),
+            "-S /bin/bash".to_string(),
+            format!("-pe {} {}", self.pe, res.cpus),
+        ];
+
+        // h_vmem is per slot
+        if let Some(mem_mb) = res.mem_mb {
+            directives.push(format!(
+                "-l h_vmem={}M",
+                mem_mb.div_ceil(res.cpus.max(1) as u64)
+            ));
+        }
+
+        if let Some(time) = res.time {
+            directives.push(format!("-l h_rt={}", hms(time)));
+        }
+
+        directives.push("-j y".to_string());
+        directives
+            .push(format!("-o {}", shell_quote(&log.display().to_string())));
+
+        directives
+            .iter()
+            .map(|d| format!("#$ {}", d))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn submit(&self, script: &Path) -> MyResult<String> {
+        // -terse prints only the ID
+        run_submit("qsub", &["-terse"], script)
+    }
+
+    fn poll(&self, ids: &[&str]) -> MyResult<Vec<Task>> {
+        let output = Command::new("qstat")
+            .output()
+            .map_err(|e| format!("Cannot run qstat: {}", e))?;
+        if !output.status.success() {
+            return Err(From::from(format!(
+                "qstat failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+
+        let queued = parse_sge_qstat(&String::from_utf8_lossy(&output.stdout));
+        let mut tasks = vec![];
+        for id in ids {
+            if let Some((_, state)) = queued.iter().find(|(qid, _)| qid == id) {
+                tasks.push(Task {
+                    id: id.to_string(),
+                    state: state.to_string(),
+                    finished: false,
+                    exit_code: None,
+                    timed_out: false,
+                    elapsed_secs: 0.,
+                });
+                continue;
+            }
+
+            // Gone from qstat, so done; accounting may lag a little
+            let qacct = Command::new("qacct").args(["-j", id]).output();
+            let mut task = match qacct {
+                Ok(out) if out.status.success() => {
+                    parse_qacct(&String::from_utf8_lossy(&out.stdout))
+                }
+                _ => Task {
+                    id: "".to_string(),
+                    state: "gone".to_string(),
+                    finished: true,
+                    exit_code: None,
+                    timed_out: false,
+                    elapsed_secs: 0.,
+                },
+            };
+            task.id = id.to_string();
+            tasks.push(task);
+        }
+
+        Ok(tasks)
+    }
+}
+
+// --------------------------------------------------
+/// The (job ID, state) rows of plain `qstat`, skipping its header
+fn parse_sge_qstat(output: &str) -> Vec<(String, String)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                [id, _prior, _name, _user, state, ..]
+                    if id.chars().all(|c| c.is_ascii_digit()) =>
+                {
+                    Some((id.to_string(), state.to_string()))
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+// --------------------------------------------------
+/// Parses the record `qacct -j ID` prints for a finished job
+fn parse_qacct(output: &str) -> Task {
+    let mut task = Task {
+        id: "".to_string(),
+        state: "done".to_string(),
+        finished: true,
+        exit_code: None,
+        timed_out: false,
+        elapsed_secs: 0.,
+    };
+
+    let mut failed = false;
+    for line in output.lines() {
+        let mut fields = line.split_whitespace();
+        let (key, val) = match (fields.next(), fields.next()) {
+            (Some(key), Some(val)) => (key, val),
+            _ => continue,
+        };
+
+        match key {
+            "jobnumber" => task.id = val.to_string(),
+            "exit_status" => task.exit_code = val.parse().ok(),
+            "ru_wallclock" => {
+                task.elapsed_secs =
+                    val.trim_end_matches('s').parse().unwrap_or(0.)
+            }
+            // e.g., "failed 37  : qmaster enforced h_rt limit"
+            "failed" => {
+                failed = val != "0";
+                task.timed_out = line.contains("h_rt");
+            }
+            _ => {}
+        }
+    }
+
+    if failed {
+        task.state = "failed".to_string();
+        if task.exit_code == Some(0) {
+            task.exit_code = Some(1);
+        }
+    }
+
+    task
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job() -> Job {
+        Job {
+            sample: "S1".to_string(),
+            out_dir: PathBuf::from("out/S1"),
+            program: "megahit".to_string(),
+            args: vec!["-o".to_string(), "out/S1".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_slurm_script() {
+        let res = Resources::new(
+            DEFAULT_CPUS,
+            Some(2_500_000_000.),
+            Some(Duration::from_secs(36 * 3600)),
+        );
+        let script = job_script(&Slurm, &job(), &res, Path::new("S1.out"));
+
+        assert!(script.starts_with("#!/bin/bash\n"));
+        assert!(script.contains("#SBATCH --cpus-per-task=8\n"));
+        assert!(script.contains("#SBATCH --mem=2500M\n"));
+        assert!(script.contains("#SBATCH --time=1-12:00:00\n"));
+        assert!(script.ends_with("megahit -o out/S1\n"));
+
+        // A fraction of the node's memory can't be requested
+        assert_eq!(Resources::new(DEFAULT_CPUS, Some(0.5), None).mem_mb, None);
+    }
+
+    #[test]
+    fn test_pbs_script() {
+        let res = Resources::new(
+            DEFAULT_CPUS,
+            Some(2_500_000_000.),
+            Some(Duration::from_secs(36 * 3600)),
+        );
+        let script = job_script(&Pbs, &job(), &res, Path::new("S1.out"));
+
+        assert!(script.contains("#PBS -N megahit-S1\n"));
+        assert!(script.contains("#PBS -l nodes=1:ppn=8\n"));
+        assert!(script.contains("#PBS -l mem=2500mb\n"));
+        assert!(script.contains("#PBS -l walltime=36:00:00\n"));
+        assert!(script.ends_with("megahit -o out/S1\n"));
+    }
+
+    #[test]
+    fn test_parse_sacct() {
+        let tasks = parse_sacct(
+            "100_1|COMPLETED|0:0|3600\n\
+             100_2|CANCELLED by 42|0:0|10\n\
+             100_3|FAILED|2:0|60\n\
+             100_[4-9]|PENDING|0:0|0\n",
+        );
+        assert_eq!(tasks.len(), 4);
+        assert!(tasks[0].finished);
+        assert_eq!(tasks[0].exit_code, Some(0));
+        assert_eq!(tasks[0].elapsed_secs, 3600.);
+        assert_eq!(tasks[1].state, "CANCELLED");
+        assert_eq!(tasks[1].exit_code, Some(1));
+        assert_eq!(tasks[2].exit_code, Some(2));
+        assert!(!tasks[3].finished);
+    }
+
+    #[test]
+    fn test_parse_qstat() {
+        let tasks = parse_qstat(
+            "Job Id: 7.head\n    Job_Name = megahit-S1\n    \
+             job_state = C\n    exit_status = 0\n    \
+             resources_used.walltime = 01:02:03\n\n\
+             Job Id: 8.head\n    job_state = R\n",
+        );
+        assert_eq!(tasks.len(), 2);
+        assert_eq!(tasks[0].id, "7.head");
+        assert!(tasks[0].finished);
+        assert_eq!(tasks[0].exit_code, Some(0));
+        assert_eq!(tasks[0].elapsed_secs, 3723.);
+        assert!(!tasks[1].finished);
+    }
+
+    #[test]
+    fn test_sge() {
+        let res = Resources::new(8, Some(16_000_000_000.), None);
+        let sge = Sge {
+            pe: "threaded".to_string(),
+        };
+        let script = job_script(&sge, &job(), &res, Path::new("S1.out"));
+        assert!(script.contains("#$ -pe threaded 8\n"));
+        assert!(script.contains("#$ -l h_vmem=2000M\n"));
+
+        let queued = parse_sge_qstat(
+            "job-ID  prior   name       user  state submit/start at\n\
+             -----------------------------------------------------\n\
+             101 0.5 megahit-S1 ken r 01/01/2024 10:00:00 all.q@n1 8\n",
+        );
+        assert_eq!(queued, vec![("101".to_string(), "r".to_string())]);
+
+        let task = parse_qacct(
+            "jobnumber    101\nexit_status  137\nru_wallclock 3600s\n\
+             failed       37  : qmaster enforced h_rt limit\n",
+        );
+        assert_eq!(task.exit_code, Some(137));
+        assert_eq!(task.elapsed_secs, 3600.);
+        assert!(task.timed_out);
+    }
+
+    #[test]
+    fn test_sge_cpus() {
+        // h_vmem is per slot, so fewer slots each get more
+        let res = Resources::new(4, Some(16_000_000_000.), None);
+        let sge = Sge {
+            pe: "threaded".to_string(),
+        };
+        let script = job_script(&sge, &job(), &res, Path::new("S1.out"));
+        assert!(script.contains("#$ -pe threaded 4\n"));
+        assert!(script.contains("#$ -l h_vmem=4000M\n"));
+    }
+}
@@ -0,0 +1,187 @@
+use crate::report::JobResult;
+use crate::Job;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::sync::Mutex;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+type MyResult<T> = Result<T, Box<dyn Error>>;
+
+/// Where the batch's progress is kept, relative to out_dir
+pub const STATE_FILE: &str = ".run_megahit_state.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Pending,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub status: Status,
+    /// How the sample's last job ended, once it has run
+    #[serde(default)]
+    pub result: Option<JobResult>,
+}
+
+/// Every sample of the batch, by name
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct State {
+    pub samples: BTreeMap<String, Entry>,
+}
+
+impl State {
+    /// The state left by an earlier run, or an empty one if there was none
+    pub fn load(path: &Path) -> MyResult<State> {
+        if !path.is_file() {
+            return Ok(State::default());
+        }
+
+        let contents = fs::read_to_string(path)?;
+        serde_json::from_str(&contents)
+            .map_err(|e| From::from(format!("{}: {}", path.display(), e)))
+    }
+
+    /// The earlier result of a sample that finished and still has its
+    /// output, meaning the job need not run again
+    pub fn completed(&self, job: &Job) -> Option<&JobResult> {
+        match self.samples.get(&job.sample) {
+            Some(Entry {
+                status: Status::Completed,
+                result: Some(res),
+            }) if job.out_dir.join("done").is_file() => Some(res),
+            _ => None,
+        }
+    }
+}
+
+/// The state of the running batch, rewritten as each job finishes so that
+/// an interrupted batch can be resumed
+#[derive(Debug)]
+pub struct StateFile {
+    path: PathBuf,
+    state: Mutex<State>,
+}
+
+impl StateFile {
+    /// Starts the file with `jobs` pending and `done` already completed
+    pub fn create(
+        path: &Path,
+        jobs: &[Job],
+        done: &[JobResult],
+    ) -> MyResult<StateFile> {
+        let mut state = State::default();
+        for job in jobs {
+            state.samples.insert(
+                job.sample.to_string(),
+                Entry {
+                    status: Status::Pending,
+                    result: None,
+                },
+            );
+        }
+
+        for res in done {
+            state.samples.insert(
+                res.sample.to_string(),
+                Entry {
+                    status: Status::Completed,
+                    result: Some(res.clone()),
+                },
+            );
+        }
+
+        let file = StateFile {
+            path: path.to_path_buf(),
+            state: Mutex::new(state),
+        };
+        file.save()?;
+        Ok(file)
+    }
+
+    /// Records how a job ended
+    pub fn record(&self, res: &JobResult) -> MyResult<()> {
+        let status = match res.exit_code {
+            Some(0) => Status::Completed,
+            Some(_) => Status::Failed,
+            None => Status::Pending,
+        };
+
+        self.state.lock().unwrap().samples.insert(
+            res.sample.to_string(),
+            Entry {
+                status,
+                result: Some(res.clone()),
+            },
+        );
+        self.save()
+    }
+
+    /// Writes the state beside its final name first so that a crash never
+    /// leaves half a file
+    pub fn save(&self) -> MyResult<()> {
+        let json = serde_json::to_string_pretty(&*self.state.lock().unwrap())?;
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        fs::create_dir_all(dir.join("A")).unwrap();
+        fs::write(dir.join("A").join("done"), "").unwrap();
+
+        let jobs: Vec<Job> = ["A", "B"]
+            .iter()
+            .map(|name| Job {
+                sample: name.to_string(),
+                out_dir: dir.join(name),
+                program: "megahit".to_string(),
+                args: vec![],
+            })
+            .collect();
+
+        let path = dir.join(STATE_FILE);
+        let file = StateFile::create(&path, &jobs, &[]).unwrap();
+        for (job, exit_code) in jobs.iter().zip([0, 1]) {
+            file.record(&JobResult {
+                sample: job.sample.to_string(),
+                out_dir: job.out_dir.clone(),
+                exit_code: Some(exit_code),
+                runtime_secs: 1.,
+                attempts: 1,
+                ..JobResult::default()
+            })
+            .unwrap();
+        }
+
+        let state = State::load(&path).unwrap();
+        assert_eq!(state.samples["B"].status, Status::Failed);
+        assert!(state.completed(&jobs[0]).is_some());
+        assert!(state.completed(&jobs[1]).is_none());
+
+        // Completed, but the output has since gone
+        fs::remove_file(dir.join("A").join("done")).unwrap();
+        assert!(state.completed(&jobs[0]).is_none());
+
+        assert!(State::load(&dir.join("missing.json"))
+            .unwrap()
+            .samples
+            .is_empty());
+    }
+}
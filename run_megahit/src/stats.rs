@@ -0,0 +1,182 @@
+use crate::reads;
+use crate::report::Metric;
+use std::error::Error;
+use std::io::BufRead;
+use std::path::Path;
+
+type MyResult<T> = Result<T, Box<dyn Error>>;
+
+/// The stats stage's table of the batch, relative to out_dir
+pub const STATS_FILE: &str = "assembly_stats.tsv";
+
+/// The size and contiguity of an assembly
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssemblyStats {
+    pub num_contigs: usize,
+    pub total_length: u64,
+    pub min_length: u64,
+    pub max_length: u64,
+    pub mean_length: f64,
+    /// Length of the contig that, with every longer one, covers half of
+    /// the total length
+    pub n50: u64,
+    /// Likewise for 90% of the total length
+    pub n90: u64,
+    /// How many contigs it takes to reach the N50
+    pub l50: usize,
+    /// Percent of the called bases (not N) that are G or C
+    pub gc: f64,
+}
+
+impl AssemblyStats {
+    /// The stats by the names of their columns in STATS_FILE
+    pub fn columns(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("contigs", self.num_contigs.to_string()),
+            ("total_length", self.total_length.to_string()),
+            ("min_length", self.min_length.to_string()),
+            ("max_length", self.max_length.to_string()),
+            ("mean_length", format!("{:.1}", self.mean_length)),
+            ("n50", self.n50.to_string()),
+            ("n90", self.n90.to_string()),
+            ("l50", self.l50.to_string()),
+            ("gc", format!("{:.2}", self.gc)),
+        ]
+    }
+
+    /// The stats for the batch summary
+    pub fn metrics(&self) -> Vec<Metric> {
+        self.columns()
+            .into_iter()
+            .map(|(name, value)| Metric {
+                stage: "stats".to_string(),
+                name: name.to_string(),
+                value,
+            })
+            .collect()
+    }
+}
+
+// --------------------------------------------------
+/// Reads the contigs of a FASTA file
+pub fn assembly_stats(path: &Path) -> MyResult<AssemblyStats> {
+    let file = path.display().to_string();
+    let mut lengths: Vec<u64> = vec![];
+    let (mut gc, mut called) = (0u64, 0u64);
+    for line in reads::open(&file)?.lines() {
+        let line = line.map_err(|e| format!("{}: {}", file, e))?;
+        if line.starts_with('>') {
+            lengths.push(0);
+        } else if let Some(last) = lengths.last_mut() {
+            let seq = line.trim_end();
+            *last += seq.len() as u64;
+            for base in seq.bytes() {
+                match base.to_ascii_uppercase() {
+                    b'G' | b'C' => {
+                        gc += 1;
+                        called += 1;
+                    }
+                    b'A' | b'T' => called += 1,
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    let mut stats = from_lengths(lengths);
+    if called > 0 {
+        stats.gc = 100. * gc as f64 / called as f64;
+    }
+    Ok(stats)
+}
+
+// --------------------------------------------------
+/// The stats of the final contigs in a sample's out_dir, for the batch
+/// summary
+pub fn read_summary(dir: &Path) -> MyResult<Vec<Metric>> {
+    Ok(assembly_stats(&dir.join("final.contigs.fa"))?.metrics())
+}
+
+// --------------------------------------------------
+fn from_lengths(mut lengths: Vec<u64>) -> AssemblyStats {
+    lengths.sort_unstable_by(|a, b| b.cmp(a));
+    let total_length: u64 = lengths.iter().sum();
+
+    // The contig that brings the running total to `percent`, and its rank
+    let nx = |percent: u64| {
+        let mut covered = 0;
+        lengths
+            .iter()
+            .position(|&len| {
+                covered += len;
+                covered * 100 >= total_length * percent
+            })
+            .map_or((0, 0), |i| (lengths[i], i + 1))
+    };
+    let (n50, l50) = nx(50);
+    let (n90, _) = nx(90);
+
+    AssemblyStats {
+        num_contigs: lengths.len(),
+        total_length,
+        min_length: lengths.last().copied().unwrap_or(0),
+        max_length: lengths.first().copied().unwrap_or(0),
+        mean_length: if lengths.is_empty() {
+            0.
+        } else {
+            total_length as f64 / lengths.len() as f64
+        },
+        n50,
+        n90,
+        l50,
+        gc: 0.,
+    }
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_assembly_stats() {
+        assert_eq!(
+            from_lengths(vec![2, 10, 3, 5]),
+            AssemblyStats {
+                num_contigs: 4,
+                total_length: 20,
+                min_length: 2,
+                max_length: 10,
+                mean_length: 5.,
+                n50: 10,
+                n90: 3,
+                l50: 1,
+                gc: 0.,
+            }
+        );
+        assert_eq!(from_lengths(vec![]).n50, 0);
+
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let fasta = dir.join("contigs.fa");
+        fs::write(&fasta, ">c1\nACGT\nAC\n>c2\nGGC\n>c3\nN\n").unwrap();
+        let stats = assembly_stats(&fasta).unwrap();
+        assert_eq!(stats.num_contigs, 3);
+        assert_eq!(stats.total_length, 10);
+        assert_eq!(stats.n50, 6);
+        assert_eq!(stats.l50, 1);
+        assert_eq!(stats.n90, 3);
+        assert_eq!(format!("{:.2}", stats.gc), "66.67");
+
+        let dir = dir.join("S1");
+        fs::create_dir_all(&dir).unwrap();
+        fs::copy(&fasta, dir.join("final.contigs.fa")).unwrap();
+        let values: Vec<String> = read_summary(&dir)
+            .unwrap()
+            .into_iter()
+            .map(|metric| metric.value)
+            .collect();
+        assert_eq!(values.join(" "), "3 10 1 6 3.3 6 3 1 66.67");
+    }
+}
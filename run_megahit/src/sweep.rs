@@ -0,0 +1,230 @@
+use crate::report::JobResult;
+use crate::{stats, PRESETS};
+use serde::Serialize;
+use std::error::Error;
+use std::{fs, path::Path};
+
+type MyResult<T> = Result<T, Box<dyn Error>>;
+
+/// The comparison of the parameter sets, relative to out_dir
+pub const TABLE_FILE: &str = "sweep.tsv";
+
+/// The options a sweep can vary, with the megahit flag each sets
+const KEYS: &[(&str, &str)] = &[
+    ("k_min", "--k-min"),
+    ("k_max", "--k-max"),
+    ("k_step", "--k-step"),
+    ("min_count", "--min-count"),
+    ("min_contig_len", "--min-contig-len"),
+    ("preset", "--presets"),
+];
+
+/// One combination of the swept values
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ParamSet {
+    /// E.g., "k_min=21,preset=meta-large"
+    pub label: String,
+    /// Where in out_dir the set's assemblies go
+    pub dir: String,
+    /// The megahit flags the set decides
+    pub flags: Vec<String>,
+    pub args: Vec<String>,
+}
+
+impl ParamSet {
+    /// The batch's megahit options with the swept ones taken from the set
+    pub fn apply(&self, options: &[String]) -> Vec<String> {
+        let mut args = vec![];
+        let mut iter = options.iter();
+        while let Some(opt) = iter.next() {
+            if self.flags.contains(opt) {
+                iter.next();
+            } else {
+                args.push(opt.to_string());
+            }
+        }
+        args.extend(self.args.iter().cloned());
+        args
+    }
+
+    /// The value the set gives a megahit flag
+    pub fn value(&self, flag: &str) -> Option<&str> {
+        self.args
+            .chunks(2)
+            .find(|pair| pair[0] == flag)
+            .map(|pair| pair[1].as_str())
+    }
+}
+
+// --------------------------------------------------
+/// Every combination of the values in "KEY=V1,V2" specs, e.g.,
+/// "k_min=21,27" and "preset=meta-sensitive,default" make four sets
+pub fn parse(specs: &[String]) -> MyResult<Vec<ParamSet>> {
+    let mut sets: Vec<Vec<(&str, &str, String)>> = vec![vec![]];
+    let mut seen: Vec<&str> = vec![];
+
+    for spec in specs {
+        let (key, values) = spec.split_once('=').ok_or_else(|| {
+            format!("--sweep \"{}\" must look like KEY=V1,V2", spec)
+        })?;
+        let key = key.trim();
+        let flag = match KEYS.iter().find(|(name, _)| *name == key) {
+            Some((_, flag)) => *flag,
+            _ => {
+                return Err(From::from(format!(
+                    "--sweep cannot vary \"{}\", choose from {}",
+                    key,
+                    KEYS.iter()
+                        .map(|(name, _)| *name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )))
+            }
+        };
+        if seen.contains(&key) {
+            return Err(From::from(format!("--sweep repeats \"{}\"", key)));
+        }
+        seen.push(key);
+
+        let values: Vec<&str> = values.split(',').map(str::trim).collect();
+        for val in &values {
+            let ok = if key == "preset" {
+                *val == "default" || PRESETS.contains(val)
+            } else {
+                val.parse::<u32>().is_ok_and(|n| n > 0)
+            };
+            if !ok {
+                return Err(From::from(format!(
+                    "--sweep {} \"{}\" is not valid",
+                    key, val
+                )));
+            }
+        }
+
+        sets = sets
+            .iter()
+            .flat_map(|set| {
+                values.iter().map(move |val| {
+                    let mut set = set.clone();
+                    set.push((key, flag, val.to_string()));
+                    set
+                })
+            })
+            .collect();
+    }
+
+    Ok(sets
+        .into_iter()
+        .map(|set| ParamSet {
+            label: set
+                .iter()
+                .map(|(key, _, val)| format!("{}={}", key, val))
+                .collect::<Vec<_>>()
+                .join(","),
+            dir: set
+                .iter()
+                .map(|(key, _, val)| format!("{}-{}", key, val))
+                .collect::<Vec<_>>()
+                .join("_"),
+            flags: set.iter().map(|(_, flag, _)| flag.to_string()).collect(),
+            // The "default" preset is no --presets at all
+            args: set
+                .iter()
+                .filter(|(key, _, val)| !(*key == "preset" && val == "default"))
+                .flat_map(|(_, flag, val)| [flag.to_string(), val.to_string()])
+                .collect(),
+        })
+        .collect())
+}
+
+// --------------------------------------------------
+/// One row per set and sample with how long the assembly took and how
+/// big and contiguous it came out
+pub fn write_table(
+    path: &Path,
+    sets: &[ParamSet],
+    results: &[JobResult],
+) -> MyResult<()> {
+    let mut lines = vec![
+        "parameters\tsample\tstatus\truntime_secs\tcontigs\ttotal_length\tn50"
+            .to_string(),
+    ];
+    for set in sets {
+        let prefix = format!("{}/", set.dir);
+        for res in results {
+            let sample = match res.sample.strip_prefix(&prefix) {
+                Some(sample) => sample,
+                _ => continue,
+            };
+
+            let stats = if res.exit_code == Some(0) {
+                stats::assembly_stats(&res.out_dir.join("final.contigs.fa"))
+                    .ok()
+            } else {
+                None
+            };
+            lines.push(format!(
+                "{}\t{}\t{}\t{:.1}\t{}",
+                set.label,
+                sample,
+                res.status(),
+                res.runtime_secs,
+                match stats {
+                    Some(stats) => format!(
+                        "{}\t{}\t{}",
+                        stats.num_contigs, stats.total_length, stats.n50
+                    ),
+                    _ => "NA\tNA\tNA".to_string(),
+                }
+            ));
+        }
+    }
+
+    fs::write(path, lines.join("\n") + "\n")?;
+    Ok(())
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let specs = vec![
+            "k_min=21,27".to_string(),
+            "preset=meta-sensitive,default".to_string(),
+        ];
+        let sets = parse(&specs).unwrap();
+        assert_eq!(sets.len(), 4);
+        assert_eq!(sets[0].label, "k_min=21,preset=meta-sensitive");
+        assert_eq!(sets[0].dir, "k_min-21_preset-meta-sensitive");
+        assert_eq!(sets[3].args, vec!["--k-min", "27"]);
+        assert_eq!(sets[3].value("--k-min"), Some("27"));
+        assert_eq!(sets[3].value("--presets"), None);
+
+        let options: Vec<String> = ["--k-min", "31", "--memory", "0.5"]
+            .iter()
+            .map(|opt| opt.to_string())
+            .collect();
+        assert_eq!(
+            sets[0].apply(&options),
+            vec![
+                "--memory",
+                "0.5",
+                "--k-min",
+                "21",
+                "--presets",
+                "meta-sensitive"
+            ]
+        );
+
+        assert!(parse(&["k_min".to_string()]).is_err());
+        assert!(parse(&["kmer=21".to_string()]).is_err());
+        assert!(parse(&["k_min=x".to_string()]).is_err());
+        assert!(parse(&["preset=fast".to_string()]).is_err());
+        assert!(
+            parse(&["k_min=21".to_string(), "k_min=27".to_string()]).is_err()
+        );
+    }
+}
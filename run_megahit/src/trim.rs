@@ -0,0 +1,195 @@
+use crate::Sample;
+use std::path::Path;
+
+/// The trimmers --trim takes
+pub const TOOLS: &[&str] = &["fastp", "trim_galore"];
+
+// --------------------------------------------------
+/// The sample with its reads swapped for the trimmed ones `dir` will
+/// hold, and the commands that trim them
+pub fn plan(
+    tool: &str,
+    sample: &Sample,
+    dir: &Path,
+    threads: Option<u32>,
+) -> (Sample, Vec<Vec<String>>) {
+    let mut trimmed = Sample {
+        fwd: vec![],
+        rev: vec![],
+        single: vec![],
+        ..sample.clone()
+    };
+    let mut commands = vec![];
+
+    for (r1, r2) in sample.fwd.iter().zip(&sample.rev) {
+        let (out1, out2) = match tool {
+            "trim_galore" => (
+                trimmed_name(dir, r1, "_val_1"),
+                trimmed_name(dir, r2, "_val_2"),
+            ),
+            _ => (in_dir(dir, r1), in_dir(dir, r2)),
+        };
+        commands.push(match tool {
+            "trim_galore" => {
+                let mut cmd = trim_galore(dir, threads);
+                cmd.extend(args(&["--paired", r1, r2]));
+                cmd
+            }
+            _ => {
+                let mut cmd = fastp(dir, r1, threads);
+                cmd.extend(args(&[
+                    "--in1",
+                    r1,
+                    "--in2",
+                    r2,
+                    "--out1",
+                    &out1,
+                    "--out2",
+                    &out2,
+                    "--detect_adapter_for_pe",
+                ]));
+                cmd
+            }
+        });
+        trimmed.fwd.push(out1);
+        trimmed.rev.push(out2);
+    }
+
+    for file in &sample.single {
+        let (out, cmd) = match tool {
+            "trim_galore" => {
+                let mut cmd = trim_galore(dir, threads);
+                cmd.push(file.to_string());
+                (trimmed_name(dir, file, "_trimmed"), cmd)
+            }
+            _ => {
+                let out = in_dir(dir, file);
+                let mut cmd = fastp(dir, file, threads);
+                cmd.extend(args(&["--in1", file, "--out1", &out]));
+                (out, cmd)
+            }
+        };
+        commands.push(cmd);
+        trimmed.single.push(out);
+    }
+
+    (trimmed, commands)
+}
+
+// --------------------------------------------------
+/// fastp with its reports for `file` in `dir`
+fn fastp(dir: &Path, file: &str, threads: Option<u32>) -> Vec<String> {
+    let (base, _) = base_name(file);
+    let report = |ext: &str| {
+        dir.join(format!("{}.fastp.{}", base, ext))
+            .display()
+            .to_string()
+    };
+    let mut cmd = args(&[
+        "fastp",
+        "--json",
+        &report("json"),
+        "--html",
+        &report("html"),
+    ]);
+    if let Some(threads) = threads {
+        // fastp takes no more than 16
+        cmd.extend(args(&["--thread", &threads.min(16).to_string()]));
+    }
+    cmd
+}
+
+// --------------------------------------------------
+fn trim_galore(dir: &Path, threads: Option<u32>) -> Vec<String> {
+    let mut cmd = args(&["trim_galore", "-o", &dir.display().to_string()]);
+    if let Some(threads) = threads {
+        cmd.extend(args(&["--cores", &threads.to_string()]));
+    }
+    cmd
+}
+
+// --------------------------------------------------
+pub fn args(words: &[&str]) -> Vec<String> {
+    words.iter().map(|word| word.to_string()).collect()
+}
+
+// --------------------------------------------------
+fn in_dir(dir: &Path, file: &str) -> String {
+    let name = Path::new(file).file_name().unwrap_or_default();
+    dir.join(name).display().to_string()
+}
+
+// --------------------------------------------------
+/// The file Trim Galore makes of `file`: its name without the FASTQ
+/// extension, then `suffix`, then "fq" (and "gz" for gzipped input)
+fn trimmed_name(dir: &Path, file: &str, suffix: &str) -> String {
+    let (base, gz) = base_name(file);
+    dir.join(format!(
+        "{}{}.fq{}",
+        base,
+        suffix,
+        if gz { ".gz" } else { "" }
+    ))
+    .display()
+    .to_string()
+}
+
+// --------------------------------------------------
+/// The file name without its read extension, and whether it is gzipped
+pub fn base_name(file: &str) -> (String, bool) {
+    let name = Path::new(file)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let (name, gz) = match name.strip_suffix(".gz") {
+        Some(name) => (name.to_string(), true),
+        _ => (name, false),
+    };
+    let base = [".fastq", ".fq", ".fasta", ".fa"]
+        .iter()
+        .find_map(|ext| name.strip_suffix(ext))
+        .unwrap_or(&name)
+        .to_string();
+    (base, gz)
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan() {
+        let sample = Sample {
+            name: "S1".to_string(),
+            fwd: vec!["in/S1_R1.fastq.gz".to_string()],
+            rev: vec!["in/S1_R2.fastq.gz".to_string()],
+            single: vec!["in/S1.fq".to_string()],
+            ..Sample::default()
+        };
+        let dir = Path::new("out/work/S1");
+
+        let (trimmed, commands) = plan("fastp", &sample, dir, Some(32));
+        assert_eq!(trimmed.fwd, vec!["out/work/S1/S1_R1.fastq.gz"]);
+        assert_eq!(trimmed.single, vec!["out/work/S1/S1.fq"]);
+        assert_eq!(commands.len(), 2);
+        assert_eq!(
+            commands[0].join(" "),
+            "fastp --json out/work/S1/S1_R1.fastp.json \
+             --html out/work/S1/S1_R1.fastp.html --thread 16 \
+             --in1 in/S1_R1.fastq.gz --in2 in/S1_R2.fastq.gz \
+             --out1 out/work/S1/S1_R1.fastq.gz \
+             --out2 out/work/S1/S1_R2.fastq.gz --detect_adapter_for_pe"
+        );
+
+        let (trimmed, commands) = plan("trim_galore", &sample, dir, None);
+        assert_eq!(trimmed.fwd, vec!["out/work/S1/S1_R1_val_1.fq.gz"]);
+        assert_eq!(trimmed.rev, vec!["out/work/S1/S1_R2_val_2.fq.gz"]);
+        assert_eq!(trimmed.single, vec!["out/work/S1/S1_trimmed.fq"]);
+        assert_eq!(
+            commands[0].join(" "),
+            "trim_galore -o out/work/S1 --paired in/S1_R1.fastq.gz \
+             in/S1_R2.fastq.gz"
+        );
+    }
+}
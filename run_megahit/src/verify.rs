@@ -0,0 +1,219 @@
+use crate::checksum;
+use crate::report::{JobResult, RunSummary};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::{fs, path::Path};
+
+type MyResult<T> = Result<T, Box<dyn Error>>;
+
+/// Digests of each finished sample's contigs, relative to out_dir
+pub const OUTPUT_MANIFEST: &str = "checksums.sha256";
+
+/// The --checksums digests of the batch's inputs, by full path, relative
+/// to out_dir
+pub const INPUT_MANIFEST: &str = "input_checksums.txt";
+
+/// Files megahit leaves in a sample directory after a successful run
+const EXPECTED_FILES: &[&str] = &["final.contigs.fa", "log", "done"];
+
+// --------------------------------------------------
+/// Records the contig checksums of every successful job
+pub fn write_output_manifest(
+    out_dir: &Path,
+    results: &[JobResult],
+) -> MyResult<()> {
+    let mut lines = vec![];
+    for res in results.iter().filter(|r| r.exit_code == Some(0)) {
+        let contigs = res.out_dir.join(EXPECTED_FILES[0]);
+        if let Ok(digest) = checksum::sha256_file(&contigs.to_string_lossy()) {
+            lines.push(format!(
+                "{}  {}/{}\n",
+                digest, res.sample, EXPECTED_FILES[0]
+            ));
+        }
+    }
+
+    fs::write(out_dir.join(OUTPUT_MANIFEST), lines.concat())?;
+    Ok(())
+}
+
+// --------------------------------------------------
+/// Records what the checksum manifest expects of each input so that
+/// `verify` can check the inputs again
+pub fn write_input_manifest(
+    out_dir: &Path,
+    manifest: &Path,
+    inputs: &[String],
+) -> MyResult<()> {
+    let lookup = checksum::read_manifest(manifest)?;
+    let mut lines = vec![];
+    for file in inputs {
+        let path = checksum::full_path(file);
+        if let Some(digest) = lookup.get(&path) {
+            lines.push(format!("{}  {}\n", digest, path));
+        }
+    }
+
+    fs::write(out_dir.join(INPUT_MANIFEST), lines.concat())?;
+    Ok(())
+}
+
+// --------------------------------------------------
+/// Checks the finished samples in out_dir against the report and the
+/// checksum manifest, and the inputs against theirs when the batch had
+/// one, returning every discrepancy found
+pub fn verify(out_dir: &Path) -> MyResult<Vec<String>> {
+    if !out_dir.is_dir() {
+        return Err(From::from(format!(
+            "\"{}\" is not a directory",
+            out_dir.display()
+        )));
+    }
+
+    let mut problems = vec![];
+
+    // sample => status according to the report, if there is one
+    let mut reported: BTreeMap<String, String> = BTreeMap::new();
+    let report = out_dir.join("report.json");
+    if report.is_file() {
+        let summary: RunSummary =
+            serde_json::from_str(&fs::read_to_string(&report)?)?;
+        for res in &summary.results {
+            reported.insert(res.sample.to_string(), res.status().to_string());
+        }
+    }
+
+    let manifest = out_dir.join(OUTPUT_MANIFEST);
+    let entries = if manifest.is_file() {
+        checksum::read_entries(&manifest)?
+    } else {
+        problems.push(format!("Missing {}", OUTPUT_MANIFEST));
+        vec![]
+    };
+
+    for (name, expected) in &entries {
+        let path = out_dir.join(name);
+        if !path.is_file() {
+            problems.push(format!("{}: missing", name));
+        } else if &checksum::digest_like(&path.to_string_lossy(), expected)?
+            != expected
+        {
+            problems.push(format!("{}: checksum mismatch", name));
+        }
+    }
+
+    let inputs = out_dir.join(INPUT_MANIFEST);
+    if inputs.is_file() {
+        for (path, expected) in checksum::read_entries(&inputs)? {
+            if !Path::new(&path).is_file() {
+                problems.push(format!("{}: missing input", path));
+            } else if checksum::digest_like(&path, &expected)? != expected {
+                problems.push(format!("{}: input checksum mismatch", path));
+            }
+        }
+    }
+
+    let in_manifest: Vec<String> = entries
+        .iter()
+        .filter_map(|(name, _)| name.split('/').next())
+        .map(|s| s.to_string())
+        .collect();
+
+    let mut samples: Vec<&String> = reported
+        .iter()
+        .filter(|(_, status)| status.as_str() == "ok")
+        .map(|(sample, _)| sample)
+        .collect();
+    for sample in &in_manifest {
+        if !samples.contains(&sample) {
+            samples.push(sample);
+        }
+    }
+    samples.sort();
+
+    for sample in samples {
+        for file in EXPECTED_FILES {
+            if !out_dir.join(sample).join(file).is_file() {
+                problems.push(format!("{}: missing {}", sample, file));
+            }
+        }
+
+        if !reported.is_empty() {
+            match reported.get(sample).map(|s| s.as_str()) {
+                Some("ok") => {}
+                Some(status) => problems.push(format!(
+                    "{}: in {} but reported as \"{}\"",
+                    sample, OUTPUT_MANIFEST, status
+                )),
+                None => problems.push(format!(
+                    "{}: in {} but not in report.json",
+                    sample, OUTPUT_MANIFEST
+                )),
+            }
+        }
+
+        if !in_manifest.contains(sample) {
+            problems.push(format!(
+                "{}: reported ok but not in {}",
+                sample, OUTPUT_MANIFEST
+            ));
+        }
+    }
+
+    Ok(problems)
+}
+
+// --------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify() {
+        let out_dir = tempfile::tempdir().unwrap();
+        let out_dir = out_dir.path();
+        let sample_dir = out_dir.join("S1");
+        fs::create_dir_all(&sample_dir).unwrap();
+        for file in EXPECTED_FILES {
+            fs::write(sample_dir.join(file), ">c1\nACGT\n").unwrap();
+        }
+
+        let results = vec![JobResult {
+            sample: "S1".to_string(),
+            out_dir: sample_dir.clone(),
+            exit_code: Some(0),
+            runtime_secs: 1.,
+            attempts: 1,
+            ..JobResult::default()
+        }];
+        write_output_manifest(out_dir, &results).unwrap();
+        assert!(verify(out_dir).unwrap().is_empty());
+
+        fs::write(sample_dir.join("final.contigs.fa"), ">c1\nAC\n").unwrap();
+        fs::remove_file(sample_dir.join("done")).unwrap();
+        assert_eq!(
+            verify(out_dir).unwrap(),
+            vec!["S1/final.contigs.fa: checksum mismatch", "S1: missing done"]
+        );
+
+        // The inputs as the --checksums manifest had them
+        let reads = out_dir.join("S1.fastq");
+        fs::write(&reads, "@r1\nACGT\n+\nIIII\n").unwrap();
+        let digest = checksum::sha256_file(&reads.to_string_lossy()).unwrap();
+        let manifest = out_dir.join("sha256sum.txt");
+        fs::write(&manifest, format!("{}  S1.fastq\n", digest)).unwrap();
+        let inputs = vec![reads.display().to_string()];
+        write_input_manifest(out_dir, &manifest, &inputs).unwrap();
+        assert_eq!(verify(out_dir).unwrap().len(), 2);
+
+        fs::write(&reads, "@r1\nACGG\n+\nIIII\n").unwrap();
+        let path = checksum::full_path(&inputs[0]);
+        assert!(verify(out_dir)
+            .unwrap()
+            .contains(&format!("{}: input checksum mismatch", path)));
+        fs::remove_file(&reads).unwrap();
+        assert!(verify(out_dir)
+            .unwrap()
+            .contains(&format!("{}: missing input", path)));
+    }
+}